@@ -0,0 +1,75 @@
+// Feeds the page's keys and size to the editor and draws what it renders.
+// The names are the `code`s of mters_key in src/lib.rs, from 1.
+const NAMED = ["Backspace", "Enter", "ArrowLeft", "ArrowRight", "ArrowUp", "ArrowDown",
+  "Home", "End", "PageUp", "PageDown", "Tab", "Delete", "Escape"];
+
+const screen = document.getElementById("screen");
+const cursor = document.getElementById("cursor");
+const rows = [];
+let ed;
+let timer;
+
+function size() {
+  // One cell is 1ch by 1.2em at 16px.
+  const cols = Math.floor((window.innerWidth - 8) / (16 * 0.6));
+  const lines = Math.floor((window.innerHeight - 8) / (16 * 1.2));
+  return [Math.max(cols, 20), Math.max(lines, 4)];
+}
+
+function draw() {
+  const len = ed.mters_render();
+  const bytes = new Uint8Array(ed.memory.buffer, ed.mters_frame(), len);
+  const [head, ...lines] = new TextDecoder().decode(bytes).split("\n");
+  for (const line of lines) {
+    if (line === "") continue;
+    const space = line.indexOf(" ");
+    const y = Number(line.slice(0, space));
+    while (rows.length <= y) {
+      const row = document.createElement("div");
+      row.className = "row";
+      screen.appendChild(row);
+      rows.push(row);
+    }
+    rows[y].textContent = line.slice(space + 1);
+  }
+  const [x, y] = head.split(" ").map(Number);
+  cursor.style.left = `calc(${x}ch + 4px)`;
+  cursor.style.top = `calc(${y * 1.2}em + 4px)`;
+}
+
+// 0 nothing to draw, 1 a new frame, 3 the editor quit.
+function after(outcome) {
+  if (outcome === 3) {
+    screen.textContent = "[Process exited]";
+    clearTimeout(timer);
+    return;
+  }
+  if (outcome === 1) draw();
+  clearTimeout(timer);
+  const wait = Math.max(ed.mters_wake_at(Date.now()) - Date.now(), 0);
+  timer = setTimeout(() => after(ed.mters_tick(Date.now())), wait);
+}
+
+screen.addEventListener("keydown", (e) => {
+  const mods = (e.shiftKey ? 1 : 0) | (e.ctrlKey ? 2 : 0) | (e.altKey ? 4 : 0);
+  let code = NAMED.indexOf(e.key) + 1;
+  let ch = 0;
+  if (code === 0) {
+    if ([...e.key].length !== 1) return;
+    ch = e.key.codePointAt(0);
+  }
+  e.preventDefault();
+  after(ed.mters_key(code, ch, mods, Date.now()));
+});
+
+window.addEventListener("resize", () => {
+  for (const row of rows.splice(0)) row.remove();
+  after(ed.mters_resize(...size(), Date.now()));
+});
+
+WebAssembly.instantiateStreaming(fetch("mters_web.wasm")).then(({ instance }) => {
+  ed = instance.exports;
+  ed.mters_start(...size(), Date.now());
+  after(1);
+  screen.focus();
+});
@@ -0,0 +1,147 @@
+//! The editor in a web page: the smallest frontend that isn't a terminal.
+//!
+//! ```sh
+//! cargo build --release --target wasm32-unknown-unknown
+//! cp target/wasm32-unknown-unknown/release/mters_web.wasm .
+//! python3 -m http.server   # then open http://localhost:8000
+//! ```
+//!
+//! No bindings generator: `main.js` calls these functions with numbers
+//! and reads frames out of the module's memory. Times are milliseconds
+//! since the Unix epoch (`Date.now()`); the core has no clock of its own.
+
+use neo2vim::core::{EditorCore, Event, Outcome};
+use neo2vim::editor::Editor;
+use neo2vim::keys::{KeyCode, KeyEvent, KeyModifiers};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::time::Duration;
+
+thread_local! {
+    static CORE: RefCell<Option<EditorCore>> = const { RefCell::new(None) };
+    /// The last frame as text, where `mters_frame` points.
+    static FRAME: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn ms(now: f64) -> Duration {
+    Duration::from_secs_f64(now.max(0.0) / 1000.0)
+}
+
+/// Run `f` on the editor; 3 (quit) once there is none.
+fn with_core(f: impl FnOnce(&mut EditorCore) -> Outcome) -> u32 {
+    CORE.with_borrow_mut(|core| {
+        let Some(ed) = core else { return 3 };
+        let outcome = f(ed);
+        if outcome == Outcome::Quit {
+            *core = None;
+        }
+        match outcome {
+            Outcome::Unchanged => 0,
+            // No shell to suspend to: the same as any change.
+            Outcome::Changed | Outcome::Suspend => 1,
+            Outcome::Quit => 3,
+        }
+    })
+}
+
+/// An empty buffer on a `cols` by `rows` grid.
+#[no_mangle]
+pub extern "C" fn mters_start(cols: u32, rows: u32, now: f64) {
+    let core = EditorCore::new(Editor::new(), cols as usize, rows as usize, ms(now));
+    CORE.set(Some(core));
+}
+
+/// A key: `ch` when `code` is 0, else one of the named keys in the order
+/// `main.js` lists them. `mods` adds up 1 Shift, 2 Ctrl, 4 Alt.
+/// Returns 0 unchanged, 1 changed (draw a frame), 3 quit.
+#[no_mangle]
+pub extern "C" fn mters_key(code: u32, ch: u32, mods: u32, now: f64) -> u32 {
+    let code = match code {
+        0 => match char::from_u32(ch) {
+            Some(c) => KeyCode::Char(c),
+            None => return 0,
+        },
+        1 => KeyCode::Backspace,
+        2 => KeyCode::Enter,
+        3 => KeyCode::Left,
+        4 => KeyCode::Right,
+        5 => KeyCode::Up,
+        6 => KeyCode::Down,
+        7 => KeyCode::Home,
+        8 => KeyCode::End,
+        9 => KeyCode::PageUp,
+        10 => KeyCode::PageDown,
+        11 => KeyCode::Tab,
+        12 => KeyCode::Delete,
+        13 => KeyCode::Esc,
+        _ => return 0,
+    };
+    let mut modifiers = KeyModifiers::NONE;
+    for (bit, m) in [
+        (1, KeyModifiers::SHIFT),
+        (2, KeyModifiers::CONTROL),
+        (4, KeyModifiers::ALT),
+    ] {
+        if mods & bit != 0 {
+            modifiers |= m;
+        }
+    }
+    let key = match (code, modifiers.contains(KeyModifiers::SHIFT)) {
+        (KeyCode::Tab, true) => KeyEvent::new(KeyCode::BackTab, modifiers - KeyModifiers::SHIFT),
+        _ => KeyEvent::new(code, modifiers),
+    };
+    with_core(|core| core.feed(Event::Key(key), ms(now), |_, _| {}))
+}
+
+#[no_mangle]
+pub extern "C" fn mters_resize(cols: u32, rows: u32, now: f64) -> u32 {
+    let event = Event::Resize {
+        cols: cols as usize,
+        rows: rows as usize,
+    };
+    with_core(|core| core.feed(event, ms(now), |_, _| {}))
+}
+
+/// Call at the time `mters_wake_at` gave, if no key came first.
+#[no_mangle]
+pub extern "C" fn mters_tick(now: f64) -> u32 {
+    with_core(|core| core.feed(Event::Tick, ms(now), |_, _| {}))
+}
+
+/// When to call `mters_tick` next, in the same milliseconds as `now`.
+#[no_mangle]
+pub extern "C" fn mters_wake_at(now: f64) -> f64 {
+    CORE.with_borrow(|core| {
+        core.as_ref()
+            .map_or(f64::MAX, |c| c.wake_at(ms(now)).as_secs_f64() * 1000.0)
+    })
+}
+
+/// Render a frame into text and return its length; `mters_frame` points
+/// at it. The first line is the cursor's `x y`, then one `y text` line per
+/// row that changed since the last frame.
+#[no_mangle]
+pub extern "C" fn mters_render() -> u32 {
+    CORE.with_borrow_mut(|core| {
+        let Some(core) = core else { return 0 };
+        let frame = core.render();
+        FRAME.with_borrow_mut(|out| {
+            out.clear();
+            let (x, y) = frame.cells.cursor.unwrap_or((0, frame.cells.height));
+            let _ = writeln!(out, "{x} {y}");
+            for (y, _) in frame.damage() {
+                let _ = write!(out, "{y} ");
+                for x in 0..frame.cells.width {
+                    out.push_str(&frame.cells.get(x, y).expect("in bounds").symbol);
+                }
+                out.push('\n');
+            }
+            out.len() as u32
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn mters_frame() -> *const u8 {
+    FRAME.with_borrow(|out| out.as_ptr())
+}
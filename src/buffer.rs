@@ -0,0 +1,236 @@
+use crate::color::{Attributes, Color};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// How a cell is drawn. `None` colours leave the terminal's default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attrs: Attributes,
+}
+
+impl Style {
+    pub fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            ..Self::default()
+        }
+    }
+
+    /// `over` drawn on top of this style: its colours win where set, and
+    /// attributes add up.
+    pub fn patch(self, over: Style) -> Self {
+        Self {
+            fg: over.fg.or(self.fg),
+            bg: over.bg.or(self.bg),
+            attrs: self.attrs | over.attrs,
+        }
+    }
+}
+
+/// Terminal columns a grapheme takes: 2 for CJK and most emoji, else 1.
+/// Zero-width ones (lone combining marks, controls) still get a cell.
+pub fn grapheme_width(g: &str) -> usize {
+    g.width().clamp(1, 2)
+}
+
+/// One screen cell: a grapheme and its style. The cell right of a
+/// double-width grapheme holds an empty symbol and is never printed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub symbol: String,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: " ".into(),
+            style: Style::default(),
+        }
+    }
+}
+
+/// Terminal cursor shape, which shows the mode at a glance.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Bar,
+    Underline,
+}
+
+/// A frame being composed: render layers draw into it, then it is compared
+/// with the previous frame so only changed cells are written.
+pub struct Buffer {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    /// Where the terminal cursor goes once the frame is drawn.
+    pub cursor: Option<(usize, usize)>,
+    pub cursor_shape: CursorShape,
+}
+
+impl Buffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+            cursor: None,
+            cursor_shape: CursorShape::default(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        (x < self.width && y < self.height).then(|| &self.cells[y * self.width + x])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Cell> {
+        (x < self.width && y < self.height).then(|| &mut self.cells[y * self.width + x])
+    }
+
+    /// Write `s` from (x, y), one cell per grapheme and two for wide ones,
+    /// clipped at the right edge. Returns the column after the last grapheme.
+    pub fn put_str(&mut self, x: usize, y: usize, s: &str, style: Style) -> usize {
+        let mut col = x;
+        for g in s.graphemes(true) {
+            let w = grapheme_width(g);
+            if y >= self.height || col + w > self.width {
+                break;
+            }
+            self.put_cell(col, y, g, style);
+            if w == 2 {
+                self.put_cell(col + 1, y, "", style);
+            }
+            col += w;
+        }
+        col
+    }
+
+    /// Set one cell, blanking the other half of any wide grapheme it cuts.
+    fn put_cell(&mut self, x: usize, y: usize, symbol: &str, style: Style) {
+        let i = y * self.width + x;
+        if self.cells[i].symbol.is_empty() && !symbol.is_empty() && x > 0 {
+            self.cells[i - 1].symbol = " ".into();
+        }
+        if !self.cells[i].symbol.is_empty()
+            && x + 1 < self.width
+            && self.cells[i + 1].symbol.is_empty()
+        {
+            self.cells[i + 1].symbol = " ".into();
+        }
+        self.cells[i] = Cell {
+            symbol: symbol.to_owned(),
+            style,
+        };
+    }
+
+    /// Restyle columns `xs` of row `y`, keeping their text.
+    pub fn set_style(&mut self, xs: Range<usize>, y: usize, style: Style) {
+        for x in xs {
+            if let Some(cell) = self.get_mut(x, y) {
+                cell.style = style;
+            }
+        }
+    }
+
+    fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    /// For each row that differs from `prev`, the columns from its first to
+    /// its last changed cell. Unchanged rows are skipped whole; every row is
+    /// damaged when there is no previous frame or its size changed.
+    pub fn damage(&self, prev: Option<&Buffer>) -> Vec<(usize, Range<usize>)> {
+        let prev = prev.filter(|p| p.width == self.width && p.height == self.height);
+        (0..self.height)
+            .filter_map(|y| {
+                let Some(prev) = prev else {
+                    return Some((y, 0..self.width));
+                };
+                let (old, new) = (prev.row(y), self.row(y));
+                let mut first = (0..self.width).find(|&x| old[x] != new[x])?;
+                let last = (0..self.width).rfind(|&x| old[x] != new[x])?;
+                // Start at the wide grapheme whose right half changed.
+                if first > 0 && new[first].symbol.is_empty() {
+                    first -= 1;
+                }
+                Some((y, first..last + 1))
+            })
+            .collect()
+    }
+
+    /// Column after the last cell of row `y` that isn't a plain blank; the
+    /// rest of the row can be cleared rather than printed.
+    pub fn blank_from(&self, y: usize) -> usize {
+        let blank = Cell::default();
+        self.row(y)
+            .iter()
+            .rposition(|c| *c != blank)
+            .map_or(0, |x| x + 1)
+    }
+
+    /// Row `y` as plain text, trailing blanks trimmed.
+    #[cfg(test)]
+    pub fn row_text(&self, y: usize) -> String {
+        let row: String = (0..self.width)
+            .filter_map(|x| self.get(x, y))
+            .map(|c| c.symbol.as_str())
+            .collect();
+        row.trim_end().to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn put_str_clips_and_keeps_graphemes_whole() {
+        let mut buf = Buffer::new(4, 2);
+        let red = Style::fg(Color::Red);
+        assert_eq!(buf.put_str(1, 0, "e\u{301}👨‍👩‍👧‍👦xyz", red), 4);
+        // The family emoji is one wide grapheme
+        assert_eq!(buf.row_text(0), " e\u{301}👨‍👩‍👧‍👦");
+        assert_eq!(buf.get(1, 0).unwrap().style, red);
+        assert_eq!(buf.put_str(0, 5, "off screen", red), 0);
+    }
+
+    #[test]
+    fn wide_graphemes_take_two_cells() {
+        let mut buf = Buffer::new(5, 1);
+        assert_eq!(buf.put_str(0, 0, "a中b文", Style::default()), 4);
+        assert_eq!(buf.row_text(0), "a中b");
+        assert_eq!(buf.get(2, 0).unwrap().symbol, "");
+
+        // Writing over half of a wide grapheme blanks the other half
+        buf.put_str(2, 0, "x", Style::default());
+        assert_eq!(buf.row_text(0), "a xb");
+        buf.put_str(0, 0, "文", Style::default());
+        assert_eq!(buf.row_text(0), "文xb");
+        buf.put_str(1, 0, "y", Style::default());
+        assert_eq!(buf.row_text(0), " yxb");
+    }
+
+    #[test]
+    fn damage_covers_changed_spans_of_changed_rows() {
+        let mut prev = Buffer::new(5, 3);
+        prev.put_str(0, 0, "abcde", Style::default());
+        prev.put_str(0, 2, "same", Style::default());
+        let mut next = Buffer::new(5, 3);
+        next.put_str(0, 0, "aXcYe", Style::default());
+        next.put_str(0, 2, "same", Style::default());
+        next.set_style(4..5, 1, Style::fg(Color::Red));
+
+        assert_eq!(next.damage(Some(&prev)), [(0, 1..4), (1, 4..5)]);
+        assert_eq!(next.damage(None).len(), 3);
+        assert_eq!(next.damage(Some(&Buffer::new(5, 2))).len(), 3);
+
+        assert_eq!(next.blank_from(0), 5);
+        assert_eq!(next.blank_from(2), 4);
+        assert_eq!(Buffer::new(5, 1).blank_from(0), 0);
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A stable name for a character or a change: who made it, and a Lamport
+/// clock reading that orders it after everything its maker had seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpId {
+    pub agent: u64,
+    pub lamport: u64,
+}
+
+impl OpId {
+    /// Concurrent inserts at one place go in descending order of this.
+    fn key(self) -> (u64, u64) {
+        (self.lamport, self.agent)
+    }
+}
+
+/// Agent 0 made the text the log started from. Every peer starting from
+/// the same text gives it the same IDs, so their changes can meet.
+pub const BASE_AGENT: u64 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpKind {
+    /// `text` after the character `origin`, or at the start when None.
+    /// Its characters are named `id`, `id + 1`, ... in order.
+    Insert { origin: Option<OpId>, text: String },
+    /// These characters, wherever they have got to.
+    Delete { targets: Vec<OpId> },
+}
+
+/// One change, as recorded and as sent to peers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Op {
+    pub id: OpId,
+    pub kind: OpKind,
+}
+
+/// What merging does to the text, in character offsets, to be applied in
+/// order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Patch {
+    Insert { at: usize, text: String },
+    Delete(Range<usize>),
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    id: OpId,
+    ch: char,
+    deleted: bool,
+}
+
+/// Every change made to a buffer, with stable IDs, and changes from peers
+/// merged in. The characters form a sequence CRDT (RGA): each insert names
+/// the character it went after, deletes leave a tombstone, and any two
+/// logs holding the same changes hold the same text whatever order they
+/// came in. Lookups walk the whole sequence; it is groundwork, off unless
+/// asked for.
+#[derive(Debug, Clone)]
+pub struct ChangeLog {
+    agent: u64,
+    clock: u64,
+    items: Vec<Item>,
+    ops: Vec<Op>,
+    known: HashSet<OpId>,
+    /// Changes from peers waiting on ones not seen yet.
+    pending: Vec<Op>,
+}
+
+impl ChangeLog {
+    /// A log of `base`, recording changes as `agent` (anything but
+    /// `BASE_AGENT`).
+    pub fn new(agent: u64, base: &str) -> Self {
+        let items: Vec<Item> = base
+            .chars()
+            .zip(1..)
+            .map(|(ch, lamport)| Item {
+                id: OpId {
+                    agent: BASE_AGENT,
+                    lamport,
+                },
+                ch,
+                deleted: false,
+            })
+            .collect();
+        Self {
+            agent,
+            clock: items.len() as u64,
+            items,
+            ops: Vec::new(),
+            known: HashSet::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn agent(&self) -> u64 {
+        self.agent
+    }
+
+    /// Every change, local or merged, in the order they were applied:
+    /// each comes after the ones it depends on.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Changes from peers still waiting on ones they depend on.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The text as the log has it.
+    pub fn text(&self) -> String {
+        self.items
+            .iter()
+            .filter(|i| !i.deleted)
+            .map(|i| i.ch)
+            .collect()
+    }
+
+    /// Record `text` typed at character `at`.
+    pub fn insert(&mut self, at: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let origin = at
+            .checked_sub(1)
+            .map(|before| self.items[self.visible(before)].id);
+        let op = Op {
+            id: self.tick(text.chars().count() as u64),
+            kind: OpKind::Insert {
+                origin,
+                text: text.to_owned(),
+            },
+        };
+        self.apply(op);
+    }
+
+    /// Record characters `range` deleted.
+    pub fn delete(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let targets = self.visible_ids(range);
+        let op = Op {
+            id: self.tick(1),
+            kind: OpKind::Delete { targets },
+        };
+        self.apply(op);
+    }
+
+    /// Take in changes from a peer, skipping ones already here and holding
+    /// back ones that depend on changes not seen yet. Returns what to do
+    /// to the text to match.
+    pub fn merge(&mut self, ops: impl IntoIterator<Item = Op>) -> Vec<Patch> {
+        self.pending.extend(ops);
+        let mut patches = Vec::new();
+        loop {
+            let waiting = std::mem::take(&mut self.pending);
+            let before = waiting.len();
+            for op in waiting {
+                if self.known.contains(&op.id) {
+                    continue;
+                }
+                if !self.ready(&op) {
+                    self.pending.push(op);
+                    continue;
+                }
+                let last = match &op.kind {
+                    OpKind::Insert { text, .. } => op.id.lamport + text.chars().count() as u64 - 1,
+                    OpKind::Delete { .. } => op.id.lamport,
+                };
+                self.clock = self.clock.max(last);
+                patches.extend(self.apply(op));
+            }
+            if self.pending.len() == before {
+                return patches;
+            }
+        }
+    }
+
+    /// The next `n` clock readings, as the ID of the first.
+    fn tick(&mut self, n: u64) -> OpId {
+        let id = OpId {
+            agent: self.agent,
+            lamport: self.clock + 1,
+        };
+        self.clock += n;
+        id
+    }
+
+    fn ready(&self, op: &Op) -> bool {
+        match &op.kind {
+            OpKind::Insert { origin, .. } => origin.is_none_or(|o| self.index_of(o).is_some()),
+            OpKind::Delete { targets } => targets.iter().all(|&t| self.index_of(t).is_some()),
+        }
+    }
+
+    fn index_of(&self, id: OpId) -> Option<usize> {
+        self.items.iter().position(|i| i.id == id)
+    }
+
+    /// Index in `items` of visible character `n`.
+    fn visible(&self, n: usize) -> usize {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| !i.deleted)
+            .nth(n)
+            .map(|(index, _)| index)
+            .expect("in the text")
+    }
+
+    fn visible_ids(&self, range: Range<usize>) -> Vec<OpId> {
+        self.items
+            .iter()
+            .filter(|i| !i.deleted)
+            .skip(range.start)
+            .take(range.len())
+            .map(|i| i.id)
+            .collect()
+    }
+
+    /// Visible characters before `items[index]`.
+    fn offset(&self, index: usize) -> usize {
+        self.items[..index].iter().filter(|i| !i.deleted).count()
+    }
+
+    fn apply(&mut self, op: Op) -> Vec<Patch> {
+        let mut patches = Vec::new();
+        match &op.kind {
+            OpKind::Insert { origin, text } => {
+                let mut origin = *origin;
+                for (ch, lamport) in text.chars().zip(op.id.lamport..) {
+                    let id = OpId {
+                        agent: op.id.agent,
+                        lamport,
+                    };
+                    let mut at = origin.map_or(0, |o| self.index_of(o).expect("ready") + 1);
+                    // Inserts made at the same place without seeing this one
+                    // stay in front of it, their own followers with them.
+                    while self.items.get(at).is_some_and(|i| i.id.key() > id.key()) {
+                        at += 1;
+                    }
+                    let offset = self.offset(at);
+                    self.items.insert(
+                        at,
+                        Item {
+                            id,
+                            ch,
+                            deleted: false,
+                        },
+                    );
+                    match patches.last_mut() {
+                        Some(Patch::Insert { at, text })
+                            if *at + text.chars().count() == offset =>
+                        {
+                            text.push(ch)
+                        }
+                        _ => patches.push(Patch::Insert {
+                            at: offset,
+                            text: ch.to_string(),
+                        }),
+                    }
+                    origin = Some(id);
+                }
+            }
+            OpKind::Delete { targets } => {
+                for &target in targets {
+                    let index = self.index_of(target).expect("ready");
+                    if self.items[index].deleted {
+                        continue;
+                    }
+                    let offset = self.offset(index);
+                    self.items[index].deleted = true;
+                    match patches.last_mut() {
+                        Some(Patch::Delete(range)) if range.start == offset => range.end += 1,
+                        _ => patches.push(Patch::Delete(offset..offset + 1)),
+                    }
+                }
+            }
+        }
+        self.known.insert(op.id);
+        self.ops.push(op);
+        patches
+    }
+}
+
+impl Op {
+    /// One line of text: `i AGENT.LAMPORT ORIGIN TEXT` or `d AGENT.LAMPORT
+    /// TARGET,...`, with `-` for no origin and the text's backslashes, tabs
+    /// and line breaks escaped.
+    pub fn to_line(&self) -> String {
+        let id = |id: OpId| format!("{}.{}", id.agent, id.lamport);
+        match &self.kind {
+            OpKind::Insert { origin, text } => format!(
+                "i {} {} {}",
+                id(self.id),
+                origin.map_or("-".to_owned(), id),
+                escape(text)
+            ),
+            OpKind::Delete { targets } => {
+                let targets: Vec<String> = targets.iter().map(|&t| id(t)).collect();
+                format!("d {} {}", id(self.id), targets.join(","))
+            }
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let id = |s: &str| {
+            let (agent, lamport) = s.split_once('.')?;
+            Some(OpId {
+                agent: agent.parse().ok()?,
+                lamport: lamport.parse().ok().filter(|&l| l > 0)?,
+            })
+        };
+        let mut fields = line.splitn(4, ' ');
+        let (kind, op_id, arg) = (fields.next()?, id(fields.next()?)?, fields.next()?);
+        let kind = match kind {
+            "i" => OpKind::Insert {
+                origin: if arg == "-" { None } else { Some(id(arg)?) },
+                text: unescape(fields.next()?).filter(|t| !t.is_empty())?,
+            },
+            "d" => OpKind::Delete {
+                targets: arg.split(',').map(id).collect::<Option<_>>()?,
+            },
+            _ => return None,
+        };
+        Some(Op { id: op_id, kind })
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `patches` done to `text`, as the editor would.
+    fn patched(text: &str, patches: &[Patch]) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        for patch in patches {
+            match patch {
+                Patch::Insert { at, text } => {
+                    chars.splice(*at..*at, text.chars());
+                }
+                Patch::Delete(range) => {
+                    chars.drain(range.clone());
+                }
+            }
+        }
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn concurrent_changes_meet_in_the_same_text() {
+        let mut a = ChangeLog::new(1, "hello world");
+        let mut b = ChangeLog::new(2, "hello world");
+        a.insert(5, ",");
+        a.delete(7..12);
+        a.insert(7, "there");
+        b.insert(5, "!");
+        b.insert(0, "> ");
+        b.delete(8..14);
+        assert_eq!(a.text(), "hello, there");
+        assert_eq!(b.text(), "> hello!");
+
+        // Each side gets the other's changes, in any order, some twice
+        let for_b: Vec<Op> = a.ops().iter().rev().cloned().collect();
+        let patches = b.merge(for_b);
+        assert_eq!(patched("> hello!", &patches), b.text());
+        let for_a = b.ops().to_vec();
+        let patches = a.merge(for_a);
+        assert_eq!(patched("hello, there", &patches), a.text());
+        assert_eq!(a.text(), b.text());
+        assert_eq!(a.text(), "> hello!,there");
+        assert_eq!((a.pending(), b.pending()), (0, 0));
+        assert!(a.merge(b.ops().to_vec()).is_empty());
+
+        // A change waits for the one it was made after
+        a.insert(14, "?");
+        let mut c = ChangeLog::new(3, "hello world");
+        c.merge(a.ops().last().cloned());
+        assert_eq!((c.pending(), c.text().as_str()), (1, "hello world"));
+        c.merge(a.ops().to_vec());
+        assert_eq!((c.pending(), c.text()), (0, a.text()));
+    }
+
+    #[test]
+    fn ops_read_back_from_their_lines() {
+        let mut log = ChangeLog::new(7, "ab");
+        log.insert(1, "x \\\n\t");
+        log.delete(0..2);
+        for op in log.ops() {
+            assert_eq!(Op::parse(&op.to_line()).as_ref(), Some(op));
+        }
+        assert_eq!(log.ops()[0].to_line(), "i 7.3 0.1 x \\\\\\n\\t");
+        assert_eq!(log.ops()[1].to_line(), "d 7.8 0.1,7.3");
+        assert_eq!(Op::parse("i 7.0 - x"), None);
+        assert_eq!(Op::parse("d 7.1 0.1,oops"), None);
+        assert_eq!(Op::parse("i 7.1 - \\q"), None);
+    }
+}
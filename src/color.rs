@@ -0,0 +1,307 @@
+use std::ops::BitOr;
+
+/// A colour as themes name it, whatever shows it. The names follow
+/// crossterm's, which the terminal frontend turns these into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// The terminal's own colour.
+    Reset,
+    Black,
+    DarkGrey,
+    Red,
+    DarkRed,
+    Green,
+    DarkGreen,
+    Yellow,
+    DarkYellow,
+    Blue,
+    DarkBlue,
+    Magenta,
+    DarkMagenta,
+    Cyan,
+    DarkCyan,
+    White,
+    Grey,
+    Rgb {
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    /// One of the 256 xterm colours.
+    AnsiValue(u8),
+}
+
+impl Color {
+    /// `red`, `dark_grey` and the rest, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "black" => Color::Black,
+            "dark_grey" => Color::DarkGrey,
+            "red" => Color::Red,
+            "dark_red" => Color::DarkRed,
+            "green" => Color::Green,
+            "dark_green" => Color::DarkGreen,
+            "yellow" => Color::Yellow,
+            "dark_yellow" => Color::DarkYellow,
+            "blue" => Color::Blue,
+            "dark_blue" => Color::DarkBlue,
+            "magenta" => Color::Magenta,
+            "dark_magenta" => Color::DarkMagenta,
+            "cyan" => Color::Cyan,
+            "dark_cyan" => Color::DarkCyan,
+            "white" => Color::White,
+            "grey" => Color::Grey,
+            _ => return None,
+        })
+    }
+
+    /// Which of the 256 xterm colours it is; None for RGB and the
+    /// terminal's default.
+    pub fn ansi_index(self) -> Option<u8> {
+        Some(match self {
+            Color::Rgb { .. } | Color::Reset => return None,
+            Color::AnsiValue(n) => n,
+            Color::Black => 0,
+            Color::DarkRed => 1,
+            Color::DarkGreen => 2,
+            Color::DarkYellow => 3,
+            Color::DarkBlue => 4,
+            Color::DarkMagenta => 5,
+            Color::DarkCyan => 6,
+            Color::Grey => 7,
+            Color::DarkGrey => 8,
+            Color::Red => 9,
+            Color::Green => 10,
+            Color::Yellow => 11,
+            Color::Blue => 12,
+            Color::Magenta => 13,
+            Color::Cyan => 14,
+            Color::White => 15,
+        })
+    }
+
+    /// Red, green and blue, as xterm shows it; None for the terminal's
+    /// default.
+    pub fn rgb(self) -> Option<(u8, u8, u8)> {
+        if let Color::Rgb { r, g, b } = self {
+            return Some((r, g, b));
+        }
+        let index = self.ansi_index()?;
+        Some(match index {
+            0..=15 => NAMED[index as usize],
+            // The 6x6x6 cube, then the grey ramp
+            16..=231 => {
+                let level = |i: u8| if i == 0 { 0 } else { 55 + 40 * i };
+                let i = index - 16;
+                (level(i / 36), level(i / 6 % 6), level(i % 6))
+            }
+            _ => {
+                let grey = 8 + 10 * (index - 232);
+                (grey, grey, grey)
+            }
+        })
+    }
+}
+
+/// The sixteen named colours, as xterm shows them.
+const NAMED: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// What a theme can ask of text besides colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute {
+    Bold,
+    Italic,
+    Underlined,
+    Reverse,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 4] = [
+        Attribute::Bold,
+        Attribute::Italic,
+        Attribute::Underlined,
+        Attribute::Reverse,
+    ];
+
+    /// Its SGR parameter, as in `ESC [ 1 m`.
+    pub fn sgr(self) -> u8 {
+        match self {
+            Attribute::Bold => 1,
+            Attribute::Italic => 3,
+            Attribute::Underlined => 4,
+            Attribute::Reverse => 7,
+        }
+    }
+}
+
+/// A set of attributes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub fn has(self, attr: Attribute) -> bool {
+        self.0 & Self::from(attr).0 != 0
+    }
+
+    pub fn set(&mut self, attr: Attribute) {
+        self.0 |= Self::from(attr).0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Attribute> {
+        Attribute::ALL.into_iter().filter(move |&a| self.has(a))
+    }
+}
+
+impl From<Attribute> for Attributes {
+    fn from(attr: Attribute) -> Self {
+        Self(1 << attr as u8)
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSupport {
+    TrueColor,
+    /// 24-bit colours are sent as the nearest of the 256 xterm colours.
+    Ansi256,
+}
+
+impl ColorSupport {
+    pub fn detect() -> Self {
+        Self::detect_with(|name| std::env::var(name).ok())
+    }
+
+    /// `COLORTERM=truecolor` (or `24bit`) is the usual promise; terminfo
+    /// entries named `*-direct` say the same through `TERM`. Windows
+    /// Terminal sets neither but has `WT_SESSION`; the older console host
+    /// has none of them.
+    pub fn detect_with(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let colorterm = lookup("COLORTERM").unwrap_or_default();
+        let term = lookup("TERM").unwrap_or_default();
+        if matches!(colorterm.as_str(), "truecolor" | "24bit")
+            || term.ends_with("-direct")
+            || lookup("WT_SESSION").is_some()
+        {
+            ColorSupport::TrueColor
+        } else {
+            ColorSupport::Ansi256
+        }
+    }
+
+    /// `color` as the terminal can show it.
+    pub fn adapt(self, color: Option<Color>) -> Option<Color> {
+        match (self, color) {
+            (ColorSupport::Ansi256, Some(Color::Rgb { r, g, b })) => {
+                Some(Color::AnsiValue(nearest_ansi(r, g, b)))
+            }
+            _ => color,
+        }
+    }
+}
+
+/// Nearest xterm colour: the closer of the 6x6x6 cube and the grey ramp.
+fn nearest_ansi(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |c: u8| {
+        (0..6)
+            .min_by_key(|&i| LEVELS[i].abs_diff(c))
+            .expect("six levels")
+    };
+    let dist = |(r2, g2, b2): (u8, u8, u8)| {
+        [(r, r2), (g, g2), (b, b2)]
+            .iter()
+            .map(|&(a, b)| (a.abs_diff(b) as u32).pow(2))
+            .sum::<u32>()
+    };
+
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+    let cube_index = 16 + 36 * ri + 6 * gi + bi;
+
+    // Greys 232..=255 run from 8 to 238 in steps of 10.
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_step = (avg.saturating_sub(3) / 10).min(23) as u8;
+    let grey = 8 + 10 * grey_step;
+
+    if dist((grey, grey, grey)) < dist(cube) {
+        232 + grey_step
+    } else {
+        cube_index as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_truecolor_from_the_environment() {
+        let env = |colorterm: &'static str, term: &'static str| {
+            move |name: &str| match name {
+                "COLORTERM" => Some(colorterm.to_string()),
+                "TERM" => Some(term.to_string()),
+                _ => None,
+            }
+        };
+        let detect = ColorSupport::detect_with;
+        assert_eq!(detect(env("truecolor", "xterm")), ColorSupport::TrueColor);
+        assert_eq!(detect(env("", "xterm-direct")), ColorSupport::TrueColor);
+        assert_eq!(detect(env("", "xterm-256color")), ColorSupport::Ansi256);
+    }
+
+    #[test]
+    fn rgb_falls_back_to_the_nearest_xterm_colour() {
+        assert_eq!(nearest_ansi(0, 0, 0), 16);
+        assert_eq!(nearest_ansi(255, 0, 0), 196);
+        assert_eq!(nearest_ansi(0x98, 0xc3, 0x79), 108);
+        // Greys use the finer ramp
+        assert_eq!(nearest_ansi(128, 128, 128), 244);
+        let named = Some(Color::Blue);
+        assert_eq!(ColorSupport::Ansi256.adapt(named), named);
+    }
+
+    #[test]
+    fn names_indexes_and_attributes() {
+        assert_eq!(Color::from_name("Dark_Grey"), Some(Color::DarkGrey));
+        assert_eq!(Color::from_name("purple"), None);
+        assert_eq!(Color::AnsiValue(196).rgb(), Some((255, 0, 0)));
+        assert_eq!(Color::AnsiValue(244).rgb(), Some((128, 128, 128)));
+        assert_eq!(Color::Reset.rgb(), None);
+        let mut attrs = Attributes::from(Attribute::Reverse);
+        attrs.set(Attribute::Bold);
+        assert!(attrs.has(Attribute::Bold) && !attrs.has(Attribute::Italic));
+        assert_eq!(
+            attrs.iter().collect::<Vec<_>>(),
+            [Attribute::Bold, Attribute::Reverse]
+        );
+    }
+}
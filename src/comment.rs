@@ -0,0 +1,93 @@
+/// `commentstring` split around its `%s`, blanks trimmed: `// %s` gives
+/// `("//", "")`, `<!-- %s -->` gives `("<!--", "-->")`.
+fn parts(cms: &str) -> (&str, &str) {
+    let (left, right) = cms.split_once("%s").unwrap_or((cms, ""));
+    (left.trim(), right.trim())
+}
+
+/// Bytes of spaces and tabs `line` starts with.
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// `line` with its comment taken off, when it is commented.
+fn uncommented(line: &str, left: &str, right: &str) -> Option<String> {
+    let indent = &line[..indent(line)];
+    let body = line.trim().strip_prefix(left)?.strip_suffix(right)?;
+    // The blank `// %s` puts either side goes with the comment.
+    let body = body.strip_prefix(' ').unwrap_or(body);
+    let body = match right {
+        "" => body,
+        _ => body.strip_suffix(' ').unwrap_or(body),
+    };
+    Some(format!("{indent}{body}"))
+}
+
+/// `gc`: the lines commented with `cms`, or uncommented when every one
+/// that isn't blank already is. Comments start at the least indent among
+/// the lines, and blank lines are left as they are.
+pub fn toggle(lines: &[String], cms: &str) -> Vec<String> {
+    let (left, right) = parts(cms);
+    let blank = |line: &String| line.trim().is_empty();
+    let stripped: Option<Vec<String>> = lines
+        .iter()
+        .map(|line| {
+            if blank(line) {
+                Some(line.clone())
+            } else {
+                uncommented(line, left, right)
+            }
+        })
+        .collect();
+    if let Some(stripped) = stripped.filter(|_| !lines.iter().all(blank)) {
+        return stripped;
+    }
+    let least = lines
+        .iter()
+        .filter(|line| !blank(line))
+        .map(|line| indent(line))
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            if blank(line) {
+                return line.clone();
+            }
+            let (head, body) = line.split_at(least);
+            let close = match right {
+                "" => String::new(),
+                right => format!(" {right}"),
+            };
+            format!("{head}{left} {body}{close}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(src: &str) -> Vec<String> {
+        src.split('\n').map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn comments_at_the_least_indent_and_back() {
+        let src = lines("fn f() {\n    x();\n\n}");
+        let commented = toggle(&src, "// %s");
+        assert_eq!(commented, lines("// fn f() {\n//     x();\n\n// }"));
+        assert_eq!(toggle(&commented, "// %s"), src);
+
+        let nested = lines("    a\n  b");
+        assert_eq!(toggle(&nested, "# %s"), lines("  #   a\n  # b"));
+        // One line not commented: the lot gets commented
+        let mixed = lines("# a\nb");
+        assert_eq!(toggle(&mixed, "# %s"), lines("# # a\n# b"));
+        // Without the blank, and with a closing part
+        assert_eq!(toggle(&lines("  #a"), "# %s"), lines("  a"));
+        let html = toggle(&lines("<p>"), "<!-- %s -->");
+        assert_eq!(html, lines("<!-- <p> -->"));
+        assert_eq!(toggle(&html, "<!--%s-->"), lines("<p>"));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::options::IsKeyword;
+use ropey::Rope;
+use std::collections::HashSet;
+
+/// Ctrl-N / Ctrl-P in Insert mode: words from the open buffers that start
+/// with the one before the cursor, tried in turn in its place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// Where the word being completed starts (absolute char).
+    pub start: usize,
+    /// What was typed before the first Ctrl-N; cycling comes back to it.
+    pub prefix: String,
+    pub items: Vec<String>,
+    /// None while the prefix itself is shown.
+    pub selected: Option<usize>,
+}
+
+impl Completion {
+    pub fn new(start: usize, prefix: String, items: Vec<String>) -> Self {
+        Self {
+            start,
+            prefix,
+            items,
+            selected: None,
+        }
+    }
+
+    /// The next item (Ctrl-N) or the one before (Ctrl-P), by way of the
+    /// prefix at either end, as Vim goes round.
+    pub fn step(&mut self, forward: bool) {
+        let last = self.items.len().checked_sub(1);
+        self.selected = match (self.selected, forward) {
+            (None, true) => last.map(|_| 0),
+            (None, false) => last,
+            (Some(i), true) => Some(i + 1).filter(|&i| i < self.items.len()),
+            (Some(i), false) => i.checked_sub(1),
+        };
+    }
+
+    /// What's in the text now: the selected item, or the prefix.
+    pub fn current(&self) -> &str {
+        match self.selected {
+            Some(i) => &self.items[i],
+            None => &self.prefix,
+        }
+    }
+}
+
+/// Words in `text` that start with `prefix` and are longer, the ones
+/// after char `from` first (wrapping round to the top), then those in
+/// `others`. Each word once.
+pub fn harvest<'a>(
+    text: &Rope,
+    from: usize,
+    others: impl IntoIterator<Item = &'a Rope>,
+    prefix: &str,
+    isk: &IsKeyword,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    let mut take = |words: Vec<(usize, String)>| {
+        for (_, word) in words {
+            if word.len() > prefix.len() && word.starts_with(prefix) && seen.insert(word.clone()) {
+                found.push(word);
+            }
+        }
+    };
+    let mut here = words(text, isk);
+    let after = here.partition_point(|&(at, _)| at < from);
+    here.rotate_left(after);
+    take(here);
+    for other in others {
+        take(words(other, isk));
+    }
+    found
+}
+
+/// Every run of keyword characters, with the char it starts at.
+fn words(text: &Rope, isk: &IsKeyword) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if isk.contains(c) {
+            word.push(c);
+        } else if !word.is_empty() {
+            let len = word.chars().count();
+            words.push((i - len, std::mem::take(&mut word)));
+        }
+    }
+    if !word.is_empty() {
+        let len = word.chars().count();
+        words.push((text.len_chars() - len, word));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_after_the_cursor_come_first() {
+        let isk = IsKeyword::default();
+        let text = Rope::from_str("format first\nfoo fo\nformat fold");
+        let other = Rope::from_str("forest foo");
+        // From the end of line 2, where `fo` is being typed
+        let items = harvest(&text, 19, [&other], "fo", &isk);
+        assert_eq!(items, ["format", "fold", "foo", "forest"]);
+        assert!(harvest(&text, 0, [], "zz", &isk).is_empty());
+
+        let mut menu = Completion::new(17, "fo".into(), items);
+        menu.step(true);
+        assert_eq!(menu.current(), "format");
+        menu.step(false);
+        assert_eq!(menu.current(), "fo");
+        menu.step(false);
+        assert_eq!(menu.current(), "forest");
+        menu.step(true);
+        assert_eq!((menu.selected, menu.current()), (None, "fo"));
+    }
+}
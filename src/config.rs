@@ -0,0 +1,28 @@
+use crate::path;
+use std::path::{Path, PathBuf};
+
+/// The editor's own directory: `$XDG_CONFIG_HOME/mters`, falling back to
+/// `%APPDATA%\mters` on Windows and `~/.config/mters` elsewhere.
+pub fn dir() -> Option<PathBuf> {
+    dir_with(|name| std::env::var(name).ok())
+}
+
+pub fn dir_with(lookup: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let config = lookup("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .or_else(|| lookup("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| path::home_with(&lookup).map(|h| Path::new(&h).join(".config")))?;
+    Some(config.join("mters"))
+}
+
+/// What a command says when it needs a feature left out of the build,
+/// as Vim does.
+#[cfg(not(feature = "config"))]
+pub const NOT_BUILT: &str = "E319: Sorry, the command is not available in this version";
+
+/// `config.toml` in `dir()`, read at startup.
+#[cfg(feature = "config")]
+pub fn file() -> Option<PathBuf> {
+    dir().map(|d| d.join("config.toml"))
+}
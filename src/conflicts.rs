@@ -0,0 +1,192 @@
+use ropey::Rope;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictSide {
+    Ours,
+    Theirs,
+    Both,
+}
+
+/// Which part of a conflict block a line belongs to (for highlighting).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictRegion {
+    Marker,
+    Ours,
+    Base,
+    Theirs,
+}
+
+/// A merge conflict block, stored as the rows of its marker lines:
+///
+/// ```text
+/// <<<<<<< ours      start
+/// ...
+/// ||||||| base      base (diff3 style only)
+/// ...
+/// =======           mid
+/// ...
+/// >>>>>>> theirs    end
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conflict {
+    pub start: usize,
+    pub base: Option<usize>,
+    pub mid: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Marker {
+    Start,
+    Base,
+    Mid,
+    End,
+}
+
+fn marker(text: &Rope, row: usize) -> Option<Marker> {
+    let line = text.line(row);
+    let mut chars = line.chars();
+    let first = chars.next()?;
+    let kind = match first {
+        '<' => Marker::Start,
+        '|' => Marker::Base,
+        '=' => Marker::Mid,
+        '>' => Marker::End,
+        _ => return None,
+    };
+    if !(1..7).all(|_| chars.next() == Some(first)) {
+        return None;
+    }
+    // Exactly seven marker chars, then end of line or a label.
+    match chars.next() {
+        None | Some('\n' | '\r') => Some(kind),
+        Some(' ') if kind != Marker::Mid => Some(kind),
+        _ => None,
+    }
+}
+
+/// Scan the whole buffer for well-formed conflict blocks, in order.
+pub fn find_conflicts(text: &Rope) -> Vec<Conflict> {
+    let mut out = Vec::new();
+    let mut start = None;
+    let mut base = None;
+    let mut mid = None;
+
+    for row in 0..text.len_lines() {
+        match marker(text, row) {
+            Some(Marker::Start) => {
+                start = Some(row);
+                base = None;
+                mid = None;
+            }
+            Some(Marker::Base) if start.is_some() && mid.is_none() => base = Some(row),
+            Some(Marker::Mid) if start.is_some() => mid = Some(row),
+            Some(Marker::End) => {
+                if let (Some(s), Some(m)) = (start, mid) {
+                    out.push(Conflict {
+                        start: s,
+                        base,
+                        mid: m,
+                        end: row,
+                    });
+                }
+                start = None;
+                base = None;
+                mid = None;
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// The conflict block containing `row`, if any.
+pub fn conflict_at(text: &Rope, row: usize) -> Option<Conflict> {
+    find_conflicts(text)
+        .into_iter()
+        .find(|c| c.start <= row && row <= c.end)
+}
+
+impl Conflict {
+    pub fn region(&self, row: usize) -> Option<ConflictRegion> {
+        if row < self.start || row > self.end {
+            return None;
+        }
+        let ours_end = self.base.unwrap_or(self.mid);
+        Some(
+            if row == self.start || Some(row) == self.base || row == self.mid || row == self.end {
+                ConflictRegion::Marker
+            } else if row < ours_end {
+                ConflictRegion::Ours
+            } else if row < self.mid {
+                ConflictRegion::Base
+            } else {
+                ConflictRegion::Theirs
+            },
+        )
+    }
+
+    /// Absolute char range covering the whole block, marker lines included.
+    pub fn char_range(&self, text: &Rope) -> std::ops::Range<usize> {
+        text.line_to_char(self.start)..text.line_to_char(self.end + 1)
+    }
+
+    /// The text the block should be replaced with when taking `side`.
+    pub fn resolved(&self, text: &Rope, side: ConflictSide) -> String {
+        let rows = |a: usize, b: usize| text.slice(text.line_to_char(a)..text.line_to_char(b));
+        let ours = rows(self.start + 1, self.base.unwrap_or(self.mid));
+        let theirs = rows(self.mid + 1, self.end);
+        match side {
+            ConflictSide::Ours => ours.to_string(),
+            ConflictSide::Theirs => theirs.to_string(),
+            ConflictSide::Both => format!("{ours}{theirs}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "keep\n<<<<<<< HEAD\nmine\n=======\nyours\n>>>>>>> topic\nend\n";
+
+    #[test]
+    fn finds_blocks_and_ignores_lookalikes() {
+        let text = Rope::from_str(SRC);
+        assert_eq!(
+            find_conflicts(&text),
+            vec![Conflict {
+                start: 1,
+                base: None,
+                mid: 3,
+                end: 5
+            }]
+        );
+
+        // Eight '=' or a stray marker outside a block is not a conflict
+        let text = Rope::from_str("========\n>>>>>>> x\n<<<<<<<\n");
+        assert!(find_conflicts(&text).is_empty());
+    }
+
+    #[test]
+    fn diff3_base_section() {
+        let text = Rope::from_str("<<<<<<< a\no\n||||||| base\nb\n=======\nt\n>>>>>>> c\n");
+        let c = find_conflicts(&text)[0];
+        assert_eq!(c.base, Some(2));
+        assert_eq!(c.region(1), Some(ConflictRegion::Ours));
+        assert_eq!(c.region(3), Some(ConflictRegion::Base));
+        assert_eq!(c.region(5), Some(ConflictRegion::Theirs));
+        assert_eq!(c.region(4), Some(ConflictRegion::Marker));
+        assert_eq!(c.resolved(&text, ConflictSide::Ours), "o\n");
+    }
+
+    #[test]
+    fn resolutions() {
+        let text = Rope::from_str(SRC);
+        let c = conflict_at(&text, 2).unwrap();
+        assert_eq!(c.resolved(&text, ConflictSide::Ours), "mine\n");
+        assert_eq!(c.resolved(&text, ConflictSide::Theirs), "yours\n");
+        assert_eq!(c.resolved(&text, ConflictSide::Both), "mine\nyours\n");
+        assert_eq!(conflict_at(&text, 0), None);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::buffer::Buffer;
+use crate::editor::Editor;
+use crate::input::{self, EditorCommand, KeyMappingResult};
+use crate::layers::{self, RESERVED_ROWS};
+use std::time::Duration;
+
+use crate::keys::{KeyCode, KeyEvent, KeyEventKind};
+
+/// How long keys must stop before a picker shows new matches and reads
+/// its preview.
+const PICKER_DELAY: Duration = Duration::from_millis(40);
+
+/// The longest a frontend waits for an event before the core wants a
+/// `Tick` anyway.
+const IDLE: Duration = Duration::from_millis(250);
+
+/// What happened, as a frontend tells it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Key(KeyEvent),
+    /// The screen is now `cols` by `rows` cells.
+    Resize {
+        cols: usize,
+        rows: usize,
+    },
+    /// Nothing happened by the time `EditorCore::wake_at` asked for.
+    Tick,
+}
+
+/// What the frontend should do after an event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Nothing on screen changed.
+    Unchanged,
+    /// Draw a new frame.
+    Changed,
+    /// Hand the screen back to the shell until `fg`, then draw a whole
+    /// frame: the shell may have written over it.
+    Suspend,
+    Quit,
+}
+
+/// A frame to draw, and the one drawn before it, if the screen still
+/// shows it: a frontend that keeps its screen can draw only the damage.
+pub struct Frame<'a> {
+    pub cells: &'a Buffer,
+    pub last: Option<&'a Buffer>,
+}
+
+impl Frame<'_> {
+    /// Rows and the columns in them that differ from the last frame;
+    /// everything when there is none.
+    pub fn damage(&self) -> Vec<(usize, std::ops::Range<usize>)> {
+        self.cells.damage(self.last)
+    }
+}
+
+/// The editor without a terminal: events go in, frames come out. A
+/// frontend (the terminal in `main.rs`, a window, a web page) reads
+/// events its own way, feeds them in, and draws the frames it gets back.
+/// Besides the `Editor` it keeps what the loop around one needs: the
+/// screen size, the last frame, and when keys last came.
+pub struct EditorCore {
+    editor: Editor,
+    cols: usize,
+    rows: usize,
+    shown: Option<Buffer>,
+    last: Option<Buffer>,
+    /// For CursorHold: when the last key came, and whether it has fired since.
+    last_key: Duration,
+    held: bool,
+}
+
+impl EditorCore {
+    /// `editor` on a `cols` by `rows` screen. Times here and in `feed` are
+    /// the frontend's clock, since the Unix epoch: the core reads no clock
+    /// itself, as a browser has none it can get at.
+    pub fn new(mut editor: Editor, cols: usize, rows: usize, now: Duration) -> Self {
+        editor.set_view_size(cols, rows.saturating_sub(RESERVED_ROWS as usize));
+        editor.set_clock(now);
+        Self {
+            editor,
+            cols,
+            rows,
+            shown: None,
+            last: None,
+            last_key: now,
+            held: false,
+        }
+    }
+
+    pub fn editor(&self) -> &Editor {
+        &self.editor
+    }
+
+    pub fn editor_mut(&mut self) -> &mut Editor {
+        &mut self.editor
+    }
+
+    /// Act on an event that came at `now`. `progress` hears how far a
+    /// previewed file has loaded when a command needs all of it.
+    pub fn feed(
+        &mut self,
+        event: Event,
+        now: Duration,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Outcome {
+        self.editor.set_clock(now);
+        let outcome = match event {
+            Event::Key(key) => self.key(key, now, &mut progress),
+            Event::Resize { cols, rows } => {
+                self.cols = cols;
+                self.rows = rows;
+                self.editor
+                    .set_view_size(cols, rows.saturating_sub(RESERVED_ROWS as usize));
+                Outcome::Changed
+            }
+            Event::Tick => self.tick(now, &mut progress),
+        };
+        if outcome == Outcome::Quit {
+            return outcome;
+        }
+        // Checked after keys too, so steady typing doesn't hold timers off
+        if self.editor.next_timer().is_some_and(|due| due <= now) {
+            self.editor = self.editor.handle_command(EditorCommand::Tick(now));
+            if self.editor.quit_requested() {
+                return Outcome::Quit;
+            }
+            return outcome.max_with(Outcome::Changed);
+        }
+        outcome
+    }
+
+    fn key(&mut self, key: KeyEvent, now: Duration, progress: &mut dyn FnMut(u64, u64)) -> Outcome {
+        // Only the kitty protocol reports releases; nothing is bound to them.
+        if key.kind == KeyEventKind::Release {
+            return Outcome::Unchanged;
+        }
+        self.last_key = now;
+        self.held = false;
+        // Enter only closes a multi-line message; other keys close it and
+        // go on to do their own thing.
+        if key.code == KeyCode::Enter && self.editor.message().is_some_and(|m| m.is_multiline()) {
+            self.editor.dismiss_message();
+            return Outcome::Changed;
+        }
+        let keys = self.editor.map_typed(key);
+        if keys.is_empty() {
+            // Waiting for the rest of a mapping
+            self.editor
+                .record_key(key, &KeyMappingResult::UpdatePending);
+            return self.showkeys();
+        }
+        self.run_keys(keys, progress)
+    }
+
+    fn tick(&mut self, now: Duration, progress: &mut dyn FnMut(u64, u64)) -> Outcome {
+        let mut outcome = Outcome::Unchanged;
+        if self.picker_waiting() && now >= self.last_key + PICKER_DELAY {
+            self.editor.update_picker();
+            outcome = Outcome::Changed;
+        }
+        if self.editor.typed_waiting() && now >= self.last_key + self.timeoutlen() {
+            let keys = self.editor.flush_typed();
+            outcome = outcome.max_with(self.run_keys(keys, progress));
+            if outcome == Outcome::Quit {
+                return outcome;
+            }
+        }
+        if !self.held && now >= self.last_key + self.updatetime() {
+            self.held = true;
+            self.editor = self.editor.handle_command(EditorCommand::CursorHold);
+            outcome = outcome.max_with(Outcome::Changed);
+        }
+        outcome
+    }
+
+    /// Act on keys, typed or from a mapping, up to the first that quits.
+    fn run_keys(&mut self, keys: Vec<KeyEvent>, progress: &mut dyn FnMut(u64, u64)) -> Outcome {
+        let mut outcome = Outcome::Unchanged;
+        for key in keys {
+            let kmr = input::map_key(key, self.editor.mode(), self.editor.pending_mut());
+            self.editor.record_key(key, &kmr);
+            match kmr {
+                KeyMappingResult::Command(EditorCommand::Quit) => return Outcome::Quit,
+                KeyMappingResult::Command(cmd) => {
+                    if self.editor.needs_materialize(&cmd) {
+                        // On failure the preview stays and the command is ignored.
+                        let _ = self.editor.materialize(&mut *progress);
+                        // The progress line was drawn over the frame
+                        self.invalidate();
+                    }
+                    self.editor = self.editor.handle_command(cmd);
+                    if self.editor.quit_requested() {
+                        return Outcome::Quit;
+                    }
+                    outcome = outcome.max_with(if self.editor.suspend_requested() {
+                        Outcome::Suspend
+                    } else {
+                        Outcome::Changed
+                    });
+                }
+                // Nothing changes but `showkeys`.
+                KeyMappingResult::UpdatePending | KeyMappingResult::Noop => {
+                    outcome = outcome.max_with(self.showkeys());
+                }
+            }
+        }
+        outcome
+    }
+
+    fn showkeys(&self) -> Outcome {
+        if self.editor.options().showkeys {
+            Outcome::Changed
+        } else {
+            Outcome::Unchanged
+        }
+    }
+
+    fn picker_waiting(&self) -> bool {
+        self.editor
+            .picker()
+            .is_some_and(|p| p.busy() || p.preview_stale())
+    }
+
+    fn timeoutlen(&self) -> Duration {
+        Duration::from_millis(self.editor.options().timeoutlen as u64)
+    }
+
+    fn updatetime(&self) -> Duration {
+        Duration::from_millis(self.editor.options().updatetime as u64)
+    }
+
+    /// When to feed a `Tick` if no other event comes first: the picker's
+    /// next look, CursorHold, the end of `timeoutlen`, the next timer, or
+    /// `IDLE` from `now`, whichever is soonest.
+    pub fn wake_at(&self, now: Duration) -> Duration {
+        let mut at = now + IDLE;
+        if self.picker_waiting() {
+            at = at.min(now + PICKER_DELAY);
+        }
+        if !self.held {
+            at = at.min(self.last_key + self.updatetime());
+        }
+        if self.editor.typed_waiting() {
+            at = at.min(self.last_key + self.timeoutlen());
+        }
+        if let Some(due) = self.editor.next_timer() {
+            at = at.min(due);
+        }
+        at.max(now)
+    }
+
+    /// The screen as it is now. Each frame is taken to be drawn: the next
+    /// one's `last` is this one.
+    pub fn render(&mut self) -> Frame<'_> {
+        self.last = self.shown.take();
+        let shown = self
+            .shown
+            .insert(layers::compose(&self.editor, self.cols, self.rows));
+        Frame {
+            cells: shown,
+            last: self.last.as_ref(),
+        }
+    }
+
+    /// Forget the last frame, so the next one is drawn whole: something
+    /// else has written to the screen.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+}
+
+impl Outcome {
+    /// The one of two that asks more of the frontend.
+    fn max_with(self, other: Outcome) -> Outcome {
+        let rank = |o| match o {
+            Outcome::Unchanged => 0,
+            Outcome::Changed => 1,
+            Outcome::Suspend => 2,
+            Outcome::Quit => 3,
+        };
+        if rank(other) > rank(self) {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// A screen and a source of events: a terminal, a window, a web page.
+pub trait Frontend {
+    type Error;
+
+    /// The time now, since the Unix epoch.
+    fn now(&self) -> Duration;
+
+    /// The next event, or `Event::Tick` once `until` (by `now`) passes
+    /// with none.
+    fn next_event(&mut self, until: Duration) -> Result<Event, Self::Error>;
+
+    fn draw(&mut self, frame: Frame) -> Result<(), Self::Error>;
+
+    /// How far a previewed file has loaded, while a command waits on it.
+    fn progress(&mut self, _done: u64, _total: u64) {}
+
+    /// Give the screen up until the process carries on; a frontend with no
+    /// job control has nothing to do. Returns the screen's size after.
+    fn suspend(&mut self) -> Result<(usize, usize), Self::Error>;
+}
+
+/// Feed `frontend`'s events to `core` and draw what changes, until `:q`.
+/// A frontend that can't block waiting for events (a web page) calls
+/// `feed`, `wake_at` and `render` from its own event handlers instead.
+pub fn run<F: Frontend>(core: &mut EditorCore, frontend: &mut F) -> Result<(), F::Error> {
+    frontend.draw(core.render())?;
+    loop {
+        let event = frontend.next_event(core.wake_at(frontend.now()))?;
+        let now = frontend.now();
+        match core.feed(event, now, |done, total| frontend.progress(done, total)) {
+            Outcome::Unchanged => continue,
+            Outcome::Changed => {}
+            Outcome::Suspend => {
+                let (cols, rows) = frontend.suspend()?;
+                let now = frontend.now();
+                core.feed(Event::Resize { cols, rows }, now, |_, _| {});
+                core.invalidate();
+            }
+            Outcome::Quit => return Ok(()),
+        }
+        frontend.draw(core.render())?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::EditorMode;
+    use crate::keys::KeyModifiers;
+
+    fn key(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn events_in_frames_out() {
+        let start = Duration::from_secs(1000);
+        let mut core = EditorCore::new(Editor::new(), 20, 5, start);
+        let first = core.render();
+        assert_eq!((first.cells.width, first.cells.height), (20, 5));
+        assert_eq!(first.damage().len(), 5);
+
+        for c in "ihi".chars() {
+            assert_eq!(core.feed(key(c), start, |_, _| {}), Outcome::Changed);
+        }
+        let frame = core.render();
+        assert_eq!(frame.cells.get(0, 0).unwrap().symbol, "h");
+        // Only the typed line and the status line changed
+        let rows: Vec<usize> = frame.damage().iter().map(|&(y, _)| y).collect();
+        assert_eq!(rows, [0, 3]);
+
+        core.feed(Event::Resize { cols: 30, rows: 8 }, start, |_, _| {});
+        assert_eq!(core.render().cells.width, 30);
+        core.invalidate();
+        assert!(core.render().last.is_none());
+
+        // Esc leaves Insert mode; again, it quits only once nothing's unsaved
+        let esc = || Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(core.feed(esc(), start, |_, _| {}), Outcome::Changed);
+        assert!(matches!(core.editor.mode(), EditorMode::Normal));
+        assert_eq!(core.feed(esc(), start, |_, _| {}), Outcome::Changed);
+        assert!(core.editor.message().unwrap().text.starts_with("E37"));
+        for c in ":q!".chars() {
+            core.feed(key(c), start, |_, _| {});
+        }
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(core.feed(enter, start, |_, _| {}), Outcome::Quit);
+    }
+
+    #[test]
+    #[cfg(feature = "config")]
+    fn mapped_esc_leaves_insert_mode() {
+        let dir = std::env::temp_dir().join(format!("mters-core-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[keymap.insert]\njk = \"<Esc>\"\n").unwrap();
+        let mut editor = Editor::new();
+        editor.load_config(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let start = Duration::from_secs(1000);
+        let mut core = EditorCore::new(editor, 20, 5, start);
+        for c in "ihi".chars() {
+            core.feed(key(c), start, |_, _| {});
+        }
+        // `j` waits for the rest of the mapping
+        assert_eq!(core.feed(key('j'), start, |_, _| {}), Outcome::Unchanged);
+        assert_eq!(core.feed(key('k'), start, |_, _| {}), Outcome::Changed);
+        assert!(matches!(core.editor.mode(), EditorMode::Normal));
+        assert_eq!(core.editor.text.to_string(), "hi");
+    }
+
+    #[test]
+    fn registers_by_name_from_keys() {
+        let start = Duration::from_secs(1000);
+        let mut core = EditorCore::new(Editor::new(), 20, 8, start);
+        let mut keys = |keys: &str| {
+            for c in keys.chars() {
+                let code = match c {
+                    '\n' => KeyCode::Enter,
+                    '\x1b' => KeyCode::Esc,
+                    c => KeyCode::Char(c),
+                };
+                core.feed(
+                    Event::Key(KeyEvent::new(code, KeyModifiers::NONE)),
+                    start,
+                    |_, _| {},
+                );
+            }
+        };
+        keys("ione\ntwo\nthree\x1b");
+        // "0 keeps the yank through a delete; "1 has the deleted line
+        keys("ggyyjdd\"0p\"1p");
+        // An unknown register is refused: `x` deletes, not `a` then `x`
+        keys("gg\"ax");
+        assert_eq!(core.editor.text.to_string(), "ne\nthree\none\ntwo");
+        assert!(matches!(core.editor.mode(), EditorMode::Normal));
+    }
+
+    /// `keys` typed into `core`, with `\r` for Enter and `\x1b` for Esc.
+    fn type_keys(core: &mut EditorCore, keys: &str, now: Duration) {
+        for c in keys.chars() {
+            let code = match c {
+                '\r' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            core.feed(
+                Event::Key(KeyEvent::new(code, KeyModifiers::NONE)),
+                now,
+                |_, _| {},
+            );
+        }
+    }
+
+    #[test]
+    fn d_key_keeps_the_crlf() {
+        let start = Duration::from_secs(1000);
+        let mut core = EditorCore::new(Editor::new(), 20, 8, start);
+        type_keys(&mut core, ":set ff=dos\rione\rtwo\x1bggD", start);
+        assert_eq!(core.editor.text.to_string(), "\r\ntwo");
+    }
+
+    #[test]
+    fn visual_shift_moves_the_selected_lines() {
+        let start = Duration::from_secs(1000);
+        let mut core = EditorCore::new(Editor::new(), 20, 8, start);
+        type_keys(&mut core, ":set sw=2 et\rione\rtwo\rthree\x1bggVj>", start);
+        assert_eq!(core.editor.text.to_string(), "  one\n  two\nthree");
+        assert!(matches!(core.editor.mode(), EditorMode::Normal));
+        // Upwards from the last line, and Esc leaves without shifting
+        type_keys(&mut core, "GVk<Vk\x1b", start);
+        assert_eq!(core.editor.text.to_string(), "  one\ntwo\nthree");
+    }
+
+    #[test]
+    fn ticks_bring_cursorhold_once() {
+        let start = Duration::from_secs(1000);
+        let mut core = EditorCore::new(Editor::new(), 20, 5, start);
+        let hold = start + core.updatetime();
+        assert_eq!(core.wake_at(start), hold.min(start + IDLE));
+        assert_eq!(core.feed(Event::Tick, hold, |_, _| {}), Outcome::Changed);
+        // Held: nothing more until a key
+        assert_eq!(core.wake_at(hold), hold + IDLE);
+        assert_eq!(
+            core.feed(Event::Tick, hold + IDLE, |_, _| {}),
+            Outcome::Unchanged
+        );
+    }
+}
@@ -0,0 +1,272 @@
+/// How one line fares going from the old text to the new.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line {
+    /// In both, at these indexes of old and new.
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Lines of context kept around each change in a unified diff.
+const CONTEXT: usize = 3;
+
+/// Edits `lines` looks through for the shortest script before settling
+/// for replacing the changed lines wholesale. The search costs time in
+/// the lines times the edits and memory in the edits squared, so a
+/// rewritten file would otherwise take seconds and gigabytes.
+pub const MAX_EDITS: usize = 1000;
+
+/// Shortest edit turning `old` into `new`, in order; past `MAX_EDITS`
+/// edits, the lines between the ends the two share removed then added.
+pub fn lines(old: &[&str], new: &[&str]) -> Vec<Line> {
+    lines_within(old, new, MAX_EDITS).unwrap_or_else(|| {
+        let (head, tail) = common_ends(old, new);
+        let removed = (head..old.len() - tail).map(Line::Removed);
+        let added = (head..new.len() - tail).map(Line::Added);
+        around(old, new, head, tail, removed.chain(added).collect())
+    })
+}
+
+/// Shortest edit turning `old` into `new`, or None if it takes more than
+/// `max` edits.
+pub fn lines_within(old: &[&str], new: &[&str], max: usize) -> Option<Vec<Line>> {
+    let (head, tail) = common_ends(old, new);
+    let script = myers(
+        &old[head..old.len() - tail],
+        &new[head..new.len() - tail],
+        max,
+    )?;
+    let shifted = script.into_iter().map(|line| match line {
+        Line::Same(i, j) => Line::Same(i + head, j + head),
+        Line::Removed(i) => Line::Removed(i + head),
+        Line::Added(j) => Line::Added(j + head),
+    });
+    Some(around(old, new, head, tail, shifted.collect()))
+}
+
+/// Lines the two start and end with alike, not counting any twice.
+fn common_ends(old: &[&str], new: &[&str]) -> (usize, usize) {
+    let head = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let tail = old[head..]
+        .iter()
+        .rev()
+        .zip(new[head..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (head, tail)
+}
+
+/// `middle` with the `head` and `tail` lines both share around it.
+fn around(old: &[&str], new: &[&str], head: usize, tail: usize, middle: Vec<Line>) -> Vec<Line> {
+    let (old_tail, new_tail) = (old.len() - tail, new.len() - tail);
+    let mut script: Vec<Line> = (0..head).map(|i| Line::Same(i, i)).collect();
+    script.extend(middle);
+    script.extend((0..tail).map(|t| Line::Same(old_tail + t, new_tail + t)));
+    script
+}
+
+/// Myers' O(ND) algorithm, giving up past `max` edits.
+fn myers(old: &[&str], new: &[&str], max: usize) -> Option<Vec<Line>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let limit = (n + m).min(max as isize);
+    let offset = limit + 1;
+    let at = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // Furthest x reached on diagonals -d..=d, kept per round for the way
+    // back; round d only looks at those the round before reached.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: {
+        for d in 0..=limit {
+            trace.push(v[at(-d)..=at(d)].to_vec());
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                    v[at(k + 1)]
+                } else {
+                    v[at(k - 1)] + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && old[x as usize] == new[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[at(k)] = x;
+                if x >= n && y >= m {
+                    break 'search;
+                }
+            }
+        }
+        return None;
+    }
+
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| (k + d) as usize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d > 0 { v[at(prev_k)] } else { 0 };
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            script.push(Line::Same(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                script.push(Line::Added(prev_y as usize));
+            } else {
+                script.push(Line::Removed(prev_x as usize));
+            }
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    script.reverse();
+    Some(script)
+}
+
+/// `text` with its whitespace shown the way `:set list` does by default:
+/// tabs as `^I`, carriage returns as `^M`, a BOM as `<feff>` and a `$` at
+/// each line end. Diffs of this show changes that are only whitespace.
+pub fn visible_whitespace(text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            let line = line
+                .replace('\t', "^I")
+                .replace('\r', "^M")
+                .replace('\u{feff}', "<feff>");
+            format!("{line}$\n")
+        })
+        .collect()
+}
+
+/// `old` against `new` as a unified diff with the usual three lines of
+/// context; empty when they are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let script = lines(&a, &b);
+    let changes: Vec<usize> = (0..script.len())
+        .filter(|&i| !matches!(script[i], Line::Same(..)))
+        .collect();
+    let Some(&first) = changes.first() else {
+        return String::new();
+    };
+
+    // Changes closer than twice the context share a hunk.
+    let mut hunks = vec![(first, first)];
+    for &i in &changes[1..] {
+        let last = hunks.last_mut().expect("one hunk at least");
+        if i - last.1 <= 2 * CONTEXT {
+            last.1 = i;
+        } else {
+            hunks.push((i, i));
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    for (start, end) in hunks {
+        let ops = &script[start.saturating_sub(CONTEXT)..(end + CONTEXT + 1).min(script.len())];
+        // Where the hunk starts in each text: the index of its first line.
+        let (mut old_at, mut new_at) = (a.len(), b.len());
+        for op in ops.iter().rev() {
+            match *op {
+                Line::Same(i, j) => (old_at, new_at) = (i, j),
+                Line::Removed(i) => old_at = i,
+                Line::Added(j) => new_at = j,
+            }
+        }
+        let old_len = ops
+            .iter()
+            .filter(|op| !matches!(op, Line::Added(_)))
+            .count();
+        let new_len = ops
+            .iter()
+            .filter(|op| !matches!(op, Line::Removed(_)))
+            .count();
+        // An empty side is numbered by the line before it.
+        let from = |at: usize, len: usize| if len == 0 { at } else { at + 1 };
+        out.push_str(&format!(
+            "@@ -{},{old_len} +{},{new_len} @@\n",
+            from(old_at, old_len),
+            from(new_at, new_len)
+        ));
+        for op in ops {
+            let line = match *op {
+                Line::Same(i, _) => format!(" {}", a[i]),
+                Line::Removed(i) => format!("-{}", a[i]),
+                Line::Added(j) => format!("+{}", b[j]),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortest_line_edit() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let script = lines(&old, &new);
+        let edits = script
+            .iter()
+            .filter(|l| !matches!(l, Line::Same(..)))
+            .count();
+        assert_eq!(edits, 5);
+        // Replaying the script gives the new text back
+        let replayed: Vec<&str> = script
+            .iter()
+            .filter_map(|l| match *l {
+                Line::Same(i, _) => Some(old[i]),
+                Line::Added(j) => Some(new[j]),
+                Line::Removed(_) => None,
+            })
+            .collect();
+        assert_eq!(replayed, new);
+        assert!(lines(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn rewritten_text_is_replaced_wholesale() {
+        let old: Vec<String> = (0..20_000).map(|i| format!("old {i}")).collect();
+        let mut new: Vec<String> = (0..20_000).map(|i| format!("new {i}")).collect();
+        new[0] = old[0].clone();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+        assert!(lines_within(&old, &new, MAX_EDITS).is_none());
+        let script = lines(&old, &new);
+        assert_eq!(script.len(), 1 + 2 * 19_999);
+        assert_eq!(script[0], Line::Same(0, 0));
+        assert_eq!(script[1], Line::Removed(1));
+        assert_eq!(script[20_000], Line::Added(1));
+    }
+
+    #[test]
+    fn unified_hunks_with_context() {
+        let mut lines: Vec<String> = (1..=12).map(|i| i.to_string()).collect();
+        let old = lines.join("\n") + "\n";
+        lines[1] = "two".into();
+        lines.remove(10);
+        let new = lines.join("\n") + "\n";
+        assert_eq!(
+            unified(&old, &new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -8,5 +8,4 @@\n 8\n 9\n 10\n-11\n 12\n"
+        );
+        assert_eq!(unified("same\n", "same\n", "a", "b"), "");
+        assert_eq!(
+            unified("", "new\n", "a", "b"),
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+new\n"
+        );
+    }
+}
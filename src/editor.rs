@@ -1,26 +1,166 @@
-use crate::input::EditorCommand;
-use crossterm::event::KeyCode;
+use crate::changelog::{ChangeLog, Op, Patch};
+use crate::comment;
+use crate::completion::{self, Completion};
+use crate::conflicts::{conflict_at, find_conflicts};
+use crate::diff;
+use crate::ex::{Address, LineRange};
+use crate::input::{EditorCommand, KeyMappingResult};
+use crate::keylog::KeyLog;
+use crate::keymap::Keymap;
+use crate::keys::{KeyCode, KeyEvent};
 
+use crate::editorconfig;
+use crate::export;
+use crate::fileformat::{self, FileFormat, LineEnding};
+use crate::filetype;
 use crate::graphemes::{
-    abs_char_to_line_gcol, line_gcol_to_abs_char, next_grapheme_abs_char, prev_grapheme_abs_char,
+    abs_char_to_line_gcol, cell_width, gcol_to_vcol, line_gcol_to_abs_char, next_grapheme_abs_char,
+    prev_grapheme_abs_char, vcol_to_gcol,
 };
+use crate::gutter;
+use crate::health;
+use crate::indent;
+use crate::lineinput::LineInput;
+use crate::marks::Marks;
+use crate::messages::{History, Message};
+use crate::modeline;
+use crate::motion::{self, first_non_blank_abs, Motion};
+use crate::notebook;
+use crate::notes;
+use crate::operator::{self, Operator};
+use crate::options::{self, Options};
+use crate::pairs;
+use crate::patch;
+use crate::picker::{self, Picker};
+use crate::preview::{self, Preview};
+use crate::prose::{self, Transform};
+use crate::quickfix::{self, QuickFix};
+use crate::registers::{Register, Registers};
+use crate::shell;
+use crate::syntax;
+use crate::tags::{self, Tag};
+use crate::textobject::{TextObject, TextObjectKind};
+use crate::theme::Theme;
+use crate::timers::{self, Timer};
+use crate::tree::FileTree;
+use crate::view::Viewport;
 use ropey::Rope;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Clone, Copy)]
 pub enum EditorMode {
     Normal,
     Insert,
-    // Visual,
-    // Command,
+    /// `V`: the lines from `Editor::selection`'s anchor to the caret's
+    /// are selected for an operator.
+    Visual,
+    Command,
+    /// `R`: typed graphemes overwrite the ones under the caret.
+    Replace,
+    /// Waiting for a yes/no/cancel answer; see `Editor::prompt`.
+    Prompt,
+    /// Choosing from a picker; see `Editor::picker`.
+    Picker,
+    /// Moving about the file tree in the sidebar; see `Editor::tree`.
+    Tree,
 }
 
+/// What a "yes" at the confirmation prompt goes on to do.
+#[derive(Clone, Debug)]
+enum PromptAction {
+    /// `:q` with unsaved changes: yes writes then quits, no quits anyway.
+    SaveAndQuit,
+    /// `:w {name}` onto an existing file: yes overwrites it.
+    Overwrite(PathBuf),
+    /// `:tselect`: a digit jumps to that tag.
+    PickTag(Vec<Tag>),
+    /// `:w` that would change only whitespace (`whitespacewarn`): yes
+    /// writes, `d` shows the diff.
+    WhitespaceOnly(Option<PathBuf>),
+}
+
+/// Where Ctrl-T goes back to after a tag jump.
+#[derive(Clone, Debug)]
+struct TagReturn {
+    /// The tag jumped to and the row jumped from, for `:tags`.
+    name: String,
+    row: usize,
+    file: Option<PathBuf>,
+    caret: usize,
+}
+
+/// A buffer other than the one shown: what `Editor` holds of the file it
+/// shows, kept as it was left until it is shown again. Options go with
+/// it, as its filetype, `.editorconfig` and modelines left them.
 #[derive(Clone)]
-// For future use: e.g., pending multi-key commands
-// Currently unused
+struct Buffer {
+    number: usize,
+    text: Rope,
+    file_path: Option<PathBuf>,
+    preview: Option<Preview>,
+    file_format: FileFormat,
+    filetype: String,
+    filetype_restore: Vec<String>,
+    editorconfig_restore: Vec<String>,
+    modeline_restore: Vec<String>,
+    modeline_filetype: Option<String>,
+    options: Options,
+    modified: bool,
+    notes: bool,
+    caret_abs: usize,
+    /// First line on screen.
+    top: usize,
+    marks: Marks,
+    snapshots: BTreeMap<String, Rope>,
+    writes: usize,
+    disk_mtime: Option<SystemTime>,
+    changelog: Option<ChangeLog>,
+}
+
+impl Buffer {
+    /// As messages name it.
+    fn name(&self) -> String {
+        self.file_path
+            .as_ref()
+            .map_or("[No Name]".to_owned(), |p| p.display().to_string())
+    }
+}
+
+/// Where the last `p` / `P` left its text, so Ctrl-P / Ctrl-N can swap it.
+#[derive(Clone, Debug)]
+struct LastPut {
+    range: Range<usize>,
+    /// Caret before the put; the text is put again from here.
+    origin: usize,
+    before: bool,
+    count: usize,
+    /// Register that was put; cycling walks the numbered ones.
+    register: char,
+    /// Where the text put is in the kill ring, for Alt-P / Alt-N.
+    ring: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Prompt {
+    message: String,
+    action: PromptAction,
+}
+
+/// Keys typed so far in an unfinished normal-mode command: counts, an
+/// operator waiting for its target, and multi-key prefixes like `g` or `i`.
+#[derive(Clone, Default)]
 pub struct Pending {
     pub count: Option<usize>,
     pub register: Option<char>,
+    pub operator: Option<Operator>,
+    /// Count typed before the operator (`2` in `2d3w`).
+    pub op_count: Option<usize>,
     pub prefix: Vec<KeyCode>,
 }
 
@@ -28,611 +168,5808 @@ impl Pending {
     pub fn clear(&mut self) {
         self.count = None;
         self.register = None;
+        self.operator = None;
+        self.op_count = None;
         self.prefix.clear();
     }
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none()
+            && self.register.is_none()
+            && self.operator.is_none()
+            && self.op_count.is_none()
+            && self.prefix.is_empty()
+    }
     pub fn push(&mut self, kc: KeyCode) {
         self.prefix.push(kc);
     }
     pub fn take_count(&mut self) -> usize {
         let n = self.count.unwrap_or(1);
         self.count = None;
-        return n;
+        n
     }
 }
 
 #[derive(Clone)]
 pub struct Editor {
+    /// The number of the buffer shown, whose file the fields below hold.
+    buffer: usize,
+    /// The other buffers, hidden, by number.
+    buffers: Vec<Buffer>,
+    /// Buffers made so far, numbering the next.
+    buffers_made: usize,
     pub cursor_row: usize,
     pub cursor_gcol: usize,      // grapheme cluster column
-    desired_gcol: Option<usize>, // for vertical moves
+    desired_vcol: Option<usize>, // for vertical moves
     pub text: Rope,
+    file_path: Option<PathBuf>,
+    /// Set while a huge file is shown read-only; `text` stays empty until
+    /// `materialize` loads it.
+    preview: Option<Preview>,
+    /// Line endings and BOM to write back.
+    file_format: FileFormat,
+    /// `:set filetype`, empty when unknown.
+    filetype: String,
+    /// Settings for each filetype from the config, as `:set` arguments,
+    /// and those that put back what the current filetype's changed.
+    filetypes: Rc<HashMap<String, Vec<String>>>,
+    filetype_restore: Vec<String>,
+    /// `:Eval` runners from the config's `[runner]` table, by language.
+    runners: Rc<HashMap<String, String>>,
+    /// What puts back the options `.editorconfig` set for this file.
+    editorconfig_restore: Vec<String>,
+    /// The same for the file's modelines.
+    modeline_restore: Vec<String>,
+    /// The filetype from before a modeline set one.
+    modeline_filetype: Option<String>,
+    /// Changed since it was loaded or last written to `file_path`.
+    modified: bool,
+    /// The buffer is the `:notes` scratch file, saved after every change.
+    notes: bool,
     caret_abs: usize,
     mode: EditorMode,
+    /// Row Visual mode started on.
+    selection_anchor: usize,
     pending: Pending,
+    marks: Marks,
+    registers: Registers,
+    /// What each grapheme typed in replace mode overwrote (None when it was
+    /// appended past the line end), so Backspace can put it back.
+    overwritten: Vec<Option<String>>,
+    /// Only set right after a put; any other command clears it.
+    last_put: Option<LastPut>,
+    options: Options,
+    theme: Rc<Theme>,
+    view: Viewport,
+    input: Option<LineInput>,
+    picker: Option<Picker>,
+    /// Ctrl-N's menu, until a key other than Ctrl-N or Ctrl-P.
+    completion: Option<Completion>,
+    /// The last `prose` transform and where its text starts, until the
+    /// next command; a backspace then takes it back.
+    transformed: Option<(usize, Transform)>,
+    /// The sidebar, when it's shown.
+    tree: Option<FileTree>,
+    /// The last `:grep` or `:make`, whatever buffer is shown.
+    quickfix: Option<QuickFix>,
+    /// `:copen`: the list shown under the text.
+    quickfix_open: bool,
+    /// Rows for the text and anything sharing them, the quickfix pane as
+    /// it opens taking its rows from the text's.
+    text_rows: usize,
+    /// Recent keys, shown with `showkeys`.
+    keys: KeyLog,
+    /// The user's mappings, and keys typed that may yet start one.
+    keymap: Rc<Keymap>,
+    typed: Vec<KeyEvent>,
+    /// Lines run from the `:` command line.
+    cmdline_history: Vec<String>,
+    prompt: Option<Prompt>,
+    /// Positions tag jumps left, most recent last.
+    tag_stack: Vec<TagReturn>,
+    /// `:snapshot save` copies of the text; ropes share their chunks, so
+    /// these stay cheap until the text drifts far from them.
+    snapshots: BTreeMap<String, Rope>,
+    /// Writes so far, numbering the `autosnapshot` snapshots.
+    writes: usize,
+    /// When the file was last read or written, for `autoread`.
+    disk_mtime: Option<SystemTime>,
+    /// When `follow` last looked at the file.
+    followed_at: Duration,
+    /// `:Changelog start`: every change to the text, for merging with a
+    /// peer's.
+    changelog: Option<ChangeLog>,
+    /// `:Every` and the config's timers, and how many were ever started,
+    /// numbering the next.
+    timers: Vec<Timer>,
+    timers_made: usize,
+    /// The time of the latest event, as the frontend's clock gave it:
+    /// since the Unix epoch. Timers start from it and messages are
+    /// stamped with it.
+    clock: Duration,
+    message: Option<Message>,
+    messages: History,
+    quit_requested: bool,
+    /// Set by Ctrl-Z / `:suspend` for the one command.
+    suspend_requested: bool,
 
     #[cfg(debug_assertions)]
     last_newline_bol: Option<(usize, usize)>,
 }
 
+/// Char offset of the start of each line, and of the end of the last.
+fn line_starts(lines: &[&str]) -> Vec<usize> {
+    let mut at = 0;
+    let mut starts = vec![0];
+    for line in lines {
+        at += line.chars().count();
+        starts.push(at);
+    }
+    starts
+}
+
+/// Whether two paths name the same file, by where they lead if they exist.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// A file's modification time, if it can be had.
+fn mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Run on `CursorHold`. Anything that wants an idle moment (autosave,
+/// hover documentation, highlighting the word under the cursor) goes here
+/// rather than keeping a timer of its own.
+const CURSOR_HOLD: &[fn(&mut Editor)] = &[Editor::autosave_on_hold, Editor::autoread_on_hold];
+
+/// How often `follow` looks at the file.
+const FOLLOW_POLL: Duration = Duration::from_millis(250);
+
+/// Columns the file tree takes, its edge included, on a wide enough screen.
+const TREE_WIDTH: usize = 30;
+
+/// Entries the quickfix pane shows at once; the rest scroll.
+const QUICKFIX_ROWS: usize = 10;
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Editor {
     pub fn new() -> Self {
         Self {
+            buffer: 1,
+            buffers: Vec::new(),
+            buffers_made: 1,
             cursor_row: 0,
             cursor_gcol: 0,
-            desired_gcol: None,
+            desired_vcol: None,
             text: Rope::new(),
+            file_path: None,
+            preview: None,
+            file_format: FileFormat::default(),
+            filetype: String::new(),
+            filetypes: Rc::default(),
+            runners: Rc::default(),
+            filetype_restore: Vec::new(),
+            editorconfig_restore: Vec::new(),
+            modeline_restore: Vec::new(),
+            modeline_filetype: None,
+            modified: false,
+            notes: false,
             caret_abs: 0,
             mode: EditorMode::Normal,
-            pending: Pending {
-                count: None,
-                register: None,
-                prefix: Vec::new(),
-            },
+            selection_anchor: 0,
+            pending: Pending::default(),
+            marks: Marks::default(),
+            registers: Registers::default(),
+            overwritten: Vec::new(),
+            last_put: None,
+            options: Options::default(),
+            theme: Rc::default(),
+            view: Viewport::default(),
+            input: None,
+            picker: None,
+            completion: None,
+            transformed: None,
+            tree: None,
+            quickfix: None,
+            quickfix_open: false,
+            text_rows: Viewport::default().height,
+            keys: KeyLog::default(),
+            keymap: Rc::default(),
+            typed: Vec::new(),
+            cmdline_history: Vec::new(),
+            prompt: None,
+            tag_stack: Vec::new(),
+            snapshots: BTreeMap::new(),
+            writes: 0,
+            disk_mtime: None,
+            followed_at: Duration::ZERO,
+            changelog: None,
+            timers: Vec::new(),
+            timers_made: 0,
+            clock: Duration::ZERO,
+            message: None,
+            messages: History::default(),
+            quit_requested: false,
+            suspend_requested: false,
             #[cfg(debug_assertions)]
             last_newline_bol: None,
         }
     }
 
-    pub fn mode(&self) -> EditorMode {
-        self.mode
+    /// Editor with `path` loaded. A missing file gives an empty buffer that
+    /// is created on the first write, like Vim.
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let mut ed = Self::new();
+        ed.load(path)?;
+        Ok(ed)
     }
 
-    pub fn pending_mut(&mut self) -> &mut Pending {
-        &mut self.pending
+    fn load_preview(&mut self, path: PathBuf) -> io::Result<()> {
+        self.preview = Some(Preview::open(path.clone())?);
+        self.text = Rope::new();
+        self.changelog = None;
+        self.file_path = Some(path);
+        self.modified = false;
+        self.marks = Marks::default();
+        self.caret_abs = 0;
+        self.sync_visual_from_caret();
+        self.view.top = 0;
+        Ok(())
     }
 
-    #[inline]
-    fn line_gcount(&self, row: usize) -> usize {
-        let s = self.text.line(row).to_string();
-        UnicodeSegmentation::graphemes(s.as_str(), true).count()
+    pub fn options(&self) -> &Options {
+        &self.options
     }
 
-    #[inline]
-    fn abs_char_at_cursor(&self) -> usize {
-        self.caret_abs
+    pub fn theme(&self) -> &Theme {
+        &self.theme
     }
 
-    #[inline]
-    fn clamp_gcol_on_row(&self, row: usize, gcol: usize) -> usize {
-        gcol.min(self.line_gcount(row))
+    pub fn file_format(&self) -> FileFormat {
+        self.file_format
     }
 
-    #[inline]
-    fn set_desired_gcol(&mut self) {
-        self.desired_gcol = Some(self.cursor_gcol);
+    pub fn file_path(&self) -> Option<&Path> {
+        self.file_path.as_deref()
     }
 
-    #[inline]
-    fn set_cursor_from_abs_char(&mut self, abs_char: usize) {
-        let (row, gcol) = abs_char_to_line_gcol(&self.text, abs_char);
-        self.cursor_row = row;
-        self.cursor_gcol = gcol;
+    pub fn modified(&self) -> bool {
+        self.modified
     }
 
-    #[inline]
-    fn clear_desired_gcol(&mut self) {
-        self.desired_gcol = None;
+    pub fn preview(&self) -> Option<&Preview> {
+        self.preview.as_ref()
     }
 
-    #[inline]
-    fn sync_visual_from_caret(&mut self) {
-        self.set_cursor_from_abs_char(self.caret_abs);
+    /// True when `cmd` needs the real buffer but only a preview is loaded.
+    pub fn needs_materialize(&self, cmd: &EditorCommand) -> bool {
+        self.preview.is_some() && !Self::previewable(cmd)
     }
 
-    #[inline]
-    fn sync_caret_from_visual(&mut self) {
-        self.caret_abs = line_gcol_to_abs_char(&self.text, self.cursor_row, self.cursor_gcol);
+    /// Commands that work on a preview: scrolling, the command line, quitting.
+    fn previewable(cmd: &EditorCommand) -> bool {
+        use EditorCommand as Cmd;
+        match cmd {
+            Cmd::Move { motion, .. } => matches!(
+                motion,
+                Motion::Up
+                    | Motion::Down
+                    | Motion::FileStart
+                    | Motion::FileEnd
+                    | Motion::GotoLine(_)
+            ),
+            Cmd::MoveUp
+            | Cmd::MoveDown
+            | Cmd::Scroll { .. }
+            | Cmd::AlignView(_)
+            | Cmd::EnterCommandMode
+            | Cmd::CmdlineInput(_)
+            | Cmd::CmdlineBackspace
+            | Cmd::CmdlineHistory { .. }
+            | Cmd::CmdlineComplete
+            | Cmd::CmdlineExecute
+            | Cmd::EnterNormalMode
+            | Cmd::PromptAnswer(_)
+            | Cmd::ShowMessages
+            | Cmd::LoadTheme(_)
+            | Cmd::GenerateTags
+            | Cmd::ShowTagStack
+            | Cmd::Exit { .. }
+            | Cmd::Edit(_)
+            | Cmd::OpenNotes
+            | Cmd::CheckHealth
+            | Cmd::StartTimer { .. }
+            | Cmd::ListBuffers
+            | Cmd::ShowBuffer(_)
+            | Cmd::StepBuffer(_)
+            | Cmd::DeleteBuffer { .. }
+            | Cmd::StopTimer(_)
+            | Cmd::ListTimers
+            | Cmd::Suspend
+            | Cmd::ShowOptions(_)
+            | Cmd::OpenPicker(_)
+            | Cmd::PickerInput(_)
+            | Cmd::PickerBackspace
+            | Cmd::PickerStep(_)
+            | Cmd::PickerAccept
+            | Cmd::Quit => true,
+            _ => false,
+        }
     }
 
-    // pub fn handle_key_event(mut self, ev: KeyEvent) -> Self {
-    //     let result = crate::input::map_key(ev, self.mode, &mut self.pending);
-    //     match result {
-    //         KeyMappingResult::Command(cmd) => {
-    //             self.pending.clear();
-    //
-    //             match cmd {
-    //                 _ => self.handle_command(cmd),
-    //             }
-    //         }
-    //         KeyMappingResult::UpdatePending => self,
-    //         KeyMappingResult::Noop => self,
-    //     }
-    // }
+    /// Load the previewed file for real, reporting progress as it reads.
+    /// The cursor lands on the first line that was on screen.
+    pub fn materialize(&mut self, progress: impl FnMut(u64, u64)) -> io::Result<()> {
+        let Some(preview) = &self.preview else {
+            return Ok(());
+        };
+        self.text = preview.materialize(progress)?;
+        self.file_format = FileFormat::detect(&mut self.text);
+        self.preview = None;
+        self.modified = false;
+        self.caret_abs = self
+            .text
+            .line_to_char(self.view.top.min(self.text.len_lines() - 1));
+        self.sync_visual_from_caret();
+        Ok(())
+    }
 
-    pub fn handle_command(&self, command: EditorCommand) -> Self {
-        let mut new = self.clone();
+    /// Preview navigation: the cursor stays on the buffer's (empty) first row
+    /// and only the view moves.
+    fn scroll_preview(&mut self, command: &EditorCommand) {
+        let Some(preview) = &self.preview else {
+            return;
+        };
+        let top = self.view.top;
+        let page = self.view.height.max(1);
+        let top = match *command {
+            EditorCommand::MoveUp => top.saturating_sub(1),
+            EditorCommand::MoveDown => top + 1,
+            EditorCommand::Move { motion, count } => match motion {
+                Motion::Up => top.saturating_sub(count),
+                Motion::Down => top + count,
+                Motion::FileStart => 0,
+                Motion::GotoLine(line) => line.saturating_sub(1),
+                _ => usize::MAX,
+            },
+            EditorCommand::Scroll { scroll, count } => {
+                let mut view = self.view.clone();
+                view.scroll(scroll, count, top, usize::MAX);
+                view.top
+            }
+            _ => top,
+        };
+        // Keep the last line at the bottom of the screen rather than the top.
+        let last = preview.clamp_row(top.saturating_add(page));
+        self.view.top = top.min((last + 1).saturating_sub(page));
+    }
 
-        #[cfg(debug_assertions)]
-        {
-            // Visual -> abs (what the next insert would compute from row/gcol)
-            let from_visual_abs = line_gcol_to_abs_char(&new.text, new.cursor_row, new.cursor_gcol);
-            // Single source of truth for insertion:
-            let anchor_abs = new.abs_char_at_cursor(); // == caret_abs
+    /// Files over `preview::THRESHOLD` only get a preview until edited.
+    fn load(&mut self, path: PathBuf) -> io::Result<()> {
+        let huge = std::fs::metadata(&path).is_ok_and(|m| m.len() > preview::THRESHOLD);
+        if huge {
+            return self.load_preview(path);
+        }
+        self.preview = None;
+        self.text = match std::fs::read_to_string(&path) {
+            Ok(s) => Rope::from_str(&s),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Rope::new(),
+            Err(e) => return Err(e),
+        };
+        self.file_format = FileFormat::detect(&mut self.text);
+        self.changelog = None;
+        self.disk_mtime = mtime(&path);
+        self.file_path = Some(path.clone());
+        self.modified = false;
+        self.undo_modeline();
+        self.undo_editorconfig();
+        let first_line = self.text.line(0).to_string();
+        self.set_filetype(filetype::detect(&path, &first_line).unwrap_or_default());
+        self.apply_editorconfig(&path);
+        self.apply_modeline();
+        self.notes = false;
+        self.marks = Marks::default();
+        self.caret_abs = 0;
+        self.sync_visual_from_caret();
+        self.clear_desired_vcol();
+        Ok(())
+    }
 
-            debug_assert_eq!(
-                from_visual_abs, anchor_abs,
-                "Drift at command entry: visual and insert anchor disagree"
-            );
+    /// `:e` of the file already open: the file read again, but only the
+    /// lines that changed are replaced. Marks and the cursor stay with
+    /// the lines they were on, where those lines are still there.
+    fn reload(&mut self) -> io::Result<()> {
+        let path = self.file_path.clone().expect("reload needs a file");
+        let huge = std::fs::metadata(&path).is_ok_and(|m| m.len() > preview::THRESHOLD);
+        if huge || self.preview.is_some() {
+            return self.load(path);
         }
-        #[cfg(debug_assertions)]
-        {
-            if let Some((row_cookie, bol_cookie)) = new.last_newline_bol.take() {
-                // Only check if we’re still on that line for the very next event
-                if new.cursor_row == row_cookie {
-                    let caret_b = new.text.char_to_byte(new.abs_char_at_cursor());
-                    if caret_b > bol_cookie {
-                        // Something inserted before the caret between Enter and this key.
-                        let span = new.text.byte_slice(bol_cookie..caret_b).to_string();
-                        panic!(
-                            "Auto-insert before caret after newline: {:?}",
-                            span.escape_debug().to_string()
-                        );
-                    }
+        let mut fresh = Rope::from_str(&std::fs::read_to_string(&path)?);
+        let file_format = FileFormat::detect(&mut fresh);
+        let (old, new) = (self.text.to_string(), fresh.to_string());
+        let a: Vec<&str> = old.split_inclusive('\n').collect();
+        let b: Vec<&str> = new.split_inclusive('\n').collect();
+        // A rewrite, as from a formatter or a checkout, isn't worth the
+        // diff: it's read in afresh.
+        let Some(script) = diff::lines_within(&a, &b, diff::MAX_EDITS) else {
+            return self.load(path);
+        };
+
+        let starts = line_starts(&a);
+        // Where the cursor's line went: the line itself, or what replaced it.
+        let row = starts.partition_point(|&start| start <= self.caret_abs) - 1;
+        let col = self.caret_abs - starts[row];
+        let mut new_row = None;
+        let (mut hunks, mut open) = (Vec::new(), None);
+        let (mut i, mut j) = (0, 0);
+        for op in &script {
+            if i == row && new_row.is_none() {
+                new_row = Some(j);
+            }
+            // Runs of changes as (old lines, new lines).
+            match op {
+                diff::Line::Same(..) => {
+                    hunks.extend(open.take());
+                    (i, j) = (i + 1, j + 1);
+                }
+                diff::Line::Removed(_) => {
+                    open.get_or_insert((i..i, j..j)).0.end = i + 1;
+                    i += 1;
+                }
+                diff::Line::Added(_) => {
+                    open.get_or_insert((i..i, j..j)).1.end = j + 1;
+                    j += 1;
                 }
             }
         }
-        match command {
-            EditorCommand::EnterInsertMode => {
-                new.mode = EditorMode::Insert;
-                return new;
-            }
-
-            EditorCommand::EnterNormalMode => {
-                new.mode = EditorMode::Normal;
-                return new;
-            }
+        hunks.extend(open);
+        // Last to first, so the offsets of earlier lines hold.
+        for (old, new) in hunks.into_iter().rev() {
+            let (start, end) = (starts[old.start], starts[old.end]);
+            // In after the old lines, then those out: marks on them land on
+            // the start of their replacement rather than after it.
+            self.insert_text(end, &b[new].concat());
+            self.remove_text(start..end);
+        }
 
-            // ── Horizontal, grapheme‑aware ────────────────────────────────────────────
-            EditorCommand::MoveLeft => {
-                let here = new.caret_abs;
-                let prev = prev_grapheme_abs_char(&new.text, here);
-                new.caret_abs = prev;
-                new.sync_visual_from_caret();
-                new.set_cursor_from_abs_char(prev);
-                new.clear_desired_gcol();
-                trace(&new, "after move left");
-            }
+        self.file_format = file_format;
+        self.modified = false;
+        self.disk_mtime = mtime(&path);
+        let new_row = new_row.unwrap_or(j).min(b.len().saturating_sub(1));
+        let line = b
+            .get(new_row)
+            .map_or("", |line| line.trim_end_matches(['\n', '\r']));
+        let start = line_starts(&b)[new_row];
+        self.caret_abs = start + col.min(line.chars().count());
+        self.sync_visual_from_caret();
+        self.clear_desired_vcol();
+        Ok(())
+    }
 
-            EditorCommand::MoveRight => {
-                let here = new.caret_abs;
-                let next = next_grapheme_abs_char(&new.text, here);
-                new.caret_abs = next;
-                new.sync_visual_from_caret();
-                new.clear_desired_gcol();
-                trace(&new, "after move right");
-            }
+    /// Replace the buffer with `text` in a new unnamed one, unmodified so
+    /// it can be left without a fuss.
+    fn load_scratch(&mut self, text: &str) {
+        self.preview = None;
+        self.text = Rope::from_str(text);
+        self.changelog = None;
+        self.file_format = FileFormat::default();
+        self.undo_modeline();
+        self.undo_editorconfig();
+        self.set_filetype("");
+        self.file_path = None;
+        self.modified = false;
+        self.notes = false;
+        self.marks = Marks::default();
+        self.caret_abs = 0;
+        self.sync_visual_from_caret();
+        self.clear_desired_vcol();
+    }
 
-            // ── Vertical, grapheme‑aware (keep desired_gcol like Vim) ────────────────
-            EditorCommand::MoveUp => {
-                if new.cursor_row > 0 {
-                    new.set_desired_gcol();
-                    new.cursor_row -= 1;
-                    let tgt = new.desired_gcol.unwrap();
-                    new.cursor_gcol = new.clamp_gcol_on_row(new.cursor_row, tgt);
-                    new.sync_caret_from_visual();
-                    trace(&new, "after move up");
-                }
-                new.clear_desired_gcol();
-            }
-            EditorCommand::MoveDown => {
-                if new.cursor_row + 1 < new.text.len_lines() {
-                    new.set_desired_gcol();
-                    new.cursor_row += 1;
-                    let tgt = new.desired_gcol.unwrap();
-                    new.cursor_gcol = new.clamp_gcol_on_row(new.cursor_row, tgt);
-                    new.sync_caret_from_visual();
-                    trace(&new, "after move down");
-                }
-                new.clear_desired_gcol();
-            }
+    fn write(&mut self, path: Option<PathBuf>) -> io::Result<()> {
+        let path = match (path, &self.file_path) {
+            (Some(p), _) => p,
+            (None, Some(p)) => p.clone(),
+            (None, None) => return Err(io::Error::other("E32: No file name")),
+        };
+        self.fix_whitespace();
+        self.save_to(&path)?;
+        // Writing an unnamed buffer gives it that name.
+        if self.file_path.is_none() {
+            self.file_path = Some(path.clone());
+        }
+        if self.file_path.as_ref() == Some(&path) {
+            self.modified = false;
+            self.disk_mtime = mtime(&path);
+        }
+        self.writes += 1;
+        if self.options.autosnapshot {
+            let name = format!("write{}", self.writes);
+            self.snapshots.insert(name, self.text.clone());
+        }
+        // A final line break ends the last line rather than starting another.
+        let len = self.text.len_chars();
+        let lines =
+            self.text.len_lines() - usize::from(len == 0 || self.text.char(len - 1) == '\n');
+        self.info(format!(
+            "\"{}\" {lines}L, {}B written",
+            path.display(),
+            self.text.len_bytes()
+        ));
+        Ok(())
+    }
 
-            // ── Insert: cursor is grapheme‑based; edits happen at char indices ───────
-            EditorCommand::InsertChar(c) => {
-                let at = new.caret_abs; // single truth
+    /// The text as it would be written, BOM and all.
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| io::Error::new(e.kind(), "E212: Can't open file for writing"))?;
+        let mut file = io::BufWriter::new(file);
+        if self.file_format.bom {
+            io::Write::write_all(&mut file, "\u{feff}".as_bytes())?;
+        }
+        self.text.write_to(&mut file)?;
+        io::Write::flush(&mut file)
+    }
 
-                if c == '\n' {
-                    let at = new.caret_abs;
-                    new.text.insert(at, "\n");
-                    // Move caret to just after the inserted '\n' (BOL of next line)
-                    new.caret_abs = at + 1;
-                    new.sync_visual_from_caret();
+    /// Keys have stopped for `updatetime` ms. Only in Normal mode, like
+    /// Vim's CursorHold; typing in Insert mode isn't a pause.
+    fn cursor_hold(&mut self) {
+        if !matches!(self.mode, EditorMode::Normal) {
+            return;
+        }
+        for hook in CURSOR_HOLD {
+            hook(self);
+        }
+    }
 
-                    #[cfg(debug_assertions)]
-                    {
-                        let bol_b = new.text.line_to_byte(new.cursor_row);
-                        new.last_newline_bol = Some((new.cursor_row, bol_b));
-                        eprintln!(
-                            "[after newline insert] row={} gcol={} | caret_abs={}",
-                            new.cursor_row, new.cursor_gcol, new.caret_abs
-                        );
-                    }
+    /// No key is half way through a command, so one can be run from
+    /// outside without getting mixed up in it.
+    fn idle(&self) -> bool {
+        let pending = &self.pending;
+        matches!(self.mode, EditorMode::Normal)
+            && pending.count.is_none()
+            && pending.register.is_none()
+            && pending.operator.is_none()
+            && pending.prefix.is_empty()
+            && self.typed.is_empty()
+    }
 
-                    new.clear_desired_gcol();
-                    return new; // early return so we don't fall through
-                } else {
-                    // inside EditorCommand::InsertChar(c), before inserting non-'\n'
-                    #[cfg(debug_assertions)]
-                    {
-                        let at_abs = new.abs_char_at_cursor();
-                        let at_b = new.text.char_to_byte(at_abs);
-                        let row = new.cursor_row;
-                        let bol_b = new.text.line_to_byte(row);
-                        let col_dbg = at_b.saturating_sub(bol_b);
-                        eprintln!(
-                            "[INSERT {:?}] row={} gcol={} | at_abs={} (byte off in line = {})",
-                            c, row, new.cursor_gcol, at_abs, col_dbg
-                        );
-                    }
-                    let mut buf = [0u8; 4];
-                    let s = c.encode_utf8(&mut buf);
-                    new.text.insert(at, s);
+    /// When the main loop should next send `Tick`. None while keys are
+    /// half way through a command: timers wait for it to finish.
+    pub fn next_timer(&self) -> Option<Duration> {
+        if !self.idle() {
+            return None;
+        }
+        let follow = self.following().then(|| self.followed_at + FOLLOW_POLL);
+        self.timers
+            .iter()
+            .filter(|t| t.buffer.is_none_or(|b| b == self.buffer))
+            .map(Timer::next)
+            .chain(follow)
+            .min()
+    }
 
-                    let next = next_grapheme_abs_char(&new.text, at);
-                    new.caret_abs = next;
-                    new.sync_visual_from_caret();
-                    trace(&new, "after char insert");
-                    new.clear_desired_gcol();
-                }
+    /// The commands of the timers due at `now`, each run as if typed.
+    fn run_timers(&mut self, now: Duration) {
+        if !self.idle() {
+            return;
+        }
+        if self.following() && self.followed_at + FOLLOW_POLL <= now {
+            self.followed_at = now;
+            self.follow_file();
+        }
+        let shown = self.buffer;
+        let due: Vec<String> = self
+            .timers
+            .iter_mut()
+            .filter(|t| t.buffer.is_none_or(|b| b == shown))
+            .filter_map(|t| t.fire(now).then(|| t.command.clone()))
+            .collect();
+        for line in due {
+            match crate::ex::parse(&line) {
+                // A big file's preview isn't read in for a timer
+                Ok(cmd) if self.needs_materialize(&cmd) => {}
+                Ok(cmd) => *self = self.handle_command(cmd),
+                Err(e) => self.error(e),
             }
-            EditorCommand::InsertNewline => {
-                let at = new.caret_abs; // single truth
-                new.text.insert(at, "\n");
-                // Move caret to just after the newline
-                let next = next_grapheme_abs_char(&new.text, at);
-                new.caret_abs = next;
-                new.sync_visual_from_caret();
+        }
+    }
 
-                #[cfg(debug_assertions)]
-                {
-                    let bol_b = new.text.line_to_byte(new.cursor_row);
-                    new.last_newline_bol = Some((new.cursor_row, bol_b));
-                }
+    fn start_timer(&mut self, every: Duration, buffer: Option<usize>, command: String) {
+        self.timers_made += 1;
+        let timer = Timer::new(self.timers_made, every, buffer, command, self.clock);
+        self.timers.push(timer);
+    }
 
-                trace(&new, "after newline insert");
-                new.clear_desired_gcol();
+    /// `autosave`: write a modified file that has a name.
+    fn autosave_on_hold(&mut self) {
+        let named = self.file_path.is_some() && self.preview.is_none() && !self.notes;
+        if self.options.autosave && self.modified && named {
+            if let Err(e) = self.write(None) {
+                self.error(e);
             }
+        }
+    }
 
-            // ── Backspace: delete previous grapheme cluster ───────────────────────────
-            EditorCommand::Backspace => {
-                let here = new.caret_abs;
-                if here > 0 {
-                    let del = if new.text.char(here - 1) == '\n' {
-                        if here >= 2 && new.text.char(here - 2) == '\r' {
-                            Some((here - 2, here))
-                        } else {
-                            Some((here - 1, here))
-                        }
-                    } else if new.text.char(here - 1) == '\r' {
-                        Some((here - 1, here))
-                    } else {
-                        None
-                    };
+    /// `autoread`: an unmodified file that changed on disk is read again.
+    fn autoread_on_hold(&mut self) {
+        let Some(path) = &self.file_path else {
+            return;
+        };
+        let changed = mtime(path).is_some_and(|t| Some(t) != self.disk_mtime);
+        if self.options.autoread && changed && !self.modified && self.preview.is_none() {
+            let name = path.display().to_string();
+            match self.reload() {
+                Ok(()) => self.info(format!("\"{name}\" reloaded")),
+                Err(e) => self.error(e),
+            }
+        }
+    }
 
-                    if let Some((start, end)) = del {
-                        new.text.remove(start..end);
-                        new.caret_abs = start;
-                    } else {
-                        let prev = prev_grapheme_abs_char(&new.text, here);
-                        new.text.remove(prev..here);
-                        new.caret_abs = prev;
-                    }
+    fn following(&self) -> bool {
+        self.options.follow && self.file_path.is_some() && self.preview.is_none()
+    }
 
-                    new.sync_visual_from_caret();
-                    trace(&new, "after backspace");
-                }
-                new.clear_desired_gcol();
-            }
+    /// Start following the file from its end, as `--follow` and
+    /// `:set follow` do.
+    pub fn follow(&mut self) {
+        self.options.follow = true;
+        self.goto_end();
+    }
 
-            // ── Delete: delete next grapheme cluster ───────────────────────────
-            EditorCommand::Delete => {
-                let here = new.caret_abs;
-                let len = new.text.len_chars();
+    fn goto_end(&mut self) {
+        self.caret_abs = first_non_blank_abs(&self.text, self.text.len_lines() - 1);
+        self.sync_visual_from_caret();
+        self.clear_desired_vcol();
+        self.follow_cursor();
+    }
 
-                if here < len {
-                    if new.text.char(here) == '\n' {
-                        new.text.remove(here..here + 1);
-                    } else if new.text.char(here) == '\r' {
-                        if here + 1 < len && new.text.char(here + 1) == '\n' {
-                            new.text.remove(here..here + 2); // CRLF as one
-                        } else {
-                            new.text.remove(here..here + 1);
-                        }
-                    } else {
-                        // delete next grapheme
-                        let next = next_grapheme_abs_char(&new.text, here);
-                        let end = if next > here { next } else { here + 1 };
-                        new.text.remove(here..end);
+    /// `follow`: read in what was appended to the file since it was last
+    /// read, or all of it if it changed some other way, keeping to the end
+    /// if the cursor was there.
+    fn follow_file(&mut self) {
+        if self.modified {
+            self.options.follow = false;
+            self.info("Following stopped: the buffer has changes".to_owned());
+            return;
+        }
+        let path = self.file_path.clone().expect("following a file");
+        let disk_mtime = mtime(&path);
+        if disk_mtime == self.disk_mtime {
+            return;
+        }
+        let at_end = self.cursor_row + 2 >= self.text.len_lines();
+        let read = std::fs::read_to_string(&path).map(|s| {
+            let mut fresh = Rope::from_str(&s);
+            FileFormat::detect(&mut fresh);
+            fresh.to_string()
+        });
+        let result = match read {
+            Ok(fresh) => {
+                let old = self.text.to_string();
+                match fresh.strip_prefix(&old) {
+                    Some(added) => {
+                        self.insert_text(self.text.len_chars(), added);
+                        self.modified = false;
+                        self.disk_mtime = disk_mtime;
+                        Ok(())
                     }
-                    // caret stays at `here`
-                    new.sync_visual_from_caret();
-                    trace(&new, "after delete");
+                    None => self.reload(),
                 }
-                new.clear_desired_gcol();
             }
-            EditorCommand::Quit | _ => {}
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            self.options.follow = false;
+            self.error(format!("E484: Can't open file {}: {e}", path.display()));
+            return;
+        }
+        if at_end {
+            self.goto_end();
         }
+    }
 
-        new
+    /// Quietly save the notes buffer once a change is done. Insert mode
+    /// waits for Esc rather than saving every key.
+    fn autosave_notes(&mut self) {
+        if !(self.notes && self.modified && matches!(self.mode, EditorMode::Normal)) {
+            return;
+        }
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| self.save_to(&path));
+        match saved {
+            Ok(()) => self.modified = false,
+            Err(e) => self.error(format!("E212: Can't save notes: {e}")),
+        }
     }
-}
 
-fn trace(editor: &Editor, tag: &str) {
-    let at_chars_from_visual =
-        line_gcol_to_abs_char(&editor.text, editor.cursor_row, editor.cursor_gcol);
-    let at_bytes = editor.text.char_to_byte(editor.caret_abs);
-    let sol_bytes = editor.text.line_to_byte(editor.cursor_row);
-    eprintln!(
-        "[{tag}] row={} gcol={} | caret_abs={} (bytes={}) | from_visual_abs={} | BOL_bytes={}",
-        editor.cursor_row,
-        editor.cursor_gcol,
-        editor.caret_abs,
-        at_bytes,
-        at_chars_from_visual,
-        sol_bytes
-    );
-}
+    /// True while the `:notes` scratch buffer is open.
+    pub fn is_notes(&self) -> bool {
+        self.notes
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    pub fn mode(&self) -> EditorMode {
+        self.mode
+    }
+
+    /// In Visual mode, the rows selected, top first.
+    pub fn selection(&self) -> Option<RangeInclusive<usize>> {
+        let EditorMode::Visual = self.mode else {
+            return None;
+        };
+        let (a, b) = (self.selection_anchor, self.cursor_row);
+        Some(a.min(b)..=a.max(b))
+    }
+
+    pub fn pending_mut(&mut self) -> &mut Pending {
+        &mut self.pending
+    }
+
+    pub fn view(&self) -> &Viewport {
+        &self.view
+    }
+
+    /// The time now, since the Unix epoch; the editor has no clock of its
+    /// own.
+    pub fn set_clock(&mut self, now: Duration) {
+        self.clock = now;
+    }
+
+    /// Called with the terminal's text rows at startup and on resize.
+    /// Terminal size left for the text: `width` includes the gutter.
+    pub fn set_view_size(&mut self, width: usize, height: usize) {
+        self.view.width = width;
+        self.text_rows = height;
+        if let Some(tree) = &mut self.tree {
+            tree.follow(height);
+        }
+        self.fit_view();
+    }
+
+    /// The text's rows: what's left by the quickfix pane.
+    fn fit_view(&mut self) {
+        self.view.height = self.text_rows - self.quickfix_rows();
+        self.follow_cursor();
+    }
+
+    pub fn quickfix(&self) -> Option<&QuickFix> {
+        self.quickfix.as_ref()
+    }
+
+    /// Rows the quickfix pane takes under the text, its title included,
+    /// or 0 while it's closed. Never more than half.
+    pub fn quickfix_rows(&self) -> usize {
+        match &self.quickfix {
+            Some(list) if self.quickfix_open => {
+                (list.entries.len().clamp(1, QUICKFIX_ROWS) + 1).min(self.text_rows / 2)
+            }
+            _ => 0,
+        }
+    }
+
+    /// A new quickfix list, and the first place in it gone to.
+    fn set_quickfix(&mut self, list: QuickFix) {
+        let empty = list.entries.is_empty();
+        self.quickfix = Some(list);
+        self.fit_view();
+        if empty {
+            self.info("No errors".into());
+        } else if let Err(e) = self.quickfix_go(|list| list.select(None)) {
+            self.error(e);
+        }
+    }
+
+    /// Go to the entry `pick` chooses, showing which it is.
+    fn quickfix_go(
+        &mut self,
+        pick: impl FnOnce(&mut QuickFix) -> Result<&quickfix::Entry, String>,
+    ) -> Result<(), String> {
+        let list = self.quickfix.as_mut().ok_or("E42: No Errors")?;
+        let entry = pick(list)?.clone();
+        let described = list.describe();
+        self.switch_file(&entry.file)?;
+        let row = (entry.line - 1).min(self.text.len_lines() - 1);
+        let bol = self.text.line_to_char(row);
+        let len = motion::line_end_abs(&self.text, row) - bol;
+        let col = entry.col.map_or(0, |c| c.saturating_sub(1)).min(len);
+        self.caret_abs = bol + col;
+        self.sync_visual_from_caret();
+        self.clear_desired_vcol();
+        self.info(described);
+        Ok(())
+    }
+
+    /// Scroll just enough to keep the cursor on screen, both ways.
+    fn follow_cursor(&mut self) {
+        self.view.follow(self.cursor_row);
+        if self.preview.is_none() {
+            let room = self.view.width.saturating_sub(self.text_left());
+            self.view.follow_col(self.cursor_vcol(), room);
+        }
+    }
+
+    /// Columns taken by the file tree and the line between it and the
+    /// rest, or 0 when it's hidden.
+    pub fn sidebar_width(&self) -> usize {
+        match self.tree {
+            Some(_) => TREE_WIDTH.min(self.view.width / 2),
+            None => 0,
+        }
+    }
+
+    /// Screen column where the text starts: right of the sidebar and the
+    /// gutter.
+    pub fn text_left(&self) -> usize {
+        self.sidebar_width() + self.gutter_width()
+    }
+
+    /// Columns taken by the gutter: those of its components shown now.
+    pub fn gutter_width(&self) -> usize {
+        self.gutter().iter().map(|&(_, width)| width).sum()
+    }
+
+    /// The gutter's components in `gutter` order, each with its width;
+    /// those with nothing to show left out.
+    pub fn gutter(&self) -> Vec<(&'static dyn gutter::Component, usize)> {
+        self.options
+            .gutter
+            .0
+            .iter()
+            .filter_map(|&(name, least)| {
+                let component = gutter::find(name)?;
+                let width = component.width(self);
+                (width > 0).then(|| (component, width.max(least.unwrap_or(0))))
+            })
+            .collect()
+    }
+
+    /// The first of the marks `a`-`z` on `row`, for the gutter.
+    pub fn mark_on(&self, row: usize) -> Option<char> {
+        let len = self.text.len_chars();
+        ('a'..='z').find(|&c| {
+            self.marks
+                .get(c)
+                .is_some_and(|at| self.text.char_to_line(at.min(len)) == row)
+        })
+    }
+
+    /// Screen column of the cursor in its line, before horizontal scrolling.
+    /// Normal mode sits on the last cell of a tab, as Vim does; inserting
+    /// happens before its first.
+    pub fn cursor_vcol(&self) -> usize {
+        let tabstop = self.options.tabstop;
+        let line = self.text.line(self.cursor_row).to_string();
+        let start = gcol_to_vcol(&line, self.cursor_gcol, tabstop);
+        match line.graphemes(true).nth(self.cursor_gcol) {
+            Some("\t") if matches!(self.mode, EditorMode::Normal | EditorMode::Visual) => {
+                start + cell_width("\t", start, tabstop) - 1
+            }
+            _ => start,
+        }
+    }
+
+    /// The open picker, in picker mode.
+    pub fn picker(&self) -> Option<&Picker> {
+        self.picker.as_ref()
+    }
+
+    /// Ctrl-N's menu, while it's open.
+    pub fn completion(&self) -> Option<&Completion> {
+        self.completion.as_ref()
+    }
+
+    /// The file tree, while the sidebar is shown.
+    pub fn tree(&self) -> Option<&FileTree> {
+        self.tree.as_ref()
+    }
+
+    /// `config.toml`, read at startup. A missing file is no error.
+    #[cfg(feature = "config")]
+    pub fn load_config(&mut self, path: &Path) {
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => return self.error(format!("E484: Can't open file {}: {e}", path.display())),
+        };
+        let table: toml::Table = match src.parse() {
+            Ok(table) => table,
+            Err(e) => return self.error(format!("E5: config: {e}")),
+        };
+        // Made again below, in case the config turns them off
+        self.undo_modeline();
+        if let Some(modeline) = table.get("modeline") {
+            match modeline.as_bool() {
+                Some(on) => self.options.modeline = on,
+                None => self.error("E474: modeline must be true or false"),
+            }
+        }
+        if let Some(filetypes) = table.get("filetype") {
+            let parsed = match filetypes.as_table() {
+                Some(filetypes) => filetype::parse_settings(filetypes),
+                None => Err("E474: filetype must be a table".into()),
+            };
+            match parsed {
+                Ok(filetypes) => {
+                    self.filetypes = Rc::new(filetypes);
+                    // The file opened before the config was read
+                    let current = self.filetype.clone();
+                    self.set_filetype(&current);
+                }
+                Err(e) => self.error(e),
+            }
+        }
+        if let Some(runners) = table.get("runner") {
+            match notebook::parse_runners(runners) {
+                Ok(runners) => self.runners = Rc::new(runners),
+                Err(e) => self.error(e),
+            }
+        }
+        if let Some(timers) = table.get("timer") {
+            match timers::parse_config(timers) {
+                Ok(timers) => {
+                    for (every, command) in timers {
+                        self.start_timer(every, None, command);
+                    }
+                }
+                Err(e) => self.error(e),
+            }
+        }
+        if let Some(keymap) = table.get("keymap") {
+            let parsed = match keymap.as_table() {
+                Some(keymap) => Keymap::parse(keymap),
+                None => Err("E474: keymap must be a table".into()),
+            };
+            match parsed {
+                Ok(keymap) => self.keymap = Rc::new(keymap),
+                Err(e) => self.error(e),
+            }
+        }
+        self.apply_modeline();
+    }
+
+    /// A key as typed, through the user's mappings: the keys to act on
+    /// now. None yet while it may be the start of a longer mapping.
+    pub fn map_typed(&mut self, key: KeyEvent) -> Vec<KeyEvent> {
+        // A pending operator or prefix takes its key as typed, as Vim's
+        // Normal mode mappings don't apply in Operator-pending mode.
+        if self.pending.operator.is_some() || !self.pending.prefix.is_empty() {
+            return vec![key];
+        }
+        let keymap = Rc::clone(&self.keymap);
+        keymap.feed(self.mode, &mut self.typed, key)
+    }
+
+    /// Keys waiting on a longer mapping, which come out after `timeoutlen`.
+    pub fn typed_waiting(&self) -> bool {
+        !self.typed.is_empty()
+    }
+
+    /// `timeoutlen` passed: the keys waiting map as far as they go.
+    pub fn flush_typed(&mut self) -> Vec<KeyEvent> {
+        let keymap = Rc::clone(&self.keymap);
+        keymap.flush(self.mode, &mut self.typed)
+    }
+
+    /// Note a key and what it mapped to, for `showkeys`.
+    pub fn record_key(&mut self, key: KeyEvent, result: &KeyMappingResult) {
+        self.keys.record(key, result);
+    }
+
+    pub fn key_log(&self) -> &KeyLog {
+        &self.keys
+    }
+
+    /// Take in the open picker's latest matches and read its preview, once
+    /// keys have stopped coming.
+    pub fn update_picker(&mut self) {
+        if let Some(picker) = &mut self.picker {
+            picker.update();
+            if picker.preview_stale() {
+                picker.load_preview();
+            }
+        }
+    }
+
+    /// The line being typed in command mode.
+    pub fn input(&self) -> Option<&LineInput> {
+        self.input.as_ref()
+    }
+
+    pub fn message(&self) -> Option<&Message> {
+        self.message.as_ref()
+    }
+
+    /// Close a multi-line message (its "Press ENTER" line).
+    pub fn dismiss_message(&mut self) {
+        self.message = None;
+    }
+
+    /// A new filetype: the settings the last one made are put back, then
+    /// the new one's from the config are made.
+    fn set_filetype(&mut self, filetype: &str) {
+        for arg in std::mem::take(&mut self.filetype_restore).iter().rev() {
+            apply_setting(&mut self.options, arg);
+        }
+        self.filetype = filetype.to_owned();
+        let filetypes = Rc::clone(&self.filetypes);
+        let configured = filetypes.get(filetype).into_iter().flatten();
+        let builtin = filetype::builtin_settings(filetype).iter().copied();
+        for arg in builtin.chain(configured.map(String::as_str)) {
+            if let Some(restore) = apply_setting(&mut self.options, arg) {
+                self.filetype_restore.push(restore);
+            }
+        }
+    }
+
+    /// `.editorconfig` settings for the file just opened, made over its
+    /// filetype's as the nearer to the file.
+    fn apply_editorconfig(&mut self, path: &Path) {
+        let (args, line_ending) = editorconfig::settings(&editorconfig::properties(path));
+        for arg in &args {
+            if let Some(restore) = apply_setting(&mut self.options, arg) {
+                self.editorconfig_restore.push(restore);
+            }
+        }
+        if let Some(to) = line_ending {
+            self.set_line_ending(to);
+        }
+    }
+
+    /// The modelines of the file just opened, made last as the nearest to
+    /// it. Only options a filetype may set are taken, and `filetype`.
+    fn apply_modeline(&mut self) {
+        let Some(path) = self.file_path.clone().filter(|_| self.options.modeline) else {
+            return;
+        };
+        let args = modeline::find(&self.text, self.options.modelines);
+        let mut refused = None;
+        // A filetype first, so what it brings is under the other settings
+        let filetype = args
+            .iter()
+            .rev()
+            .find_map(|arg| match crate::ex::setting(arg) {
+                Ok(EditorCommand::SetFiletype(ft)) => Some(ft),
+                _ => None,
+            });
+        if let Some(filetype) = filetype {
+            self.modeline_filetype = Some(self.filetype.clone());
+            self.change_filetype(&path, &filetype);
+        }
+        for arg in &args {
+            let name = match crate::ex::setting(arg) {
+                Ok(EditorCommand::SetFiletype(_)) => continue,
+                Ok(
+                    EditorCommand::SetFlag { name, .. }
+                    | EditorCommand::SetNumber { name, .. }
+                    | EditorCommand::ToggleFlag(name),
+                ) => options::full_name(&name),
+                Ok(_) => None,
+                Err(e) => {
+                    refused.get_or_insert(e);
+                    continue;
+                }
+            };
+            // A file doesn't get to turn modelines back on for itself
+            let restore = match name {
+                Some("modeline" | "modelines") => None,
+                _ => apply_setting(&mut self.options, arg),
+            };
+            match restore {
+                Some(restore) => self.modeline_restore.push(restore),
+                None => {
+                    refused.get_or_insert(format!("E520: Not allowed in a modeline: {arg}"));
+                }
+            }
+        }
+        if let Some(e) = refused {
+            self.error(e);
+        }
+    }
+
+    fn undo_modeline(&mut self) {
+        for arg in std::mem::take(&mut self.modeline_restore).iter().rev() {
+            apply_setting(&mut self.options, arg);
+        }
+        if let Some((filetype, path)) = self.modeline_filetype.take().zip(self.file_path.clone()) {
+            self.change_filetype(&path, &filetype);
+        }
+    }
+
+    /// A new filetype under `.editorconfig` settings already made.
+    fn change_filetype(&mut self, path: &Path, filetype: &str) {
+        self.undo_editorconfig();
+        self.set_filetype(filetype);
+        self.apply_editorconfig(path);
+    }
+
+    fn undo_editorconfig(&mut self) {
+        for arg in std::mem::take(&mut self.editorconfig_restore).iter().rev() {
+            apply_setting(&mut self.options, arg);
+        }
+    }
+
+    /// Every line break rewritten as `to`.
+    fn set_line_ending(&mut self, to: LineEnding) {
+        for at in fileformat::conversion_points(&self.text, to) {
+            match to {
+                LineEnding::Lf => self.remove_text(at..at + 1),
+                LineEnding::Crlf => self.insert_text(at, "\r"),
+            }
+        }
+        if self.file_format.line_ending != to {
+            self.modified = true;
+        }
+        self.file_format.line_ending = to;
+        self.caret_abs = self.caret_abs.min(self.text.len_chars());
+        self.sync_visual_from_caret();
+        self.sync_caret_from_visual();
+    }
+
+    /// `trimtrailing` and `fixendofline`, just before a write.
+    fn fix_whitespace(&mut self) {
+        if self.options.trimtrailing {
+            for row in (0..self.text.len_lines()).rev() {
+                let line = self.text.line(row).to_string();
+                let body = line.trim_end_matches(['\n', '\r']);
+                let kept = body.trim_end_matches([' ', '\t']).chars().count();
+                let start = self.text.line_to_char(row);
+                self.remove_text(start + kept..start + body.chars().count());
+            }
+            self.sync_caret_from_visual();
+        }
+        let len = self.text.len_chars();
+        if self.options.fixendofline && len > 0 && self.text.char(len - 1) != '\n' {
+            let brk = self.file_format.line_ending.as_str();
+            self.insert_text(len, brk);
+        }
+    }
+
+    /// `:set name?` for an option of `Options` or of the file.
+    fn show_option(&self, name: &str) -> Option<String> {
+        match name {
+            "ff" | "fileformat" => Some(format!(
+                "fileformat={}",
+                self.file_format.line_ending.name()
+            )),
+            "ft" | "filetype" => Some(format!("filetype={}", self.filetype)),
+            "bomb" if self.file_format.bom => Some("bomb".to_owned()),
+            "bomb" => Some("nobomb".to_owned()),
+            _ => self.options.show(name),
+        }
+    }
+
+    fn info(&mut self, text: String) {
+        self.show(Message { text, error: false });
+    }
+
+    fn error(&mut self, text: impl ToString) {
+        self.show(Message {
+            text: text.to_string(),
+            error: true,
+        });
+    }
+
+    fn show(&mut self, message: Message) {
+        self.messages.push(self.clock, message.clone());
+        self.message = Some(message);
+    }
+
+    /// Question shown on the command line while in prompt mode.
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_ref().map(|p| p.message.as_str())
+    }
+
+    fn ask(&mut self, message: String, action: PromptAction) {
+        self.prompt = Some(Prompt {
+            message: format!("{message} (Y)es, (N)o, (C)ancel: "),
+            action,
+        });
+        self.mode = EditorMode::Prompt;
+    }
+
+    /// The file on disk and the text as it would be written over it, when
+    /// they differ only in whitespace, line endings or a BOM.
+    fn whitespace_only_change(&self, path: &Path) -> Option<(String, String)> {
+        let disk = String::from_utf8_lossy(&std::fs::read(path).ok()?).into_owned();
+        let bom = if self.file_format.bom { "\u{feff}" } else { "" };
+        let ours = format!("{bom}{}", self.text);
+        let visible = |s: &str| -> String {
+            s.chars()
+                .filter(|c| !c.is_whitespace() && *c != '\u{feff}')
+                .collect()
+        };
+        (disk != ours && visible(&disk) == visible(&ours)).then_some((disk, ours))
+    }
+
+    fn ask_whitespace_only(&mut self, path: Option<PathBuf>, diff: Option<String>) {
+        let name = path
+            .as_ref()
+            .or(self.file_path.as_ref())
+            .expect("a file on disk");
+        let question = format!(
+            "Only whitespace would change in \"{}\". Write anyway? (Y)es, (N)o, (D)iff: ",
+            name.display()
+        );
+        self.prompt = Some(Prompt {
+            message: match diff {
+                Some(diff) => format!("{}\n{question}", diff.trim_end()),
+                None => question,
+            },
+            action: PromptAction::WhitespaceOnly(path),
+        });
+        self.mode = EditorMode::Prompt;
+    }
+
+    /// Tags offered by `:tselect`, numbered for a one-key answer. Only the
+    /// first nine are listed.
+    fn ask_tag(&mut self, name: &str, mut tags: Vec<Tag>) {
+        tags.truncate(9);
+        let width = name.len().max(3);
+        let mut message = format!("  # kind {:<width$}  file", "tag");
+        for (i, tag) in tags.iter().enumerate() {
+            let kind = tag.kind.as_deref().unwrap_or("");
+            let file = tag.file.display();
+            message.push_str(&format!("\n  {} {kind:<4} {name:<width$}  {file}", i + 1));
+        }
+        message.push_str(&format!("\nType number (1-{}) or (C)ancel: ", tags.len()));
+        self.prompt = Some(Prompt {
+            message,
+            action: PromptAction::PickTag(tags),
+        });
+        self.mode = EditorMode::Prompt;
+    }
+
+    /// The identifier under the cursor, for Ctrl-] and `:tselect`.
+    fn word_under_cursor(&self) -> Result<String, String> {
+        let word = TextObject {
+            kind: TextObjectKind::Word,
+            around: false,
+        };
+        // A whole identifier, even when `w` stops at its humps
+        let opts = Options {
+            subword: false,
+            ..self.options.clone()
+        };
+        word.resolve(&self.text, self.caret_abs, &opts)
+            .map(|r| self.text.slice(r).to_string())
+            .filter(|w| w.chars().all(|c| opts.iskeyword.contains(c)))
+            .ok_or_else(|| "E349: No identifier under cursor".to_owned())
+    }
+
+    fn is_current_file(&self, path: &Path) -> bool {
+        self.file_path
+            .as_deref()
+            .is_some_and(|current| same_file(current, path))
+    }
+
+    /// Show another existing file for a tag jump or Ctrl-T.
+    fn switch_file(&mut self, path: &Path) -> Result<(), String> {
+        if self.is_current_file(path) {
+            return Ok(());
+        }
+        if !path.exists() {
+            return Err(format!("E429: File \"{}\" does not exist", path.display()));
+        }
+        self.open_buffer(path.to_path_buf())
+            .map_err(|e| format!("E484: Can't open file {}: {e}", path.display()))
+    }
+
+    /// The buffer shown, as it is kept while hidden.
+    fn hidden(&self) -> Buffer {
+        Buffer {
+            number: self.buffer,
+            text: self.text.clone(),
+            file_path: self.file_path.clone(),
+            preview: self.preview.clone(),
+            file_format: self.file_format,
+            filetype: self.filetype.clone(),
+            filetype_restore: self.filetype_restore.clone(),
+            editorconfig_restore: self.editorconfig_restore.clone(),
+            modeline_restore: self.modeline_restore.clone(),
+            modeline_filetype: self.modeline_filetype.clone(),
+            options: self.options.clone(),
+            modified: self.modified,
+            notes: self.notes,
+            caret_abs: self.caret_abs,
+            top: self.view.top,
+            marks: self.marks.clone(),
+            snapshots: self.snapshots.clone(),
+            writes: self.writes,
+            disk_mtime: self.disk_mtime,
+            changelog: self.changelog.clone(),
+        }
+    }
+
+    /// Keep the buffer shown among the hidden ones.
+    fn hide(&mut self, buffer: Buffer) {
+        let at = self.buffers.partition_point(|b| b.number < buffer.number);
+        self.buffers.insert(at, buffer);
+    }
+
+    /// Show `buffer` in place of the current one, which should be hidden
+    /// first to be kept.
+    fn show_buffer(&mut self, buffer: Buffer) {
+        self.buffer = buffer.number;
+        self.text = buffer.text;
+        self.file_path = buffer.file_path;
+        self.preview = buffer.preview;
+        self.file_format = buffer.file_format;
+        self.filetype = buffer.filetype;
+        self.filetype_restore = buffer.filetype_restore;
+        self.editorconfig_restore = buffer.editorconfig_restore;
+        self.modeline_restore = buffer.modeline_restore;
+        self.modeline_filetype = buffer.modeline_filetype;
+        self.options = buffer.options;
+        self.modified = buffer.modified;
+        self.notes = buffer.notes;
+        self.caret_abs = buffer.caret_abs;
+        self.view.top = buffer.top;
+        self.marks = buffer.marks;
+        self.snapshots = buffer.snapshots;
+        self.writes = buffer.writes;
+        self.disk_mtime = buffer.disk_mtime;
+        self.changelog = buffer.changelog;
+        self.last_put = None;
+        self.sync_visual_from_caret();
+        self.clear_desired_vcol();
+    }
+
+    /// Show `path`: the buffer that has it, or a new one it's read into.
+    /// The current buffer is kept hidden, changes and all, unless it's
+    /// the empty unnamed one the editor starts with.
+    fn open_buffer(&mut self, path: PathBuf) -> io::Result<()> {
+        if self.is_current_file(&path) {
+            return Ok(());
+        }
+        let found = self.buffers.iter().position(|b| {
+            b.file_path
+                .as_deref()
+                .is_some_and(|file| same_file(file, &path))
+        });
+        if let Some(i) = found {
+            self.switch_buffer(self.buffers[i].number);
+            return Ok(());
+        }
+        let kept = (!self.blank()).then(|| self.hidden());
+        // Nothing changes if the file can't be read
+        self.load(path)?;
+        if let Some(kept) = kept {
+            self.hide(kept);
+            self.number_new_buffer();
+        }
+        Ok(())
+    }
+
+    /// The empty unnamed buffer the editor starts with, which a file
+    /// opened takes over rather than hides.
+    fn blank(&self) -> bool {
+        self.file_path.is_none()
+            && !self.modified
+            && self.preview.is_none()
+            && self.text.len_chars() == 0
+    }
+
+    /// `text` in a new unnamed buffer, the current one kept hidden.
+    fn open_scratch(&mut self, text: &str) {
+        if !self.blank() {
+            self.hide(self.hidden());
+            self.number_new_buffer();
+        }
+        self.load_scratch(text);
+        self.disk_mtime = None;
+    }
+
+    /// What's loaded made a buffer of its own, with a number and nothing
+    /// from the one it replaced.
+    fn number_new_buffer(&mut self) {
+        self.buffers_made += 1;
+        self.buffer = self.buffers_made;
+        self.snapshots.clear();
+        self.writes = 0;
+        self.last_put = None;
+        self.view.top = 0;
+    }
+
+    /// Show buffer `number`, keeping the current one hidden.
+    fn switch_buffer(&mut self, number: usize) {
+        let Some(i) = self.buffers.iter().position(|b| b.number == number) else {
+            return;
+        };
+        let buffer = self.buffers.remove(i);
+        self.hide(self.hidden());
+        self.show_buffer(buffer);
+    }
+
+    /// `:bn` and `:bp`: `count` buffers on by number, round to the first
+    /// after the last.
+    fn step_buffer(&mut self, count: isize) {
+        let mut numbers: Vec<usize> = self.buffers.iter().map(|b| b.number).collect();
+        let at = numbers.partition_point(|&n| n < self.buffer);
+        numbers.insert(at, self.buffer);
+        let to = (at as isize + count).rem_euclid(numbers.len() as isize);
+        self.switch_buffer(numbers[to as usize]);
+    }
+
+    /// The buffer `arg` names, by number or by a part of its file name
+    /// that no other buffer's has.
+    fn find_buffer(&self, arg: &str) -> Result<usize, String> {
+        let all = std::iter::once((self.buffer, self.file_path.as_deref())).chain(
+            self.buffers
+                .iter()
+                .map(|b| (b.number, b.file_path.as_deref())),
+        );
+        if let Ok(number) = arg.parse() {
+            return all
+                .map(|(n, _)| n)
+                .find(|&n| n == number)
+                .ok_or_else(|| format!("E86: Buffer {number} does not exist"));
+        }
+        let named: Vec<(usize, String)> = all
+            .filter_map(|(n, path)| Some((n, path?.display().to_string())))
+            .collect();
+        if let Some(&(n, _)) = named.iter().find(|(_, name)| name == arg) {
+            return Ok(n);
+        }
+        let found: Vec<usize> = named
+            .iter()
+            .filter(|(_, name)| name.contains(arg))
+            .map(|&(n, _)| n)
+            .collect();
+        match found[..] {
+            [n] => Ok(n),
+            [] => Err(format!("E94: No matching buffer for {arg}")),
+            _ => Err(format!("E93: More than one match for {arg}")),
+        }
+    }
+
+    /// `:bd`: buffer `number` closed, and its timers stopped. The next
+    /// buffer takes the current one's place, or a new empty one when it
+    /// was the last.
+    fn delete_buffer(&mut self, number: usize, force: bool) -> Result<(), String> {
+        let modified = if number == self.buffer {
+            self.modified
+        } else {
+            self.buffers
+                .iter()
+                .any(|b| b.number == number && b.modified)
+        };
+        if modified && !force {
+            return Err(format!(
+                "E89: No write since last change for buffer {number} (add ! to override)"
+            ));
+        }
+        self.timers.retain(|t| t.buffer != Some(number));
+        if number != self.buffer {
+            self.buffers.retain(|b| b.number != number);
+        } else if self.buffers.is_empty() {
+            self.load_scratch("");
+            self.disk_mtime = None;
+            self.number_new_buffer();
+        } else {
+            let next = self.buffers.partition_point(|b| b.number < number) % self.buffers.len();
+            let buffer = self.buffers.remove(next);
+            self.show_buffer(buffer);
+        }
+        Ok(())
+    }
+
+    /// Go to `tag`, remembering where we were for Ctrl-T. Call on a copy:
+    /// a failure may leave another file loaded.
+    fn jump_to_tag(&mut self, tag: &Tag) -> Result<(), String> {
+        let here = TagReturn {
+            name: tag.name.clone(),
+            row: self.cursor_row,
+            file: self.file_path.clone(),
+            caret: self.caret_abs,
+        };
+        self.switch_file(&tag.file)?;
+        let row = tags::locate(&self.text, &tag.address)
+            .ok_or_else(|| format!("E434: Can't find tag pattern: {}", tag.name))?;
+        self.caret_abs = self.text.line_to_char(row);
+        self.sync_visual_from_caret();
+        self.tag_stack.push(here);
+        Ok(())
+    }
+
+    /// 1-based byte offset of the cursor, as `:goto` takes it.
+    pub fn cursor_byte(&self) -> usize {
+        self.text.char_to_byte(self.caret_abs) + 1
+    }
+
+    /// Set by `:q`; the main loop exits once it sees this.
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    /// Set by Ctrl-Z or `:suspend`: the main loop hands the terminal back
+    /// and stops the process until the shell's `fg`.
+    pub fn suspend_requested(&self) -> bool {
+        self.suspend_requested
+    }
+
+    #[inline]
+    fn line_gcount(&self, row: usize) -> usize {
+        let s = self.text.line(row).to_string();
+        UnicodeSegmentation::graphemes(s.as_str(), true).count()
+    }
+
+    #[inline]
+    fn abs_char_at_cursor(&self) -> usize {
+        self.caret_abs
+    }
+
+    #[inline]
+    fn clamp_gcol_on_row(&self, row: usize, gcol: usize) -> usize {
+        gcol.min(self.line_gcount(row))
+    }
+
+    #[inline]
+    fn set_desired_vcol(&mut self) {
+        let line = self.text.line(self.cursor_row).to_string();
+        self.desired_vcol = Some(gcol_to_vcol(&line, self.cursor_gcol, self.options.tabstop));
+    }
+
+    /// The grapheme on `row` drawn over screen column `vcol`, so moving
+    /// across tabs and wide graphemes keeps the cursor where it was seen.
+    fn gcol_at_vcol(&self, row: usize, vcol: usize) -> usize {
+        let line = self.text.line(row).to_string();
+        self.clamp_gcol_on_row(row, vcol_to_gcol(&line, vcol, self.options.tabstop))
+    }
+
+    #[inline]
+    fn set_cursor_from_abs_char(&mut self, abs_char: usize) {
+        let (row, gcol) = abs_char_to_line_gcol(&self.text, abs_char);
+        self.cursor_row = row;
+        self.cursor_gcol = gcol;
+    }
+
+    #[inline]
+    fn clear_desired_vcol(&mut self) {
+        self.desired_vcol = None;
+    }
+
+    #[inline]
+    fn sync_visual_from_caret(&mut self) {
+        self.set_cursor_from_abs_char(self.caret_abs);
+    }
+
+    #[inline]
+    fn sync_caret_from_visual(&mut self) {
+        self.caret_abs = line_gcol_to_abs_char(&self.text, self.cursor_row, self.cursor_gcol);
+    }
+
+    /// Make `c` typed at `at` over by `prose`, if it's on and one of its
+    /// transforms applies; the caret's new place if so.
+    fn prose_transform(&mut self, c: char, at: usize) -> Option<usize> {
+        if !self.options.prose || !matches!(self.mode, EditorMode::Insert) {
+            return None;
+        }
+        let row = self.text.char_to_line(at);
+        let bol = self.text.line_to_char(row);
+        let before = self.text.slice(bol..at).to_string();
+        let paragraph = row == 0 || self.text.line(row - 1).chars().all(char::is_whitespace);
+        let t = prose::transform(c, &before, paragraph, &self.options)?;
+        let start = at - t.replaced;
+        self.remove_text(start..at);
+        self.insert_text(start, &t.text);
+        let next = start + t.text.chars().count();
+        self.transformed = Some((start, t));
+        Some(next)
+    }
+
+    // ── Text mutation: every edit goes through here so marks stay valid ──────
+
+    fn insert_text(&mut self, at: usize, s: &str) {
+        self.modified |= !s.is_empty();
+        self.text.insert(at, s);
+        self.marks.on_insert(at, s.chars().count());
+        if let Some(log) = &mut self.changelog {
+            log.insert(at, s);
+        }
+    }
+
+    fn remove_text(&mut self, range: Range<usize>) {
+        self.modified |= !range.is_empty();
+        self.text.remove(range.clone());
+        if let Some(log) = &mut self.changelog {
+            log.delete(range.clone());
+        }
+        self.marks.on_remove(range);
+    }
+
+    /// The change log, once `:Changelog start` has begun one.
+    pub fn changelog(&self) -> Option<&ChangeLog> {
+        self.changelog.as_ref()
+    }
+
+    /// Merge a peer's changes into the text, the cursor staying with the
+    /// text it was on. Returns how many are still waiting on changes not
+    /// seen yet.
+    pub fn merge_changes(&mut self, ops: Vec<Op>) -> usize {
+        let Some(log) = &mut self.changelog else {
+            return ops.len();
+        };
+        let patches = log.merge(ops);
+        let waiting = log.pending();
+        for patch in patches {
+            match patch {
+                Patch::Insert { at, text } => {
+                    let len = text.chars().count();
+                    self.text.insert(at, &text);
+                    self.marks.on_insert(at, len);
+                    if at <= self.caret_abs {
+                        self.caret_abs += len;
+                    }
+                }
+                Patch::Delete(range) => {
+                    self.text.remove(range.clone());
+                    self.marks.on_remove(range.clone());
+                    if self.caret_abs > range.start {
+                        self.caret_abs = range.start.max(self.caret_abs - range.len());
+                    }
+                }
+            }
+            self.modified = true;
+        }
+        self.caret_abs = self.caret_abs.min(self.text.len_chars());
+        self.sync_visual_from_caret();
+        waiting
+    }
+
+    /// End (absolute char) of up to `count` graphemes starting at `at`,
+    /// stopping at the end of the line.
+    fn graphemes_on_line(&self, at: usize, count: usize) -> usize {
+        let eol = motion::line_end_abs(&self.text, self.text.char_to_line(at));
+        let mut end = at;
+        for _ in 0..count {
+            if end >= eol {
+                break;
+            }
+            end = next_grapheme_abs_char(&self.text, end).min(eol);
+        }
+        end
+    }
+
+    fn apply_operator(&mut self, op: Operator, span: operator::OpRange) {
+        let operator::OpRange {
+            mut range,
+            linewise,
+        } = span;
+        let mut yanked = self.text.slice(range.clone()).to_string();
+        if linewise && !yanked.ends_with('\n') {
+            // Last line without a line break: registers still hold whole lines.
+            yanked.push('\n');
+        }
+
+        match op {
+            Operator::Delete => {
+                self.registers.record_delete(yanked, linewise);
+                let len = self.text.len_chars();
+                if linewise && range.end == len && range.start > 0 {
+                    // Deleting through EOF also drops the line break before the span,
+                    // unless the span itself carried the final one.
+                    if self.text.char(len - 1) != '\n' {
+                        range.start -= 1;
+                        if range.start > 0 && self.text.char(range.start - 1) == '\r' {
+                            range.start -= 1;
+                        }
+                    }
+                }
+                let start = range.start;
+                self.remove_text(range);
+                self.caret_abs = if linewise {
+                    let len = self.text.len_chars();
+                    let mut row = self.text.char_to_line(start.min(len));
+                    // Not onto the phantom line after a final '\n'.
+                    if row > 0 && self.text.line_to_char(row) == len {
+                        row -= 1;
+                    }
+                    first_non_blank_abs(&self.text, row)
+                } else {
+                    start
+                };
+            }
+            Operator::Change => {
+                self.registers.record_delete(yanked, linewise);
+                if linewise {
+                    // `cc` / `S` empty the lines but keep one to type on,
+                    // and with autoindent the first one's indent as well.
+                    if self.options.autoindent || self.options.smartindent {
+                        let line = self.text.line(self.text.char_to_line(range.start));
+                        range.start += indent::leading(&line.to_string()).chars().count();
+                    }
+                    let last_row = self.text.char_to_line(range.end.saturating_sub(1));
+                    range.end = motion::line_end_abs(&self.text, last_row).max(range.start);
+                }
+                let start = range.start;
+                self.remove_text(range);
+                self.caret_abs = start;
+                self.mode = EditorMode::Insert;
+            }
+            Operator::Yank => {
+                self.registers.record_yank(yanked, linewise);
+                if !linewise {
+                    self.caret_abs = range.start;
+                } else if range.start < self.text.line_to_char(self.cursor_row) {
+                    self.caret_abs =
+                        first_non_blank_abs(&self.text, self.text.char_to_line(range.start));
+                }
+            }
+            Operator::Lowercase | Operator::Uppercase => {
+                let src = self.text.slice(range.clone()).to_string();
+                let mapped = if op == Operator::Lowercase {
+                    src.to_lowercase()
+                } else {
+                    src.to_uppercase()
+                };
+                let start = range.start;
+                self.remove_text(range);
+                self.insert_text(start, &mapped);
+                if !linewise {
+                    self.caret_abs = start;
+                }
+                self.caret_abs = self.caret_abs.min(self.text.len_chars());
+            }
+            Operator::Indent | Operator::Outdent => {
+                // Always whole lines, even after a characterwise motion.
+                let levels = if op == Operator::Indent { 1 } else { -1 };
+                let first = self.text.char_to_line(range.start);
+                let last = self
+                    .text
+                    .char_to_line(range.end.saturating_sub(1).max(range.start));
+                for row in first..=last {
+                    let bol = self.text.line_to_char(row);
+                    let body = first_non_blank_abs(&self.text, row);
+                    // Like Vim, `>>` leaves empty lines alone.
+                    if levels > 0 && body == motion::line_end_abs(&self.text, row) {
+                        continue;
+                    }
+                    let leading = self.text.slice(bol..body).to_string();
+                    let shifted = indent::shift(&leading, levels, &self.options);
+                    if shifted != leading {
+                        self.remove_text(bol..body);
+                        self.insert_text(bol, &shifted);
+                    }
+                }
+                self.caret_abs = first_non_blank_abs(&self.text, first);
+            }
+            Operator::Comment => {
+                if self.options.commentstring.is_empty() {
+                    return self.error("E474: 'commentstring' is empty");
+                }
+                // Whole lines too, as `gcw` comments the line it starts on
+                let first = self.text.char_to_line(range.start);
+                let last = self
+                    .text
+                    .char_to_line(range.end.saturating_sub(1).max(range.start));
+                let lines: Vec<String> = (first..=last)
+                    .map(|row| {
+                        let bol = self.text.line_to_char(row);
+                        let eol = motion::line_end_abs(&self.text, row);
+                        self.text.slice(bol..eol).to_string()
+                    })
+                    .collect();
+                let toggled = comment::toggle(&lines, &self.options.commentstring);
+                for (row, (old, new)) in (first..).zip(lines.iter().zip(&toggled)) {
+                    if old != new {
+                        let bol = self.text.line_to_char(row);
+                        self.remove_text(bol..motion::line_end_abs(&self.text, row));
+                        self.insert_text(bol, new);
+                    }
+                }
+                self.caret_abs = first_non_blank_abs(&self.text, first);
+            }
+        }
+        self.sync_visual_from_caret();
+        self.sync_caret_from_visual();
+    }
+
+    /// `:Eval`: the block under the cursor through its language's runner.
+    /// Its output goes in an `output` block under it, in place of the one
+    /// an earlier run left.
+    fn eval_block(&mut self) -> Result<(), String> {
+        let block = notebook::block_at(&self.text, self.cursor_row)
+            .ok_or("No fenced code block under the cursor")?;
+        let runner = notebook::runner(&block.lang, &self.runners).ok_or_else(|| {
+            format!(
+                "No runner for \"{}\": add one to [runner] in config.toml",
+                block.lang
+            )
+        })?;
+        let code = self.text.slice(
+            self.text.line_to_char(block.code.start)..self.text.line_to_char(block.code.end),
+        );
+        let output = shell::filter(runner, &code.to_string())?;
+        let below = self.text.line_to_char(block.close + 1);
+        if let Some(old) = notebook::output_after(&self.text, block.close) {
+            self.remove_text(below..self.text.line_to_char(old.end() + 1));
+        }
+        // After a closing fence with no line break, the output needs one.
+        let brk = if below > 0 && self.text.char(below - 1) != '\n' {
+            "\n"
+        } else {
+            ""
+        };
+        self.insert_text(below, &format!("{brk}{}", notebook::fenced(&output)));
+        let lines = output.lines().count();
+        self.info(format!(
+            "{lines} line{} of output",
+            if lines == 1 { "" } else { "s" }
+        ));
+        Ok(())
+    }
+
+    /// Rows of an ex line range, checked against the text.
+    fn rows(&self, range: LineRange) -> Result<RangeInclusive<usize>, &'static str> {
+        let last = self.text.len_lines() - 1;
+        let row = |address| match address {
+            Address::Line(line) => Ok(usize::saturating_sub(line, 1)),
+            Address::Current => Ok(self.cursor_row),
+            Address::Last => Ok(last),
+            Address::Mark(name) => match self.marks.get(name) {
+                Some(at) => Ok(self.text.char_to_line(at)),
+                None => Err("E20: Mark not set"),
+            },
+        };
+        let (from, to) = (row(range.from)?, row(range.to)?);
+        if to > last {
+            return Err("E16: Invalid range");
+        }
+        if from > to {
+            return Err("E493: Backwards range given");
+        }
+        Ok(from..=to)
+    }
+
+    /// `:retab` over `rows`, with indentation measured at `tabstop` and
+    /// rebuilt for the options now set. The lines go in one remove and
+    /// insert. Returns how many changed.
+    fn retab(&mut self, rows: RangeInclusive<usize>, tabstop: usize, all: bool) -> usize {
+        let start = self.text.line_to_char(*rows.start());
+        let end = self.text.line_to_char(*rows.end() + 1);
+        let mut changed = 0;
+        let text: String = self
+            .text
+            .slice(start..end)
+            .to_string()
+            .split_inclusive('\n')
+            .map(|line| {
+                let body = line.trim_start_matches([' ', '\t']);
+                let leading = &line[..line.len() - body.len()];
+                match indent::retab(leading, tabstop, &self.options, all) {
+                    Some(indent) => {
+                        changed += 1;
+                        indent + body
+                    }
+                    None => line.to_owned(),
+                }
+            })
+            .collect();
+        if changed > 0 {
+            self.remove_text(start..end);
+            self.insert_text(start, &text);
+            if rows.contains(&self.cursor_row) {
+                self.caret_abs = first_non_blank_abs(&self.text, self.cursor_row);
+                self.sync_visual_from_caret();
+            } else {
+                self.sync_caret_from_visual();
+            }
+        }
+        changed
+    }
+
+    /// Undo the last put and put `reg` in its place, the same way.
+    fn replace_put(&mut self, last: LastPut, reg: &Register, register: char, ring: Option<usize>) {
+        self.remove_text(last.range);
+        self.caret_abs = last.origin;
+        self.sync_visual_from_caret();
+        let range = self.put(reg, last.before, last.count);
+        self.last_put = Some(LastPut {
+            range,
+            register,
+            ring,
+            ..last
+        });
+    }
+
+    /// Put `reg` `count` times after (or before) the cursor and return the
+    /// inserted range. Lines go below / above the cursor line.
+    fn put(&mut self, reg: &Register, before: bool, count: usize) -> Range<usize> {
+        let body = reg.text.repeat(count.max(1));
+        let row = self.cursor_row;
+        let (at, text) = if !reg.linewise {
+            let at = if before {
+                self.caret_abs
+            } else {
+                self.graphemes_on_line(self.caret_abs, 1)
+            };
+            (at, body)
+        } else if before {
+            (self.text.line_to_char(row), body)
+        } else if row + 1 < self.text.len_lines() {
+            (self.text.line_to_char(row + 1), body)
+        } else {
+            // Last line has no break to put after: move the break to the front.
+            let lines = body.strip_suffix('\n').unwrap_or(&body);
+            (self.text.len_chars(), format!("\n{lines}"))
+        };
+
+        self.insert_text(at, &text);
+        let end = at + text.chars().count();
+        self.caret_abs = if reg.linewise {
+            first_non_blank_abs(
+                &self.text,
+                self.text.char_to_line(at) + usize::from(text.starts_with('\n')),
+            )
+        } else if text.contains('\n') {
+            at
+        } else {
+            // On the last grapheme put.
+            prev_grapheme_abs_char(&self.text, end).max(at)
+        };
+        self.sync_visual_from_caret();
+        at..end
+    }
+
+    // pub fn handle_key_event(mut self, ev: KeyEvent) -> Self {
+    //     let result = crate::input::map_key(ev, self.mode, &mut self.pending);
+    //     match result {
+    //         KeyMappingResult::Command(cmd) => {
+    //             self.pending.clear();
+    //
+    //             match cmd {
+    //                 _ => self.handle_command(cmd),
+    //             }
+    //         }
+    //         KeyMappingResult::UpdatePending => self,
+    //         KeyMappingResult::Noop => self,
+    //     }
+    // }
+
+    pub fn handle_command(&self, command: EditorCommand) -> Self {
+        // Not a command of the user's: the message and all stay.
+        if let EditorCommand::CursorHold = command {
+            let mut new = self.clone();
+            new.cursor_hold();
+            return new;
+        }
+        if let EditorCommand::Tick(now) = command {
+            let mut new = self.clone();
+            new.run_timers(now);
+            return new;
+        }
+        if self.preview.is_some() {
+            // Read-only until materialized; only scrolling and ex commands work.
+            let mut new = self.clone();
+            new.message = None;
+            if matches!(
+                command,
+                EditorCommand::MoveUp
+                    | EditorCommand::MoveDown
+                    | EditorCommand::Move { .. }
+                    | EditorCommand::Scroll { .. }
+                    | EditorCommand::AlignView(_)
+            ) {
+                new.scroll_preview(&command);
+                return new;
+            }
+            if !Self::previewable(&command) {
+                return new;
+            }
+            return new.dispatch(command);
+        }
+        let mut new = self.dispatch(command);
+        new.autosave_notes();
+        // Whatever the command did, keep the cursor on screen.
+        new.follow_cursor();
+        new
+    }
+
+    fn dispatch(&self, command: EditorCommand) -> Self {
+        let mut new = self.clone();
+        new.message = None;
+        new.suspend_requested = false;
+        if !matches!(command, EditorCommand::CompleteWord { .. }) {
+            new.completion = None;
+        }
+        let transformed = new.transformed.take();
+
+        #[cfg(debug_assertions)]
+        {
+            // Visual -> abs (what the next insert would compute from row/gcol)
+            let from_visual_abs = line_gcol_to_abs_char(&new.text, new.cursor_row, new.cursor_gcol);
+            // Single source of truth for insertion:
+            let anchor_abs = new.abs_char_at_cursor(); // == caret_abs
+
+            debug_assert_eq!(
+                from_visual_abs, anchor_abs,
+                "Drift at command entry: visual and insert anchor disagree"
+            );
+        }
+        #[cfg(debug_assertions)]
+        {
+            if let Some((row_cookie, bol_cookie)) = new.last_newline_bol.take() {
+                // Only check if we’re still on that line for the very next event
+                if new.cursor_row == row_cookie {
+                    let caret_b = new.text.char_to_byte(new.abs_char_at_cursor());
+                    if caret_b > bol_cookie {
+                        // Something inserted before the caret between Enter and this key.
+                        let span = new.text.byte_slice(bol_cookie..caret_b).to_string();
+                        panic!(
+                            "Auto-insert before caret after newline: {:?}",
+                            span.escape_debug().to_string()
+                        );
+                    }
+                }
+            }
+        }
+        let last_put = new.last_put.take();
+        match command {
+            EditorCommand::EnterInsertMode => {
+                new.mode = EditorMode::Insert;
+                return new;
+            }
+
+            // ── Insert entry variants: place the caret, then insert ──────────────────
+            EditorCommand::Append
+            | EditorCommand::AppendLineEnd
+            | EditorCommand::InsertLineStart => {
+                let row = new.cursor_row;
+                new.caret_abs = match command {
+                    EditorCommand::Append => {
+                        motion::apply(&new.text, new.caret_abs, Motion::Right, 1, &new.options)
+                    }
+                    EditorCommand::AppendLineEnd => motion::line_end_abs(&new.text, row),
+                    _ => first_non_blank_abs(&new.text, row),
+                };
+                new.sync_visual_from_caret();
+                new.clear_desired_vcol();
+                new.mode = EditorMode::Insert;
+                return new;
+            }
+
+            EditorCommand::EnterNormalMode => {
+                new.input = None;
+                new.picker = None;
+                new.mode = EditorMode::Normal;
+                return new;
+            }
+
+            // ── Replace mode: overwrite graphemes, Backspace restores them ──────────
+            EditorCommand::EnterReplaceMode => {
+                new.overwritten.clear();
+                new.mode = EditorMode::Replace;
+            }
+
+            // ── Visual mode: whole lines from the anchor to the caret ────────────────
+            EditorCommand::EnterVisualLine => {
+                new.selection_anchor = new.cursor_row;
+                new.mode = EditorMode::Visual;
+            }
+            EditorCommand::OperateSelection(op) => {
+                let Some(rows) = new.selection() else {
+                    return new;
+                };
+                new.mode = EditorMode::Normal;
+                let span = operator::OpRange {
+                    range: operator::rows_range(&new.text, *rows.start(), *rows.end()),
+                    linewise: true,
+                };
+                new.apply_operator(op, span);
+                new.clear_desired_vcol();
+            }
+            EditorCommand::OverwriteChar(c) => {
+                let at = new.caret_abs;
+                // A line break is inserted, never overwritten; neither is anything past EOL.
+                let end = if c == '\n' {
+                    at
+                } else {
+                    new.graphemes_on_line(at, 1)
+                };
+                let old = (end > at).then(|| new.text.slice(at..end).to_string());
+                new.remove_text(at..end);
+                new.insert_text(at, c.encode_utf8(&mut [0u8; 4]));
+                new.overwritten.push(old);
+                new.caret_abs = at + 1;
+                new.sync_visual_from_caret();
+                new.sync_caret_from_visual();
+                new.clear_desired_vcol();
+            }
+            EditorCommand::RestoreOverwritten => {
+                let here = new.caret_abs;
+                let prev = prev_grapheme_abs_char(&new.text, here);
+                match new.overwritten.pop() {
+                    Some(old) => {
+                        new.remove_text(prev..here);
+                        if let Some(old) = old {
+                            new.insert_text(prev, &old);
+                        }
+                        new.caret_abs = prev;
+                    }
+                    // Nothing typed here: just step left, staying on the line.
+                    None if here > new.text.line_to_char(new.cursor_row) => new.caret_abs = prev,
+                    None => {}
+                }
+                new.sync_visual_from_caret();
+                new.clear_desired_vcol();
+            }
+
+            // ── Horizontal, grapheme‑aware ────────────────────────────────────────────
+            EditorCommand::MoveLeft => {
+                let here = new.caret_abs;
+                let prev = prev_grapheme_abs_char(&new.text, here);
+                new.caret_abs = prev;
+                new.sync_visual_from_caret();
+                new.set_cursor_from_abs_char(prev);
+                new.clear_desired_vcol();
+            }
+
+            EditorCommand::MoveRight => {
+                let here = new.caret_abs;
+                let next = next_grapheme_abs_char(&new.text, here);
+                new.caret_abs = next;
+                new.sync_visual_from_caret();
+                new.clear_desired_vcol();
+            }
+
+            // ── Vertical, grapheme‑aware (keep desired_vcol like Vim) ────────────────
+            EditorCommand::MoveUp => {
+                if new.cursor_row > 0 {
+                    new.set_desired_vcol();
+                    new.cursor_row -= 1;
+                    let tgt = new.desired_vcol.unwrap();
+                    new.cursor_gcol = new.gcol_at_vcol(new.cursor_row, tgt);
+                    new.sync_caret_from_visual();
+                }
+                new.clear_desired_vcol();
+            }
+            EditorCommand::MoveDown => {
+                if new.cursor_row + 1 < new.text.len_lines() {
+                    new.set_desired_vcol();
+                    new.cursor_row += 1;
+                    let tgt = new.desired_vcol.unwrap();
+                    new.cursor_gcol = new.gcol_at_vcol(new.cursor_row, tgt);
+                    new.sync_caret_from_visual();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Insert: cursor is grapheme‑based; edits happen at char indices ───────
+            EditorCommand::CompleteWord { forward } => {
+                if !matches!(new.mode, EditorMode::Insert) {
+                    return new;
+                }
+                if new.completion.is_none() {
+                    let isk = &new.options.iskeyword;
+                    let caret = new.caret_abs;
+                    let line_start = new.text.line_to_char(new.cursor_row);
+                    let typed: Vec<char> = new.text.slice(line_start..caret).chars().collect();
+                    let len = typed.iter().rev().take_while(|&&c| isk.contains(c)).count();
+                    let prefix: String = typed[typed.len() - len..].iter().collect();
+                    let others = new.buffers.iter().map(|b| &b.text);
+                    let items = completion::harvest(&new.text, caret, others, &prefix, isk);
+                    if items.is_empty() {
+                        new.error("Pattern not found");
+                        return new;
+                    }
+                    new.completion = Some(Completion::new(caret - len, prefix, items));
+                }
+                let menu = new.completion.as_mut().expect("just made");
+                let shown = menu.current().chars().count();
+                menu.step(forward);
+                let (start, word) = (menu.start, menu.current().to_owned());
+                new.remove_text(start..start + shown);
+                new.insert_text(start, &word);
+                new.caret_abs = start + word.chars().count();
+                new.sync_visual_from_caret();
+                new.clear_desired_vcol();
+            }
+            EditorCommand::InsertChar(c) => {
+                let at = new.caret_abs; // single truth
+
+                if c == '\n' {
+                    let at = new.caret_abs;
+                    // A dos file gets CRLF, so lines never mix breaks by accident.
+                    let brk = new.file_format.line_ending.as_str();
+                    new.insert_text(at, brk);
+                    // Move caret to just after the inserted break (BOL of next line)
+                    new.caret_abs = at + brk.len();
+                    new.sync_visual_from_caret();
+
+                    #[cfg(debug_assertions)]
+                    {
+                        let bol_b = new.text.line_to_byte(new.cursor_row);
+                        new.last_newline_bol = Some((new.cursor_row, bol_b));
+                    }
+
+                    new.clear_desired_vcol();
+                    return new; // early return so we don't fall through
+                } else {
+                    if let Some(next) = new.prose_transform(c, at) {
+                        new.caret_abs = next;
+                        new.sync_visual_from_caret();
+                        new.clear_desired_vcol();
+                        return new;
+                    }
+                    let typed = if new.options.autopairs && matches!(new.mode, EditorMode::Insert) {
+                        let before = at.checked_sub(1).map(|i| new.text.char(i));
+                        pairs::typed(c, before, new.text.get_char(at))
+                    } else {
+                        pairs::Typed::Plain
+                    };
+                    match typed {
+                        pairs::Typed::Over => {}
+                        pairs::Typed::Pair(close) => new.insert_text(at, &format!("{c}{close}")),
+                        pairs::Typed::Plain => {
+                            let mut buf = [0u8; 4];
+                            new.insert_text(at, c.encode_utf8(&mut buf));
+                        }
+                    }
+
+                    let next = next_grapheme_abs_char(&new.text, at);
+                    new.caret_abs = next;
+                    new.sync_visual_from_caret();
+                    new.clear_desired_vcol();
+                }
+            }
+            // ── Single-grapheme edits under the caret (never cross the line break) ──
+            EditorCommand::ReplaceChar { ch, count } => {
+                let here = new.caret_abs;
+                let end = new.graphemes_on_line(here, count);
+                let replaced = new.text.slice(here..end).to_string();
+                // Like Vim, do nothing unless there are `count` graphemes to replace.
+                if replaced.graphemes(true).count() == count {
+                    let with: String = std::iter::repeat_n(ch, count).collect();
+                    new.remove_text(here..end);
+                    new.insert_text(here, &with);
+                    // Caret rests on the last replaced grapheme.
+                    new.caret_abs = here + count - 1;
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+            EditorCommand::ToggleCase { count } => {
+                let here = new.caret_abs;
+                let end = new.graphemes_on_line(here, count);
+                if end > here {
+                    let toggled: String = new
+                        .text
+                        .slice(here..end)
+                        .chars()
+                        .flat_map(|c| {
+                            if c.is_lowercase() {
+                                c.to_uppercase().collect::<Vec<_>>()
+                            } else if c.is_uppercase() {
+                                c.to_lowercase().collect()
+                            } else {
+                                vec![c]
+                            }
+                        })
+                        .collect();
+                    new.remove_text(here..end);
+                    new.insert_text(here, &toggled);
+                    new.caret_abs = here + toggled.chars().count();
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Join lines: J squeezes the seam to one space, gJ keeps it as is ─────
+            EditorCommand::JoinLines { count, spaces } => {
+                // `J` and `2J` both join two lines; `3J` joins three.
+                for _ in 1..count.max(2) {
+                    let row = new.cursor_row;
+                    let next_bol = new.text.line_to_char(row + 1);
+                    // No next line (the phantom line after a final '\n' doesn't count).
+                    if row + 1 >= new.text.len_lines() || next_bol == new.text.len_chars() {
+                        break;
+                    }
+                    let eol = motion::line_end_abs(&new.text, row);
+                    let mut end = next_bol;
+                    let mut sep = "";
+                    if spaces {
+                        end = first_non_blank_abs(&new.text, row + 1);
+                        let next_empty = end == motion::line_end_abs(&new.text, row + 1);
+                        let bol = new.text.line_to_char(row);
+                        let ends_blank = eol > bol && matches!(new.text.char(eol - 1), ' ' | '\t');
+                        if !(next_empty || ends_blank || new.text.char(end) == ')') {
+                            sep = " ";
+                        }
+                    }
+                    new.remove_text(eol..end);
+                    new.insert_text(eol, sep);
+                    new.caret_abs = eol;
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Open a line below / above and start inserting on it ─────────────────
+            EditorCommand::OpenLineBelow | EditorCommand::OpenLineAbove => {
+                let row = new.cursor_row;
+                let eol = motion::line_end_abs(&new.text, row);
+                // Reuse the current line's break so CRLF files stay CRLF.
+                let brk = if new.text.slice(eol..new.text.line_to_char(row + 1)) == "\r\n" {
+                    "\r\n"
+                } else {
+                    "\n"
+                };
+                let line = new.text.slice(new.text.line_to_char(row)..eol).to_string();
+                if matches!(command, EditorCommand::OpenLineBelow) {
+                    let indent = indent::next_line(&line, &new.filetype, &new.options);
+                    new.insert_text(eol, &format!("{brk}{indent}"));
+                    new.caret_abs = eol + brk.len() + indent.chars().count();
+                } else {
+                    // Above, the line's own indent, never a level more
+                    let indent = if new.options.autoindent || new.options.smartindent {
+                        indent::leading(&line)
+                    } else {
+                        ""
+                    };
+                    let bol = new.text.line_to_char(row);
+                    new.insert_text(bol, &format!("{indent}{brk}"));
+                    new.caret_abs = bol + indent.chars().count();
+                }
+                new.sync_visual_from_caret();
+                new.mode = EditorMode::Insert;
+                new.clear_desired_vcol();
+            }
+
+            EditorCommand::InsertNewline => {
+                let at = new.caret_abs; // single truth
+                let bol = new.text.line_to_char(new.text.char_to_line(at));
+                let before = new.text.slice(bol..at).to_string();
+                let indent = indent::next_line(&before, &new.filetype, &new.options);
+                if new.options.autoindent || new.options.smartindent {
+                    // The blanks moving down give way to the new indent
+                    let blanks = new
+                        .text
+                        .chars_at(at)
+                        .take_while(|c| matches!(c, ' ' | '\t'))
+                        .count();
+                    if blanks > 0 {
+                        new.remove_text(at..at + blanks);
+                    }
+                }
+                let brk = new.file_format.line_ending.as_str();
+                new.insert_text(at, &format!("{brk}{indent}"));
+                // Move caret to just after the newline and the indent
+                let next = next_grapheme_abs_char(&new.text, at);
+                new.caret_abs = next + indent.chars().count();
+                new.sync_visual_from_caret();
+
+                #[cfg(debug_assertions)]
+                {
+                    // Past the indent, which autoindent puts there on purpose
+                    let bol_b = new.text.char_to_byte(new.caret_abs);
+                    new.last_newline_bol = Some((new.cursor_row, bol_b));
+                }
+
+                new.clear_desired_vcol();
+            }
+
+            // ── Backspace: delete previous grapheme cluster ───────────────────────────
+            EditorCommand::Backspace => {
+                let here = new.caret_abs;
+                if let Some((start, t)) = transformed.filter(|(start, t)| {
+                    let end = *start + t.text.chars().count();
+                    end == here && new.text.slice(*start..end) == t.text.as_str()
+                }) {
+                    // Straight after a `prose` transform: what was typed
+                    new.remove_text(start..here);
+                    new.insert_text(start, &t.literal);
+                    new.caret_abs = start + t.literal.chars().count();
+                    new.sync_visual_from_caret();
+                } else if here > 0 {
+                    let del = if new.text.char(here - 1) == '\n' {
+                        if here >= 2 && new.text.char(here - 2) == '\r' {
+                            Some((here - 2, here))
+                        } else {
+                            Some((here - 1, here))
+                        }
+                    } else if new.text.char(here - 1) == '\r' {
+                        Some((here - 1, here))
+                    } else {
+                        None
+                    };
+
+                    let pair = new.options.autopairs
+                        && matches!(new.mode, EditorMode::Insert)
+                        && pairs::empty_pair(
+                            Some(new.text.char(here - 1)),
+                            new.text.get_char(here),
+                        );
+                    if let Some((start, end)) = del {
+                        new.remove_text(start..end);
+                        new.caret_abs = start;
+                    } else if pair {
+                        new.remove_text(here - 1..here + 1);
+                        new.caret_abs = here - 1;
+                    } else {
+                        let prev = prev_grapheme_abs_char(&new.text, here);
+                        new.remove_text(prev..here);
+                        new.caret_abs = prev;
+                    }
+
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Delete: delete next grapheme cluster ───────────────────────────
+            EditorCommand::Delete => {
+                let here = new.caret_abs;
+                let len = new.text.len_chars();
+
+                if here < len {
+                    if new.text.char(here) == '\n' {
+                        new.remove_text(here..here + 1);
+                    } else if new.text.char(here) == '\r' {
+                        if here + 1 < len && new.text.char(here + 1) == '\n' {
+                            new.remove_text(here..here + 2); // CRLF as one
+                        } else {
+                            new.remove_text(here..here + 1);
+                        }
+                    } else {
+                        // delete next grapheme
+                        let next = next_grapheme_abs_char(&new.text, here);
+                        let end = if next > here { next } else { here + 1 };
+                        new.remove_text(here..end);
+                    }
+                    // caret stays at `here`
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Motions (counted, line-bound horizontally) ───────────────────────────
+            EditorCommand::Move { motion, count } => match motion {
+                // Vertical moves keep the desired-column behaviour of MoveUp/MoveDown.
+                Motion::Up | Motion::Down => {
+                    for _ in 0..count.max(1) {
+                        new = new.handle_command(if motion == Motion::Up {
+                            EditorCommand::MoveUp
+                        } else {
+                            EditorCommand::MoveDown
+                        });
+                    }
+                }
+                _ => {
+                    new.caret_abs =
+                        motion::apply(&new.text, new.caret_abs, motion, count, &new.options);
+                    new.sync_visual_from_caret();
+                    new.clear_desired_vcol();
+                }
+            },
+
+            // ── Scrolling: view and cursor move together ─────────────────────────────
+            EditorCommand::Scroll { scroll, count } => {
+                let last_row = new.text.len_lines() - 1;
+                let row = new.view.scroll(scroll, count, new.cursor_row, last_row);
+                new.cursor_row = row;
+                new.cursor_gcol = new.clamp_gcol_on_row(row, new.cursor_gcol);
+                new.sync_caret_from_visual();
+                new.clear_desired_vcol();
+            }
+
+            EditorCommand::AlignView(how) => {
+                new.view.align(new.cursor_row, how);
+            }
+
+            // ── Operators: resolve the target span, then act on it ───────────────────
+            EditorCommand::Operate { op, target, count } => {
+                if let Some(span) =
+                    operator::resolve(&new.text, new.caret_abs, op, target, count, &new.options)
+                {
+                    new.apply_operator(op, span);
+                } else if op == Operator::Change {
+                    // Nothing to remove (e.g. `C` on an empty line): still start typing.
+                    new.mode = EditorMode::Insert;
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Put and register cycling ─────────────────────────────────────────────
+            EditorCommand::Put {
+                register,
+                before,
+                count,
+            } => {
+                if let Some(reg) = new.registers.get(register).cloned() {
+                    let origin = new.caret_abs;
+                    let range = new.put(&reg, before, count);
+                    new.last_put = Some(LastPut {
+                        range,
+                        origin,
+                        before,
+                        count,
+                        register,
+                        ring: new.registers.ring_position(&reg),
+                    });
+                }
+                new.clear_desired_vcol();
+            }
+            EditorCommand::CyclePut { older } => {
+                let Some(last) = last_put else {
+                    return new;
+                };
+                // `"1` is the newest numbered register; the others cycle into it.
+                let n = last.register.to_digit(10).filter(|&n| n > 0).unwrap_or(0);
+                let next = if older { n + 1 } else { n.saturating_sub(1) };
+                let next = char::from_digit(next, 10).filter(|&c| c != '0');
+                match next.and_then(|c| Some((c, new.registers.get(c)?.clone()))) {
+                    Some((register, reg)) => {
+                        let ring = new.registers.ring_position(&reg);
+                        new.replace_put(last, &reg, register, ring);
+                    }
+                    // Ran off either end: stay put, but keep cycling possible.
+                    None => new.last_put = Some(last),
+                }
+                new.clear_desired_vcol();
+            }
+            EditorCommand::YankPop { older } => {
+                let Some(last) = last_put else {
+                    return new;
+                };
+                // Text that isn't in the ring (long since pushed out) starts
+                // the walk at its newest entry.
+                let next = match (last.ring, older) {
+                    (Some(i), true) => Some(i + 1),
+                    (None, true) => Some(0),
+                    (Some(i), false) => i.checked_sub(1),
+                    (None, false) => None,
+                };
+                match next.and_then(|i| Some((i, new.registers.ring(i)?.clone()))) {
+                    Some((i, reg)) => new.replace_put(last, &reg, '"', Some(i)),
+                    None => new.last_put = Some(last),
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Merge conflicts ──────────────────────────────────────────────────────
+            EditorCommand::NextConflict | EditorCommand::PrevConflict => {
+                let row = new.cursor_row;
+                let conflicts = find_conflicts(&new.text);
+                let target = if matches!(command, EditorCommand::NextConflict) {
+                    conflicts.iter().find(|c| c.start > row)
+                } else {
+                    conflicts.iter().rev().find(|c| c.start < row)
+                };
+                if let Some(c) = target {
+                    new.caret_abs = new.text.line_to_char(c.start);
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+            EditorCommand::ResolveConflict(side) => {
+                // Replace the whole block in one remove + insert.
+                if let Some(c) = conflict_at(&new.text, new.cursor_row) {
+                    let range = c.char_range(&new.text);
+                    let kept = c.resolved(&new.text, side);
+                    let start = range.start;
+                    new.remove_text(range);
+                    new.insert_text(start, &kept);
+                    new.caret_abs = start;
+                    new.sync_visual_from_caret();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Unified diffs: write the hunk under the cursor to its file ──────────
+            EditorCommand::ApplyHunk { reverse } => {
+                if let Some(hunk) = patch::hunk_at(&new.text, new.cursor_row) {
+                    // On failure the file is left untouched.
+                    if let Err(e) = hunk.apply_to_file(reverse) {
+                        new.error(e);
+                    }
+                }
+            }
+
+            // ── Pickers: type to filter, Enter runs the selected item ───────────────
+            EditorCommand::OpenPicker(name) => {
+                let Some(source) = picker::source(&name) else {
+                    new.error(format!("E475: Invalid argument: {name}"));
+                    return new;
+                };
+                let ctx = picker::Context {
+                    cwd: std::env::current_dir().unwrap_or_default(),
+                    file: new.file_path.clone(),
+                };
+                new.picker = Some(Picker::new(source, ctx));
+                new.mode = EditorMode::Picker;
+            }
+            EditorCommand::PickerInput(c) => {
+                if let Some(picker) = &mut new.picker {
+                    picker.push(c);
+                }
+            }
+            EditorCommand::PickerBackspace => {
+                if let Some(picker) = &mut new.picker {
+                    picker.backspace();
+                }
+            }
+            EditorCommand::PickerStep(by) => {
+                if let Some(picker) = &mut new.picker {
+                    picker.step(by);
+                }
+            }
+            EditorCommand::PickerAccept => {
+                // Enter picks from the matches for everything typed.
+                let action = new.picker.take().and_then(|mut p| {
+                    p.wait();
+                    p.accept()
+                });
+                new.mode = EditorMode::Normal;
+                for cmd in action.unwrap_or_default() {
+                    new = new.handle_command(cmd);
+                }
+            }
+            EditorCommand::GotoTag(tag) => {
+                let mut jumped = new.clone();
+                match jumped.jump_to_tag(&tag) {
+                    Ok(()) => new = jumped,
+                    Err(e) => new.error(e),
+                }
+            }
+
+            // ── File tree: a sidebar listing the working directory ──────────────────
+            EditorCommand::ToggleTree => {
+                if new.tree.take().is_some() {
+                    if matches!(new.mode, EditorMode::Tree) {
+                        new.mode = EditorMode::Normal;
+                    }
+                } else {
+                    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                    new.tree = Some(FileTree::new(cwd));
+                    new.mode = EditorMode::Tree;
+                }
+                new.follow_cursor();
+            }
+            EditorCommand::SwitchPane => {
+                if new.tree.is_some() {
+                    new.mode = match new.mode {
+                        EditorMode::Tree => EditorMode::Normal,
+                        _ => EditorMode::Tree,
+                    };
+                }
+            }
+            EditorCommand::TreeStep(by) => {
+                let height = new.view.height;
+                if let Some(tree) = &mut new.tree {
+                    tree.step(by);
+                    tree.follow(height);
+                }
+            }
+            EditorCommand::TreeOpen => {
+                let height = new.view.height;
+                let Some(tree) = &mut new.tree else {
+                    return new;
+                };
+                let Some(entry) = tree.selected() else {
+                    return new;
+                };
+                if entry.dir {
+                    tree.toggle();
+                    tree.follow(height);
+                } else {
+                    let path = entry.path.clone();
+                    new.mode = EditorMode::Normal;
+                    return new.handle_command(EditorCommand::Edit(Some(path)));
+                }
+            }
+            EditorCommand::TreeCollapse => {
+                let height = new.view.height;
+                if let Some(tree) = &mut new.tree {
+                    tree.collapse();
+                    tree.follow(height);
+                }
+            }
+            EditorCommand::TreeRefresh => {
+                let height = new.view.height;
+                if let Some(tree) = &mut new.tree {
+                    tree.refresh();
+                    tree.follow(height);
+                }
+            }
+
+            // ── Command line ─────────────────────────────────────────────────────────
+            EditorCommand::EnterCommandMode => {
+                new.input = Some(LineInput::new(crate::ex::COMMAND_LINE));
+                new.mode = EditorMode::Command;
+            }
+            EditorCommand::CmdlineInput(c) => {
+                if let Some(input) = &mut new.input {
+                    input.push(c);
+                }
+            }
+            EditorCommand::CmdlineBackspace => {
+                // Backspace on an empty line leaves command mode, like Vim.
+                if !new.input.as_mut().is_some_and(LineInput::backspace) {
+                    new.input = None;
+                    new.mode = EditorMode::Normal;
+                }
+            }
+            EditorCommand::CmdlineHistory { older } => {
+                if let Some(input) = &mut new.input {
+                    input.walk_history(&new.cmdline_history, older);
+                }
+            }
+            EditorCommand::CmdlineComplete => {
+                if let Some(input) = &mut new.input {
+                    input.complete();
+                }
+            }
+            EditorCommand::CmdlineExecute => {
+                new.mode = EditorMode::Normal;
+                let Some(input) = new.input.take() else {
+                    return new;
+                };
+                match input.finish(&mut new.cmdline_history) {
+                    Ok(line) => {
+                        let cmd = crate::ex::parse(&line).expect("validated");
+                        return new.handle_command(cmd);
+                    }
+                    Err(e) => new.error(e),
+                }
+            }
+            EditorCommand::PromptAnswer(answer) => {
+                let Some(prompt) = new.prompt.clone() else {
+                    new.mode = EditorMode::Normal;
+                    return new;
+                };
+                let valid = match &prompt.action {
+                    PromptAction::PickTag(tags) => {
+                        answer == 'c' || (answer as usize).wrapping_sub('1' as usize) < tags.len()
+                    }
+                    PromptAction::WhitespaceOnly(_) => matches!(answer, 'y' | 'n' | 'c' | 'd'),
+                    _ => matches!(answer, 'y' | 'n' | 'c'),
+                };
+                if !valid {
+                    return new; // keep asking
+                }
+                if let (PromptAction::WhitespaceOnly(path), 'd') = (&prompt.action, answer) {
+                    // The diff goes above the question, which stays.
+                    let target = path.as_ref().or(new.file_path.as_ref()).expect("a file");
+                    if let Some((disk, ours)) = new.whitespace_only_change(target) {
+                        let diff = diff::unified(
+                            &diff::visible_whitespace(&disk),
+                            &diff::visible_whitespace(&ours),
+                            &target.display().to_string(),
+                            "buffer",
+                        );
+                        new.ask_whitespace_only(path.clone(), Some(diff));
+                    }
+                    return new;
+                }
+                new.prompt = None;
+                new.mode = EditorMode::Normal;
+                match (prompt.action, answer) {
+                    (PromptAction::SaveAndQuit, 'y') => match new.write(None) {
+                        Ok(()) => new.quit_requested = true,
+                        Err(e) => new.error(e),
+                    },
+                    (PromptAction::SaveAndQuit, 'n') => new.quit_requested = true,
+                    (PromptAction::Overwrite(path), 'y') => {
+                        if let Err(e) = new.write(Some(path)) {
+                            new.error(e);
+                        }
+                    }
+                    (PromptAction::WhitespaceOnly(path), 'y') => {
+                        if let Err(e) = new.write(path) {
+                            new.error(e);
+                        }
+                    }
+                    (PromptAction::PickTag(tags), n) if n != 'c' => {
+                        let tag = &tags[n as usize - '1' as usize];
+                        let mut jumped = new.clone();
+                        match jumped.jump_to_tag(tag) {
+                            Ok(()) => new = jumped,
+                            Err(e) => new.error(e),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            EditorCommand::Exit { force, confirm } => {
+                // Hidden buffers' changes aren't asked about, only kept
+                let hidden = new.buffers.iter().find(|b| b.modified).map(Buffer::name);
+                if force || !new.modified && hidden.is_none() {
+                    new.quit_requested = true;
+                } else if let Some(name) = hidden.as_ref().filter(|_| !new.modified) {
+                    new.error(format!(
+                        "E162: No write since last change for buffer \"{name}\""
+                    ));
+                } else if (confirm || new.options.confirm) && hidden.is_none() {
+                    let name = new
+                        .file_path
+                        .as_ref()
+                        .map_or("Untitled".to_owned(), |p| p.display().to_string());
+                    new.ask(
+                        format!("Save changes to \"{name}\"?"),
+                        PromptAction::SaveAndQuit,
+                    );
+                } else {
+                    new.error("E37: No write since last change (add ! to override)");
+                }
+            }
+            EditorCommand::Quit => {
+                new.quit_requested = true;
+            }
+            EditorCommand::Suspend => new.suspend_requested = true,
+            // ── Tags: Ctrl-] jumps, Ctrl-T comes back ───────────────────────────────
+            EditorCommand::JumpToTag(name) => {
+                let found = match name {
+                    Some(name) => Ok(name),
+                    None => new.word_under_cursor(),
+                }
+                .and_then(|name| Ok((tags::lookup(&name, new.file_path())?, name)));
+                let (found, name) = match found {
+                    Ok(found) => found,
+                    Err(e) => {
+                        new.error(e);
+                        return new;
+                    }
+                };
+                let mut jumped = new.clone();
+                match jumped.jump_to_tag(&found[0]) {
+                    Ok(()) => new = jumped,
+                    Err(e) => {
+                        new.error(e);
+                        return new;
+                    }
+                }
+                if found.len() > 1 {
+                    new.info(format!(
+                        "tag 1 of {} for {name}; :tselect lists them",
+                        found.len()
+                    ));
+                }
+            }
+            EditorCommand::SelectTag(name) => {
+                let name = match name.map_or_else(|| new.word_under_cursor(), Ok) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        new.error(e);
+                        return new;
+                    }
+                };
+                match tags::lookup(&name, new.file_path()) {
+                    Ok(found) => new.ask_tag(&name, found),
+                    Err(e) => new.error(e),
+                }
+            }
+            EditorCommand::PopTag => {
+                let Some(back) = new.tag_stack.last().cloned() else {
+                    new.error("E73: Tag stack empty");
+                    return new;
+                };
+                if let Some(file) = &back.file {
+                    if let Err(e) = new.switch_file(file) {
+                        new.error(e);
+                        return new;
+                    }
+                }
+                new.tag_stack.pop();
+                new.caret_abs = back.caret.min(new.text.len_chars());
+                new.sync_visual_from_caret();
+            }
+            EditorCommand::ShowTagStack => {
+                let mut lines = vec!["  # TO tag         FROM line  in file".to_owned()];
+                for (i, back) in new.tag_stack.iter().enumerate() {
+                    let file = back
+                        .file
+                        .as_ref()
+                        .map_or("[No Name]".into(), |p| p.display().to_string());
+                    let (n, name, row) = (i + 1, &back.name, back.row + 1);
+                    lines.push(format!("{n:>3} {name:<16} {row:>9}  {file}"));
+                }
+                new.message = Some(Message {
+                    text: lines.join("\n"),
+                    error: false,
+                });
+            }
+            EditorCommand::GenerateTags => match tags::generate() {
+                Ok(()) => new.info("tags written".into()),
+                Err(e) => new.error(e),
+            },
+
+            // ── Quickfix: places from a command's output, gone to in turn ──────
+            EditorCommand::Grep(args) => {
+                let cmd = format!("grep -rn --exclude-dir=.git --exclude-dir=target {args}");
+                let dir = std::env::current_dir().unwrap_or_default();
+                match shell::run(&cmd) {
+                    Ok(output) => {
+                        let list = QuickFix::new(format!(":grep {args}"), dir, &output);
+                        if list.entries.is_empty() {
+                            new.error(format!("E480: No match: {args}"));
+                        } else {
+                            new.set_quickfix(list);
+                        }
+                    }
+                    Err(e) => new.error(e),
+                }
+            }
+            EditorCommand::Make(args) => {
+                let cmd = format!("{} {args}", new.options.makeprg);
+                let cmd = cmd.trim();
+                let dir = std::env::current_dir().unwrap_or_default();
+                match shell::run(cmd) {
+                    Ok(output) => new.set_quickfix(QuickFix::new(format!(":!{cmd}"), dir, &output)),
+                    Err(e) => new.error(e),
+                }
+            }
+            EditorCommand::QuickfixFromBuffer => {
+                let dir = std::env::current_dir().unwrap_or_default();
+                let list = QuickFix::new(":cbuffer".into(), dir, &new.text.to_string());
+                new.set_quickfix(list);
+            }
+            EditorCommand::QuickfixStep(by) => {
+                if let Err(e) = new.quickfix_go(|list| list.step(by)) {
+                    new.error(e);
+                }
+            }
+            EditorCommand::QuickfixGo(n) => {
+                if let Err(e) = new.quickfix_go(|list| list.select(n)) {
+                    new.error(e);
+                }
+            }
+            EditorCommand::QuickfixPane(open) => {
+                if open && new.quickfix.is_none() {
+                    new.error("E42: No Errors");
+                    return new;
+                }
+                new.quickfix_open = open;
+                new.fit_view();
+            }
+
+            // ── Snapshots: named copies of the text to go back to or compare ───────
+            EditorCommand::SaveSnapshot(name) => {
+                new.snapshots.insert(name.clone(), new.text.clone());
+                new.info(format!("Snapshot \"{name}\" saved"));
+            }
+            EditorCommand::RestoreSnapshot(name) => {
+                let Some(saved) = new.snapshots.get(&name).cloned() else {
+                    new.error(format!("E94: No matching snapshot: {name}"));
+                    return new;
+                };
+                // The text it replaces can be restored in turn.
+                new.snapshots.insert("previous".into(), new.text.clone());
+                let row = new.cursor_row;
+                new.remove_text(0..new.text.len_chars());
+                new.insert_text(0, &saved.to_string());
+                let row = row.min(new.text.len_lines() - 1);
+                new.caret_abs = first_non_blank_abs(&new.text, row);
+                new.sync_visual_from_caret();
+                new.clear_desired_vcol();
+                new.info(format!("Snapshot \"{name}\" restored"));
+            }
+            EditorCommand::DiffSnapshot(name) => {
+                let Some(saved) = new.snapshots.get(&name) else {
+                    new.error(format!("E94: No matching snapshot: {name}"));
+                    return new;
+                };
+                let diff = diff::unified(
+                    &saved.to_string(),
+                    &new.text.to_string(),
+                    &format!("snapshot {name}"),
+                    "buffer",
+                );
+                // Shown like `:messages`, not logged
+                new.message = Some(Message {
+                    text: if diff.is_empty() {
+                        format!("No differences from snapshot \"{name}\"")
+                    } else {
+                        diff.trim_end().to_owned()
+                    },
+                    error: false,
+                });
+            }
+            EditorCommand::StartTimer { every, command } => {
+                new.start_timer(every, Some(new.buffer), command);
+                new.info(format!("timer {} started", new.timers_made));
+            }
+            EditorCommand::StopTimer(None) => new.timers.clear(),
+            EditorCommand::StopTimer(Some(id)) => {
+                let before = new.timers.len();
+                new.timers.retain(|t| t.id != id);
+                if new.timers.len() == before {
+                    new.error(format!("E475: Invalid argument: {id}"));
+                }
+            }
+            EditorCommand::ListTimers => {
+                let lines: Vec<String> = new
+                    .timers
+                    .iter()
+                    .map(|t| {
+                        let in_buffer = t.buffer.map_or("any".to_owned(), |b| b.to_string());
+                        let every = timers::show_interval(t.every);
+                        format!(
+                            "{:>3}  every {every:<6} buffer {in_buffer:<4} {}",
+                            t.id, t.command
+                        )
+                    })
+                    .collect();
+                let text = if lines.is_empty() {
+                    "No timers".to_owned()
+                } else {
+                    lines.join("\n")
+                };
+                new.message = Some(Message { text, error: false });
+            }
+            EditorCommand::StartChangeLog => {
+                if new.changelog.is_none() {
+                    use std::hash::BuildHasher;
+                    // Peers need agents of their own: random where there's
+                    // randomness to be had, and the clock mixed in.
+                    let agent = std::collections::hash_map::RandomState::new()
+                        .hash_one(new.clock)
+                        .max(1);
+                    new.changelog = Some(ChangeLog::new(agent, &new.text.to_string()));
+                }
+                new.info("Recording changes".to_owned());
+            }
+            EditorCommand::StopChangeLog => new.changelog = None,
+            EditorCommand::WriteChangeLog(path) => {
+                let Some(log) = &new.changelog else {
+                    new.error("No change log (:Changelog start)");
+                    return new;
+                };
+                let lines: String = log.ops().iter().map(|op| op.to_line() + "\n").collect();
+                let written = log.ops().len();
+                match std::fs::write(&path, lines) {
+                    Ok(()) => new.info(format!("\"{}\" {written} changes written", path.display())),
+                    Err(e) => new.error(format!("E212: Can't open file for writing: {e}")),
+                }
+            }
+            EditorCommand::MergeChangeLog(path) => {
+                if new.changelog.is_none() {
+                    new.error("No change log (:Changelog start)");
+                    return new;
+                }
+                let text = match std::fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        new.error(format!("E484: Can't open file {}: {e}", path.display()));
+                        return new;
+                    }
+                };
+                let mut ops = Vec::new();
+                for (n, line) in text.lines().enumerate() {
+                    match Op::parse(line) {
+                        Some(op) => ops.push(op),
+                        None => {
+                            new.error(format!("E474: Invalid argument: line {}", n + 1));
+                            return new;
+                        }
+                    }
+                }
+                let waiting = new.merge_changes(ops);
+                new.info(match waiting {
+                    0 => "Changes merged".to_owned(),
+                    n => format!("Changes merged; {n} wait for changes not seen yet"),
+                });
+            }
+            EditorCommand::ShowChangeLog => {
+                let text = match &new.changelog {
+                    Some(log) => format!(
+                        "{} changes as agent {}, {} waiting",
+                        log.ops().len(),
+                        log.agent(),
+                        log.pending()
+                    ),
+                    None => "No change log".to_owned(),
+                };
+                new.message = Some(Message { text, error: false });
+            }
+
+            EditorCommand::ListSnapshots => {
+                let names: Vec<&str> = new.snapshots.keys().map(String::as_str).collect();
+                let text = if names.is_empty() {
+                    "No snapshots".to_owned()
+                } else {
+                    names.join("\n")
+                };
+                new.message = Some(Message { text, error: false });
+            }
+
+            // Not logged itself, so repeated `:messages` don't pile up.
+            EditorCommand::ShowMessages => {
+                if !new.messages.is_empty() {
+                    new.message = Some(Message {
+                        text: new.messages.render(),
+                        error: false,
+                    });
+                }
+            }
+
+            EditorCommand::LoadTheme(path) => match Theme::load(&path) {
+                Ok(theme) => new.theme = Rc::new(theme),
+                Err(e) => new.error(e),
+            },
+            EditorCommand::SetFlag { name, value } => {
+                if value && options::full_name(&name) == Some("follow") {
+                    new.follow();
+                } else if let Some(flag) = new.options.flag_mut(&name) {
+                    *flag = value;
+                }
+            }
+            EditorCommand::SetListChars(chars) => new.options.listchars = chars,
+            EditorCommand::SetGutter(columns) => new.options.gutter = columns,
+            EditorCommand::SetIsKeyword(isk) => new.options.iskeyword = isk,
+            EditorCommand::SetCommentString(cms) => new.options.commentstring = cms,
+            EditorCommand::SetMakePrg(prg) => new.options.makeprg = prg,
+            EditorCommand::ChangeIsKeyword { parts, add } => {
+                match new.options.iskeyword.changed(&parts, add) {
+                    Ok(isk) => new.options.iskeyword = isk,
+                    Err(e) => new.error(e),
+                }
+            }
+            EditorCommand::SetColorColumn(columns) => new.options.colorcolumn = columns,
+            EditorCommand::SetNumber { name, value } => {
+                if let Some(number) = new.options.number_mut(&name) {
+                    *number = value;
+                }
+            }
+            EditorCommand::SetFiletype(filetype) => new.set_filetype(&filetype),
+            EditorCommand::ToggleFlag(name) => {
+                if let Some(flag) = new.options.flag_mut(&name) {
+                    *flag = !*flag;
+                }
+            }
+            EditorCommand::SetMany(settings) => {
+                for cmd in settings {
+                    new = new.dispatch(cmd);
+                    if new.message.as_ref().is_some_and(|m| m.error) {
+                        break;
+                    }
+                }
+            }
+            EditorCommand::ShowOptions(names) => {
+                let shown: Vec<String> = if names.is_empty() {
+                    new.options.changed()
+                } else {
+                    names.iter().filter_map(|n| new.show_option(n)).collect()
+                };
+                match shown.as_slice() {
+                    [one] => new.info(one.clone()),
+                    _ => new.info(
+                        std::iter::once("--- Options ---".to_owned())
+                            .chain(shown.iter().map(|o| format!("  {o}")))
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    ),
+                }
+            }
+
+            // ── File format: `:set ff=` rewrites every line break, `:set bomb` ───────
+            EditorCommand::SetLineEnding(to) => new.set_line_ending(to),
+            EditorCommand::SetBom(bom) => {
+                new.modified |= new.file_format.bom != bom;
+                new.file_format.bom = bom;
+            }
+
+            // ── Files ────────────────────────────────────────────────────────────────
+            // Failed loads and writes leave the buffer as it was.
+            EditorCommand::Edit(path) => {
+                let Some(path) = path.or_else(|| new.file_path.clone()) else {
+                    new.error("E32: No file name");
+                    return new;
+                };
+                let mut loaded = new.clone();
+                let result = if new.file_path.as_ref() == Some(&path) && path.exists() {
+                    loaded.reload()
+                } else {
+                    loaded.open_buffer(path.clone())
+                };
+                match result {
+                    Ok(()) => new = loaded,
+                    Err(e) => new.error(format!("E484: Can't open file {}: {e}", path.display())),
+                }
+            }
+
+            EditorCommand::OpenNotes => {
+                if new.notes {
+                    return new;
+                }
+                let path = match std::env::current_dir() {
+                    Ok(cwd) => notes::path(&cwd),
+                    Err(e) => Err(e.to_string()),
+                };
+                let path = match path {
+                    Ok(path) => path,
+                    Err(e) => {
+                        new.error(e);
+                        return new;
+                    }
+                };
+                let mut loaded = new.clone();
+                match loaded.open_buffer(path.clone()) {
+                    Ok(()) => {
+                        new = loaded;
+                        new.notes = true;
+                    }
+                    Err(e) => new.error(format!("E484: Can't open file {}: {e}", path.display())),
+                }
+            }
+            // handle_command takes it first, keeping the message.
+            EditorCommand::CursorHold => new.cursor_hold(),
+            EditorCommand::Tick(now) => new.run_timers(now),
+            EditorCommand::Retab {
+                range,
+                tabstop,
+                force,
+            } => {
+                let rows = match range.map(|range| new.rows(range)) {
+                    Some(Ok(rows)) => rows,
+                    Some(Err(e)) => {
+                        new.error(e);
+                        return new;
+                    }
+                    None => 0..=new.text.len_lines() - 1,
+                };
+                let measured = new.options.tabstop;
+                if let Some(tabstop) = tabstop {
+                    new.options.tabstop = tabstop;
+                }
+                let changed = new.retab(rows, measured, force);
+                let s = if changed == 1 { "" } else { "s" };
+                new.info(format!("{changed} line{s} changed"));
+            }
+            EditorCommand::EvalBlock => {
+                if let Err(e) = new.eval_block() {
+                    new.error(e);
+                }
+            }
+            EditorCommand::Pipe {
+                range,
+                command,
+                force,
+            } => {
+                if new.modified && !force {
+                    new.error("E37: No write since last change (add ! to override)");
+                    return new;
+                }
+                let rows = match range.map(|range| new.rows(range)) {
+                    Some(Ok(rows)) => rows,
+                    Some(Err(e)) => {
+                        new.error(e);
+                        return new;
+                    }
+                    None => 0..=new.text.len_lines() - 1,
+                };
+                let (start, end) = (*rows.start(), *rows.end() + 1);
+                let input = new
+                    .text
+                    .slice(new.text.line_to_char(start)..new.text.line_to_char(end))
+                    .to_string();
+                match shell::filter(&command, &input) {
+                    Ok(output) => new.load_scratch(&output),
+                    Err(e) => new.error(e),
+                }
+            }
+            EditorCommand::Export {
+                range,
+                format,
+                path,
+                force,
+            } => {
+                let rows = match range.map(|range| new.rows(range)) {
+                    Some(Ok(rows)) => rows,
+                    Some(Err(e)) => {
+                        new.error(e);
+                        return new;
+                    }
+                    None => 0..=new.text.len_lines() - 1,
+                };
+                let (start, end) = (*rows.start(), *rows.end() + 1);
+                let text = new
+                    .text
+                    .slice(new.text.line_to_char(start)..new.text.line_to_char(end))
+                    .to_string();
+                let lines: Vec<&str> = text.lines().collect();
+                let syntax = new.file_path.as_deref().and_then(syntax::for_path);
+                let title = new
+                    .file_path
+                    .as_ref()
+                    .map_or("[No Name]".to_owned(), |p| p.display().to_string());
+                let out = export::render(
+                    &lines,
+                    syntax,
+                    &new.theme,
+                    format,
+                    &title,
+                    new.options.tabstop,
+                );
+                match path {
+                    Some(path) if path.exists() && !force => {
+                        new.error("E13: File exists (add ! to override)");
+                    }
+                    Some(path) => match std::fs::write(&path, out) {
+                        Ok(()) => new.info(format!("\"{}\" written", path.display())),
+                        Err(e) => new.error(format!("E212: Can't open file for writing: {e}")),
+                    },
+                    None => new.open_scratch(&out),
+                }
+            }
+            EditorCommand::CheckHealth => {
+                if new.modified {
+                    new.error("E37: No write since last change (add ! to override)");
+                    return new;
+                }
+                let cwd = std::env::current_dir().unwrap_or_default();
+                new.load_scratch(&health::report(&cwd));
+            }
+            EditorCommand::Write {
+                path,
+                force,
+                confirm,
+            } => {
+                // Writing over some other existing file needs `!` (else E13) or a yes.
+                let clobbers = path
+                    .as_ref()
+                    .is_some_and(|p| new.file_path.as_ref() != Some(p) && p.exists());
+                // Forced writes and other files go ahead without the check.
+                let own = path.is_none() || path == new.file_path;
+                let whitespace_only = !force
+                    && own
+                    && new.options.whitespacewarn
+                    && new
+                        .file_path
+                        .as_ref()
+                        .is_some_and(|p| new.whitespace_only_change(p).is_some());
+                if whitespace_only {
+                    new.ask_whitespace_only(path, None);
+                } else if !clobbers || force {
+                    if let Err(e) = new.write(path) {
+                        new.error(e);
+                    }
+                } else if confirm || new.options.confirm {
+                    let path = path.expect("clobbers implies a path");
+                    new.ask(
+                        format!("Overwrite existing file \"{}\"?", path.display()),
+                        PromptAction::Overwrite(path),
+                    );
+                } else {
+                    new.error("E13: File exists (add ! to override)");
+                }
+            }
+
+            // ── Buffers: `:ls`, `:b`, `:bn` / `:bp` and `:bd` ──────────────────────
+            EditorCommand::ListBuffers => {
+                let shown = new.hidden();
+                let mut all: Vec<&Buffer> = new.buffers.iter().chain([&shown]).collect();
+                all.sort_by_key(|b| b.number);
+                let text = all
+                    .iter()
+                    .map(|b| {
+                        let flags = if b.number == new.buffer { "%a" } else { " h" };
+                        let plus = if b.modified { '+' } else { ' ' };
+                        let line = b.text.char_to_line(b.caret_abs.min(b.text.len_chars())) + 1;
+                        format!(
+                            "{:>3} {flags} {plus} \"{}\"  line {line}",
+                            b.number,
+                            b.name()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                new.message = Some(Message { text, error: false });
+            }
+            EditorCommand::ShowBuffer(arg) if arg.is_empty() => {}
+            EditorCommand::ShowBuffer(arg) => match new.find_buffer(&arg) {
+                Ok(number) => new.switch_buffer(number),
+                Err(e) => new.error(e),
+            },
+            EditorCommand::StepBuffer(count) => new.step_buffer(count),
+            EditorCommand::DeleteBuffer { name, force } => {
+                let found = match name {
+                    Some(name) => new.find_buffer(&name),
+                    None => Ok(new.buffer),
+                };
+                if let Err(e) = found.and_then(|number| new.delete_buffer(number, force)) {
+                    new.error(e);
+                }
+            }
+
+            // ── Marks ────────────────────────────────────────────────────────────────
+            EditorCommand::SetMark(name) => {
+                new.marks.set(name, new.caret_abs);
+            }
+            EditorCommand::JumpToMark { name, linewise } => {
+                if let Some(abs) = new.marks.get(name) {
+                    let abs = abs.min(new.text.len_chars());
+                    new.caret_abs = if linewise {
+                        first_non_blank_abs(&new.text, new.text.char_to_line(abs))
+                    } else {
+                        abs
+                    };
+                    // Snap onto a grapheme boundary in case edits split the mark's cluster.
+                    new.sync_visual_from_caret();
+                    new.sync_caret_from_visual();
+                }
+                new.clear_desired_vcol();
+            }
+
+            // ── Byte offsets: `:goto` / `go`, 1-based like Vim's ────────────────────
+            EditorCommand::GotoByte(byte) => {
+                let byte = byte.saturating_sub(1).min(new.text.len_bytes());
+                // The char holding that byte, then back to its grapheme's start.
+                new.caret_abs = new.text.byte_to_char(byte);
+                new.sync_visual_from_caret();
+                new.sync_caret_from_visual();
+                new.clear_desired_vcol();
+            }
+        }
+
+        new
+    }
+}
+
+/// A `:set` argument made on `options`, for the kinds a filetype may set.
+/// Returns the argument that would set the option back.
+fn apply_setting(options: &mut Options, arg: &str) -> Option<String> {
+    let cmd = crate::ex::setting(arg).ok()?;
+    let name = match &cmd {
+        EditorCommand::SetFlag { name, .. }
+        | EditorCommand::SetNumber { name, .. }
+        | EditorCommand::ToggleFlag(name) => name.as_str(),
+        EditorCommand::SetColorColumn(_) => "colorcolumn",
+        EditorCommand::SetListChars(_) => "listchars",
+        EditorCommand::SetGutter(_) => "gutter",
+        EditorCommand::SetIsKeyword(_) | EditorCommand::ChangeIsKeyword { .. } => "iskeyword",
+        EditorCommand::SetCommentString(_) => "commentstring",
+        EditorCommand::SetMakePrg(_) => "makeprg",
+        _ => return None,
+    };
+    let before = options.show(name)?;
+    match cmd {
+        EditorCommand::SetFlag { name, value } => *options.flag_mut(&name)? = value,
+        EditorCommand::SetNumber { name, value } => *options.number_mut(&name)? = value,
+        EditorCommand::ToggleFlag(name) => {
+            let flag = options.flag_mut(&name)?;
+            *flag = !*flag;
+        }
+        EditorCommand::SetColorColumn(columns) => options.colorcolumn = columns,
+        EditorCommand::SetListChars(chars) => options.listchars = chars,
+        EditorCommand::SetGutter(columns) => options.gutter = columns,
+        EditorCommand::SetIsKeyword(isk) => options.iskeyword = isk,
+        EditorCommand::SetCommentString(cms) => options.commentstring = cms,
+        EditorCommand::SetMakePrg(prg) => options.makeprg = prg,
+        EditorCommand::ChangeIsKeyword { parts, add } => {
+            options.iskeyword = options.iskeyword.changed(&parts, add).ok()?
+        }
+        _ => return None,
+    }
+    Some(before)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conflicts::ConflictSide;
     use crate::input::EditorCommand;
+    use crate::operator::Target;
+    use crate::textobject::TextObject;
+    use crate::view::{Align, Scroll};
+
+    fn type_str(mut ed: Editor, s: &str) -> Editor {
+        for ch in s.chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        ed
+    }
+
+    #[test]
+    fn test_insert_char() {
+        let editor = Editor::new();
+        let updated = editor.handle_command(EditorCommand::InsertChar('a'));
+
+        assert_eq!(updated.text.line(0).to_string(), "a");
+        assert_eq!(updated.cursor_gcol, 1);
+        assert_eq!(updated.cursor_row, 0);
+    }
+
+    #[test]
+    fn test_move_down_and_up() {
+        let mut editor = Editor::new();
+        editor = editor.handle_command(EditorCommand::InsertChar('a'));
+        editor = editor.handle_command(EditorCommand::InsertChar('\n'));
+        editor = editor.handle_command(EditorCommand::InsertChar('b'));
+
+        // After typing "a\nb", we have two lines: "a\n" and "b"
+        // MoveDown should keep us at last line (row 1)
+        let down = editor.handle_command(EditorCommand::MoveDown);
+        assert_eq!(down.cursor_row, 1);
+
+        let up = down.handle_command(EditorCommand::MoveUp);
+        assert_eq!(up.cursor_row, 0);
+    }
+
+    #[test]
+    fn emoji_is_one_step() {
+        // "a👨‍👩‍👧‍👦b" — family emoji is a single grapheme made of multiple scalars.
+        let mut ed = Editor::new();
+        for ch in "a👨‍👩‍👧‍👦b".chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+
+        // Move left once: should jump from after 'b' to start of 'b'
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        assert_eq!(ed.cursor_row, 0);
+        assert_eq!(ed.cursor_gcol, 2); // a, [emoji], |b|
+
+        // Move left once more: should skip whole emoji in one step
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        assert_eq!(ed.cursor_gcol, 1); // a, |[emoji], b
+    }
+
+    #[test]
+    fn combining_mark_is_one_step() {
+        // "e\u{0301}" = "é" precomposed via combining acute
+        let mut ed = Editor::new();
+        for ch in "e\u{0301}".chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        assert_eq!(ed.cursor_gcol, 1); // one grapheme on the first line
+
+        // Backspace should delete the whole grapheme
+        ed = ed.handle_command(EditorCommand::Backspace);
+        assert_eq!(ed.cursor_gcol, 0);
+        assert_eq!(ed.text.line(0).to_string(), "");
+    }
+    #[test]
+    fn backspace_clears_combining_grapheme_and_resets_col() {
+        let mut ed = Editor::new();
+        for ch in "e\u{0301}".chars() {
+            // "é"
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        // One grapheme on the line
+        assert_eq!(ed.cursor_gcol, 1);
+
+        // Backspace should delete the full grapheme and move to col 0
+        ed = ed.handle_command(EditorCommand::Backspace);
+        assert_eq!(ed.text.line(0).to_string(), "");
+        assert_eq!(ed.cursor_row, 0);
+        assert_eq!(ed.cursor_gcol, 0);
+    }
+    #[test]
+    fn newline_moves_caret_to_bol_and_next_char_is_col0() {
+        // Start: ""
+        let mut ed = Editor::new();
+
+        // Type "hello", move left twice to end up after 'l'
+        ed = type_str(ed, "hello");
+        ed = ed.handle_command(EditorCommand::MoveLeft); // after 'l'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // after second 'l'
+
+        // Press Enter: caret_abs must move to start of the next line (col 0)
+        ed = ed.handle_command(EditorCommand::InsertChar('\n'));
+
+        // Assert visual & anchor agree on BOL
+        assert_eq!(ed.cursor_gcol, 0, "visual gcol should be 0 after newline");
+        let caret_byte = ed.text.char_to_byte(ed.abs_char_at_cursor());
+        let bol_byte = ed.text.line_to_byte(ed.cursor_row);
+        assert_eq!(
+            caret_byte, bol_byte,
+            "caret_abs must be at BOL after newline"
+        );
+
+        // Now type 'X' — it MUST appear at column 0 on the new line
+        ed = ed.handle_command(EditorCommand::InsertChar('X'));
+
+        let line = ed.text.line(ed.cursor_row).to_string();
+        assert!(
+            line.starts_with('X'),
+            "expected 'X' at col 0, got line {:?}",
+            line
+        );
+        assert_eq!(
+            ed.cursor_gcol, 1,
+            "cursor should advance to col 1 after typing 'X'"
+        );
+    }
+
+    #[test]
+    fn vertical_move_resyncs_caret_abs_then_inserts_there() {
+        // Buffer: "aa\nbb\ncc"
+        let mut ed = Editor::new();
+        ed = type_str(ed, "aa\nbb\ncc");
+
+        // Put caret at end of first line: row 0, gcol 2
+        // (We are currently at end of buffer; move up twice, then right to clamp)
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::MoveUp);
+
+        // MoveDown once: should land at row 1, same gcol (min with line length)
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        assert_eq!(ed.cursor_row, 1);
+
+        // Type 'Z' — must go into line 1 at the current visual gcol
+        let before = ed.text.line(ed.cursor_row).to_string();
+        ed = ed.handle_command(EditorCommand::InsertChar('Z'));
+        let after = ed.text.line(ed.cursor_row).to_string();
+        assert_ne!(before, after, "line should change after insert");
+        assert!(
+            after.contains('Z'),
+            "expected 'Z' inserted on the target line"
+        );
+    }
+
+    #[test]
+    fn backspace_across_newline_moves_to_prev_line_end() {
+        // Make two lines: "abc\n"
+        let mut ed = Editor::new();
+        ed = type_str(ed, "abc\n");
+
+        // Now at start of second (empty) line; Backspace should delete the '\n'
+        // and move caret to end of "abc"
+        ed = ed.handle_command(EditorCommand::Backspace);
+
+        assert_eq!(ed.text.to_string(), "abc");
+        assert_eq!(ed.cursor_row, 0);
+        assert_eq!(ed.cursor_gcol, 3);
+
+        // Also check the anchor is at EOL in bytes
+        let caret_byte = ed.text.char_to_byte(ed.abs_char_at_cursor());
+        let eol_byte = ed.text.line_to_byte(0) + ed.text.line(0).len_bytes();
+        assert_eq!(
+            caret_byte, eol_byte,
+            "caret_abs should end up at EOL of previous line"
+        );
+    }
+
+    #[test]
+    fn emoji_is_single_grapheme_for_moves_and_backspace() {
+        // "a👨‍👩‍👧‍👦b" — family emoji is one grapheme
+        let mut ed = Editor::new();
+        ed = type_str(ed, "a");
+        for ch in "👨‍👩‍👧‍👦".chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        ed = ed.handle_command(EditorCommand::InsertChar('b'));
+        assert_eq!(ed.cursor_row, 0);
+
+        // MoveLeft: b -> [emoji]
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        let (row, gcol) = (ed.cursor_row, ed.cursor_gcol);
+        // MoveLeft again: [emoji] -> a (skip entire cluster)
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        assert_eq!(ed.cursor_row, row);
+        assert_eq!(ed.cursor_gcol, gcol - 1, "emoji should count as one step");
+
+        // MoveRight back onto emoji then Backspace once: removes the whole emoji
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        let len_before = ed.text.len_chars();
+        ed = ed.handle_command(EditorCommand::Backspace);
+        let len_after = ed.text.len_chars();
+        assert!(
+            len_after < len_before,
+            "one backspace should remove entire emoji cluster"
+        );
+    }
+
+    #[test]
+    fn delete_over_newline_joins_lines_without_moving_caret_abs() {
+        // Build: "foo\nbar"
+        let mut ed = Editor::new();
+        for ch in "foo\nbar".chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        // Caret is at end (after 'r'). Move left 4 times:
+        // r -> a -> b -> (start of line 1) -> just before '\n'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // after 'a'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // after 'b'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // after '\n' (row 1, col 0)
+        ed = ed.handle_command(EditorCommand::MoveLeft); // before '\n' (row 0, col 3)
+
+        // Sanity: we are at EOL of first line
+        assert_eq!(ed.cursor_row, 0);
+        assert_eq!(ed.cursor_gcol, 3);
+
+        // Delete should remove the newline and join lines.
+        ed = ed.handle_command(EditorCommand::Delete);
+
+        assert_eq!(ed.text.to_string(), "foobar");
+        // Caret stays at the same absolute char position (now before the old 'b')
+        assert_eq!(ed.cursor_row, 0);
+        assert_eq!(ed.cursor_gcol, 3);
+    }
+
+    #[test]
+    fn delete_at_eol_joins_unix() {
+        let mut ed = Editor::new();
+        for ch in "foo\nbar".chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        // Move to just before '\n'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // 'a'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // 'b'
+        ed = ed.handle_command(EditorCommand::MoveLeft); // at row1 col0 (after '\n')
+        ed = ed.handle_command(EditorCommand::MoveLeft); // before '\n' (row0 col3)
+
+        ed = ed.handle_command(EditorCommand::Delete);
+        assert_eq!(ed.text.to_string(), "foobar");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 3));
+    }
+
+    #[test]
+    fn delete_at_eol_joins_crlf() {
+        let mut ed = Editor::new();
+        // simulate CRLF explicitly
+        for ch in "foo\r\nbar".chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(ch));
+        }
+        // go to before '\r'
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+
+        ed = ed.handle_command(EditorCommand::Delete);
+        assert_eq!(ed.text.to_string(), "foobar");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 3));
+    }
+
+    #[test]
+    fn marks_follow_edits_before_them() {
+        // "abc\ndef", mark 'a' on the 'e'
+        let mut ed = type_str(Editor::new(), "abc\ndef");
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::SetMark('a'));
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+
+        // Insert text on the line above: the mark must still point at 'e'
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = type_str(ed, "XY");
+        ed = ed.handle_command(EditorCommand::JumpToMark {
+            name: 'a',
+            linewise: false,
+        });
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+        assert_eq!(ed.text.char(ed.caret_abs), 'e');
+
+        // Delete a char before the mark on the same line
+        ed = ed.handle_command(EditorCommand::Backspace);
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::JumpToMark {
+            name: 'a',
+            linewise: false,
+        });
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+        assert_eq!(ed.text.char(ed.caret_abs), 'e');
+    }
+
+    #[test]
+    fn linewise_mark_jump_lands_on_first_non_blank() {
+        let mut ed = type_str(Editor::new(), "one\n   two");
+        ed = ed.handle_command(EditorCommand::SetMark('b'));
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::JumpToMark {
+            name: 'b',
+            linewise: true,
+        });
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 3));
+
+        // Unset marks are a no-op
+        let before = (ed.cursor_row, ed.cursor_gcol);
+        ed = ed.handle_command(EditorCommand::JumpToMark {
+            name: 'z',
+            linewise: false,
+        });
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), before);
+    }
+
+    #[test]
+    fn delete_inner_quote_and_around_word() {
+        let mut ed = type_str(Editor::new(), "say \"hello world\" now");
+        // caret at end; walk back into the string
+        for _ in 0..8 {
+            ed = ed.handle_command(EditorCommand::MoveLeft);
+        }
+        let inner = TextObject::from_key(false, '"').unwrap();
+        ed = ed.handle_command(EditorCommand::Operate {
+            op: Operator::Delete,
+            target: Target::TextObject(inner),
+            count: 1,
+        });
+        assert_eq!(ed.text.to_string(), "say \"\" now");
+        assert_eq!(ed.cursor_gcol, 5);
+
+        let word = TextObject::from_key(true, 'w').unwrap();
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::Operate {
+            op: Operator::Delete,
+            target: Target::TextObject(word),
+            count: 1,
+        });
+        assert_eq!(ed.text.to_string(), "say \"\"");
+    }
+
+    #[test]
+    fn conflict_navigation_and_take_theirs() {
+        let src = "a\n<<<<<<< HEAD\nmine\n=======\nyours\n>>>>>>> topic\nb\n";
+        let mut ed = type_str(Editor::new(), src);
+        for _ in 0..7 {
+            ed = ed.handle_command(EditorCommand::MoveUp);
+        }
+        assert_eq!(ed.cursor_row, 0);
+        ed = ed.handle_command(EditorCommand::NextConflict);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+        // No further conflict: ]x stays put
+        ed = ed.handle_command(EditorCommand::NextConflict);
+        assert_eq!(ed.cursor_row, 1);
+
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        ed = ed.handle_command(EditorCommand::ResolveConflict(ConflictSide::Theirs));
+        assert_eq!(ed.text.to_string(), "a\nyours\nb\n");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+    }
+
+    #[test]
+    fn command_line_runs_ex_commands() {
+        let src = "<<<<<<< HEAD\nmine\n=======\nyours\n>>>>>>> topic\n";
+        let mut ed = type_str(Editor::new(), src);
+        ed = ed.handle_command(EditorCommand::PrevConflict);
+        assert_eq!(ed.cursor_row, 0);
+
+        ed = ed.handle_command(EditorCommand::EnterCommandMode);
+        assert!(matches!(ed.mode(), EditorMode::Command));
+        for c in "ConflictTake both".chars() {
+            ed = ed.handle_command(EditorCommand::CmdlineInput(c));
+        }
+        ed = ed.handle_command(EditorCommand::CmdlineExecute);
+        assert!(matches!(ed.mode(), EditorMode::Normal));
+        assert_eq!(ed.text.to_string(), "mine\nyours\n");
+        assert!(ed.input().is_none());
+
+        // Up brings it back
+        ed = ed.handle_command(EditorCommand::EnterCommandMode);
+        ed = ed.handle_command(EditorCommand::CmdlineHistory { older: true });
+        assert_eq!(ed.input().unwrap().text(), "ConflictTake both");
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+
+        ed = ed.handle_command(EditorCommand::EnterCommandMode);
+        ed = ed.handle_command(EditorCommand::CmdlineInput('q'));
+        ed = ed.handle_command(EditorCommand::CmdlineInput('!'));
+        ed = ed.handle_command(EditorCommand::CmdlineExecute);
+        assert!(ed.quit_requested());
+    }
+
+    fn op(ed: Editor, op: Operator, target: Target, count: usize) -> Editor {
+        ed.handle_command(EditorCommand::Operate { op, target, count })
+    }
+
+    #[test]
+    fn delete_word_and_lines() {
+        let mut ed = type_str(Editor::new(), "one two three\nfour\nfive");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::WordForward), 1);
+        assert_eq!(ed.text.to_string(), "two three\nfour\nfive");
+
+        // dd on the last line (no trailing newline) removes the break before it
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileEnd,
+            count: 1,
+        });
+        ed = op(ed, Operator::Delete, Target::Line, 1);
+        assert_eq!(ed.text.to_string(), "two three\nfour");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+
+        // 2dd from the top leaves an empty buffer
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        ed = op(ed, Operator::Delete, Target::Line, 2);
+        assert_eq!(ed.text.to_string(), "");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 0));
+    }
+
+    #[test]
+    fn yank_keeps_text_and_case_operators_rewrite() {
+        let mut ed = type_str(Editor::new(), "héllo wörld");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        ed = op(ed, Operator::Yank, Target::Motion(Motion::WordEnd), 1);
+        assert_eq!(ed.text.to_string(), "héllo wörld");
+        assert_eq!(
+            ed.registers.unnamed.as_ref().map(|r| r.text.as_str()),
+            Some("héllo")
+        );
+
+        ed = op(ed, Operator::Uppercase, Target::Motion(Motion::LineEnd), 1);
+        assert_eq!(ed.text.to_string(), "HÉLLO WÖRLD");
+        assert_eq!(ed.cursor_gcol, 0);
+        ed = op(ed, Operator::Lowercase, Target::Line, 1);
+        assert_eq!(ed.text.to_string(), "héllo wörld");
+    }
+
+    #[test]
+    fn change_enters_insert_mode_in_place() {
+        let mut ed = type_str(Editor::new(), "  keep\nold line\nlast");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = op(ed, Operator::Change, Target::Line, 1);
+        assert!(matches!(ed.mode(), EditorMode::Insert));
+        assert_eq!(ed.text.to_string(), "  keep\n\nlast");
+        ed = type_str(ed, "new");
+        assert_eq!(ed.text.to_string(), "  keep\nnew\nlast");
+
+        // The indent stays with autoindent, as in Vim, and goes without it
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = op(ed, Operator::Change, Target::Line, 1);
+        ed = type_str(ed, "kept");
+        assert_eq!(ed.text.to_string(), "  kept\nnew\nlast");
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        ed.options.autoindent = false;
+        ed = op(ed, Operator::Change, Target::Line, 1);
+        assert_eq!(ed.text.to_string(), "\nnew\nlast");
+
+        // C on an empty line has nothing to delete but still starts insert
+        let mut ed = type_str(Editor::new(), "a\n");
+        ed = op(ed, Operator::Change, Target::Motion(Motion::LineEnd), 1);
+        assert!(matches!(ed.mode(), EditorMode::Insert));
+
+        // ciw replaces just the word
+        let mut ed = type_str(Editor::new(), "foo bar");
+        let iw = TextObject::from_key(false, 'w').unwrap();
+        ed = op(ed, Operator::Change, Target::TextObject(iw), 1);
+        ed = type_str(ed, "baz");
+        assert_eq!(ed.text.to_string(), "foo baz");
+    }
+
+    #[test]
+    fn x_r_and_tilde_are_grapheme_aware() {
+        let mut ed = type_str(Editor::new(), "a👨‍👩‍👧‍👦e\u{0301}z");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::MoveRight);
+
+        // x removes the whole family emoji
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::Right), 1);
+        assert_eq!(ed.text.to_string(), "ae\u{0301}z");
+
+        // ~ upper-cases the base letter and keeps the combining mark with it
+        ed = ed.handle_command(EditorCommand::ToggleCase { count: 1 });
+        assert_eq!(ed.text.to_string(), "aE\u{0301}z");
+        assert_eq!(ed.cursor_gcol, 2);
+
+        // r replaces the whole cluster; 3rx fails with only one grapheme left
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::ReplaceChar { ch: 'o', count: 1 });
+        assert_eq!(ed.text.to_string(), "aoz");
+        assert_eq!(ed.cursor_gcol, 1);
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::ReplaceChar { ch: 'q', count: 3 });
+        assert_eq!(ed.text.to_string(), "aoz");
+    }
+
+    #[test]
+    fn counted_x_and_tilde_stop_at_line_end() {
+        let mut ed = type_str(Editor::new(), "abc\ndef");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::ToggleCase { count: 10 });
+        assert_eq!(ed.text.to_string(), "ABC\ndef");
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::Right), 5);
+        assert_eq!(ed.text.to_string(), "AB\ndef");
+    }
+
+    #[test]
+    fn open_line_below_and_above_keep_caret_in_sync() {
+        let mut ed = type_str(Editor::new(), "one\ntwo");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::OpenLineBelow);
+        assert!(matches!(ed.mode(), EditorMode::Insert));
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+        ed = type_str(ed, "mid");
+        assert_eq!(ed.text.to_string(), "one\nmid\ntwo");
+
+        ed = ed.handle_command(EditorCommand::OpenLineAbove);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+        ed = type_str(ed, "X");
+        assert_eq!(ed.text.to_string(), "one\nX\nmid\ntwo");
+
+        // `o` on the last line (no trailing newline) appends a line
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileEnd,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::OpenLineBelow);
+        ed = type_str(ed, "end");
+        assert_eq!(ed.text.to_string(), "one\nX\nmid\ntwo\nend");
+    }
+
+    #[test]
+    fn open_line_keeps_crlf_line_breaks() {
+        let mut ed = type_str(Editor::new(), "a\r\nb");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::OpenLineBelow);
+        ed = type_str(ed, "c");
+        assert_eq!(ed.text.to_string(), "a\r\nc\r\nb");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+    }
+
+    fn write_cmd(path: Option<PathBuf>) -> EditorCommand {
+        EditorCommand::Write {
+            path,
+            force: false,
+            confirm: false,
+        }
+    }
+
+    #[test]
+    fn open_write_and_reload_files() {
+        let dir = std::env::temp_dir().join(format!("mters-editor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("new.txt");
+
+        // Missing file: empty buffer, created on :w
+        let mut ed = Editor::open(path.clone()).unwrap();
+        assert_eq!(ed.text.len_chars(), 0);
+        ed = type_str(ed, "hello\n");
+        ed = ed.handle_command(write_cmd(None));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+
+        // :e reloads from disk; the caret stays on what replaced its line
+        std::fs::write(&path, "changed").unwrap();
+        ed = ed.handle_command(EditorCommand::Edit(None));
+        assert_eq!(ed.text.to_string(), "changed");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 0));
+
+        // :w {name} on an unnamed buffer names it
+        let other = dir.join("other.txt");
+        let mut ed = type_str(Editor::new(), "x");
+        ed = ed.handle_command(write_cmd(Some(other.clone())));
+        ed = ed.handle_command(write_cmd(None));
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "x");
+        assert_eq!(ed.file_path, Some(other));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn append_variants_place_caret_before_inserting() {
+        let mut ed = type_str(Editor::new(), "  a👨‍👩‍👧‍👦c");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+
+        ed = ed.handle_command(EditorCommand::InsertLineStart);
+        assert!(matches!(ed.mode(), EditorMode::Insert));
+        assert_eq!(ed.cursor_gcol, 2);
+
+        // `a` on the 'a' appends after it, `a` again skips the whole emoji
+        ed = ed.handle_command(EditorCommand::Append);
+        ed = type_str(ed, "-");
+        assert_eq!(ed.text.to_string(), "  a-👨‍👩‍👧‍👦c");
+        ed = ed.handle_command(EditorCommand::Append);
+        ed = type_str(ed, "+");
+        assert_eq!(ed.text.to_string(), "  a-👨‍👩‍👧‍👦+c");
+
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::AppendLineEnd);
+        ed = type_str(ed, "!");
+        assert_eq!(ed.text.to_string(), "  a-👨‍👩‍👧‍👦+c!");
+    }
+
+    #[test]
+    fn join_lines_with_and_without_spaces() {
+        let mut ed = type_str(Editor::new(), "fn f(\n    a,\n    b\n)\n");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::JoinLines {
+            count: 3,
+            spaces: true,
+        });
+        assert_eq!(ed.text.to_string(), "fn f( a, b\n)\n");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 8));
+
+        // No space before ')' and nothing to join after the last line
+        ed = ed.handle_command(EditorCommand::JoinLines {
+            count: 5,
+            spaces: true,
+        });
+        assert_eq!(ed.text.to_string(), "fn f( a, b)\n");
+
+        let mut ed = type_str(Editor::new(), "a\r\n  b");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::JoinLines {
+            count: 1,
+            spaces: false,
+        });
+        assert_eq!(ed.text.to_string(), "a  b");
+        assert_eq!(ed.cursor_gcol, 1);
+    }
+
+    #[test]
+    fn indent_and_outdent_lines() {
+        let mut ed = type_str(Editor::new(), "fn f() {\nx();\n\n\ty();\n}");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        ed = op(ed, Operator::Indent, Target::Line, 3);
+        assert_eq!(
+            ed.text.to_string(),
+            "fn f() {\n    x();\n\n            y();\n}"
+        );
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 4));
+
+        // A motion target still shifts whole lines; outdenting stops at column 0
+        ed = op(ed, Operator::Outdent, Target::Motion(Motion::Down), 2);
+        assert_eq!(ed.text.to_string(), "fn f() {\nx();\n\n        y();\n}");
+        ed = op(ed, Operator::Outdent, Target::Line, 1);
+        assert_eq!(ed.text.to_string(), "fn f() {\nx();\n\n        y();\n}");
+    }
+
+    #[test]
+    fn confirm_before_losing_changes_or_clobbering() {
+        let dir = std::env::temp_dir().join(format!("mters-confirm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        let other = dir.join("b.txt");
+        std::fs::write(&other, "keep me").unwrap();
+
+        let quit = |confirm| EditorCommand::Exit {
+            force: false,
+            confirm,
+        };
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed = ed.handle_command(quit(false));
+        assert!(ed.quit_requested(), "unmodified buffers quit freely");
+
+        // Modified: plain :q refuses, :confirm q asks
+        let mut ed = type_str(Editor::open(path.clone()).unwrap(), "new");
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        ed = ed.handle_command(quit(false));
+        assert!(!ed.quit_requested());
+        ed = ed.handle_command(quit(true));
+        assert!(matches!(ed.mode(), EditorMode::Prompt));
+        assert!(ed.prompt().unwrap().starts_with("Save changes to"));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('x'));
+        assert!(matches!(ed.mode(), EditorMode::Prompt));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('c'));
+        assert!(matches!(ed.mode(), EditorMode::Normal));
+        assert!(!ed.quit_requested());
+        ed = ed.handle_command(quit(true));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('y'));
+        assert!(ed.quit_requested());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+
+        // :w onto another existing file refuses, asks with confirm, or forces with !
+        let mut ed = ed.handle_command(write_cmd(Some(other.clone())));
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "keep me");
+        ed.options.confirm = true;
+        ed = ed.handle_command(write_cmd(Some(other.clone())));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('n'));
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "keep me");
+        ed.handle_command(EditorCommand::Write {
+            path: Some(other.clone()),
+            force: true,
+            confirm: false,
+        });
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "new");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn put_with_count_and_lines() {
+        let mut ed = type_str(Editor::new(), "ab\ncd");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        ed = op(ed, Operator::Yank, Target::Motion(Motion::LineEnd), 1);
+        ed = ed.handle_command(EditorCommand::Put {
+            register: '"',
+            before: false,
+            count: 3,
+        });
+        assert_eq!(ed.text.to_string(), "aabababb\ncd");
+        assert_eq!(ed.cursor_gcol, 6);
+
+        // Linewise: below the last line (which has no break) and above the cursor
+        ed = op(ed, Operator::Yank, Target::Line, 1);
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        ed = ed.handle_command(EditorCommand::Put {
+            register: '"',
+            before: false,
+            count: 1,
+        });
+        assert_eq!(ed.text.to_string(), "aabababb\ncd\naabababb");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (2, 0));
+        ed = ed.handle_command(EditorCommand::Put {
+            register: '"',
+            before: true,
+            count: 2,
+        });
+        assert_eq!(
+            ed.text.to_string(),
+            "aabababb\ncd\naabababb\naabababb\naabababb"
+        );
+        assert_eq!(ed.cursor_row, 2);
+    }
+
+    #[test]
+    fn numbered_registers_recover_and_cycle_deletes() {
+        let mut ed = type_str(Editor::new(), "one\ntwo\nthree\nfour");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        for _ in 0..3 {
+            ed = op(ed, Operator::Delete, Target::Line, 1);
+        }
+        // A small delete and a yank don't disturb "1-"9
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::Right), 1);
+        ed = op(ed, Operator::Yank, Target::Line, 1);
+        assert_eq!(ed.text.to_string(), "our");
+
+        let put = |register| EditorCommand::Put {
+            register,
+            before: true,
+            count: 1,
+        };
+        ed = ed.handle_command(put('2'));
+        assert_eq!(ed.text.to_string(), "two\nour");
+        ed = ed.handle_command(EditorCommand::CyclePut { older: true });
+        assert_eq!(ed.text.to_string(), "one\nour");
+        ed = ed.handle_command(EditorCommand::CyclePut { older: true });
+        assert_eq!(ed.text.to_string(), "one\nour", "stops at the oldest");
+        ed = ed.handle_command(EditorCommand::CyclePut { older: false });
+        ed = ed.handle_command(EditorCommand::CyclePut { older: false });
+        assert_eq!(ed.text.to_string(), "three\nour");
+
+        // Any other command ends the cycle
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        ed = ed.handle_command(EditorCommand::CyclePut { older: true });
+        assert_eq!(ed.text.to_string(), "three\nour");
+
+        ed = ed.handle_command(put('-'));
+        ed = ed.handle_command(put('0'));
+        assert_eq!(ed.text.to_string(), "three\nour\nfour");
+    }
+
+    #[test]
+    fn replace_mode_overwrites_and_backspace_restores() {
+        let mut ed = type_str(Editor::new(), "ab👨‍👩‍👧‍👦\nnext");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::EnterReplaceMode);
+        assert!(matches!(ed.mode(), EditorMode::Replace));
+        for c in "XYZ".chars() {
+            ed = ed.handle_command(EditorCommand::OverwriteChar(c));
+        }
+        // The emoji was one grapheme; Z went past the line end
+        assert_eq!(ed.text.to_string(), "aXYZ\nnext");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 4));
+
+        for _ in 0..3 {
+            ed = ed.handle_command(EditorCommand::RestoreOverwritten);
+        }
+        assert_eq!(ed.text.to_string(), "ab👨‍👩‍👧‍👦\nnext");
+        assert_eq!(ed.cursor_gcol, 1);
+        // Before the first typed grapheme Backspace only moves
+        ed = ed.handle_command(EditorCommand::RestoreOverwritten);
+        ed = ed.handle_command(EditorCommand::RestoreOverwritten);
+        assert_eq!(ed.text.to_string(), "ab👨‍👩‍👧‍👦\nnext");
+        assert_eq!(ed.cursor_gcol, 0);
+    }
+
+    #[test]
+    fn scrolling_moves_view_and_cursor() {
+        let mut ed = Editor::new();
+        ed.set_view_size(80, 10);
+        for i in 0..40 {
+            ed = type_str(ed, &format!("line {i}\n"));
+        }
+        assert_eq!(ed.view().top, 31, "typing keeps the cursor on screen");
+
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        assert_eq!(ed.view().top, 0);
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineEnd,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::Scroll {
+            scroll: Scroll::HalfDown,
+            count: None,
+        });
+        assert_eq!((ed.view().top, ed.cursor_row, ed.cursor_gcol), (5, 5, 5));
+        ed = ed.handle_command(EditorCommand::Scroll {
+            scroll: Scroll::PageDown,
+            count: Some(2),
+        });
+        assert_eq!((ed.view().top, ed.cursor_row), (21, 21));
+        ed = ed.handle_command(EditorCommand::Scroll {
+            scroll: Scroll::PageUp,
+            count: None,
+        });
+        assert_eq!((ed.view().top, ed.cursor_row), (13, 21));
+    }
+
+    #[test]
+    fn preview_scrolls_read_only_then_materializes() {
+        let path = std::env::temp_dir().join(format!("mters-huge-{}", std::process::id()));
+        let content: String = (0..100).map(|i| format!("{i}\n")).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let mut ed = Editor::new();
+        ed.load_preview(path.clone()).unwrap();
+        ed.set_view_size(80, 10);
+        ed = ed.handle_command(EditorCommand::Scroll {
+            scroll: Scroll::HalfDown,
+            count: None,
+        });
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        assert_eq!(ed.view().top, 6);
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileEnd,
+            count: 1,
+        });
+        assert_eq!(ed.view().top, 90, "last line at the bottom of the screen");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::Up,
+            count: 5,
+        });
+
+        // Edits are refused until the file is materialized
+        let edit = EditorCommand::InsertChar('x');
+        assert!(ed.needs_materialize(&edit));
+        ed = ed.handle_command(EditorCommand::InsertChar('x'));
+        assert_eq!(ed.text.len_chars(), 0);
+
+        let mut seen = 0;
+        ed.materialize(|done, _| seen = done).unwrap();
+        assert_eq!(seen, content.len() as u64);
+        assert!(ed.preview().is_none() && !ed.needs_materialize(&edit));
+        assert_eq!((ed.cursor_row, ed.view().top), (85, 85));
+        ed = ed.handle_command(edit);
+        assert_eq!(ed.text.line(85).to_string(), "x85\n");
+        assert!(ed.modified);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_format_round_trips_and_converts() {
+        let dir = std::env::temp_dir().join(format!("mters-ff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("win.txt");
+        std::fs::write(&path, "\u{feff}one\r\ntwo\r\n").unwrap();
+
+        let mut ed = Editor::open(path.clone()).unwrap();
+        assert_eq!(ed.text.to_string(), "one\r\ntwo\r\n");
+        assert_eq!(ed.file_format().label(), "utf-8[BOM] dos");
+        ed.marks.set('a', 6);
+        ed = ed.handle_command(write_cmd(None));
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            "\u{feff}one\r\ntwo\r\n".as_bytes()
+        );
+
+        ed = ed.handle_command(EditorCommand::SetLineEnding(LineEnding::Lf));
+        ed = ed.handle_command(EditorCommand::SetBom(false));
+        assert!(ed.modified);
+        assert_eq!(ed.text.to_string(), "one\ntwo\n");
+        assert_eq!(ed.marks.get('a'), Some(5), "marks follow the conversion");
+        ed.handle_command(write_cmd(None));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn align_view_keeps_cursor() {
+        let mut ed = Editor::new();
+        ed.set_view_size(80, 10);
+        ed = type_str(ed, &"x\n".repeat(50));
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::Up,
+            count: 20,
+        });
+        let at = (ed.cursor_row, ed.cursor_gcol);
+        ed = ed.handle_command(EditorCommand::AlignView(Align::Center));
+        assert_eq!(ed.view().top, 26);
+        ed = ed.handle_command(EditorCommand::AlignView(Align::Top));
+        assert_eq!(ed.view().top, 30);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), at);
+    }
+
+    #[test]
+    fn nul_bytes_edit_and_round_trip() {
+        let dir = std::env::temp_dir().join(format!("mters-nul-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bin.dat");
+        let bytes = b"a\0\0b\r\n\0\n";
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed = ed.handle_command(write_cmd(None));
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        // Each NUL is one grapheme for movement and editing
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineEnd,
+            count: 1,
+        });
+        assert_eq!(ed.cursor_gcol, 3);
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::Left), 1);
+        assert_eq!(ed.text.to_string(), "a\0b\r\n\0\n");
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::ReplaceChar { ch: 'x', count: 1 });
+        assert_eq!(ed.text.to_string(), "a\0b\r\nx\n");
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::Right), 1);
+        assert_eq!(ed.text.to_string(), "a\0b\r\n\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commands_report_errors_and_writes_until_the_next_key() {
+        let dir = std::env::temp_dir().join(format!("mters-msg-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+
+        let mut ed = type_str(Editor::new(), "one\ntwo\n");
+        ed = ed.handle_command(write_cmd(None));
+        assert_eq!(ed.message().unwrap().text, "E32: No file name");
+        ed = ed.handle_command(EditorCommand::Exit {
+            force: false,
+            confirm: false,
+        });
+        let msg = ed.message().unwrap();
+        assert!(msg.error && msg.text.starts_with("E37:"));
+
+        ed = ed.handle_command(write_cmd(Some(path.clone())));
+        let msg = ed.message().unwrap();
+        assert!(!msg.error);
+        assert_eq!(msg.text, format!("\"{}\" 2L, 8B written", path.display()));
+
+        ed = ed.handle_command(write_cmd(Some(dir.join("no/such/dir"))));
+        assert_eq!(
+            ed.message().unwrap().text,
+            "E212: Can't open file for writing"
+        );
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        assert_eq!(ed.message(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn linewise_edits_at_the_end_of_the_file() {
+        let goto = |ed: Editor, line| {
+            ed.handle_command(EditorCommand::Move {
+                motion: Motion::GotoLine(line),
+                count: 1,
+            })
+        };
+        // dG from the middle, with and without a final newline
+        for (src, left) in [("a\nb\nc", "a"), ("a\nb\nc\n", "a\n")] {
+            let mut ed = goto(type_str(Editor::new(), src), 2);
+            ed = op(ed, Operator::Delete, Target::Motion(Motion::FileEnd), 1);
+            assert_eq!(ed.text.to_string(), left);
+            assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 0));
+        }
+
+        // 9G past the end lands on the last line; d1G deletes up to the top
+        let mut ed = goto(type_str(Editor::new(), "a\n  b\nc"), 9);
+        assert_eq!(ed.cursor_row, 2);
+        ed = goto(ed, 2);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 2));
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::GotoLine(1)), 1);
+        assert_eq!(ed.text.to_string(), "c");
+
+        // dd on the only line leaves an empty buffer at 0,0
+        ed = op(ed, Operator::Delete, Target::Line, 1);
+        assert_eq!(ed.text.to_string(), "");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 0));
+
+        // o on a last line without a newline adds one
+        let mut ed = type_str(Editor::new(), "x");
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        ed = ed.handle_command(EditorCommand::OpenLineBelow);
+        ed = type_str(ed, "y");
+        assert_eq!(ed.text.to_string(), "x\ny");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+    }
+
+    #[test]
+    fn crlf_pairs_act_as_one_line_break() {
+        // Each case runs with LF and with CRLF for `{}`, starting on the last
+        // char of line 2; breaks must come out the way they went in.
+        type Edit = fn(Editor) -> Editor;
+        let cases: &[(&str, Edit, &str)] = &[
+            (
+                "a{}b{}c",
+                |ed| op(ed, Operator::Delete, Target::Line, 1),
+                "a{}c",
+            ),
+            ("a{}b", |ed| op(ed, Operator::Delete, Target::Line, 1), "a"),
+            (
+                "a{}b{}c",
+                |ed| {
+                    ed.handle_command(EditorCommand::JoinLines {
+                        count: 2,
+                        spaces: true,
+                    })
+                },
+                "a{}b c",
+            ),
+            (
+                "a{}bc{}d",
+                |ed| {
+                    let ed = ed.handle_command(EditorCommand::MoveLeft);
+                    op(ed, Operator::Delete, Target::Motion(Motion::LineEnd), 1)
+                },
+                "a{}{}d",
+            ),
+            (
+                "a{}bc{}d",
+                |ed| op(ed, Operator::Delete, Target::Motion(Motion::Right), 5),
+                "a{}b{}d",
+            ),
+            (
+                "a{}bc{}d",
+                |ed| op(ed, Operator::Delete, Target::Motion(Motion::WordForward), 1),
+                "a{}b{}d",
+            ),
+            (
+                "a{}bc{}d",
+                |ed| op(ed, Operator::Change, Target::Line, 1),
+                "a{}{}d",
+            ),
+            (
+                "a{}bc{}d",
+                |ed| {
+                    let ed = op(ed, Operator::Yank, Target::Line, 1);
+                    ed.handle_command(EditorCommand::Put {
+                        register: '"',
+                        before: false,
+                        count: 1,
+                    })
+                },
+                "a{}bc{}bc{}d",
+            ),
+            (
+                "a{}b{}c",
+                |ed| {
+                    let ed = ed.handle_command(EditorCommand::Move {
+                        motion: Motion::LineStart,
+                        count: 1,
+                    });
+                    let ed = ed.handle_command(EditorCommand::EnterInsertMode);
+                    ed.handle_command(EditorCommand::Backspace)
+                },
+                "ab{}c",
+            ),
+        ];
+        for (src, run, want) in cases {
+            for brk in ["\n", "\r\n"] {
+                let src = src.replace("{}", brk);
+                let mut ed = type_str(Editor::new(), &src);
+                ed = ed.handle_command(EditorCommand::EnterNormalMode);
+                ed = ed.handle_command(EditorCommand::Move {
+                    motion: Motion::GotoLine(2),
+                    count: 1,
+                });
+                ed = ed.handle_command(EditorCommand::Move {
+                    motion: Motion::LineEnd,
+                    count: 1,
+                });
+                let got = run(ed).text.to_string();
+                assert_eq!(got, want.replace("{}", brk), "{src:?}");
+            }
+        }
+
+        // Mixed: each line keeps its own break
+        let mut ed = type_str(Editor::new(), "a\r\nb\nc\r\nd");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::GotoLine(2),
+            count: 1,
+        });
+        ed = op(ed, Operator::Delete, Target::Line, 2);
+        assert_eq!(ed.text.to_string(), "a\r\nd");
+
+        // Enter in a dos file starts the line with CRLF
+        let mut ed = type_str(Editor::new(), "a");
+        ed = ed.handle_command(EditorCommand::SetLineEnding(LineEnding::Crlf));
+        ed = type_str(ed, "\nb");
+        assert_eq!(ed.text.to_string(), "a\r\nb");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+    }
+
+    #[test]
+    fn goto_byte_lands_on_the_containing_grapheme() {
+        // "é" is e + combining acute (3 bytes); "日" is 3 bytes
+        let mut ed = type_str(Editor::new(), "ae\u{301}\n日x");
+        let goto = |ed: Editor, byte| ed.handle_command(EditorCommand::GotoByte(byte));
+        ed = goto(ed, 4);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 1));
+        assert_eq!(ed.cursor_byte(), 2);
+        ed = goto(ed, 8);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+        ed = goto(ed, 9);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 1));
+        assert_eq!(ed.cursor_byte(), 9);
+        ed = goto(ed, 1000);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 2));
+    }
+
+    #[test]
+    fn tag_jumps_and_the_tag_stack() {
+        let dir = std::env::temp_dir().join(format!("mters-tags-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.rs"), "fn main() {\n    helper();\n}\n").unwrap();
+        std::fs::write(dir.join("b.rs"), "// b\nfn helper() {}\n").unwrap();
+        std::fs::write(
+            dir.join("tags"),
+            "helper\tb.rs\t/^fn helper() {}$/;\"\tf\n\
+             main\ta.rs\t/^fn main() {$/;\"\tf\n\
+             main\tb.rs\t1;\"\tv\n",
+        )
+        .unwrap();
+
+        let mut ed = Editor::open(dir.join("a.rs")).unwrap();
+        ed = ed.handle_command(EditorCommand::MoveDown);
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineEnd,
+            count: 1,
+        });
+        assert_eq!(
+            ed.handle_command(EditorCommand::JumpToTag(None))
+                .message()
+                .unwrap()
+                .text,
+            "E349: No identifier under cursor"
+        );
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FirstNonBlank,
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::JumpToTag(None));
+        assert_eq!(ed.file_path(), Some(dir.join("b.rs").as_path()));
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 0));
+
+        // Ambiguous: the first is taken, :tselect offers the rest
+        ed = ed.handle_command(EditorCommand::JumpToTag(Some("main".into())));
+        assert_eq!(ed.file_path(), Some(dir.join("a.rs").as_path()));
+        assert!(ed.message().unwrap().text.starts_with("tag 1 of 2"));
+        ed = ed.handle_command(EditorCommand::SelectTag(Some("main".into())));
+        assert!(matches!(ed.mode(), EditorMode::Prompt));
+        assert!(ed.prompt().unwrap().contains("\n  2 v    main  "));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('3'));
+        assert!(matches!(ed.mode(), EditorMode::Prompt));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('2'));
+        assert_eq!(ed.file_path(), Some(dir.join("b.rs").as_path()));
+        assert_eq!(ed.cursor_row, 0);
+
+        let stack = ed.handle_command(EditorCommand::ShowTagStack);
+        let lines: Vec<&str> = stack.message().unwrap().text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("  1 helper                   2  "));
+        assert!(
+            lines[3].starts_with("  3 main                     1  ") && lines[3].ends_with("a.rs")
+        );
+
+        // Back through every jump, across files
+        for (file, row) in [("a.rs", 0), ("b.rs", 1), ("a.rs", 1)] {
+            ed = ed.handle_command(EditorCommand::PopTag);
+            assert_eq!(ed.file_path(), Some(dir.join(file).as_path()));
+            assert_eq!(ed.cursor_row, row);
+        }
+        ed = ed.handle_command(EditorCommand::PopTag);
+        assert_eq!(ed.message().unwrap().text, "E73: Tag stack empty");
+        ed = ed.handle_command(EditorCommand::JumpToTag(Some("nope".into())));
+        assert_eq!(ed.message().unwrap().text, "E426: Tag not found: nope");
+
+        // Unsaved changes are kept in the buffer left behind
+        ed = ed.handle_command(EditorCommand::InsertChar('x'));
+        ed = ed.handle_command(EditorCommand::JumpToTag(Some("helper".into())));
+        assert_eq!(ed.file_path(), Some(dir.join("b.rs").as_path()));
+        ed = ed.handle_command(EditorCommand::PopTag);
+        assert_eq!(ed.file_path(), Some(dir.join("a.rs").as_path()));
+        assert!(ed.modified() && ed.text.to_string().contains('x'));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn notes_save_themselves_after_each_change() {
+        let dir = std::env::temp_dir().join(format!("mters-notes-{}", std::process::id()));
+        let path = dir.join("state/notes.md");
+        let mut ed = Editor::new();
+        ed.load(path.clone()).unwrap();
+        ed.notes = true;
+
+        // Saved when insert mode ends, creating the state directory
+        ed = ed.handle_command(EditorCommand::EnterInsertMode);
+        ed = type_str(ed, "todo");
+        assert!(ed.modified() && !path.exists());
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        assert!(!ed.modified());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "todo");
+        assert_eq!(ed.message(), None);
+
+        // Normal-mode edits save straight away
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::Left), 1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "tod");
+
+        // Another file is an ordinary buffer again
+        ed = ed.handle_command(EditorCommand::Edit(Some(dir.join("other.txt"))));
+        assert!(!ed.is_notes());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_restore_and_diff() {
+        let mut ed = type_str(Editor::new(), "one\ntwo\n");
+        ed = ed.handle_command(EditorCommand::SaveSnapshot("start".into()));
+        ed = type_str(ed, "three\n");
+        ed = ed.handle_command(EditorCommand::DiffSnapshot("start".into()));
+        assert_eq!(
+            ed.message().unwrap().text,
+            "--- snapshot start\n+++ buffer\n@@ -1,2 +1,3 @@\n one\n two\n+three"
+        );
+
+        ed = ed.handle_command(EditorCommand::RestoreSnapshot("start".into()));
+        assert_eq!(ed.text.to_string(), "one\ntwo\n");
+        assert!(ed.modified());
+        // What was replaced is kept too
+        ed = ed.handle_command(EditorCommand::RestoreSnapshot("previous".into()));
+        assert_eq!(ed.text.to_string(), "one\ntwo\nthree\n");
+        ed = ed.handle_command(EditorCommand::ListSnapshots);
+        assert_eq!(ed.message().unwrap().text, "previous\nstart");
+
+        ed = ed.handle_command(EditorCommand::DiffSnapshot("nope".into()));
+        assert_eq!(
+            ed.message().unwrap().text,
+            "E94: No matching snapshot: nope"
+        );
+
+        // `autosnapshot` keeps what each write wrote
+        let path = std::env::temp_dir().join(format!("mters-snap-{}", std::process::id()));
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "asn".into(),
+            value: true,
+        });
+        ed = ed.handle_command(write_cmd(Some(path.clone())));
+        assert_eq!(ed.snapshots["write1"].to_string(), "one\ntwo\nthree\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pickers_filter_and_run_the_pick() {
+        let mut ed = Editor::new().handle_command(EditorCommand::OpenPicker("commands".into()));
+        assert!(matches!(ed.mode, EditorMode::Picker));
+        for c in "snap".chars() {
+            ed = ed.handle_command(EditorCommand::PickerInput(c));
+        }
+        ed = ed.handle_command(EditorCommand::PickerAccept);
+        assert!(matches!(ed.mode, EditorMode::Command));
+        assert_eq!(ed.input.as_ref().unwrap().text(), "snapshot ");
+        assert!(ed.picker().is_none());
+
+        ed = Editor::new().handle_command(EditorCommand::OpenPicker("nope".into()));
+        assert!(matches!(ed.mode, EditorMode::Normal));
+        assert_eq!(ed.message().unwrap().text, "E475: Invalid argument: nope");
+    }
+
+    #[test]
+    fn checkhealth_reports_in_an_unnamed_buffer() {
+        let ed = type_str(Editor::new(), "draft");
+        let refused = ed.handle_command(EditorCommand::CheckHealth);
+        assert_eq!(refused.text.to_string(), "draft");
+        assert!(refused.message().unwrap().error);
+
+        let mut ed = Editor::new().handle_command(EditorCommand::CheckHealth);
+        assert!(ed.text.to_string().starts_with("mters "));
+        assert!(ed.text.to_string().contains("\nTerminal\n"));
+        assert!(ed.file_path().is_none());
+        assert!(!ed.modified());
+        ed = ed.handle_command(EditorCommand::Exit {
+            force: false,
+            confirm: false,
+        });
+        assert!(ed.quit_requested());
+    }
+
+    #[test]
+    fn cursor_hold_autosaves_in_normal_mode() {
+        let path = std::env::temp_dir().join(format!("mters-hold-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "autosave".into(),
+            value: true,
+        });
+        ed = ed.handle_command(EditorCommand::EnterInsertMode);
+        ed = type_str(ed, "hi");
+        // Typing isn't idling
+        ed = ed.handle_command(EditorCommand::CursorHold);
+        assert!(ed.modified());
+
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        ed = ed.handle_command(EditorCommand::CursorHold);
+        assert!(!ed.modified());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi");
+        assert!(ed.message().unwrap().text.ends_with("1L, 2B written"));
+        // Nothing to do; the message stays
+        ed = ed.handle_command(EditorCommand::CursorHold);
+        assert!(ed.message().is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn suspend_is_asked_for_one_command_at_a_time() {
+        let ed = Editor::new().handle_command(EditorCommand::Suspend);
+        assert!(ed.suspend_requested());
+        let ed = ed.handle_command(EditorCommand::MoveDown);
+        assert!(!ed.suspend_requested());
+        // `:suspend` is the same command
+        assert_eq!(crate::ex::parse("sus"), Ok(EditorCommand::Suspend));
+    }
+
+    #[test]
+    fn alt_p_walks_the_kill_ring_from_the_last_put() {
+        let mut ed = type_str(Editor::new(), "one two three");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        // Small deletes, which "1-"9 don't keep
+        for _ in 0..3 {
+            ed = op(ed, Operator::Delete, Target::Motion(Motion::WordForward), 1);
+        }
+        assert_eq!(ed.text.to_string(), "");
+        ed = ed.handle_command(EditorCommand::Put {
+            register: '"',
+            before: true,
+            count: 1,
+        });
+        assert_eq!(ed.text.to_string(), "three");
+        let pop = |older| EditorCommand::YankPop { older };
+        ed = ed.handle_command(pop(true));
+        assert_eq!(ed.text.to_string(), "two ");
+        ed = ed.handle_command(pop(true));
+        assert_eq!(ed.text.to_string(), "one ");
+        ed = ed.handle_command(pop(true));
+        assert_eq!(ed.text.to_string(), "one ", "stops at the oldest");
+        ed = ed.handle_command(pop(false));
+        assert_eq!(ed.text.to_string(), "two ");
+
+        // Only straight after a put
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(pop(true));
+        assert_eq!(ed.text.to_string(), "two ");
+    }
+
+    #[test]
+    fn retab_converts_indentation_in_a_range() {
+        let mut ed = type_str(Editor::new(), "\tone\n\t\ttwo\n        three\n\tfour");
+        let retab = |range, tabstop, force| EditorCommand::Retab {
+            range,
+            tabstop,
+            force,
+        };
+        let first_two = LineRange {
+            from: Address::Line(1),
+            to: Address::Line(2),
+        };
+        ed = ed.handle_command(retab(Some(first_two), None, false));
+        assert_eq!(
+            ed.text.to_string(),
+            format!(
+                "{}one\n{}two\n        three\n\tfour",
+                " ".repeat(8),
+                " ".repeat(16)
+            )
+        );
+        assert_eq!(ed.message().unwrap().text, "2 lines changed");
+
+        // Back to tabs at a new tab stop; `!` takes the space-only runs too
+        ed.options.expandtab = false;
+        ed = ed.handle_command(retab(None, Some(4), true));
+        assert_eq!(
+            ed.text.to_string(),
+            "\t\tone\n\t\t\t\ttwo\n\t\tthree\n\t\tfour"
+        );
+        assert_eq!(ed.options.tabstop, 4);
+
+        let backwards = LineRange {
+            from: Address::Line(3),
+            to: Address::Line(1),
+        };
+        ed = ed.handle_command(retab(Some(backwards), None, false));
+        assert!(ed.message().unwrap().error);
+    }
+
+    #[test]
+    fn whitespace_only_writes_ask_first() {
+        let dir = std::env::temp_dir().join(format!("mters-wsw-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed.options.whitespacewarn = true;
+        ed = ed.handle_command(EditorCommand::SetLineEnding(LineEnding::Crlf));
+        ed = ed.handle_command(write_cmd(None));
+        assert!(matches!(ed.mode, EditorMode::Prompt));
+
+        // `d` shows what would change and keeps asking
+        ed = ed.handle_command(EditorCommand::PromptAnswer('d'));
+        let prompt = ed.prompt().unwrap();
+        assert!(prompt.contains("\n-one$\n") && prompt.contains("\n+one^M$\n"));
+        assert!(prompt.ends_with("(Y)es, (N)o, (D)iff: "));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('n'));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        ed = ed.handle_command(write_cmd(None));
+        ed = ed.handle_command(EditorCommand::PromptAnswer('y'));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\r\ntwo\r\n");
+
+        // Real changes, and forced writes, don't ask
+        ed = ed.handle_command(EditorCommand::InsertChar('x'));
+        ed = ed.handle_command(write_cmd(None));
+        assert!(!matches!(ed.mode, EditorMode::Prompt));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reloading_keeps_the_cursor_and_marks_on_their_lines() {
+        let dir = std::env::temp_dir().join(format!("mters-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::GotoLine(4),
+            count: 1,
+        });
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::SetMark('a'));
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        ed = ed.handle_command(EditorCommand::SetMark('b'));
+
+        // A line in at the top and the marked "three" changed
+        std::fs::write(&path, "zero\none\ntwo\nTHREE!\nfour\n").unwrap();
+        ed = ed.handle_command(EditorCommand::Edit(None));
+        assert_eq!(ed.text.to_string(), "zero\none\ntwo\nTHREE!\nfour\n");
+        assert!(!ed.modified);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (3, 1));
+        let line_of = |ed: &Editor, mark| ed.text.char_to_line(ed.marks.get(mark).unwrap());
+        assert_eq!(line_of(&ed, 'a'), 4);
+        assert_eq!(line_of(&ed, 'b'), 3);
+
+        // autoread does the same on a pause, for an unmodified buffer
+        ed.options.autoread = true;
+        std::fs::write(&path, "zero\n").unwrap();
+        ed.disk_mtime = None;
+        ed = ed.handle_command(EditorCommand::CursorHold);
+        assert_eq!(ed.text.to_string(), "zero\n");
+        assert!(ed.message().unwrap().text.ends_with("reloaded"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_rewritten_file_is_read_afresh() {
+        let dir = std::env::temp_dir().join(format!("mters-rewrite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        let lines = |word| {
+            (0..20_000)
+                .map(|i| format!("{word} {i}\n"))
+                .collect::<String>()
+        };
+        std::fs::write(&path, lines("old")).unwrap();
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::GotoLine(100),
+            count: 1,
+        });
+
+        // Every line changed: far too many edits to diff
+        std::fs::write(&path, lines("new")).unwrap();
+        ed = ed.handle_command(EditorCommand::Edit(None));
+        assert_eq!(ed.text.to_string(), lines("new"));
+        assert!(!ed.modified);
+        assert_eq!(ed.cursor_row, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "config")]
+    fn config_keymaps_apply_to_typed_keys() {
+        let dir = std::env::temp_dir().join(format!("mters-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[keymap.normal]\nQ = \"dd\"\n").unwrap();
+        let mut ed = Editor::new();
+        ed.load_config(&path);
+        assert!(ed.message().is_none());
+        let q = KeyEvent::new(KeyCode::Char('Q'), crate::keys::KeyModifiers::SHIFT);
+        let keys = ed.map_typed(q);
+        assert_eq!(keys.len(), 2);
+        assert!(!ed.typed_waiting());
+
+        std::fs::write(&path, "[keymap.visual]\n").unwrap();
+        ed.load_config(&path);
+        assert!(ed.message().unwrap().text.contains("unknown mode"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_toggles_and_shows_options() {
+        let set = |ed: Editor, arg: &str| ed.handle_command(crate::ex::parse(arg).unwrap());
+        let mut ed = set(Editor::new(), "set");
+        // Nothing changed yet
+        assert_eq!(ed.message().unwrap().text, "--- Options ---");
+        ed = set(ed, "set invnu");
+        ed = set(ed, "set ts=4");
+        assert!(ed.options.number);
+        ed = set(ed, "set ts?");
+        assert_eq!(ed.message().unwrap().text, "tabstop=4");
+        ed = set(ed, "set");
+        assert_eq!(
+            ed.message().unwrap().text,
+            "--- Options ---\n  number\n  tabstop=4"
+        );
+        ed = set(ed, "set number!");
+        ed = set(ed, "set nu?");
+        assert_eq!(ed.message().unwrap().text, "nonumber");
+        // Naming an option that isn't a flag shows it
+        ed = set(ed, "set sw");
+        assert_eq!(ed.message().unwrap().text, "shiftwidth=4");
+        ed = set(ed, "set ff?");
+        assert_eq!(ed.message().unwrap().text, "fileformat=unix");
+        // Several at once
+        ed = set(ed, "set ts=3 sw=0 nonu");
+        assert_eq!((ed.options.tabstop, ed.options.shift_columns()), (3, 3));
+        ed = set(ed, "set ts? sw?");
+        assert_eq!(
+            ed.message().unwrap().text,
+            "--- Options ---\n  tabstop=3\n  shiftwidth=0"
+        );
 
-    fn type_str(mut ed: Editor, s: &str) -> Editor {
-        for ch in s.chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        ed
+        assert!(crate::ex::parse("set invts").is_err());
+        assert!(crate::ex::parse("set nu=1").is_err());
+        assert!(crate::ex::parse("set nope?").is_err());
     }
 
     #[test]
-    fn test_insert_char() {
-        let editor = Editor::new();
-        let updated = editor.handle_command(EditorCommand::InsertChar('a'));
+    #[cfg(feature = "config")]
+    fn filetypes_bring_their_settings_and_take_them_away() {
+        let dir = std::env::temp_dir().join(format!("mters-filetype-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(
+            &config,
+            "[filetype.yaml]\nshiftwidth = 2\n\n[filetype.make]\nexpandtab = false\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("ci.yml"), "on: push\n").unwrap();
+        std::fs::write(dir.join("Makefile"), "all:\n").unwrap();
 
-        assert_eq!(updated.text.line(0).to_string(), "a");
-        assert_eq!(updated.cursor_gcol, 1);
-        assert_eq!(updated.cursor_row, 0);
+        let mut ed = Editor::open(dir.join("ci.yml")).unwrap();
+        ed.load_config(&config);
+        assert_eq!(ed.show_option("ft").unwrap(), "filetype=yaml");
+        assert_eq!(ed.options.shiftwidth, 2);
+
+        ed = ed.handle_command(EditorCommand::Edit(Some(dir.join("Makefile"))));
+        assert_eq!(ed.filetype, "make");
+        assert_eq!(ed.options.shiftwidth, 4);
+        assert!(!ed.options.expandtab);
+
+        ed = ed.handle_command(crate::ex::parse("set ft=yaml").unwrap());
+        assert!(ed.options.expandtab);
+        assert_eq!(ed.options.shiftwidth, 2);
+
+        std::fs::write(&config, "[filetype.go]\nfileformat = \"dos\"\n").unwrap();
+        ed.load_config(&config);
+        assert!(ed
+            .message()
+            .unwrap()
+            .text
+            .contains("isn't set per filetype"));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_move_down_and_up() {
-        let mut editor = Editor::new();
-        editor = editor.handle_command(EditorCommand::InsertChar('a'));
-        editor = editor.handle_command(EditorCommand::InsertChar('\n'));
-        editor = editor.handle_command(EditorCommand::InsertChar('b'));
+    fn editorconfig_sets_indents_and_fixes_whitespace_on_write() {
+        let dir = std::env::temp_dir().join(format!("mters-ec-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("proj")).unwrap();
+        std::fs::write(
+            dir.join("proj/.editorconfig"),
+            "root = true\n[*.txt]\nindent_style = tab\nindent_size = 2\n\
+             trim_trailing_whitespace = true\ninsert_final_newline = true\n",
+        )
+        .unwrap();
+        let path = dir.join("proj/a.txt");
+        std::fs::write(&path, "x  \ny\t").unwrap();
+        std::fs::write(dir.join("b.txt"), "").unwrap();
 
-        // After typing "a\nb", we have two lines: "a\n" and "b"
-        // MoveDown should keep us at last line (row 1)
-        let down = editor.handle_command(EditorCommand::MoveDown);
-        assert_eq!(down.cursor_row, 1);
+        let mut ed = Editor::open(path.clone()).unwrap();
+        assert!(!ed.options.expandtab);
+        assert_eq!((ed.options.shiftwidth, ed.options.tabstop), (2, 2));
+        ed = ed.handle_command(write_cmd(None));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x\ny\n");
+        assert!(!ed.modified());
 
-        let up = down.handle_command(EditorCommand::MoveUp);
-        assert_eq!(up.cursor_row, 0);
+        // Another file, out of the project: the options go back
+        ed = ed.handle_command(EditorCommand::Edit(Some(dir.join("b.txt"))));
+        assert!(ed.options.expandtab);
+        assert_eq!((ed.options.shiftwidth, ed.options.tabstop), (4, 8));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn emoji_is_one_step() {
-        // "a👨‍👩‍👧‍👦b" — family emoji is a single grapheme made of multiple scalars.
-        let mut ed = Editor::new();
-        for ch in "a👨‍👩‍👧‍👦b".chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
+    fn iskeyword_decides_what_words_are() {
+        let set = |ed: Editor, arg: &str| ed.handle_command(crate::ex::parse(arg).unwrap());
+        let start = |ed: Editor| {
+            ed.handle_command(EditorCommand::Move {
+                motion: Motion::FileStart,
+                count: 1,
+            })
+        };
+        let mut ed = start(type_str(Editor::new(), "font-size: 1em"));
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::WordForward), 1);
+        assert_eq!(ed.text.to_string(), "-size: 1em");
 
-        // Move left once: should jump from after 'b' to start of 'b'
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        assert_eq!(ed.cursor_row, 0);
-        assert_eq!(ed.cursor_gcol, 2); // a, [emoji], |b|
+        ed = start(set(type_str(Editor::new(), "font-size: 1em"), "set isk+=-"));
+        ed = op(ed, Operator::Delete, Target::Motion(Motion::WordForward), 1);
+        assert_eq!(ed.text.to_string(), ": 1em");
+        ed = set(ed, "set isk?");
+        assert_eq!(ed.message().unwrap().text, "iskeyword=@,48-57,_,-");
+        ed = set(ed, "set isk-=-");
+        assert_eq!(ed.options.iskeyword, crate::options::IsKeyword::default());
 
-        // Move left once more: should skip whole emoji in one step
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        assert_eq!(ed.cursor_gcol, 1); // a, |[emoji], b
+        // CSS comes with `-` in its words
+        ed = set(ed, "set ft=css");
+        assert!(ed.options.iskeyword.contains('-'));
+        ed = set(ed, "set ft=yaml");
+        assert!(!ed.options.iskeyword.contains('-'));
     }
 
     #[test]
-    fn combining_mark_is_one_step() {
-        // "e\u{0301}" = "é" precomposed via combining acute
-        let mut ed = Editor::new();
-        for ch in "e\u{0301}".chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        assert_eq!(ed.cursor_gcol, 1); // one grapheme on the first line
+    #[cfg(feature = "config")]
+    fn modelines_set_options_unless_turned_off() {
+        let dir = std::env::temp_dir().join(format!("mters-modeline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("build");
+        std::fs::write(&script, "echo hi\n# vim: set ft=sh ts=2 et nomodeline:\n").unwrap();
+        std::fs::write(dir.join("plain.txt"), "text\n").unwrap();
 
-        // Backspace should delete the whole grapheme
-        ed = ed.handle_command(EditorCommand::Backspace);
-        assert_eq!(ed.cursor_gcol, 0);
-        assert_eq!(ed.text.line(0).to_string(), "");
+        let mut ed = Editor::open(script.clone()).unwrap();
+        assert_eq!(ed.filetype, "sh");
+        assert_eq!(ed.options.tabstop, 2);
+        assert!(ed.options.expandtab && ed.options.modeline);
+        assert_eq!(
+            ed.message().unwrap().text,
+            "E520: Not allowed in a modeline: nomodeline"
+        );
+        // Gone with the file
+        ed = ed.handle_command(EditorCommand::Edit(Some(dir.join("plain.txt"))));
+        assert_eq!(ed.options.tabstop, Options::default().tabstop);
+        assert_eq!(ed.options.expandtab, Options::default().expandtab);
+
+        let config = dir.join("config.toml");
+        std::fs::write(&config, "modeline = false\n").unwrap();
+        let mut ed = Editor::open(script).unwrap();
+        ed.load_config(&config);
+        assert_eq!(ed.options.tabstop, Options::default().tabstop);
+        assert_eq!(ed.filetype, "");
+        std::fs::remove_dir_all(&dir).unwrap();
     }
+
     #[test]
-    fn backspace_clears_combining_grapheme_and_resets_col() {
-        let mut ed = Editor::new();
-        for ch in "e\u{0301}".chars() {
-            // "é"
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        // One grapheme on the line
-        assert_eq!(ed.cursor_gcol, 1);
+    fn new_lines_take_the_indent_of_the_last() {
+        let enter = |ed: Editor| ed.handle_command(EditorCommand::InsertNewline);
+        let mut ed = type_str(Editor::new(), "    let x = {");
+        ed = enter(ed);
+        ed = type_str(ed, "y");
+        assert_eq!(ed.text.to_string(), "    let x = {\n    y");
 
-        // Backspace should delete the full grapheme and move to col 0
-        ed = ed.handle_command(EditorCommand::Backspace);
-        assert_eq!(ed.text.line(0).to_string(), "");
-        assert_eq!(ed.cursor_row, 0);
-        assert_eq!(ed.cursor_gcol, 0);
+        // Splitting a line: the blanks moved down give way to the indent
+        let mut ed = type_str(Editor::new(), "  a   b");
+        ed.caret_abs = 3;
+        ed.sync_visual_from_caret();
+        ed = enter(ed);
+        assert_eq!(ed.text.to_string(), "  a\n  b");
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 2));
+
+        let set = |ed: Editor, arg: &str| ed.handle_command(crate::ex::parse(arg).unwrap());
+        let mut ed = set(type_str(Editor::new(), "  if x {"), "set si");
+        ed = type_str(enter(ed), "y");
+        assert_eq!(ed.text.to_string(), "  if x {\n      y");
+        ed = ed.handle_command(EditorCommand::OpenLineAbove);
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (1, 6));
+
+        // `:` opens a block in Python only
+        let mut ed = set(
+            set(type_str(Editor::new(), "def f():"), "set si"),
+            "set ft=python",
+        );
+        ed = ed.handle_command(EditorCommand::OpenLineBelow);
+        assert_eq!(ed.text.to_string(), "def f():\n    ");
+        let mut ed = set(type_str(Editor::new(), "  note:"), "set si");
+        ed = enter(set(ed, "set noai"));
+        assert_eq!(ed.text.to_string(), "  note:\n  ");
+        ed = enter(set(ed, "set nosi"));
+        assert_eq!(ed.text.to_string(), "  note:\n  \n");
     }
+
     #[test]
-    fn newline_moves_caret_to_bol_and_next_char_is_col0() {
-        // Start: ""
-        let mut ed = Editor::new();
+    fn autopairs_close_step_over_and_delete_together() {
+        let mut ed = Editor::new().handle_command(crate::ex::parse("set ap").unwrap());
+        ed = ed.handle_command(EditorCommand::EnterInsertMode);
+        ed = type_str(ed, "f(\"a");
+        assert_eq!(ed.text.to_string(), "f(\"a\")");
+        ed = type_str(ed, "\")");
+        assert_eq!(ed.text.to_string(), "f(\"a\")");
+        assert_eq!(ed.caret_abs, 6);
 
-        // Type "hello", move left twice to end up after 'l'
-        ed = type_str(ed, "hello");
-        ed = ed.handle_command(EditorCommand::MoveLeft); // after 'l'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // after second 'l'
+        ed = type_str(ed, " [");
+        ed = ed.handle_command(EditorCommand::Backspace);
+        assert_eq!(ed.text.to_string(), "f(\"a\") ");
+        // Only in Insert mode
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        ed = type_str(ed, "(");
+        assert_eq!(ed.text.to_string(), "f(\"a\") (");
+    }
 
-        // Press Enter: caret_abs must move to start of the next line (col 0)
-        ed = ed.handle_command(EditorCommand::InsertChar('\n'));
+    #[test]
+    fn prose_transforms_and_backspace_takes_one_back() {
+        let mut ed = Editor::new().handle_command(crate::ex::parse("set prose").unwrap());
+        ed = ed.handle_command(EditorCommand::EnterInsertMode);
+        ed = type_str(ed, "she said \"it's fine\" -- then left. s");
+        assert_eq!(ed.text.to_string(), "She said “it’s fine” – then left. S");
 
-        // Assert visual & anchor agree on BOL
-        assert_eq!(ed.cursor_gcol, 0, "visual gcol should be 0 after newline");
-        let caret_byte = ed.text.char_to_byte(ed.abs_char_at_cursor());
-        let bol_byte = ed.text.line_to_byte(ed.cursor_row);
+        // Only the last transform comes back, as typed
+        ed = ed.handle_command(EditorCommand::Backspace);
+        assert_eq!(ed.text.to_string(), "She said “it’s fine” – then left. s");
+        ed = type_str(ed, " 1--");
+        ed = ed.handle_command(EditorCommand::Backspace);
         assert_eq!(
-            caret_byte, bol_byte,
-            "caret_abs must be at BOL after newline"
+            ed.text.to_string(),
+            "She said “it’s fine” – then left. s 1--"
         );
 
-        // Now type 'X' — it MUST appear at column 0 on the new line
-        ed = ed.handle_command(EditorCommand::InsertChar('X'));
+        ed = ed.handle_command(crate::ex::parse("set nosmartquotes").unwrap());
+        ed = type_str(ed, " \"");
+        assert!(ed.text.to_string().ends_with(" \""));
+        ed = ed.handle_command(crate::ex::parse("set noprose").unwrap());
+        ed = type_str(ed, "\n\nno ---");
+        assert!(ed.text.to_string().ends_with("\n\nno ---"));
+    }
 
-        let line = ed.text.line(ed.cursor_row).to_string();
-        assert!(
-            line.starts_with('X'),
-            "expected 'X' at col 0, got line {:?}",
-            line
+    #[test]
+    #[cfg(unix)]
+    fn quickfix_goes_through_the_places_in_a_command_s_output() {
+        let dir = std::env::temp_dir().join(format!("mters-quickfix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.rs"), "one\ntwo\nthree\n").unwrap();
+        std::fs::write(dir.join("b.rs"), "four\n").unwrap();
+        let run = |ed: Editor, line: &str| ed.handle_command(crate::ex::parse(line).unwrap());
+
+        let mut ed = run(Editor::new(), "cnext");
+        assert_eq!(ed.message().unwrap().text, "E42: No Errors");
+        let (a, b) = (dir.join("a.rs"), dir.join("b.rs"));
+        ed = run(
+            ed,
+            &format!(
+                "set makeprg=printf\\ '{}:3:2:\\ bad\\\\n{}:1:\\ worse\\\\n'",
+                a.display(),
+                b.display()
+            ),
+        );
+        ed = run(ed, "make");
+        assert_eq!(ed.file_path(), Some(a.as_path()));
+        assert_eq!((ed.cursor_row, ed.cursor_gcol), (2, 1));
+        assert_eq!(ed.message().unwrap().text, "(1 of 2): bad");
+
+        ed = run(ed, "cnext");
+        assert_eq!(ed.file_path(), Some(b.as_path()));
+        assert_eq!(ed.message().unwrap().text, "(2 of 2): worse");
+        ed = run(ed, "cnext");
+        assert_eq!(ed.message().unwrap().text, "E553: No more items");
+        ed = run(ed, "cc 1");
+        assert_eq!(ed.file_path(), Some(a.as_path()));
+
+        // The pane takes its rows from the text's, and gives them back
+        ed.set_view_size(80, 20);
+        ed = run(ed, "copen");
+        assert_eq!((ed.quickfix_rows(), ed.view.height), (3, 17));
+        ed = run(ed, "cclose");
+        assert_eq!(ed.view.height, 20);
+
+        ed = run(ed, "set makeprg=true");
+        ed = run(ed, "make");
+        assert_eq!(ed.message().unwrap().text, "No errors");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pipe_shows_a_command_s_output_in_a_scratch_buffer() {
+        let pipe = |ed: Editor, line: &str| ed.handle_command(crate::ex::parse(line).unwrap());
+        let mut ed = type_str(Editor::new(), "one\ntwo\nthree\n");
+        ed = pipe(ed, "Pipe wc -l");
+        assert!(ed.message().unwrap().text.starts_with("E37"));
+
+        ed.modified = false;
+        ed = pipe(ed, "2,$Pipe! tr a-z A-Z");
+        assert_eq!(ed.text.to_string(), "TWO\nTHREE\n");
+        assert_eq!(ed.file_path, None);
+        assert!(!ed.modified);
+
+        ed = pipe(ed, "'q,.Pipe cat");
+        assert_eq!(ed.message().unwrap().text, "E20: Mark not set");
+        ed = pipe(ed, "Pipe exit 1");
+        assert_eq!(ed.message().unwrap().text, "shell returned 1");
+        assert_eq!(ed.text.to_string(), "TWO\nTHREE\n");
+    }
+
+    #[test]
+    fn gc_toggles_line_comments() {
+        let mut ed = type_str(Editor::new(), "fn f() {\n    x();\n}");
+        ed = op(ed, Operator::Comment, Target::Line, 1);
+        assert!(ed
+            .message()
+            .unwrap()
+            .text
+            .contains("'commentstring' is empty"));
+
+        ed = ed.handle_command(EditorCommand::SetFiletype("rust".into()));
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        ed = op(ed, Operator::Comment, Target::Motion(Motion::Down), 1);
+        assert_eq!(ed.text.to_string(), "// fn f() {\n//     x();\n}");
+        ed = op(ed, Operator::Comment, Target::Line, 3);
+        assert_eq!(ed.text.to_string(), "// // fn f() {\n// //     x();\n// }");
+        ed = op(ed, Operator::Comment, Target::Line, 3);
+        ed = op(ed, Operator::Comment, Target::Line, 2);
+        assert_eq!(ed.text.to_string(), "fn f() {\n    x();\n}");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn eval_runs_the_block_and_replaces_its_output() {
+        let eval = |ed: Editor| ed.handle_command(crate::ex::parse("Eval").unwrap());
+        let mut ed = type_str(Editor::new(), "Try:\n```sh\necho $((6 * 7))\n```\nafter");
+        ed = eval(ed);
+        assert_eq!(
+            ed.message().unwrap().text,
+            "No fenced code block under the cursor"
         );
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::GotoLine(3),
+            count: 1,
+        });
+        ed = eval(ed);
         assert_eq!(
-            ed.cursor_gcol, 1,
-            "cursor should advance to col 1 after typing 'X'"
+            ed.text.to_string(),
+            "Try:\n```sh\necho $((6 * 7))\n```\n```output\n42\n```\nafter"
         );
+        assert_eq!(ed.message().unwrap().text, "1 line of output");
+        // Run again: the new output takes the old one's place
+        let once = ed.text.to_string();
+        ed = eval(ed);
+        assert_eq!(ed.text.to_string(), once);
+
+        let mut ed = type_str(Editor::new(), "```cobol\nDISPLAY 'HI'.\n```");
+        ed = eval(ed);
+        assert!(ed
+            .message()
+            .unwrap()
+            .text
+            .starts_with("No runner for \"cobol\""));
     }
 
     #[test]
-    fn vertical_move_resyncs_caret_abs_then_inserts_there() {
-        // Buffer: "aa\nbb\ncc"
-        let mut ed = Editor::new();
-        ed = type_str(ed, "aa\nbb\ncc");
-
-        // Put caret at end of first line: row 0, gcol 2
-        // (We are currently at end of buffer; move up twice, then right to clamp)
-        ed = ed.handle_command(EditorCommand::MoveUp);
-        ed = ed.handle_command(EditorCommand::MoveUp);
+    fn buffers_keep_their_own_text_cursor_and_options() {
+        let dir = std::env::temp_dir().join(format!("mters-buffers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.py"));
+        std::fs::write(&a, "one\ntwo\n").unwrap();
+        std::fs::write(&b, "x = 1\n").unwrap();
+        let edit =
+            |ed: Editor, path: &Path| ed.handle_command(EditorCommand::Edit(Some(path.into())));
 
-        // MoveDown once: should land at row 1, same gcol (min with line length)
+        // The empty buffer at startup is taken over rather than kept
+        let mut ed = edit(Editor::new(), &a);
+        assert!(ed.buffers.is_empty());
         ed = ed.handle_command(EditorCommand::MoveDown);
-        assert_eq!(ed.cursor_row, 1);
+        ed = ed.handle_command(EditorCommand::InsertChar('!'));
+        ed = edit(ed, &b);
+        assert_eq!(ed.text.to_string(), "x = 1\n");
+        assert_eq!((ed.buffer, ed.options.commentstring.as_str()), (2, "# %s"));
+        assert!(!ed.modified());
 
-        // Type 'Z' — must go into line 1 at the current visual gcol
-        let before = ed.text.line(ed.cursor_row).to_string();
-        ed = ed.handle_command(EditorCommand::InsertChar('Z'));
-        let after = ed.text.line(ed.cursor_row).to_string();
-        assert_ne!(before, after, "line should change after insert");
-        assert!(
-            after.contains('Z'),
-            "expected 'Z' inserted on the target line"
+        // Back to the first as it was left
+        ed = edit(ed, &a);
+        assert_eq!(ed.buffer, 1);
+        assert_eq!(ed.text.to_string(), "one\n!two\n");
+        assert_eq!((ed.cursor_row, ed.modified()), (1, true));
+        assert_eq!(ed.options.commentstring, "");
+
+        // Quitting minds the changes of buffers not shown
+        ed = edit(ed, &b);
+        let quit = |ed: &Editor, force| {
+            ed.handle_command(EditorCommand::Exit {
+                force,
+                confirm: false,
+            })
+        };
+        let refused = quit(&ed, false);
+        assert_eq!(
+            refused.message().unwrap().text,
+            format!(
+                "E162: No write since last change for buffer \"{}\"",
+                a.display()
+            )
         );
+        assert!(!refused.quit_requested());
+        assert!(quit(&ed, true).quit_requested());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn backspace_across_newline_moves_to_prev_line_end() {
-        // Make two lines: "abc\n"
+    fn timers_run_their_command_in_their_buffer() {
+        let dir = std::env::temp_dir().join(format!("mters-timers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        let run = |ed: Editor, line: &str| ed.handle_command(crate::ex::parse(line).unwrap());
+        let mut ed = Editor::open(a.clone()).unwrap();
+        let start = Duration::from_secs(1000);
+        ed.set_clock(start);
+        let after = |secs| start + Duration::from_secs(secs);
+        ed = run(ed, "Every 10s w");
+        assert_eq!(ed.message().unwrap().text, "timer 1 started");
+        ed = type_str(ed, "x");
+        assert!(ed.next_timer().is_some_and(|due| due <= after(11)));
+
+        // Not before it's due, nor half way through a command
+        ed = ed.handle_command(EditorCommand::Tick(after(5)));
+        assert!(ed.modified());
+        ed.pending.operator = Some(Operator::Delete);
+        assert_eq!(ed.next_timer(), None);
+        ed = ed.handle_command(EditorCommand::Tick(after(11)));
+        assert!(ed.modified());
+        ed.pending.clear();
+        ed = ed.handle_command(EditorCommand::Tick(after(11)));
+        assert!(!ed.modified());
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "x");
+
+        // Only while its buffer is shown
+        ed = ed.handle_command(EditorCommand::Edit(Some(b.clone())));
+        ed = type_str(ed, "y");
+        assert_eq!(ed.next_timer(), None);
+        ed = ed.handle_command(EditorCommand::Tick(after(25)));
+        assert!(ed.modified() && !b.exists());
+
+        ed = run(ed, "Every");
+        assert!(ed.message().unwrap().text.contains("every 10s"));
+        ed = run(ed, "Every stop 2");
+        assert_eq!(ed.message().unwrap().text, "E475: Invalid argument: 2");
+        ed = run(ed, "Every stop 1");
+        ed = run(ed, "Every");
+        assert_eq!(ed.message().unwrap().text, "No timers");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn buffer_commands_list_cycle_switch_and_delete() {
+        let dir = std::env::temp_dir().join(format!("mters-bufcmds-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let run = |ed: Editor, line: &str| ed.handle_command(crate::ex::parse(line).unwrap());
         let mut ed = Editor::new();
-        ed = type_str(ed, "abc\n");
+        for name in ["alpha.txt", "beta.txt", "gamma.txt"] {
+            std::fs::write(dir.join(name), format!("{name}\n")).unwrap();
+            ed = ed.handle_command(EditorCommand::Edit(Some(dir.join(name))));
+        }
+        ed = type_str(ed, "x");
+        ed = run(ed, "ls");
+        let listed = ed.message().unwrap().text.clone();
+        let lines: Vec<&str> = listed.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("  1  h   \"") && lines[0].ends_with("alpha.txt\"  line 1"));
+        assert!(lines[2].starts_with("  3 %a + \""));
 
-        // Now at start of second (empty) line; Backspace should delete the '\n'
-        // and move caret to end of "abc"
-        ed = ed.handle_command(EditorCommand::Backspace);
+        // Round from the last to the first, and back
+        ed = run(ed, "bn");
+        assert_eq!(ed.buffer, 1);
+        ed = run(ed, "bp");
+        assert_eq!((ed.buffer, ed.modified()), (3, true));
+        ed = run(ed, "b bet");
+        assert_eq!(ed.text.to_string(), "beta.txt\n");
+        ed = run(ed, "b .txt");
+        assert_eq!(
+            ed.message().unwrap().text,
+            "E93: More than one match for .txt"
+        );
+        ed = run(ed, "b 9");
+        assert_eq!(ed.message().unwrap().text, "E86: Buffer 9 does not exist");
 
-        assert_eq!(ed.text.to_string(), "abc");
-        assert_eq!(ed.cursor_row, 0);
-        assert_eq!(ed.cursor_gcol, 3);
+        // Unsaved changes need `!`; the next buffer takes the place of a
+        // deleted one, and an empty one that of the last
+        ed = run(ed, "bd gamma");
+        assert!(ed.message().unwrap().text.starts_with("E89:"));
+        ed = run(ed, "bd! gamma");
+        ed = run(ed, "bd");
+        assert_eq!((ed.buffer, ed.text.to_string()), (1, "alpha.txt\n".into()));
+        ed = run(ed, "bd");
+        assert_eq!((ed.buffer, ed.file_path()), (4, None));
+        assert!(ed.buffers.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-        // Also check the anchor is at EOL in bytes
-        let caret_byte = ed.text.char_to_byte(ed.abs_char_at_cursor());
-        let eol_byte = ed.text.line_to_byte(0) + ed.text.line(0).len_bytes();
+    #[test]
+    fn export_writes_html_or_shows_it_in_a_new_buffer() {
+        let dir = std::env::temp_dir().join(format!("mters-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.rs");
+        std::fs::write(&source, "fn a() {}\n// b\n").unwrap();
+        let run = |ed: Editor, line: &str| ed.handle_command(crate::ex::parse(line).unwrap());
+        let mut ed = Editor::open(source.clone()).unwrap();
+
+        ed = run(ed, "2Export ansi");
+        assert_eq!(ed.text.to_string(), "\x1b[38;5;8m// b\x1b[0m\n");
+        assert_eq!((ed.buffer, ed.file_path()), (2, None));
+        ed = run(ed, "bn");
+        assert_eq!(ed.file_path(), Some(source.as_path()));
+
+        let out = dir.join("a.html");
+        ed = run(ed, &format!("Export html {}", out.display()));
+        let html = std::fs::read_to_string(&out).unwrap();
+        assert!(html.contains("<span style=\"color: #ff00ff\">fn</span> a() {}\n"));
+        ed = run(ed, &format!("Export html {}", out.display()));
         assert_eq!(
-            caret_byte, eol_byte,
-            "caret_abs should end up at EOL of previous line"
+            ed.message().unwrap().text,
+            "E13: File exists (add ! to override)"
         );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn emoji_is_single_grapheme_for_moves_and_backspace() {
-        // "a👨‍👩‍👧‍👦b" — family emoji is one grapheme
+    fn file_tree_opens_files_and_hands_focus_back() {
+        let dir = std::env::temp_dir().join(format!("mters-tree-ed-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
         let mut ed = Editor::new();
-        ed = type_str(ed, "a");
-        for ch in "👨‍👩‍👧‍👦".chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        ed = ed.handle_command(EditorCommand::InsertChar('b'));
-        assert_eq!(ed.cursor_row, 0);
+        ed.set_view_size(80, 10);
+        ed = ed.handle_command(crate::ex::parse("Tree").unwrap());
+        assert!(matches!(ed.mode, EditorMode::Tree));
+        assert_eq!((ed.sidebar_width(), ed.text_left()), (30, 30));
+        ed.tree = Some(FileTree::new(dir.clone()));
 
-        // MoveLeft: b -> [emoji]
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        let (row, gcol) = (ed.cursor_row, ed.cursor_gcol);
-        // MoveLeft again: [emoji] -> a (skip entire cluster)
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        assert_eq!(ed.cursor_row, row);
-        assert_eq!(ed.cursor_gcol, gcol - 1, "emoji should count as one step");
+        // Enter on a directory expands it; on a file, opens it
+        ed = ed.handle_command(EditorCommand::TreeOpen);
+        ed = ed.handle_command(EditorCommand::TreeStep(1));
+        ed = ed.handle_command(EditorCommand::TreeOpen);
+        assert_eq!(ed.file_path(), Some(dir.join("src/main.rs").as_path()));
+        assert!(matches!(ed.mode, EditorMode::Normal));
+        assert!(ed.tree().is_some());
 
-        // MoveRight back onto emoji then Backspace once: removes the whole emoji
-        ed = ed.handle_command(EditorCommand::MoveRight);
-        let len_before = ed.text.len_chars();
-        ed = ed.handle_command(EditorCommand::Backspace);
-        let len_after = ed.text.len_chars();
-        assert!(
-            len_after < len_before,
-            "one backspace should remove entire emoji cluster"
+        // Ctrl-W goes back and forth; `q` hides the tree
+        ed = ed.handle_command(EditorCommand::SwitchPane);
+        assert!(matches!(ed.mode, EditorMode::Tree));
+        ed = ed.handle_command(EditorCommand::ToggleTree);
+        assert!(matches!(ed.mode, EditorMode::Normal));
+        assert_eq!(ed.text_left(), 0);
+        ed = ed.handle_command(EditorCommand::SwitchPane);
+        assert!(matches!(ed.mode, EditorMode::Normal));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn change_logs_merge_both_ways() {
+        let dir = std::env::temp_dir().join(format!("mters-changelog-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let run = |ed: Editor, line: &str| ed.handle_command(crate::ex::parse(line).unwrap());
+        let start = |now| {
+            let mut ed = Editor::new();
+            ed.load_scratch("one\ntwo\n");
+            ed.set_clock(Duration::from_secs(now));
+            run(ed, "Changelog start")
+        };
+        let (mut a, mut b) = (start(1), start(2));
+        assert_ne!(
+            a.changelog().unwrap().agent(),
+            b.changelog().unwrap().agent()
+        );
+        a = type_str(a, "A");
+        b = b.handle_command(EditorCommand::MoveDown);
+        b = type_str(b, "B");
+
+        a = run(
+            a,
+            &format!("Changelog write {}", dir.join("a.log").display()),
+        );
+        b = run(
+            b,
+            &format!("Changelog write {}", dir.join("b.log").display()),
         );
+        a = run(
+            a,
+            &format!("Changelog merge {}", dir.join("b.log").display()),
+        );
+        b = run(
+            b,
+            &format!("Changelog merge {}", dir.join("a.log").display()),
+        );
+        assert_eq!(a.text.to_string(), "Aone\nBtwo\n");
+        assert_eq!(b.text.to_string(), a.text.to_string());
+        // The cursor stays with the text it was on
+        assert_eq!((b.cursor_row, b.cursor_gcol), (1, 1));
+        assert_eq!((a.cursor_row, a.cursor_gcol), (0, 1));
+        assert_eq!(a.message().unwrap().text, "Changes merged");
+        a = run(a, "Changelog");
+        assert!(a.message().unwrap().text.starts_with("2 changes as agent"));
+
+        std::fs::write(dir.join("bad.log"), "i 1.1 - x\nnonsense\n").unwrap();
+        a = run(
+            a,
+            &format!("Changelog merge {}", dir.join("bad.log").display()),
+        );
+        assert_eq!(a.message().unwrap().text, "E474: Invalid argument: line 2");
+        a = run(a, "Changelog stop");
+        assert!(a.changelog().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn delete_over_newline_joins_lines_without_moving_caret_abs() {
-        // Build: "foo\nbar"
-        let mut ed = Editor::new();
-        for ch in "foo\nbar".chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        // Caret is at end (after 'r'). Move left 4 times:
-        // r -> a -> b -> (start of line 1) -> just before '\n'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // after 'a'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // after 'b'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // after '\n' (row 1, col 0)
-        ed = ed.handle_command(EditorCommand::MoveLeft); // before '\n' (row 0, col 3)
+    fn follow_reads_in_what_is_appended() {
+        let dir = std::env::temp_dir().join(format!("mters-follow-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.log");
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed.follow();
+        assert_eq!(ed.cursor_row, 2);
+        let second = Duration::from_secs(1);
+        assert_eq!(ed.next_timer(), Some(FOLLOW_POLL));
 
-        // Sanity: we are at EOL of first line
-        assert_eq!(ed.cursor_row, 0);
-        assert_eq!(ed.cursor_gcol, 3);
+        // Mtimes can be coarse; forgetting the last one stands in for a tick
+        let append = |ed: &mut Editor, text: &str, at: Duration| {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            io::Write::write_all(&mut file, text.as_bytes()).unwrap();
+            ed.disk_mtime = None;
+            *ed = ed.handle_command(EditorCommand::Tick(at));
+        };
+        append(&mut ed, "thr", second);
+        append(&mut ed, "ee\n", second * 2);
+        assert_eq!(ed.text.to_string(), "one\ntwo\nthree\n");
+        assert_eq!((ed.cursor_row, ed.modified), (3, false));
+        assert_eq!(ed.next_timer(), Some(second * 2 + FOLLOW_POLL));
 
-        // Delete should remove the newline and join lines.
-        ed = ed.handle_command(EditorCommand::Delete);
+        // Away from the end, the cursor stays put
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileStart,
+            count: 1,
+        });
+        append(&mut ed, "four\n", second * 3);
+        assert_eq!((ed.text.len_lines(), ed.cursor_row), (5, 0));
 
-        assert_eq!(ed.text.to_string(), "foobar");
-        // Caret stays at the same absolute char position (now before the old 'b')
-        assert_eq!(ed.cursor_row, 0);
-        assert_eq!(ed.cursor_gcol, 3);
+        // A change of the buffer's own ends it
+        ed = type_str(ed.handle_command(EditorCommand::EnterInsertMode), "x");
+        ed = ed.handle_command(EditorCommand::EnterNormalMode);
+        append(&mut ed, "five\n", second * 4);
+        assert!(!ed.options.follow);
+        assert_eq!(ed.text.len_lines(), 5);
+        assert_eq!(
+            ed.message().unwrap().text,
+            "Following stopped: the buffer has changes"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn delete_at_eol_joins_unix() {
-        let mut ed = Editor::new();
-        for ch in "foo\nbar".chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        // Move to just before '\n'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // 'a'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // 'b'
-        ed = ed.handle_command(EditorCommand::MoveLeft); // at row1 col0 (after '\n')
-        ed = ed.handle_command(EditorCommand::MoveLeft); // before '\n' (row0 col3)
+    fn follow_reads_a_rewritten_file_afresh() {
+        let dir = std::env::temp_dir().join(format!("mters-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build.log");
+        let lines = |word| {
+            (0..20_000)
+                .map(|i| format!("{word} {i}\n"))
+                .collect::<String>()
+        };
+        std::fs::write(&path, lines("old")).unwrap();
+        let mut ed = Editor::open(path.clone()).unwrap();
+        ed.follow();
 
-        ed = ed.handle_command(EditorCommand::Delete);
-        assert_eq!(ed.text.to_string(), "foobar");
-        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 3));
+        // Rotated: nothing of the old text left to diff against
+        std::fs::write(&path, lines("new")).unwrap();
+        ed.disk_mtime = None;
+        ed = ed.handle_command(EditorCommand::Tick(Duration::from_secs(1)));
+        assert_eq!(ed.text.to_string(), lines("new"));
+        assert!(ed.options.follow);
+        assert_eq!(ed.cursor_row, 20_000);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn delete_at_eol_joins_crlf() {
+    fn ctrl_n_completes_words_from_the_buffers() {
         let mut ed = Editor::new();
-        // simulate CRLF explicitly
-        for ch in "foo\r\nbar".chars() {
-            ed = ed.handle_command(EditorCommand::InsertChar(ch));
-        }
-        // go to before '\r'
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        ed = ed.handle_command(EditorCommand::MoveLeft);
-        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed.load_scratch("let counter = 0;\ncount");
+        ed.hide(ed.hidden());
+        ed.load_scratch("counting\n");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::FileEnd,
+            count: 1,
+        });
+        ed = type_str(ed.handle_command(EditorCommand::EnterInsertMode), "cou");
+        let complete =
+            |ed: Editor, forward| ed.handle_command(EditorCommand::CompleteWord { forward });
+        ed = complete(ed, true);
+        assert_eq!(ed.text.to_string(), "counting\ncounting");
+        ed = complete(ed, true);
+        assert_eq!(ed.text.to_string(), "counting\ncounter");
+        assert_eq!(
+            ed.completion().unwrap().items,
+            ["counting", "counter", "count"]
+        );
+        ed = complete(ed, false);
+        ed = complete(ed, false);
+        assert_eq!(ed.text.to_string(), "counting\ncou");
+        assert_eq!(ed.cursor_gcol, 3);
 
-        ed = ed.handle_command(EditorCommand::Delete);
-        assert_eq!(ed.text.to_string(), "foobar");
-        assert_eq!((ed.cursor_row, ed.cursor_gcol), (0, 3));
+        // Another key keeps the word and closes the menu
+        ed = complete(ed, false);
+        ed = type_str(ed, "s");
+        assert_eq!(ed.text.to_string(), "counting\ncounts");
+        assert!(ed.completion().is_none());
+        ed = type_str(ed, " zz");
+        ed = complete(ed, true);
+        assert_eq!(ed.message().unwrap().text, "Pattern not found");
     }
 }
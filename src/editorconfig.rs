@@ -0,0 +1,280 @@
+use crate::fileformat::LineEnding;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// One `.editorconfig`: whether it is the root, and its sections in order.
+struct File {
+    root: bool,
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+fn parse(src: &str) -> File {
+    let mut file = File {
+        root: false,
+        sections: Vec::new(),
+    };
+    for line in src.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            file.sections.push((glob.to_owned(), Vec::new()));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_ascii_lowercase(), value.trim().to_owned());
+        match file.sections.last_mut() {
+            Some((_, properties)) => properties.push((key, value)),
+            None if key == "root" => file.root = value.eq_ignore_ascii_case("true"),
+            None => {}
+        }
+    }
+    file
+}
+
+/// `{a,b}` and `{1..3}` spelled out, giving plain globs.
+fn expand_braces(glob: &str) -> Vec<String> {
+    let Some(open) = glob.find('{') else {
+        return vec![glob.to_owned()];
+    };
+    // The brace closing this one, past any nested ones
+    let mut depth = 0;
+    let close = glob[open..].char_indices().find_map(|(i, c)| {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        (depth == 0).then_some(open + i)
+    });
+    let Some(close) = close else {
+        return vec![glob.to_owned()];
+    };
+    let (head, inner, tail) = (&glob[..open], &glob[open + 1..close], &glob[close + 1..]);
+    let range = inner
+        .split_once("..")
+        .and_then(|(a, b)| Some((a.parse::<i64>().ok()?, b.parse::<i64>().ok()?)));
+    let choices: Vec<String> = match range {
+        Some((a, b)) => (a.min(b)..=a.max(b)).map(|n| n.to_string()).collect(),
+        None if inner.contains(',') => split_top_level(inner),
+        // `{single}` is taken literally
+        None => vec![format!("{{{inner}}}")],
+    };
+    choices
+        .iter()
+        .flat_map(|choice| expand_braces(&format!("{head}{choice}{tail}")))
+        .collect()
+}
+
+/// `a,{b,c},d` split on the commas outside braces.
+fn split_top_level(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().expect("never empty").push(c);
+    }
+    parts
+}
+
+/// `*`, `**`, `?` and `[...]` matched against a whole path.
+fn glob_match(glob: &[char], path: &[char]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        // `**/` also matches no directories at all
+        ['*', '*', '/', rest @ ..] => {
+            glob_match(rest, path)
+                || (0..path.len()).any(|i| path[i] == '/' && glob_match(rest, &path[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+        ['*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| glob_match(rest, &path[i..])),
+        ['?', rest @ ..] => path.first().is_some_and(|&c| c != '/') && glob_match(rest, &path[1..]),
+        ['[', rest @ ..] if rest.contains(&']') => {
+            let end = rest.iter().position(|&c| c == ']').expect("checked");
+            let (set, rest) = (&rest[..end], &rest[end + 1..]);
+            let (negated, set) = match set {
+                ['!', set @ ..] => (true, set),
+                set => (false, set),
+            };
+            let Some(&c) = path.first() else {
+                return false;
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    found |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && glob_match(rest, &path[1..])
+        }
+        ['\\', c, rest @ ..] | [c, rest @ ..] => {
+            path.first() == Some(c) && glob_match(rest, &path[1..])
+        }
+    }
+}
+
+/// Whether a section applies to `path`, given relative to the directory
+/// of its `.editorconfig`. A glob without `/` matches in any directory.
+fn section_matches(glob: &str, path: &str) -> bool {
+    let glob = match glob.strip_prefix('/') {
+        Some(anchored) => anchored.to_owned(),
+        None if glob.contains('/') => glob.to_owned(),
+        None => format!("**/{glob}"),
+    };
+    let path: Vec<char> = format!("/{path}").chars().collect();
+    expand_braces(&glob).iter().any(|glob| {
+        let glob: Vec<char> = format!("/{glob}").chars().collect();
+        glob_match(&glob, &path)
+    })
+}
+
+/// The properties for `path` from the `.editorconfig` files above it, up
+/// to the one saying `root = true`. Nearer files and later sections win.
+pub fn properties(path: &Path) -> HashMap<String, String> {
+    let Ok(path) = std::path::absolute(path) else {
+        return HashMap::new();
+    };
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(src) = std::fs::read_to_string(dir.join(".editorconfig")) else {
+            continue;
+        };
+        let file = parse(&src);
+        let root = file.root;
+        files.push((dir, file));
+        if root {
+            break;
+        }
+    }
+    let mut properties = HashMap::new();
+    for (dir, file) in files.iter().rev() {
+        let Some(relative) = path.strip_prefix(dir).ok().and_then(|p| p.to_str()) else {
+            continue;
+        };
+        for (glob, section) in &file.sections {
+            if section_matches(glob, relative) {
+                for (key, value) in section {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+    properties
+}
+
+/// What the properties ask of the buffer: `:set` arguments for its
+/// options, and the line ending to write with.
+pub fn settings(properties: &HashMap<String, String>) -> (Vec<String>, Option<LineEnding>) {
+    let get = |key: &str| {
+        properties
+            .get(key)
+            .map(|v| v.to_ascii_lowercase())
+            .filter(|v| v != "unset")
+    };
+    let number = |key: &str| {
+        get(key)
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+    };
+    let mut args = Vec::new();
+    match get("indent_style").as_deref() {
+        Some("tab") => args.push("noexpandtab".to_owned()),
+        Some("space") => args.push("expandtab".to_owned()),
+        _ => {}
+    }
+    let tab_width = number("tab_width");
+    // indent_size = tab indents by a tab's width
+    let indent_size = match get("indent_size").as_deref() {
+        Some("tab") => tab_width,
+        _ => number("indent_size"),
+    };
+    if let Some(size) = indent_size {
+        args.push(format!("shiftwidth={size}"));
+    }
+    // tab_width defaults to indent_size
+    if let Some(width) = tab_width.or(number("indent_size")) {
+        args.push(format!("tabstop={width}"));
+    }
+    let flag = |key: &str, option: &str| match get(key).as_deref() {
+        Some("true") => Some(option.to_owned()),
+        Some("false") => Some(format!("no{option}")),
+        _ => None,
+    };
+    args.extend(flag("trim_trailing_whitespace", "trimtrailing"));
+    args.extend(flag("insert_final_newline", "fixendofline"));
+    let line_ending = match get("end_of_line").as_deref() {
+        Some("lf") => Some(LineEnding::Lf),
+        Some("crlf") => Some(LineEnding::Crlf),
+        // Lone CRs aren't a format this editor writes
+        _ => None,
+    };
+    (args, line_ending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_like_editorconfig() {
+        assert!(section_matches("*", "a.rs"));
+        assert!(section_matches("*.rs", "src/deep/a.rs"));
+        assert!(!section_matches("/*.rs", "src/a.rs"));
+        assert!(section_matches("src/**.rs", "src/deep/a.rs"));
+        assert!(!section_matches("src/*.rs", "src/deep/a.rs"));
+        assert!(section_matches("*.{yml,yaml}", "ci.yaml"));
+        assert!(section_matches("{Makefile,*.mk}", "sub/Makefile"));
+        assert!(section_matches("file{1..3}.txt", "file2.txt"));
+        assert!(!section_matches("file{1..3}.txt", "file4.txt"));
+        assert!(section_matches("[!a]?.c", "bx.c"));
+        assert!(!section_matches("[a-c].c", "d.c"));
+    }
+
+    #[test]
+    fn nearer_files_win_up_to_the_root() {
+        let dir = std::env::temp_dir().join(format!("mters-editorconfig-{}", std::process::id()));
+        let sub = dir.join("proj/sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(dir.join(".editorconfig"), "[*]\nindent_style = tab\n").unwrap();
+        std::fs::write(
+            dir.join("proj/.editorconfig"),
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 2\nend_of_line = crlf\n\n\
+             [Makefile]\nindent_style = tab\n",
+        )
+        .unwrap();
+        std::fs::write(
+            sub.join(".editorconfig"),
+            "# nearer\n[*.md]\ntrim_trailing_whitespace = false\nindent_size = 4\n",
+        )
+        .unwrap();
+
+        let props = properties(&sub.join("notes.md"));
+        let (args, eol) = settings(&props);
+        assert_eq!(
+            args,
+            ["expandtab", "shiftwidth=4", "tabstop=4", "notrimtrailing"]
+        );
+        assert_eq!(eol, Some(LineEnding::Crlf));
+        // Above the root file: not read
+        let (args, _) = settings(&properties(&dir.join("proj/Makefile")));
+        assert_eq!(args, ["noexpandtab", "shiftwidth=2", "tabstop=2"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,849 @@
+use crate::conflicts::ConflictSide;
+use crate::export::Format;
+use crate::fileformat::LineEnding;
+use crate::input::EditorCommand;
+use crate::lineinput::Spec;
+use crate::options::{self, GutterColumns, IsKeyword, ListChars, Options};
+use crate::path;
+use crate::picker;
+use crate::timers;
+use std::path::PathBuf;
+
+/// Optional file argument, expanded through the shared path helper.
+fn path_arg(arg: &str) -> Result<Option<PathBuf>, String> {
+    if arg.is_empty() {
+        Ok(None)
+    } else {
+        path::expand(arg).map(Some)
+    }
+}
+
+/// `:snapshot [save|restore|diff {name}]`
+fn snapshot(arg: &str) -> Result<EditorCommand, String> {
+    let (action, name) = match arg.split_once(char::is_whitespace) {
+        Some((action, name)) => (action, name.trim()),
+        None => (arg, ""),
+    };
+    let command = match action {
+        "" => return Ok(EditorCommand::ListSnapshots),
+        "save" => EditorCommand::SaveSnapshot,
+        "restore" => EditorCommand::RestoreSnapshot,
+        "diff" => EditorCommand::DiffSnapshot,
+        _ => return Err(format!("E475: Invalid argument: {arg}")),
+    };
+    if name.is_empty() {
+        return Err("E471: Argument required".into());
+    }
+    Ok(command(name.to_owned()))
+}
+
+/// `:Changelog [start|stop|write {file}|merge {file}]`
+fn changelog(arg: &str) -> Result<EditorCommand, String> {
+    let (action, file) = match arg.split_once(char::is_whitespace) {
+        Some((action, file)) => (action, file.trim()),
+        None => (arg, ""),
+    };
+    let with_file = match action {
+        "" => return Ok(EditorCommand::ShowChangeLog),
+        "start" if file.is_empty() => return Ok(EditorCommand::StartChangeLog),
+        "stop" if file.is_empty() => return Ok(EditorCommand::StopChangeLog),
+        "write" => EditorCommand::WriteChangeLog,
+        "merge" => EditorCommand::MergeChangeLog,
+        _ => return Err(format!("E475: Invalid argument: {arg}")),
+    };
+    match path_arg(file)? {
+        Some(path) => Ok(with_file(path)),
+        None => Err("E471: Argument required".into()),
+    }
+}
+
+/// `:Every {interval} {command}` starts a timer, `:Every stop [id]` stops
+/// one or all, and `:Every` on its own lists them.
+fn every(arg: &str) -> Result<EditorCommand, String> {
+    let (first, rest) = match arg.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest.trim()),
+        None => (arg, ""),
+    };
+    match first {
+        "" => Ok(EditorCommand::ListTimers),
+        "stop" if rest.is_empty() => Ok(EditorCommand::StopTimer(None)),
+        "stop" => rest
+            .parse()
+            .map(|id| EditorCommand::StopTimer(Some(id)))
+            .map_err(|_| format!("E475: Invalid argument: {rest}")),
+        _ if rest.is_empty() => Err("E471: Argument required".into()),
+        _ => {
+            let every = timers::parse_interval(first)
+                .ok_or_else(|| format!("E475: Invalid argument: {first}"))?;
+            // Checked now rather than each time it runs
+            parse(rest)?;
+            Ok(EditorCommand::StartTimer {
+                every,
+                command: rest.to_owned(),
+            })
+        }
+    }
+}
+
+/// Options that belong to the file rather than `Options`.
+const FILE_OPTIONS: &[&str] = &["ff", "fileformat", "bomb", "ft", "filetype"];
+
+/// `:set`: `name`, `noname`, `invname` / `name!`, `name=value`, and
+/// `name?` to show one. On its own it lists what differs from the
+/// defaults; `:set all` lists everything. Several arguments are made in
+/// turn, as `:set ts=4 sw=4`.
+fn set(arg: &str) -> Result<EditorCommand, String> {
+    let mut settings = set_args(arg)
+        .iter()
+        .map(|arg| setting(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    if settings.len() < 2 {
+        return settings.pop().map_or_else(|| setting(""), Ok);
+    }
+    // Options asked about are shown together
+    let mut shown = Vec::new();
+    for cmd in &settings {
+        match cmd {
+            EditorCommand::ShowOptions(names) => shown.extend_from_slice(names),
+            _ => return Ok(EditorCommand::SetMany(settings)),
+        }
+    }
+    Ok(EditorCommand::ShowOptions(shown))
+}
+
+/// `:set`'s arguments, split at blanks. A backslash keeps a blank or a
+/// backslash after it, so `makeprg=cargo\ build` is one.
+fn set_args(arg: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.clone().next() {
+                Some(next) if next == '\\' || next.is_whitespace() => {
+                    current.push(next);
+                    chars.next();
+                }
+                _ => current.push(c),
+            },
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    args
+}
+
+/// One `:set` argument, blanks and all.
+pub fn setting(arg: &str) -> Result<EditorCommand, String> {
+    let known = |name: &str| options::full_name(name).is_some() || FILE_OPTIONS.contains(&name);
+    let is_flag = |name: &str| Options::default().flag_mut(name).is_some();
+    if arg.is_empty() {
+        return Ok(EditorCommand::ShowOptions(Vec::new()));
+    }
+    if arg == "all" {
+        let all = options::NAMES.iter().map(|&(name, _)| name.to_owned());
+        return Ok(EditorCommand::ShowOptions(
+            all.chain(["fileformat", "bomb", "filetype"].map(String::from))
+                .collect(),
+        ));
+    }
+    if let Some(name) = arg.strip_suffix('?') {
+        if !known(name) {
+            return Err(format!("E518: Unknown option: {name}"));
+        }
+        return Ok(EditorCommand::ShowOptions(vec![name.to_owned()]));
+    }
+    if let Some(name) = arg.strip_prefix("inv").or(arg.strip_suffix('!')) {
+        if !is_flag(name) {
+            return Err(format!("E474: Invalid argument: {arg}"));
+        }
+        return Ok(EditorCommand::ToggleFlag(name.to_owned()));
+    }
+    match arg.split_once('=') {
+        Some(("ff" | "fileformat", value)) => LineEnding::from_name(value)
+            .map(EditorCommand::SetLineEnding)
+            .ok_or_else(|| format!("E474: Invalid argument: {arg}")),
+        Some((name, value)) if Options::default().number_mut(name).is_some() => {
+            match value.parse() {
+                // A zero tab stop would never advance; other options take 0
+                Ok(0) if matches!(name, "ts" | "tabstop") => {
+                    Err(format!("E487: Argument must be positive: {arg}"))
+                }
+                Ok(value) => Ok(EditorCommand::SetNumber {
+                    name: name.to_owned(),
+                    value,
+                }),
+                Err(_) => Err(format!("E521: Number required after =: {arg}")),
+            }
+        }
+        Some(("ft" | "filetype", value)) => Ok(EditorCommand::SetFiletype(value.to_owned())),
+        Some(("cc" | "colorcolumn", value)) => value
+            .split(',')
+            .filter(|c| !c.is_empty())
+            .map(|c| c.parse().ok().filter(|&c| c > 0))
+            .collect::<Option<Vec<usize>>>()
+            .map(EditorCommand::SetColorColumn)
+            .ok_or_else(|| format!("E474: Invalid argument: {arg}")),
+        Some(("lcs" | "listchars", value)) => {
+            ListChars::parse(value).map(EditorCommand::SetListChars)
+        }
+        Some(("gutter", value)) => GutterColumns::parse(value).map(EditorCommand::SetGutter),
+        Some(("mp" | "makeprg", value)) => Ok(EditorCommand::SetMakePrg(value.to_owned())),
+        Some(("cms" | "commentstring", value)) => {
+            if !value.is_empty() && !value.contains("%s") {
+                return Err("E537: 'commentstring' must be empty or contain %s".into());
+            }
+            Ok(EditorCommand::SetCommentString(value.to_owned()))
+        }
+        Some(("isk" | "iskeyword", value)) => {
+            IsKeyword::parse(value).map(EditorCommand::SetIsKeyword)
+        }
+        Some((name @ ("isk+" | "iskeyword+" | "isk-" | "iskeyword-"), parts)) => {
+            // The parts are checked here; what they change comes later
+            IsKeyword::parse(parts)?;
+            Ok(EditorCommand::ChangeIsKeyword {
+                parts: parts.to_owned(),
+                add: name.ends_with('+'),
+            })
+        }
+        Some((name, _)) if is_flag(name) => Err(format!("E474: Invalid argument: {arg}")),
+        None if arg == "bomb" => Ok(EditorCommand::SetBom(true)),
+        None if arg == "nobomb" => Ok(EditorCommand::SetBom(false)),
+        // Like Vim, naming an option that isn't a flag shows it
+        None if known(arg) && !is_flag(arg) => Ok(EditorCommand::ShowOptions(vec![arg.to_owned()])),
+        None => {
+            let (name, value) = match arg.strip_prefix("no") {
+                Some(name) if is_flag(name) => (name, false),
+                _ => (arg, true),
+            };
+            if !is_flag(name) {
+                return Err(format!("E518: Unknown option: {arg}"));
+            }
+            Ok(EditorCommand::SetFlag {
+                name: name.to_owned(),
+                value,
+            })
+        }
+        _ => Err(format!("E518: Unknown option: {arg}")),
+    }
+}
+
+/// The `:` command line.
+pub const COMMAND_LINE: Spec = Spec {
+    prefix: ':',
+    complete,
+    validate: |line| parse(line).map(drop),
+};
+
+/// Names offered by Tab, in the order they are tried.
+pub const NAMES: &[&str] = &[
+    "bdelete",
+    "bnext",
+    "bprevious",
+    "buffer",
+    "buffers",
+    "cbuffer",
+    "cc",
+    "cclose",
+    "checkhealth",
+    "cnext",
+    "colorscheme",
+    "confirm",
+    "copen",
+    "cprevious",
+    "Ctags",
+    "edit",
+    "goto",
+    "grep",
+    "ls",
+    "make",
+    "messages",
+    "notes",
+    "pop",
+    "quit",
+    "retab",
+    "set",
+    "snapshot",
+    "stop",
+    "suspend",
+    "tag",
+    "tags",
+    "tselect",
+    "write",
+    "Changelog",
+    "ConflictTake",
+    "Eval",
+    "Every",
+    "Export",
+    "HunkApply",
+    "HunkRevert",
+    "Pick",
+    "Pipe",
+    "Tree",
+];
+
+/// Command names, or the file argument of `:e`, `:w` and `:colorscheme`.
+fn complete(line: &str) -> Vec<String> {
+    let Some((name, arg)) = line.split_once(' ') else {
+        return NAMES
+            .iter()
+            .filter(|n| n.starts_with(line))
+            .map(|n| n.to_string())
+            .collect();
+    };
+    if matches!(name, "se" | "set") {
+        return options::NAMES
+            .iter()
+            .filter(|&&(option, _)| option.starts_with(arg))
+            .map(|&(option, _)| format!("{name} {option}"))
+            .collect();
+    }
+    if name == "Pick" {
+        return picker::SOURCES
+            .iter()
+            .filter(|s| s.name.starts_with(arg))
+            .map(|s| format!("Pick {}", s.name))
+            .collect();
+    }
+    if !matches!(name, "e" | "edit" | "w" | "write" | "colo" | "colorscheme") {
+        return Vec::new();
+    }
+    let (dir, file) = path::split_last(arg, path::WINDOWS);
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let mut found: Vec<String> = entries
+        .flatten()
+        .filter_map(|e| {
+            let entry = e.file_name().into_string().ok()?;
+            // Dotfiles only when asked for
+            if !entry.starts_with(file) || (entry.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+            let slash = if e.path().is_dir() { "/" } else { "" };
+            Some(format!("{name} {dir}{entry}{slash}"))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// One end of a line range: a line number (from 1), `.`, `$` or the
+/// line of a mark, `'a`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Address {
+    Line(usize),
+    Current,
+    Last,
+    Mark(char),
+}
+
+/// `{from},{to}` before a command, inclusive; `%` is `1,$`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub from: Address,
+    pub to: Address,
+}
+
+fn address(s: &str) -> Option<(Address, &str)> {
+    if let Some(rest) = s.strip_prefix('.') {
+        return Some((Address::Current, rest));
+    }
+    if let Some(rest) = s.strip_prefix('$') {
+        return Some((Address::Last, rest));
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        let mark = rest.chars().next()?;
+        return Some((Address::Mark(mark), &rest[mark.len_utf8()..]));
+    }
+    let digits = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let line = s[..digits].parse().ok()?;
+    Some((Address::Line(line), &s[digits..]))
+}
+
+/// The range at the start of `line`, if there is one, and the rest.
+fn line_range(line: &str) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = line.strip_prefix('%') {
+        let whole = LineRange {
+            from: Address::Line(1),
+            to: Address::Last,
+        };
+        return Ok((Some(whole), rest));
+    }
+    let Some((from, rest)) = address(line) else {
+        return Ok((None, line));
+    };
+    let Some(rest) = rest.strip_prefix(',') else {
+        return Ok((Some(LineRange { from, to: from }), rest));
+    };
+    let (to, rest) = address(rest).ok_or("E14: Invalid address")?;
+    Ok((Some(LineRange { from, to }), rest))
+}
+
+/// `:[range]retab[!] [tabstop]`
+fn retab(arg: &str, force: bool) -> Result<EditorCommand, String> {
+    let tabstop = match arg {
+        "" => None,
+        _ => match arg.parse() {
+            Ok(tabstop) if tabstop > 0 => Some(tabstop),
+            _ => return Err(format!("E487: Argument must be positive: {arg}")),
+        },
+    };
+    Ok(EditorCommand::Retab {
+        range: None,
+        tabstop,
+        force,
+    })
+}
+
+/// Parse an ex command line (without the leading ':') into an editor command.
+pub fn parse(line: &str) -> Result<EditorCommand, String> {
+    let line = line.trim();
+    match line_range(line)? {
+        (Some(range), rest) if !rest.trim().is_empty() => match command(rest.trim())? {
+            EditorCommand::Retab { tabstop, force, .. } => Ok(EditorCommand::Retab {
+                range: Some(range),
+                tabstop,
+                force,
+            }),
+            EditorCommand::Pipe { command, force, .. } => Ok(EditorCommand::Pipe {
+                range: Some(range),
+                command,
+                force,
+            }),
+            EditorCommand::Export {
+                format,
+                path,
+                force,
+                ..
+            } => Ok(EditorCommand::Export {
+                range: Some(range),
+                format,
+                path,
+                force,
+            }),
+            _ => Err("E481: No range allowed".into()),
+        },
+        (Some(_), _) => Err(format!("E492: Not an editor command: {line}")),
+        (None, _) => command(line),
+    }
+}
+
+/// A command line without a range.
+fn command(line: &str) -> Result<EditorCommand, String> {
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+
+    // A trailing '!' forces the command past the modified/exists checks.
+    let (name, force) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+
+    match name {
+        "conf" | "confirm" => match parse(arg)? {
+            EditorCommand::Exit { force, .. } => Ok(EditorCommand::Exit {
+                force,
+                confirm: true,
+            }),
+            EditorCommand::Write { path, force, .. } => Ok(EditorCommand::Write {
+                path,
+                force,
+                confirm: true,
+            }),
+            other => Ok(other),
+        },
+        "q" | "quit" => Ok(EditorCommand::Exit {
+            force,
+            confirm: false,
+        }),
+        "e" | "edit" => Ok(EditorCommand::Edit(path_arg(arg)?)),
+        "w" | "write" => Ok(EditorCommand::Write {
+            path: path_arg(arg)?,
+            force,
+            confirm: false,
+        }),
+        "ConflictTake" => match arg {
+            "ours" => Ok(EditorCommand::ResolveConflict(ConflictSide::Ours)),
+            "theirs" => Ok(EditorCommand::ResolveConflict(ConflictSide::Theirs)),
+            "both" => Ok(EditorCommand::ResolveConflict(ConflictSide::Both)),
+            _ => Err(format!("E475: Invalid argument: {arg}")),
+        },
+        "ls" | "buffers" | "files" => Ok(EditorCommand::ListBuffers),
+        "b" | "buffer" => Ok(EditorCommand::ShowBuffer(arg.to_owned())),
+        "bn" | "bnext" | "bp" | "bprevious" | "bN" | "bNext" => {
+            let count = match arg {
+                "" => 1,
+                _ => match arg.parse::<isize>() {
+                    Ok(count) if count > 0 => count,
+                    _ => return Err(format!("E487: Argument must be positive: {arg}")),
+                },
+            };
+            let back = !name.starts_with("bn");
+            Ok(EditorCommand::StepBuffer(if back { -count } else { count }))
+        }
+        "bd" | "bdelete" => Ok(EditorCommand::DeleteBuffer {
+            name: Some(arg.to_owned()).filter(|a| !a.is_empty()),
+            force,
+        }),
+        "mes" | "messages" => Ok(EditorCommand::ShowMessages),
+        "notes" => Ok(EditorCommand::OpenNotes),
+        "checkh" | "checkhealth" => Ok(EditorCommand::CheckHealth),
+        "st" | "stop" | "sus" | "suspend" => Ok(EditorCommand::Suspend),
+        "go" | "goto" => match arg {
+            "" => Ok(EditorCommand::GotoByte(1)),
+            _ => arg
+                .parse()
+                .map(EditorCommand::GotoByte)
+                .map_err(|_| format!("E474: Invalid argument: {arg}")),
+        },
+        "colo" | "colorscheme" => match path_arg(arg)? {
+            Some(path) => Ok(EditorCommand::LoadTheme(path)),
+            None => Err("E471: Argument required".into()),
+        },
+        "ret" | "retab" => retab(arg, force),
+        "se" | "set" => set(arg),
+        "snap" | "snapshot" => snapshot(arg),
+        "Changelog" => changelog(arg),
+        "ta" | "tag" => match arg {
+            "" => Err("E471: Argument required".into()),
+            _ => Ok(EditorCommand::JumpToTag(Some(arg.to_owned()))),
+        },
+        "ts" | "tselect" => Ok(EditorCommand::SelectTag(
+            Some(arg.to_owned()).filter(|a| !a.is_empty()),
+        )),
+        "po" | "pop" => Ok(EditorCommand::PopTag),
+        "tags" => Ok(EditorCommand::ShowTagStack),
+        "gr" | "grep" => match arg {
+            "" => Err("E471: Argument required".into()),
+            _ => Ok(EditorCommand::Grep(arg.to_owned())),
+        },
+        "mak" | "make" => Ok(EditorCommand::Make(arg.to_owned())),
+        "cb" | "cbuffer" => Ok(EditorCommand::QuickfixFromBuffer),
+        "cn" | "cnext" | "cp" | "cprevious" | "cN" | "cNext" => {
+            let count = match arg {
+                "" => 1,
+                _ => match arg.parse::<isize>() {
+                    Ok(count) if count > 0 => count,
+                    _ => return Err(format!("E487: Argument must be positive: {arg}")),
+                },
+            };
+            let back = !name.starts_with("cn");
+            Ok(EditorCommand::QuickfixStep(if back {
+                -count
+            } else {
+                count
+            }))
+        }
+        "cc" => match arg {
+            "" => Ok(EditorCommand::QuickfixGo(None)),
+            _ => arg
+                .parse()
+                .map(|n| EditorCommand::QuickfixGo(Some(n)))
+                .map_err(|_| format!("E474: Invalid argument: {arg}")),
+        },
+        "cope" | "copen" => Ok(EditorCommand::QuickfixPane(true)),
+        "ccl" | "cclose" => Ok(EditorCommand::QuickfixPane(false)),
+        "Ctags" => Ok(EditorCommand::GenerateTags),
+        "Eval" => Ok(EditorCommand::EvalBlock),
+        "Every" => every(arg),
+        "Pipe" => match arg {
+            "" => Err("E471: Argument required".into()),
+            _ => Ok(EditorCommand::Pipe {
+                range: None,
+                command: arg.to_owned(),
+                force,
+            }),
+        },
+        "Export" => {
+            let (format, file) = match arg.split_once(char::is_whitespace) {
+                Some((format, file)) => (format, file.trim()),
+                None => (arg, ""),
+            };
+            match Format::from_name(format) {
+                Some(format) => Ok(EditorCommand::Export {
+                    range: None,
+                    format,
+                    path: path_arg(file)?,
+                    force,
+                }),
+                None if format.is_empty() => Err("E471: Argument required".into()),
+                None => Err(format!("E475: Invalid argument: {format}")),
+            }
+        }
+        "Pick" => match picker::source(arg) {
+            Some(source) => Ok(EditorCommand::OpenPicker(source.name.to_owned())),
+            None => Err(format!("E475: Invalid argument: {arg}")),
+        },
+        "Tree" => Ok(EditorCommand::ToggleTree),
+        "HunkApply" => Ok(EditorCommand::ApplyHunk { reverse: false }),
+        "HunkRevert" => Ok(EditorCommand::ApplyHunk { reverse: true }),
+        _ => Err(format!("E492: Not an editor command: {line}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_commands() {
+        assert_eq!(
+            parse("q"),
+            Ok(EditorCommand::Exit {
+                force: false,
+                confirm: false
+            })
+        );
+        assert_eq!(
+            parse(" ConflictTake  theirs "),
+            Ok(EditorCommand::ResolveConflict(ConflictSide::Theirs))
+        );
+        assert!(parse("ConflictTake mine").is_err());
+        assert_eq!(
+            parse("HunkRevert"),
+            Ok(EditorCommand::ApplyHunk { reverse: true })
+        );
+        assert!(parse("frobnicate").is_err());
+        assert_eq!(
+            parse("ta main"),
+            Ok(EditorCommand::JumpToTag(Some("main".into())))
+        );
+        assert!(parse("tag").is_err());
+        assert_eq!(parse("tselect"), Ok(EditorCommand::SelectTag(None)));
+        assert_eq!(parse("tags"), Ok(EditorCommand::ShowTagStack));
+        assert_eq!(
+            parse("snap save before refactor"),
+            Ok(EditorCommand::SaveSnapshot("before refactor".into()))
+        );
+        assert_eq!(parse("snapshot"), Ok(EditorCommand::ListSnapshots));
+        assert_eq!(
+            parse("Changelog merge peer.log"),
+            Ok(EditorCommand::MergeChangeLog("peer.log".into()))
+        );
+        assert_eq!(
+            parse("Changelog write"),
+            Err("E471: Argument required".into())
+        );
+        assert!(parse("Changelog stop now").is_err());
+        assert!(parse("snapshot restore").is_err());
+    }
+
+    #[test]
+    fn ranges_go_to_the_commands_that_take_them() {
+        let retab = |range, tabstop, force| {
+            Ok(EditorCommand::Retab {
+                range,
+                tabstop,
+                force,
+            })
+        };
+        assert_eq!(parse("retab"), retab(None, None, false));
+        let whole = LineRange {
+            from: Address::Line(1),
+            to: Address::Last,
+        };
+        assert_eq!(parse("%ret! 4"), retab(Some(whole), Some(4), true));
+        let here_on = LineRange {
+            from: Address::Current,
+            to: Address::Last,
+        };
+        assert_eq!(parse(".,$retab"), retab(Some(here_on), None, false));
+        assert_eq!(
+            parse("'a,'bPipe! jq .name"),
+            Ok(EditorCommand::Pipe {
+                range: Some(LineRange {
+                    from: Address::Mark('a'),
+                    to: Address::Mark('b'),
+                }),
+                command: "jq .name".into(),
+                force: true,
+            })
+        );
+        assert!(parse("Pipe").is_err());
+        assert_eq!(
+            parse("Every 5m  w"),
+            Ok(EditorCommand::StartTimer {
+                every: std::time::Duration::from_secs(300),
+                command: "w".into(),
+            })
+        );
+        assert_eq!(parse("Every stop 2"), Ok(EditorCommand::StopTimer(Some(2))));
+        assert_eq!(parse("Every"), Ok(EditorCommand::ListTimers));
+        assert_eq!(parse("Tree"), Ok(EditorCommand::ToggleTree));
+        assert_eq!(
+            parse("%Export! html out.html"),
+            Ok(EditorCommand::Export {
+                range: Some(LineRange {
+                    from: Address::Line(1),
+                    to: Address::Last,
+                }),
+                format: Format::Html,
+                path: Some("out.html".into()),
+                force: true,
+            })
+        );
+        assert!(parse("Export pdf").is_err());
+        assert_eq!(parse("bp 2"), Ok(EditorCommand::StepBuffer(-2)));
+        assert_eq!(parse("bn"), Ok(EditorCommand::StepBuffer(1)));
+        assert!(parse("bn 0").is_err());
+        assert_eq!(parse("cN 3"), Ok(EditorCommand::QuickfixStep(-3)));
+        assert_eq!(parse("cc"), Ok(EditorCommand::QuickfixGo(None)));
+        assert!(parse("grep").is_err());
+        assert_eq!(
+            parse("bd! 3"),
+            Ok(EditorCommand::DeleteBuffer {
+                name: Some("3".into()),
+                force: true
+            })
+        );
+        assert!(parse("Every 5m").is_err());
+        assert!(parse("Every soon w").is_err());
+        assert!(parse("Every 5m frobnicate").is_err());
+        let one = LineRange {
+            from: Address::Line(12),
+            to: Address::Line(12),
+        };
+        assert_eq!(parse("12 retab"), retab(Some(one), None, false));
+        assert!(parse("retab 0").is_err());
+        assert_eq!(parse("1,w"), Err("E14: Invalid address".into()));
+        assert_eq!(parse("%w"), Err("E481: No range allowed".into()));
+    }
+
+    #[test]
+    fn file_commands_expand_their_path() {
+        assert_eq!(
+            parse("w"),
+            Ok(EditorCommand::Write {
+                path: None,
+                force: false,
+                confirm: false
+            })
+        );
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(
+            parse("e ~/x.txt"),
+            Ok(EditorCommand::Edit(Some(PathBuf::from(format!(
+                "{home}/x.txt"
+            )))))
+        );
+        assert!(parse("w $MTERS_SURELY_UNSET_VAR/x").is_err());
+    }
+
+    #[test]
+    fn bang_and_confirm_modifiers() {
+        assert_eq!(
+            parse("q!"),
+            Ok(EditorCommand::Exit {
+                force: true,
+                confirm: false
+            })
+        );
+        assert_eq!(
+            parse("confirm w! out.txt"),
+            Ok(EditorCommand::Write {
+                path: Some(PathBuf::from("out.txt")),
+                force: true,
+                confirm: true
+            })
+        );
+        assert_eq!(
+            parse("conf HunkApply"),
+            Ok(EditorCommand::ApplyHunk { reverse: false })
+        );
+    }
+
+    #[test]
+    fn set_file_format() {
+        assert_eq!(
+            parse("set ff=dos"),
+            Ok(EditorCommand::SetLineEnding(LineEnding::Crlf))
+        );
+        assert_eq!(parse("se nobomb"), Ok(EditorCommand::SetBom(false)));
+        assert!(parse("set ff=mac").is_err());
+        assert_eq!(
+            parse("set nonu"),
+            Ok(EditorCommand::SetFlag {
+                name: "nu".into(),
+                value: false
+            })
+        );
+        assert!(parse("set frobnicate").is_err());
+        assert_eq!(
+            parse("set ts=4"),
+            Ok(EditorCommand::SetNumber {
+                name: "ts".into(),
+                value: 4
+            })
+        );
+        assert!(parse("set tabstop=0").is_err());
+        assert!(parse("set sw=x").is_err());
+        assert_eq!(
+            parse("set sw=0"),
+            Ok(EditorCommand::SetNumber {
+                name: "sw".into(),
+                value: 0
+            })
+        );
+        assert_eq!(
+            parse(r"set nonu mp=cargo\ build\\x"),
+            Ok(EditorCommand::SetMany(vec![
+                EditorCommand::SetFlag {
+                    name: "nu".into(),
+                    value: false
+                },
+                EditorCommand::SetMakePrg(r"cargo build\x".into()),
+            ]))
+        );
+        assert!(parse("set nu frobnicate").is_err());
+        assert!(matches!(
+            parse("set lcs=eol:$"),
+            Ok(EditorCommand::SetListChars(_))
+        ));
+        assert!(parse("set listchars=eol:").is_err());
+        assert_eq!(
+            parse("set cc=80,100"),
+            Ok(EditorCommand::SetColorColumn(vec![80, 100]))
+        );
+        assert_eq!(parse("set cc="), Ok(EditorCommand::SetColorColumn(vec![])));
+        assert_eq!(
+            parse("set isk+=-"),
+            Ok(EditorCommand::ChangeIsKeyword {
+                parts: "-".to_owned(),
+                add: true
+            })
+        );
+        assert!(parse("set isk=1-999").is_err());
+        assert!(parse("set cc=0").is_err());
+    }
+
+    #[test]
+    fn completes_names_and_file_arguments() {
+        assert_eq!(complete("me"), ["messages"]);
+        assert_eq!(complete("Hunk"), ["HunkApply", "HunkRevert"]);
+
+        let dir = std::env::temp_dir().join(format!("mters-complete-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("notes.md"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let d = dir.display();
+        assert_eq!(
+            complete(&format!("e {d}/")),
+            [format!("e {d}/notes.md"), format!("e {d}/sub/")]
+        );
+        assert_eq!(complete(&format!("w {d}/.h")), [format!("w {d}/.hidden")]);
+        assert!(complete("set ff").is_empty());
+        assert_eq!(complete("set rel"), ["set relativenumber"]);
+        assert_eq!(complete("Pick f"), ["Pick files"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
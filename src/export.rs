@@ -0,0 +1,188 @@
+use crate::buffer::Style;
+use crate::color::{Attribute, Color};
+use crate::syntax::Syntax;
+use crate::theme::{Group, Theme};
+use std::ops::Range;
+
+/// What `:Export` writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// A page of its own, colours and all, to open in a browser.
+    Html,
+    /// Text with terminal escapes, for `cat` or `less -R`.
+    Ansi,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "html" => Some(Format::Html),
+            "ansi" => Some(Format::Ansi),
+            _ => None,
+        }
+    }
+}
+
+/// A line cut into runs of one style each.
+fn runs<'a>(line: &'a str, syntax: Option<&Syntax>, theme: &Theme) -> Vec<(&'a str, Style)> {
+    let spans: Vec<(Range<usize>, Group)> = syntax.map(|s| s.highlight(line)).unwrap_or_default();
+    let mut runs = Vec::new();
+    let mut at = 0;
+    for (range, group) in spans {
+        if at < range.start {
+            runs.push((&line[at..range.start], Style::default()));
+        }
+        runs.push((&line[range.clone()], theme.get(group)));
+        at = range.end;
+    }
+    if at < line.len() {
+        runs.push((&line[at..], Style::default()));
+    }
+    runs
+}
+
+/// `lines` (without their line breaks) coloured as `syntax` and `theme`
+/// would colour them, in `format`.
+pub fn render(
+    lines: &[&str],
+    syntax: Option<&Syntax>,
+    theme: &Theme,
+    format: Format,
+    title: &str,
+    tabstop: usize,
+) -> String {
+    let mut out = String::new();
+    if format == Format::Html {
+        out.push_str(&format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>pre {{ tab-size: {tabstop}; }}</style>\n\
+             </head>\n<body>\n<pre>",
+            escape(title)
+        ));
+    }
+    for line in lines {
+        for (text, style) in runs(line, syntax, theme) {
+            match format {
+                Format::Html => match css(style) {
+                    Some(css) => {
+                        out.push_str(&format!("<span style=\"{css}\">{}</span>", escape(text)))
+                    }
+                    None => out.push_str(&escape(text)),
+                },
+                Format::Ansi => out.push_str(&sgr(text, style)),
+            }
+        }
+        out.push('\n');
+    }
+    if format == Format::Html {
+        out.push_str("</pre>\n</body>\n</html>\n");
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A style as CSS, or None for the page's own look.
+fn css(style: Style) -> Option<String> {
+    let (mut fg, mut bg) = (style.fg.and_then(hex), style.bg.and_then(hex));
+    if style.attrs.has(Attribute::Reverse) {
+        // The page's colours where the style has none
+        (fg, bg) = (
+            Some(bg.unwrap_or_else(|| "Canvas".to_owned())),
+            Some(fg.unwrap_or_else(|| "CanvasText".to_owned())),
+        );
+    }
+    let mut css = Vec::new();
+    css.extend(fg.map(|c| format!("color: {c}")));
+    css.extend(bg.map(|c| format!("background: {c}")));
+    if style.attrs.has(Attribute::Bold) {
+        css.push("font-weight: bold".to_owned());
+    }
+    if style.attrs.has(Attribute::Italic) {
+        css.push("font-style: italic".to_owned());
+    }
+    if style.attrs.has(Attribute::Underlined) {
+        css.push("text-decoration: underline".to_owned());
+    }
+    (!css.is_empty()).then(|| css.join("; "))
+}
+
+/// `#rrggbb` for a terminal colour; None for the terminal's default.
+fn hex(color: Color) -> Option<String> {
+    let (r, g, b) = color.rgb()?;
+    Some(format!("#{r:02x}{g:02x}{b:02x}"))
+}
+
+/// `text` between the escapes that style it and the one that undoes it.
+fn sgr(text: &str, style: Style) -> String {
+    let mut params: Vec<String> = style.attrs.iter().map(|a| a.sgr().to_string()).collect();
+    for (color, base) in [(style.fg, 38), (style.bg, 48)] {
+        params.extend(color.and_then(|c| match c {
+            Color::Reset => None,
+            Color::Rgb { r, g, b } => Some(format!("{base};2;{r};{g};{b}")),
+            _ => c.ansi_index().map(|n| format!("{base};5;{n}")),
+        }));
+    }
+    if params.is_empty() {
+        return text.to_owned();
+    }
+    format!("\x1b[{}m{text}\x1b[0m", params.join(";"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+    use std::path::Path;
+
+    #[test]
+    fn html_and_ansi_keep_the_colours() {
+        let rust = syntax::for_path(Path::new("a.rs"));
+        let theme = Theme::default();
+        let lines = ["let s = \"<b>\"; // &"];
+        let html = render(&lines, rust, &theme, Format::Html, "a.rs", 4);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>a.rs</title>"));
+        assert!(html.contains(
+            "<pre><span style=\"color: #ff00ff\">let</span> s = \
+             <span style=\"color: #00ff00\">&quot;&lt;b&gt;&quot;</span>; \
+             <span style=\"color: #7f7f7f\">// &amp;</span>\n</pre>"
+        ));
+
+        let ansi = render(&lines, rust, &theme, Format::Ansi, "a.rs", 4);
+        assert!(ansi.starts_with("\x1b[38;5;13mlet\x1b[0m s = "));
+        assert!(ansi.ends_with("\x1b[38;5;8m// &\x1b[0m\n"));
+        let bold_on_red = Style {
+            bg: Some(Color::Rgb { r: 255, g: 0, b: 0 }),
+            attrs: Attribute::Bold.into(),
+            ..Style::default()
+        };
+        assert_eq!(sgr("x", bold_on_red), "\x1b[1;48;2;255;0;0mx\x1b[0m");
+        // Nothing to colour: the text as it is
+        assert_eq!(
+            render(&["plain"], None, &theme, Format::Ansi, "", 4),
+            "plain\n"
+        );
+    }
+
+    #[test]
+    fn terminal_colours_as_css() {
+        assert_eq!(hex(Color::Rgb { r: 1, g: 2, b: 255 }).unwrap(), "#0102ff");
+        assert_eq!(hex(Color::AnsiValue(196)).unwrap(), "#ff0000");
+        assert_eq!(hex(Color::AnsiValue(244)).unwrap(), "#808080");
+        assert_eq!(hex(Color::Reset), None);
+        let reverse = Style {
+            attrs: Attribute::Reverse.into(),
+            ..Style::default()
+        };
+        assert_eq!(
+            css(reverse).unwrap(),
+            "color: Canvas; background: CanvasText"
+        );
+    }
+}
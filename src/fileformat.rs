@@ -0,0 +1,143 @@
+use ropey::Rope;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// What new files get: CRLF on Windows, like Vim's `fileformats`
+    /// there, LF elsewhere.
+    pub const NATIVE: Self = if cfg!(windows) {
+        LineEnding::Crlf
+    } else {
+        LineEnding::Lf
+    };
+
+    /// Name used by `:set ff=` and shown in the status line.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+        }
+    }
+
+    /// The break itself, as typed by Enter.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Lf),
+            "dos" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+}
+
+/// How the file is stored on disk, as opposed to what's in the buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    /// The file starts with a UTF-8 byte order mark, kept out of the buffer.
+    pub bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::NATIVE,
+            bom: false,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Detect the format of freshly read text and strip its BOM. Like Vim,
+    /// the file is `dos` only if every line break is CRLF; with no line
+    /// breaks at all it is whatever new files are.
+    pub fn detect(text: &mut Rope) -> Self {
+        let bom = text.len_chars() > 0 && text.char(0) == '\u{feff}';
+        if bom {
+            text.remove(0..1);
+        }
+        let mut breaks = 0;
+        let mut crlf = 0;
+        let mut prev = '\0';
+        for c in text.chars() {
+            if c == '\n' {
+                breaks += 1;
+                crlf += usize::from(prev == '\r');
+            }
+            prev = c;
+        }
+        let line_ending = if breaks == 0 {
+            LineEnding::NATIVE
+        } else if crlf == breaks {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        };
+        Self { line_ending, bom }
+    }
+
+    /// Status line label, e.g. `utf-8[BOM] dos`.
+    pub fn label(&self) -> String {
+        let bom = if self.bom { "[BOM]" } else { "" };
+        format!("utf-8{bom} {}", self.line_ending.name())
+    }
+}
+
+/// Positions where line breaks must change to convert `text` to `to`:
+/// a '\r' to remove (Lf) or a '\n' to put a '\r' before (Crlf), last first
+/// so each edit leaves the earlier positions valid.
+pub fn conversion_points(text: &Rope, to: LineEnding) -> Vec<usize> {
+    let mut points = Vec::new();
+    let mut prev = '\0';
+    for (i, c) in text.chars().enumerate() {
+        if c == '\n' {
+            match (to, prev == '\r') {
+                (LineEnding::Lf, true) => points.push(i - 1),
+                (LineEnding::Crlf, false) => points.push(i),
+                _ => {}
+            }
+        }
+        prev = c;
+    }
+    points.reverse();
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_line_endings_and_bom() {
+        let mut text = Rope::from_str("\u{feff}a\r\nb\r\n");
+        let ff = FileFormat::detect(&mut text);
+        assert_eq!(text.to_string(), "a\r\nb\r\n");
+        assert_eq!(ff.label(), "utf-8[BOM] dos");
+
+        // Mixed breaks: unix
+        let mut mixed = Rope::from_str("a\r\nb\n");
+        assert_eq!(FileFormat::detect(&mut mixed).line_ending, LineEnding::Lf);
+        // No breaks: as a new file
+        assert_eq!(
+            FileFormat::detect(&mut Rope::from_str("one line")).line_ending,
+            LineEnding::NATIVE
+        );
+    }
+
+    #[test]
+    fn finds_breaks_to_convert() {
+        let text = Rope::from_str("a\r\nb\nc\r\n");
+        assert_eq!(conversion_points(&text, LineEnding::Lf), vec![6, 1]);
+        assert_eq!(conversion_points(&text, LineEnding::Crlf), vec![4]);
+    }
+}
@@ -0,0 +1,189 @@
+#[cfg(feature = "config")]
+use crate::input::EditorCommand;
+use std::path::Path;
+
+/// Whole file names with a type of their own.
+const NAMES: &[(&str, &str)] = &[
+    ("Makefile", "make"),
+    ("makefile", "make"),
+    ("GNUmakefile", "make"),
+    ("Dockerfile", "dockerfile"),
+    ("Cargo.lock", "toml"),
+];
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("go", "go"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("hpp", "cpp"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("ts", "typescript"),
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("toml", "toml"),
+    ("json", "json"),
+    ("md", "markdown"),
+    ("txt", "text"),
+    ("mk", "make"),
+    ("html", "html"),
+    ("css", "css"),
+    ("lisp", "lisp"),
+    ("el", "lisp"),
+];
+
+/// Interpreters named on a `#!` line.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("zsh", "sh"),
+    ("python", "python"),
+    ("node", "javascript"),
+    ("make", "make"),
+];
+
+/// The type of a file, as `:set filetype?` names it: by its name, its
+/// extension, and failing those the `#!` line it starts with.
+pub fn detect(path: &Path, first_line: &str) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    if let Some(&(_, ft)) = NAMES.iter().find(|&&(n, _)| n == name) {
+        return Some(ft);
+    }
+    let by_extension = path.extension().and_then(|e| e.to_str()).and_then(|ext| {
+        EXTENSIONS
+            .iter()
+            .find(|&&(e, _)| e == ext)
+            .map(|&(_, ft)| ft)
+    });
+    by_extension.or_else(|| shebang(first_line))
+}
+
+/// `#!/bin/sh`, `#!/usr/bin/env python3`, `#!/usr/bin/env -S node --flag`.
+fn shebang(line: &str) -> Option<&'static str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    // python3, python3.12
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|&&(p, _)| p == program)
+        .map(|&(_, ft)| ft)
+}
+
+/// Settings a filetype comes with before the config file has its say:
+/// how it writes comments, and `-` as part of a name in CSS and Lisp.
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("rust", &["commentstring=// %s"]),
+    ("go", &["commentstring=// %s"]),
+    ("c", &["commentstring=// %s"]),
+    ("cpp", &["commentstring=// %s"]),
+    ("java", &["commentstring=// %s"]),
+    ("javascript", &["commentstring=// %s"]),
+    ("typescript", &["commentstring=// %s"]),
+    ("python", &["commentstring=# %s"]),
+    ("sh", &["commentstring=# %s"]),
+    ("yaml", &["commentstring=# %s"]),
+    ("toml", &["commentstring=# %s"]),
+    ("make", &["commentstring=# %s"]),
+    ("dockerfile", &["commentstring=# %s"]),
+    ("html", &["commentstring=<!-- %s -->"]),
+    ("markdown", &["commentstring=<!-- %s -->"]),
+    ("css", &["iskeyword+=-", "commentstring=/* %s */"]),
+    ("lisp", &["iskeyword+=-", "commentstring=;; %s"]),
+];
+
+pub fn builtin_settings(filetype: &str) -> &'static [&'static str] {
+    BUILTIN
+        .iter()
+        .find(|&&(ft, _)| ft == filetype)
+        .map_or(&[], |&(_, args)| args)
+}
+
+/// Per-filetype settings from the `[filetype]` table of the config file,
+/// each kept as the `:set` argument that applies it:
+///
+/// ```toml
+/// [filetype.yaml]
+/// shiftwidth = 2
+///
+/// [filetype.make]
+/// expandtab = false
+/// ```
+#[cfg(feature = "config")]
+pub fn parse_settings(
+    table: &toml::Table,
+) -> Result<std::collections::HashMap<String, Vec<String>>, String> {
+    let mut settings = std::collections::HashMap::new();
+    for (filetype, options) in table {
+        let Some(options) = options.as_table() else {
+            return Err(format!("E474: filetype.{filetype} must be a table"));
+        };
+        let mut args = Vec::new();
+        for (name, value) in options {
+            let arg = match value {
+                toml::Value::Boolean(true) => name.clone(),
+                toml::Value::Boolean(false) => format!("no{name}"),
+                toml::Value::Integer(n) => format!("{name}={n}"),
+                toml::Value::String(s) => format!("{name}={s}"),
+                _ => {
+                    return Err(format!(
+                        "E474: filetype.{filetype}: Invalid argument: {name}"
+                    ))
+                }
+            };
+            // Checked as `:set` would check it, so a bad one shows at startup.
+            let cmd = crate::ex::parse(&format!("set {arg}"))
+                .map_err(|e| format!("filetype.{filetype}: {e}"))?;
+            if !matches!(
+                cmd,
+                EditorCommand::SetFlag { .. }
+                    | EditorCommand::SetNumber { .. }
+                    | EditorCommand::SetColorColumn(_)
+                    | EditorCommand::SetListChars(_)
+                    | EditorCommand::SetGutter(_)
+                    | EditorCommand::SetIsKeyword(_)
+                    | EditorCommand::SetCommentString(_)
+                    | EditorCommand::SetMakePrg(_)
+                    | EditorCommand::ChangeIsKeyword { .. }
+            ) {
+                return Err(format!(
+                    "E474: filetype.{filetype}: {name} isn't set per filetype"
+                ));
+            }
+            args.push(arg);
+        }
+        settings.insert(filetype.clone(), args);
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_name_extension_and_shebang() {
+        let ft = |path: &str, line: &str| detect(Path::new(path), line);
+        assert_eq!(ft("src/main.rs", ""), Some("rust"));
+        assert_eq!(ft("ci.yml", ""), Some("yaml"));
+        assert_eq!(ft("sub/Makefile", ""), Some("make"));
+        assert_eq!(ft("build", "#!/bin/bash"), Some("sh"));
+        assert_eq!(ft("tool", "#!/usr/bin/env python3.12"), Some("python"));
+        assert_eq!(
+            ft("run", "#!/usr/bin/env -S node --harmony"),
+            Some("javascript")
+        );
+        // The extension wins over the first line
+        assert_eq!(ft("notes.md", "#!/bin/sh"), Some("markdown"));
+        assert_eq!(ft("README", "hello"), None);
+    }
+}
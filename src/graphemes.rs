@@ -1,10 +1,11 @@
+use crate::buffer::grapheme_width;
 use ropey::{
     str_utils::{byte_to_char_idx, char_to_byte_idx},
     Rope,
 };
-use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
 
-/// ------ Internal byte/char helpers (no allocation) -------------------------
+// ------ Internal byte/char helpers (no allocation) -------------------------
 
 #[inline]
 fn abs_char_to_abs_byte(text: &Rope, ci: usize) -> usize {
@@ -85,7 +86,7 @@ fn step_grapheme_bound(text: &Rope, from_byte: usize, forward: bool) -> usize {
     }
 }
 
-/// ------ Public: allocation-free next/prev grapheme at absolute char index ----
+// ------ Public: allocation-free next/prev grapheme at absolute char index ----
 
 /// Next grapheme boundary (absolute *char* index) from an absolute *char* index.
 /// If already at end, returns `text.len_chars()`.
@@ -103,7 +104,7 @@ pub fn prev_grapheme_abs_char(text: &Rope, abs_ci: usize) -> usize {
     abs_byte_to_abs_char(text, prev_byte)
 }
 
-/// ------ Public: line-relative helpers (allocation-free) ---------------------
+// ------ Public: line-relative helpers (allocation-free) ---------------------
 
 /// Count grapheme clusters on a line without allocating.
 pub fn line_gcount(text: &Rope, row: usize) -> usize {
@@ -188,3 +189,41 @@ pub fn abs_char_to_line_gcol(text: &Rope, abs_ci: usize) -> (usize, usize) {
 
     (row, gcol)
 }
+
+// ------ Public: screen columns ----------------------------------------------
+
+/// Cells grapheme `g` takes when drawn from screen column `col`: a tab runs
+/// to the next multiple of `tabstop`, anything else is 1 or 2 wide.
+pub fn cell_width(g: &str, col: usize, tabstop: usize) -> usize {
+    if g == "\t" {
+        let tabstop = tabstop.max(1);
+        tabstop - col % tabstop
+    } else {
+        grapheme_width(g)
+    }
+}
+
+/// Screen column where grapheme `gcol` of `line` starts; past the end each
+/// missing grapheme counts one.
+pub fn gcol_to_vcol(line: &str, gcol: usize, tabstop: usize) -> usize {
+    let mut vcol = 0;
+    let mut seen = 0;
+    for g in line.graphemes(true).take(gcol) {
+        vcol += cell_width(g, vcol, tabstop);
+        seen += 1;
+    }
+    vcol + (gcol - seen)
+}
+
+/// Grapheme column of `line` drawn over screen column `vcol`, or the
+/// grapheme count when the line is shorter.
+pub fn vcol_to_gcol(line: &str, vcol: usize, tabstop: usize) -> usize {
+    let mut col = 0;
+    for (gcol, g) in line.graphemes(true).enumerate() {
+        col += cell_width(g, col, tabstop);
+        if col > vcol {
+            return gcol;
+        }
+    }
+    line.graphemes(true).count()
+}
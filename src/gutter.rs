@@ -0,0 +1,75 @@
+use crate::editor::Editor;
+use crate::theme::Group;
+
+/// One part of the gutter. The `gutter` option names the ones shown and
+/// their order; a new one goes in `COMPONENTS` to be nameable there.
+pub trait Component: Sync {
+    /// What `gutter` calls it.
+    fn name(&self) -> &'static str;
+    /// Columns it needs now, its own trailing space included; 0 leaves it
+    /// out, as the numbers are without `number` or `relativenumber`.
+    fn width(&self, editor: &Editor) -> usize;
+    /// What it shows beside `row`, in `width` columns.
+    fn cell(&self, editor: &Editor, row: usize, width: usize) -> (String, Group);
+}
+
+pub const COMPONENTS: &[&dyn Component] = &[&Number, &Marks];
+
+pub fn find(name: &str) -> Option<&'static dyn Component> {
+    COMPONENTS.iter().copied().find(|c| c.name() == name)
+}
+
+/// Number shown for `row`: with `relativenumber`, lines other than the
+/// cursor's show how far away they are, so `5j` is easy to eyeball.
+pub fn line_number(editor: &Editor, row: usize) -> usize {
+    let cursor = editor.cursor_row;
+    if editor.options().relativenumber && row != cursor && editor.preview().is_none() {
+        row.abs_diff(cursor)
+    } else {
+        row + 1
+    }
+}
+
+/// Line numbers, right-aligned.
+pub struct Number;
+
+impl Component for Number {
+    fn name(&self) -> &'static str {
+        "number"
+    }
+
+    fn width(&self, editor: &Editor) -> usize {
+        let opts = editor.options();
+        if !(opts.number || opts.relativenumber) {
+            return 0;
+        }
+        let last_row = match editor.preview() {
+            Some(_) => editor.view().top + editor.view().height,
+            None => editor.text.len_lines() - 1,
+        };
+        (last_row + 1).to_string().len().max(3) + 1
+    }
+
+    fn cell(&self, editor: &Editor, row: usize, width: usize) -> (String, Group) {
+        let number = format!("{:>w$} ", line_number(editor, row), w = width - 1);
+        (number, Group::LineNumber)
+    }
+}
+
+/// The first mark (`a`-`z`) on each line.
+pub struct Marks;
+
+impl Component for Marks {
+    fn name(&self) -> &'static str {
+        "marks"
+    }
+
+    fn width(&self, _: &Editor) -> usize {
+        2
+    }
+
+    fn cell(&self, editor: &Editor, row: usize, width: usize) -> (String, Group) {
+        let mark = editor.mark_on(row).unwrap_or(' ');
+        (format!("{mark:<width$}"), Group::LineNumber)
+    }
+}
@@ -0,0 +1,309 @@
+use crate::color::ColorSupport;
+#[cfg(feature = "config")]
+use crate::config;
+#[cfg(feature = "config")]
+use crate::filetype;
+#[cfg(feature = "config")]
+use crate::keymap::Keymap;
+#[cfg(feature = "config")]
+use crate::notebook;
+use crate::notes;
+#[cfg(feature = "config")]
+use crate::theme::{self, Theme};
+#[cfg(feature = "config")]
+use crate::timers;
+use std::path::{Path, PathBuf};
+
+/// How one check came out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Ok,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Info => "INFO",
+            Status::Warning => "WARNING",
+            Status::Error => "ERROR",
+        }
+    }
+}
+
+/// A heading and what was found under it.
+struct Section {
+    title: &'static str,
+    checks: Vec<(Status, String)>,
+}
+
+/// Clipboard commands, in the order they are usually preferred.
+const CLIPBOARD_TOOLS: &[&str] = &["wl-copy", "xclip", "xsel", "pbcopy", "clip.exe"];
+
+/// `:checkhealth`: what the editor can see of its environment, as text to
+/// paste into a bug report.
+pub fn report(cwd: &Path) -> String {
+    report_with(cwd, |name| std::env::var(name).ok())
+}
+
+/// `report` with an injectable variable lookup (for tests).
+fn report_with(cwd: &Path, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let sections = [
+        terminal(&lookup),
+        clipboard(&lookup),
+        tools(&lookup),
+        config(cwd, &lookup),
+    ];
+    let mut out = format!("mters {} health report\n", env!("CARGO_PKG_VERSION"));
+    for section in sections {
+        out.push_str(&format!("\n{}\n", section.title));
+        for (status, text) in section.checks {
+            out.push_str(&format!("- {}: {text}\n", status.label()));
+        }
+    }
+    out
+}
+
+fn terminal(lookup: &impl Fn(&str) -> Option<String>) -> Section {
+    let term = lookup("TERM").unwrap_or_default();
+    let windows = lookup("OS").as_deref() == Some("Windows_NT");
+    let mut checks = vec![match term.as_str() {
+        // Windows consoles don't set TERM
+        "" if lookup("WT_SESSION").is_some() => (Status::Ok, "Windows Terminal".to_owned()),
+        "" if windows => (
+            Status::Warning,
+            "the Windows console host (conhost): Windows Terminal has more colours and keys"
+                .to_owned(),
+        ),
+        "" => (Status::Warning, "$TERM is not set".to_owned()),
+        "dumb" => (
+            Status::Error,
+            "TERM=dumb can't show a full-screen editor".to_owned(),
+        ),
+        _ => (Status::Ok, format!("TERM={term}")),
+    }];
+    checks.push(match ColorSupport::detect_with(lookup) {
+        ColorSupport::TrueColor => (Status::Ok, "24-bit colour".to_owned()),
+        ColorSupport::Ansi256 => (
+            Status::Warning,
+            "no 24-bit colour: theme colours are shown as the nearest of 256. \
+             Set COLORTERM=truecolor if the terminal has it"
+                .to_owned(),
+        ),
+    });
+    let kitty = term == "xterm-kitty"
+        || lookup("KITTY_WINDOW_ID").is_some()
+        || matches!(
+            lookup("TERM_PROGRAM").as_deref(),
+            Some("WezTerm" | "ghostty")
+        );
+    checks.push((
+        Status::Info,
+        if kitty {
+            "the terminal looks to speak the kitty keyboard protocol".to_owned()
+        } else {
+            "no sign of the kitty keyboard protocol".to_owned()
+        },
+    ));
+    Section {
+        title: "Terminal",
+        checks,
+    }
+}
+
+fn clipboard(lookup: &impl Fn(&str) -> Option<String>) -> Section {
+    let path = lookup("PATH").unwrap_or_default();
+    let found: Vec<&str> = CLIPBOARD_TOOLS
+        .iter()
+        .copied()
+        .filter(|tool| find_in_path(tool, &path).is_some())
+        .collect();
+    let check = match found.first() {
+        Some(tool) => (Status::Ok, format!("clipboard tool: {tool}")),
+        None => (
+            Status::Warning,
+            format!(
+                "no clipboard tool on $PATH (tried {})",
+                CLIPBOARD_TOOLS.join(", ")
+            ),
+        ),
+    };
+    Section {
+        title: "Clipboard",
+        checks: vec![check],
+    }
+}
+
+fn tools(lookup: &impl Fn(&str) -> Option<String>) -> Section {
+    let path = lookup("PATH").unwrap_or_default();
+    let ctags = match find_in_path("ctags", &path) {
+        Some(found) => (
+            Status::Ok,
+            format!("ctags (for :Ctags): {}", found.display()),
+        ),
+        None => (
+            Status::Warning,
+            "ctags not on $PATH: :Ctags won't work".to_owned(),
+        ),
+    };
+    Section {
+        title: "External tools",
+        checks: vec![ctags],
+    }
+}
+
+fn config(cwd: &Path, lookup: &impl Fn(&str) -> Option<String>) -> Section {
+    let mut checks = Vec::new();
+    #[cfg(feature = "config")]
+    config_files(lookup, &mut checks);
+    #[cfg(not(feature = "config"))]
+    checks.push((
+        Status::Info,
+        "built without the config feature: no config.toml or themes".to_owned(),
+    ));
+    checks.push(match notes::path_with(cwd, lookup) {
+        Ok(path) => (Status::Ok, format!(":notes file: {}", path.display())),
+        Err(e) => (Status::Error, format!(":notes has nowhere to go: {e}")),
+    });
+    let tags = cwd.join("tags");
+    checks.push(if tags.exists() {
+        (Status::Ok, format!("tags file: {}", tags.display()))
+    } else {
+        (
+            Status::Info,
+            "no tags file here; :Ctags makes one".to_owned(),
+        )
+    });
+    Section {
+        title: "Configuration",
+        checks,
+    }
+}
+
+/// `config.toml` and the theme files, parsed as the editor would.
+#[cfg(feature = "config")]
+fn config_files(lookup: &impl Fn(&str) -> Option<String>, checks: &mut Vec<(Status, String)>) {
+    if let Some(file) = config::dir_with(lookup).map(|d| d.join("config.toml")) {
+        checks.push(match std::fs::read_to_string(&file) {
+            Ok(src) => match src.parse::<toml::Table>() {
+                Ok(table) => {
+                    let section = |name| table.get(name).and_then(|t| t.as_table());
+                    let keymap = section("keymap").map(|t| Keymap::parse(t).map(drop));
+                    let filetypes =
+                        section("filetype").map(|t| filetype::parse_settings(t).map(drop));
+                    let modeline = table.get("modeline").map(|v| {
+                        v.as_bool()
+                            .map(drop)
+                            .ok_or_else(|| "E474: modeline must be true or false".to_owned())
+                    });
+                    let runners = table
+                        .get("runner")
+                        .map(|t| notebook::parse_runners(t).map(drop));
+                    let timers = table
+                        .get("timer")
+                        .map(|t| timers::parse_config(t).map(drop));
+                    let mut results = keymap
+                        .into_iter()
+                        .chain(filetypes)
+                        .chain(modeline)
+                        .chain(runners)
+                        .chain(timers);
+                    match results.find_map(Result::err) {
+                        Some(e) => (Status::Error, format!("{}: {e}", file.display())),
+                        None => (Status::Ok, format!("config file: {}", file.display())),
+                    }
+                }
+                Err(e) => (Status::Error, format!("{}: {e}", file.display())),
+            },
+            Err(_) => (
+                Status::Info,
+                format!("no config file at {}", file.display()),
+            ),
+        });
+    }
+    match theme::dir_with(lookup) {
+        Some(dir) => match std::fs::read_dir(&dir) {
+            Ok(entries) => {
+                let mut themes: Vec<PathBuf> = entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                    .collect();
+                themes.sort();
+                checks.push((
+                    Status::Ok,
+                    format!("{} theme(s) in {}", themes.len(), dir.display()),
+                ));
+                for path in themes {
+                    if let Err(e) = Theme::load(&path) {
+                        checks.push((Status::Error, format!("{}: {e}", path.display())));
+                    }
+                }
+            }
+            Err(_) => checks.push((Status::Info, format!("no themes in {}", dir.display()))),
+        },
+        None => checks.push((
+            Status::Warning,
+            "neither $XDG_CONFIG_HOME nor $HOME is set: no themes".to_owned(),
+        )),
+    }
+}
+
+/// The first `name` in the directories of a `$PATH`-style list.
+fn find_in_path(name: &str, path: &str) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .find(|file| file.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_what_it_finds() {
+        let bin = std::env::temp_dir().join(format!("mters-health-{}", std::process::id()));
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("xclip"), "").unwrap();
+        let path = bin.display().to_string();
+        let env = move |name: &str| match name {
+            "TERM" => Some("xterm-kitty".to_owned()),
+            "PATH" => Some(path.clone()),
+            "HOME" => Some("/nonexistent".to_owned()),
+            _ => None,
+        };
+        let report = report_with(Path::new("/nonexistent"), env);
+        assert!(report.starts_with("mters "));
+        assert!(report.contains("\nTerminal\n- OK: TERM=xterm-kitty\n"));
+        assert!(report.contains("- WARNING: no 24-bit colour"));
+        assert!(report.contains("- INFO: the terminal looks to speak the kitty"));
+        assert!(report.contains("- OK: clipboard tool: xclip\n"));
+        assert!(report.contains("- WARNING: ctags not on $PATH"));
+        if cfg!(feature = "config") {
+            assert!(report
+                .contains("- INFO: no config file at /nonexistent/.config/mters/config.toml\n"));
+            assert!(report.contains("- INFO: no themes in /nonexistent/.config/mters/themes\n"));
+        } else {
+            assert!(report.contains("- INFO: built without the config feature"));
+        }
+        std::fs::remove_dir_all(&bin).unwrap();
+    }
+
+    #[test]
+    fn tells_windows_consoles_apart() {
+        let report = |wt: bool| {
+            report_with(Path::new("/nonexistent"), move |name: &str| match name {
+                "OS" => Some("Windows_NT".to_owned()),
+                "WT_SESSION" if wt => Some("1".to_owned()),
+                "USERPROFILE" => Some(r"C:\Users\me".to_owned()),
+                _ => None,
+            })
+        };
+        assert!(report(true).contains("- OK: Windows Terminal\n- OK: 24-bit colour"));
+        assert!(report(false).contains("- WARNING: the Windows console host (conhost)"));
+        assert!(report(false).contains("- WARNING: no 24-bit colour"));
+    }
+}
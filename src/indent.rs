@@ -0,0 +1,114 @@
+use crate::options::Options;
+
+/// Display width of a run of leading whitespace.
+pub fn width(leading: &str, tabstop: usize) -> usize {
+    leading.chars().fold(0, |col, c| match c {
+        '\t' => (col / tabstop + 1) * tabstop,
+        _ => col + 1,
+    })
+}
+
+/// Leading whitespace `width` columns wide, using tabs unless `expandtab`.
+pub fn build(width: usize, opts: &Options) -> String {
+    if opts.expandtab {
+        " ".repeat(width)
+    } else {
+        let mut s = "\t".repeat(width / opts.tabstop);
+        s.push_str(&" ".repeat(width % opts.tabstop));
+        s
+    }
+}
+
+/// What ends a line that opens a block in each filetype, besides the
+/// brackets that open one everywhere.
+const OPENERS: &[(&str, &[char])] = &[("python", &[':']), ("yaml", &[':']), ("make", &[':'])];
+
+/// The spaces and tabs `line` starts with.
+pub fn leading(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Whether a line ending like `line` opens a block in `filetype`: the
+/// lines after it go a level further in.
+pub fn opens_block(line: &str, filetype: &str) -> bool {
+    let Some(last) = line.trim_end().chars().last() else {
+        return false;
+    };
+    matches!(last, '{' | '(' | '[')
+        || OPENERS
+            .iter()
+            .any(|&(ft, chars)| ft == filetype && chars.contains(&last))
+}
+
+/// The indent for a line opened after `line`: none, or with `autoindent`
+/// the same as `line`'s, and with `smartindent` a level more when `line`
+/// opens a block.
+pub fn next_line(line: &str, filetype: &str, opts: &Options) -> String {
+    if opts.smartindent && opens_block(line, filetype) {
+        shift(leading(line), 1, opts)
+    } else if opts.autoindent || opts.smartindent {
+        leading(line).to_owned()
+    } else {
+        String::new()
+    }
+}
+
+/// New leading whitespace after shifting `leading` by `levels` shiftwidths
+/// (negative to outdent). Outdenting stops at column 0.
+pub fn shift(leading: &str, levels: isize, opts: &Options) -> String {
+    let old = width(leading, opts.tabstop) as isize;
+    let new = (old + levels * opts.shift_columns() as isize).max(0);
+    build(new as usize, opts)
+}
+
+/// `:retab` for one line's indentation: the same width, measured with
+/// `tabstop`, rebuilt for `opts`. Runs without a tab stay as they are
+/// unless `all`. None when nothing would change.
+pub fn retab(leading: &str, tabstop: usize, opts: &Options, all: bool) -> Option<String> {
+    if !all && !leading.contains('\t') {
+        return None;
+    }
+    let rebuilt = build(width(leading, tabstop), opts);
+    (rebuilt != leading).then_some(rebuilt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_by_shiftwidth() {
+        let spaces = Options::default();
+        assert_eq!(shift("", 1, &spaces), "    ");
+        assert_eq!(shift("  ", -1, &spaces), "");
+        assert_eq!(shift("\t", -1, &spaces), "    ");
+
+        let tabs = Options {
+            expandtab: false,
+            ..Options::default()
+        };
+        assert_eq!(shift("    ", 1, &tabs), "\t");
+        assert_eq!(shift("\t", 1, &tabs), "\t    ");
+        assert_eq!(width(" \t x", 8), 10);
+    }
+
+    #[test]
+    fn retab_keeps_the_width() {
+        let spaces = Options::default();
+        assert_eq!(
+            retab("\t  ", 8, &spaces, false).as_deref(),
+            Some(" ".repeat(10).as_str())
+        );
+        assert_eq!(retab("  ", 8, &spaces, true), None);
+
+        let tabs = Options {
+            expandtab: false,
+            tabstop: 4,
+            ..Options::default()
+        };
+        // Without `all`, only runs that have a tab in them
+        assert_eq!(retab("        ", 8, &tabs, false), None);
+        assert_eq!(retab("        ", 8, &tabs, true).as_deref(), Some("\t\t"));
+        assert_eq!(retab(" \t", 8, &tabs, false).as_deref(), Some("\t\t"));
+    }
+}
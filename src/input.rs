@@ -1,7 +1,20 @@
+use crate::conflicts::ConflictSide;
 use crate::editor::{EditorMode, Pending};
-use crossterm::event::{KeyCode, KeyEvent};
+use crate::ex::LineRange;
+use crate::export::Format;
+use crate::fileformat::LineEnding;
+use crate::keys::{KeyCode, KeyEvent, KeyModifiers};
+use crate::motion::Motion;
+use crate::operator::{Operator, Target};
+use crate::options::{GutterColumns, IsKeyword, ListChars};
+use crate::registers::Registers;
+use crate::tags::Tag;
+use crate::textobject::TextObject;
+use crate::view::{Align, Scroll};
+use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum EditorCommand {
     // Movement
     MoveUp,
@@ -9,19 +22,308 @@ pub enum EditorCommand {
     MoveLeft,
     MoveRight,
 
+    Move {
+        motion: Motion,
+        count: usize,
+    },
+    /// `zz` / `zt` / `zb`: move the view, not the cursor.
+    AlignView(Align),
+    /// Ctrl-D/U take a count of lines, Ctrl-F/B a count of pages.
+    Scroll {
+        scroll: Scroll,
+        count: Option<usize>,
+    },
+
     // Editing
     InsertChar(char),
+    /// Ctrl-N / Ctrl-P in Insert mode: the next or previous word from the
+    /// open buffers that starts like the one before the cursor.
+    CompleteWord {
+        forward: bool,
+    },
+    ReplaceChar {
+        ch: char,
+        count: usize,
+    },
+    ToggleCase {
+        count: usize,
+    },
+    OpenLineBelow,
+    OpenLineAbove,
+    JoinLines {
+        count: usize,
+        spaces: bool,
+    },
     InsertNewline,
-    DeleteLine { count: usize },
-    MoveToStartOfFile,
-    WordForward { count: usize },
+    Operate {
+        op: Operator,
+        target: Target,
+        count: usize,
+    },
     Backspace,
     Delete,
+    /// Replace mode: type over the grapheme under the caret.
+    OverwriteChar(char),
+    /// Replace mode Backspace: step back, restoring what was overwritten.
+    RestoreOverwritten,
+    /// `p` / `P`: a register `count` times after / before the cursor.
+    Put {
+        register: char,
+        before: bool,
+        count: usize,
+    },
+    /// Swap the text just put for an older (or newer) numbered register.
+    CyclePut {
+        older: bool,
+    },
+    /// Swap the text just put for an older (or newer) kill ring entry,
+    /// like Emacs's yank-pop.
+    YankPop {
+        older: bool,
+    },
+
+    /// `:goto {byte}` / `{count}go`
+    GotoByte(usize),
+
+    // Marks
+    SetMark(char),
+    JumpToMark {
+        name: char,
+        linewise: bool,
+    },
+
+    // Merge conflicts
+    NextConflict,
+    PrevConflict,
+    ResolveConflict(ConflictSide),
+
+    // Unified diffs
+    ApplyHunk {
+        reverse: bool,
+    },
+
+    // Options
+    /// `:set name` / `:set noname` for a boolean option.
+    SetFlag {
+        name: String,
+        value: bool,
+    },
+    /// `:set colorcolumn=80,100`
+    SetColorColumn(Vec<usize>),
+    /// `:set listchars=...`
+    SetListChars(ListChars),
+    /// `:set gutter=marks,number`
+    SetGutter(GutterColumns),
+    /// `:set iskeyword=...`
+    SetIsKeyword(IsKeyword),
+    /// `:set commentstring=//\ %s`
+    SetCommentString(String),
+    /// `:set makeprg=cargo\ build`
+    SetMakePrg(String),
+    /// `:set iskeyword+=...` (`add`) or `:set iskeyword-=...`
+    ChangeIsKeyword {
+        parts: String,
+        add: bool,
+    },
+    /// `:set name=N` for a numeric option.
+    SetNumber {
+        name: String,
+        value: usize,
+    },
+    /// `:set filetype=...`: that type's settings from the config.
+    SetFiletype(String),
+    /// `:set invname` / `:set name!`
+    ToggleFlag(String),
+    /// `:set name?`, or with no names the options changed from their defaults.
+    ShowOptions(Vec<String>),
+    /// `:set` with several arguments: each in turn.
+    SetMany(Vec<EditorCommand>),
+
+    /// `:colorscheme {file}`
+    LoadTheme(PathBuf),
+    /// `:notes`: the project's scratch buffer.
+    OpenNotes,
+    /// `:[range]retab[!] [tabstop]`: indentation rebuilt for `expandtab`
+    /// at the same width; with `!`, runs of spaces alone too.
+    Retab {
+        range: Option<LineRange>,
+        tabstop: Option<usize>,
+        force: bool,
+    },
+    /// `:checkhealth`: a report on the environment, in an unnamed buffer.
+    CheckHealth,
+    /// No key for `updatetime` ms; sent by the main loop, not mapped.
+    CursorHold,
+    /// A timer may be due at the time given; sent by the main loop, not
+    /// mapped.
+    Tick(Duration),
+    /// `:Every {interval} {command}`
+    StartTimer {
+        every: Duration,
+        command: String,
+    },
+    /// `:Every stop [id]`: one timer, or all of them.
+    StopTimer(Option<usize>),
+    /// `:Every` on its own.
+    ListTimers,
+
+    // Pickers
+    /// `:Pick {source}`
+    OpenPicker(String),
+    PickerInput(char),
+    PickerBackspace,
+    /// Up/Down or Ctrl-P/Ctrl-N.
+    PickerStep(isize),
+    PickerAccept,
+
+    // File tree
+    /// `:Tree`: show the sidebar and move into it, or hide it.
+    ToggleTree,
+    /// Ctrl-W: between the sidebar and the text.
+    SwitchPane,
+    /// j/k or Down/Up in the sidebar.
+    TreeStep(isize),
+    /// Enter, `l` or `o`: open the selected file, or expand or collapse
+    /// the selected directory.
+    TreeOpen,
+    /// `h`: collapse the selected directory, or go up to its parent.
+    TreeCollapse,
+    /// `R`: read the directories again.
+    TreeRefresh,
+
+    // Snapshots
+    /// `:snapshot save {name}`
+    SaveSnapshot(String),
+    /// `:snapshot restore {name}`
+    RestoreSnapshot(String),
+    /// `:snapshot diff {name}`: what changed since the snapshot.
+    DiffSnapshot(String),
+    /// `:snapshot` on its own.
+    ListSnapshots,
+
+    // Change log
+    /// `:Changelog start`: record every change from here on, with stable
+    /// IDs a peer can merge.
+    StartChangeLog,
+    /// `:Changelog stop`
+    StopChangeLog,
+    /// `:Changelog write {file}`: every change recorded, one per line.
+    WriteChangeLog(PathBuf),
+    /// `:Changelog merge {file}`: changes a peer wrote, merged into the
+    /// text.
+    MergeChangeLog(PathBuf),
+    /// `:Changelog` on its own.
+    ShowChangeLog,
+
+    // Tags
+    /// Ctrl-] (the word under the cursor) or `:tag {name}`.
+    JumpToTag(Option<String>),
+    /// `:tselect [name]`: pick one of several matches.
+    SelectTag(Option<String>),
+    /// Ctrl-T / `:pop`: back to where the last tag jump started.
+    PopTag,
+    /// `:tags`: the tag stack, oldest jump first.
+    ShowTagStack,
+    /// A tag chosen in the symbols picker.
+    GotoTag(Tag),
+    /// `:Ctags`: run `ctags -R` here.
+    GenerateTags,
+
+    // Quickfix
+    /// `:grep {args}`: `grep -rn` here, its matches made the quickfix list.
+    Grep(String),
+    /// `:make [args]`: `makeprg`, the places in its output made the list.
+    Make(String),
+    /// `:cbuffer`: the list read from the lines of the buffer.
+    QuickfixFromBuffer,
+    /// `:cnext` / `:cprevious`, `count` entries on.
+    QuickfixStep(isize),
+    /// `:cc [N]`: entry N, or the current one again.
+    QuickfixGo(Option<usize>),
+    /// `:copen` (true) / `:cclose`: the list in a pane under the text.
+    QuickfixPane(bool),
+    /// `:Eval`: run the fenced code block under the cursor and put what it
+    /// printed under it.
+    EvalBlock,
+    /// `:[range]Pipe[!] {command}`: the lines through a shell command,
+    /// its output shown in a scratch buffer. The whole buffer when no
+    /// range is given.
+    Pipe {
+        range: Option<LineRange>,
+        command: String,
+        force: bool,
+    },
+    /// `:[range]Export[!] {html|ansi} [file]`: the lines coloured as the
+    /// theme colours them, written to the file or else shown in a new
+    /// buffer.
+    Export {
+        range: Option<LineRange>,
+        format: Format,
+        path: Option<PathBuf>,
+        force: bool,
+    },
+
+    // File format
+    SetLineEnding(LineEnding),
+    SetBom(bool),
+
+    // Files
+    Edit(Option<PathBuf>),
+    Write {
+        path: Option<PathBuf>,
+        /// `:w!` writes over an existing file without asking.
+        force: bool,
+        confirm: bool,
+    },
+
+    // Buffers
+    /// `:ls`
+    ListBuffers,
+    /// `:b {number or part of a name}`
+    ShowBuffer(String),
+    /// `:bn` / `]b` forward, `:bp` / `[b` back, wrapping around.
+    StepBuffer(isize),
+    /// `:bd[!] [number or name]`, the current buffer when none is given.
+    DeleteBuffer {
+        name: Option<String>,
+        force: bool,
+    },
+
+    // Command line
+    EnterCommandMode,
+    CmdlineInput(char),
+    CmdlineBackspace,
+    /// Up / Down: recall earlier lines.
+    CmdlineHistory {
+        older: bool,
+    },
+    /// Tab
+    CmdlineComplete,
+    CmdlineExecute,
+    /// Key typed at a yes/no/cancel prompt, lowercased.
+    PromptAnswer(char),
+    /// `:messages`: the message history.
+    ShowMessages,
 
     // Control
     EnterInsertMode,
+    EnterReplaceMode,
+    /// `V`: linewise Visual mode.
+    EnterVisualLine,
+    /// An operator typed in Visual mode, on the lines selected.
+    OperateSelection(Operator),
+    Append,
+    AppendLineEnd,
+    InsertLineStart,
     EnterNormalMode,
+    /// `:q`: refuses (or asks, with `confirm`) while there are unsaved changes.
+    Exit {
+        force: bool,
+        confirm: bool,
+    },
     Quit,
+    /// Ctrl-Z / `:suspend`: stop, as a shell job, until `fg`.
+    Suspend,
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,21 +333,97 @@ pub enum KeyMappingResult {
     Noop,
 }
 
+/// Under the kitty keyboard protocol Ctrl-I, Ctrl-M and Ctrl-[ arrive
+/// apart from Tab, Enter and Esc. Nothing binds them on their own, so they
+/// keep the meaning they have on every other terminal.
+fn legacy_alias(event: KeyEvent) -> KeyEvent {
+    if event.modifiers != KeyModifiers::CONTROL {
+        return event;
+    }
+    let code = match event.code {
+        KeyCode::Char('i') => KeyCode::Tab,
+        KeyCode::Char('m') => KeyCode::Enter,
+        KeyCode::Char('[') => KeyCode::Esc,
+        _ => return event,
+    };
+    KeyEvent::new_with_kind(code, KeyModifiers::NONE, event.kind)
+}
+
 pub fn map_key(event: KeyEvent, mode: EditorMode, pending: &mut Pending) -> KeyMappingResult {
     use EditorCommand as Cmd;
     use KeyCode::*;
-
-    if event.code == KeyCode::Esc {
-        pending.clear();
-        return KeyMappingResult::Command(Cmd::Quit);
-    }
+    let event = legacy_alias(event);
 
     match mode {
+        EditorMode::Command => match event.code {
+            KeyCode::Char(c) => KeyMappingResult::Command(Cmd::CmdlineInput(c)),
+            KeyCode::Backspace => KeyMappingResult::Command(Cmd::CmdlineBackspace),
+            KeyCode::Up => KeyMappingResult::Command(Cmd::CmdlineHistory { older: true }),
+            KeyCode::Down => KeyMappingResult::Command(Cmd::CmdlineHistory { older: false }),
+            KeyCode::Tab => KeyMappingResult::Command(Cmd::CmdlineComplete),
+            KeyCode::Enter => KeyMappingResult::Command(Cmd::CmdlineExecute),
+            KeyCode::Esc => KeyMappingResult::Command(Cmd::EnterNormalMode),
+            _ => KeyMappingResult::Noop,
+        },
+
+        EditorMode::Picker => {
+            let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+            match (event.code, ctrl) {
+                (Up, _) | (Char('p'), true) => KeyMappingResult::Command(Cmd::PickerStep(-1)),
+                (Down, _) | (Char('n'), true) => KeyMappingResult::Command(Cmd::PickerStep(1)),
+                (Char(c), false) => KeyMappingResult::Command(Cmd::PickerInput(c)),
+                (Backspace, _) => KeyMappingResult::Command(Cmd::PickerBackspace),
+                (Enter, _) => KeyMappingResult::Command(Cmd::PickerAccept),
+                (Esc, _) => KeyMappingResult::Command(Cmd::EnterNormalMode),
+                _ => KeyMappingResult::Noop,
+            }
+        }
+
+        // Esc goes back to the text, leaving the sidebar open.
+        EditorMode::Tree => {
+            let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+            match (event.code, ctrl) {
+                (Char('w'), true) => KeyMappingResult::Command(Cmd::SwitchPane),
+                (_, true) => KeyMappingResult::Noop,
+                (Up | Char('k'), _) => KeyMappingResult::Command(Cmd::TreeStep(-1)),
+                (Down | Char('j'), _) => KeyMappingResult::Command(Cmd::TreeStep(1)),
+                (Enter | Right | Char('l' | 'o'), _) => KeyMappingResult::Command(Cmd::TreeOpen),
+                (Left | Char('h'), _) => KeyMappingResult::Command(Cmd::TreeCollapse),
+                (Char('R'), _) => KeyMappingResult::Command(Cmd::TreeRefresh),
+                (Char('q'), _) => KeyMappingResult::Command(Cmd::ToggleTree),
+                (Char(':'), _) => KeyMappingResult::Command(Cmd::EnterCommandMode),
+                (Esc, _) => KeyMappingResult::Command(Cmd::EnterNormalMode),
+                _ => KeyMappingResult::Noop,
+            }
+        }
+
+        // Esc answers "cancel".
+        EditorMode::Prompt => match event.code {
+            KeyCode::Char(c) => {
+                KeyMappingResult::Command(Cmd::PromptAnswer(c.to_ascii_lowercase()))
+            }
+            KeyCode::Esc => KeyMappingResult::Command(Cmd::PromptAnswer('c')),
+            _ => KeyMappingResult::Noop,
+        },
+
         EditorMode::Insert => {
             if event.code == Esc {
                 pending.clear();
                 return KeyMappingResult::Command(Cmd::EnterNormalMode);
             }
+            // Ctrl-Enter / Shift-Enter need the kitty protocol; elsewhere
+            // they are plain Enter.
+            if event.code == Enter && event.modifiers.contains(KeyModifiers::CONTROL) {
+                return KeyMappingResult::Command(Cmd::OpenLineBelow);
+            }
+            if event.code == Enter && event.modifiers.contains(KeyModifiers::SHIFT) {
+                return KeyMappingResult::Command(Cmd::OpenLineAbove);
+            }
+            if let (Char(c @ ('n' | 'p')), true) =
+                (event.code, event.modifiers.contains(KeyModifiers::CONTROL))
+            {
+                return KeyMappingResult::Command(Cmd::CompleteWord { forward: c == 'n' });
+            }
             match event.code {
                 KeyCode::Char(c) => KeyMappingResult::Command(Cmd::InsertChar(c)),
                 KeyCode::Delete => KeyMappingResult::Command(Cmd::Delete),
@@ -60,14 +438,48 @@ pub fn map_key(event: KeyEvent, mode: EditorMode, pending: &mut Pending) -> KeyM
             }
         }
 
+        // Motions move the caret end of the selection; `d`, `c`, `y`, `>`
+        // and `<` act on the lines and leave Visual mode.
+        EditorMode::Visual => match event.code {
+            Esc | Char('V') => KeyMappingResult::Command(Cmd::EnterNormalMode),
+            Up => KeyMappingResult::Command(Cmd::MoveUp),
+            Down => KeyMappingResult::Command(Cmd::MoveDown),
+            code => match (Operator::from_keys(&key_text(code)), motion_for_key(code)) {
+                (Some(op), _) => KeyMappingResult::Command(Cmd::OperateSelection(op)),
+                (None, Some(motion)) => KeyMappingResult::Command(Cmd::Move { motion, count: 1 }),
+                (None, None) => KeyMappingResult::Noop,
+            },
+        },
+
+        EditorMode::Replace => match event.code {
+            KeyCode::Char(c) => KeyMappingResult::Command(Cmd::OverwriteChar(c)),
+            KeyCode::Enter => KeyMappingResult::Command(Cmd::OverwriteChar('\n')),
+            KeyCode::Backspace => KeyMappingResult::Command(Cmd::RestoreOverwritten),
+            KeyCode::Left => KeyMappingResult::Command(Cmd::MoveLeft),
+            KeyCode::Right => KeyMappingResult::Command(Cmd::MoveRight),
+            KeyCode::Esc => KeyMappingResult::Command(Cmd::EnterNormalMode),
+            _ => KeyMappingResult::Noop,
+        },
+
         EditorMode::Normal => {
+            // Esc drops a half-typed command; with none, it quits as `:q`
+            // does, asking about unsaved changes.
             if event.code == Esc {
-                pending.clear();
-                return KeyMappingResult::Command(Cmd::Quit);
+                if !pending.is_empty() {
+                    pending.clear();
+                    return KeyMappingResult::Noop;
+                }
+                return KeyMappingResult::Command(Cmd::Exit {
+                    force: false,
+                    confirm: false,
+                });
             }
-            // ---- Count accumulation (e.g., "12w", "3dd") ----
+            // ---- Count accumulation (e.g., "12w", "3dd", "2d3w") ----
+            // A leading '0' is the line-start motion, not a count, and a digit
+            // after a prefix is that prefix's argument (`r5`).
             if let Char(d) = event.code {
-                if d.is_ascii_digit() {
+                let counting = pending.count.is_some() || d != '0';
+                if d.is_ascii_digit() && counting && pending.prefix.is_empty() {
                     // accumulate digits: None -> d, 3 -> 3d, etc.
                     let digit = d.to_digit(10).unwrap() as usize;
                     let cur = pending.count.unwrap_or(0);
@@ -76,47 +488,164 @@ pub fn map_key(event: KeyEvent, mode: EditorMode, pending: &mut Pending) -> KeyM
                 }
             }
 
-            // ---- Handle two-key prefixes already started ----
-            match (pending.prefix.as_slice(), event.code) {
-                // 'd' then 'd' => DeleteLine {count}
-                ([KeyCode::Char('d')], KeyCode::Char('d')) => {
-                    let n = pending.take_count();
+            // ---- Finish multi-key sequences already started ----
+            if !pending.prefix.is_empty() {
+                if let Some(result) = map_prefixed(event.code, pending) {
+                    return result;
+                }
+                // Unknown key after a prefix: dropped, interpret it fresh below.
+            }
+
+            // ---- Operator pending: doubled form, text object, or motion ----
+            if let Some(op) = pending.operator {
+                return match event.code {
+                    code if op.doubles(&key_text(code)) => finish_operator(pending, Target::Line),
+                    Char(c @ ('i' | 'a' | 'g')) => {
+                        pending.push(Char(c));
+                        KeyMappingResult::UpdatePending
+                    }
+                    code => match motion_for_key(code) {
+                        Some(m) => finish_operator(pending, Target::Motion(m)),
+                        None => {
+                            pending.clear();
+                            KeyMappingResult::Noop
+                        }
+                    },
+                };
+            }
+
+            // ---- Scrolling: Ctrl-D/U half a screen, Ctrl-F/B a full one ----
+            if event.modifiers.contains(KeyModifiers::CONTROL) {
+                let scroll = match event.code {
+                    Char('d') => Some(Scroll::HalfDown),
+                    Char('u') => Some(Scroll::HalfUp),
+                    Char('f') => Some(Scroll::PageDown),
+                    Char('b') => Some(Scroll::PageUp),
+                    _ => None,
+                };
+                if let Some(scroll) = scroll {
+                    let count = pending.count.take();
                     pending.clear();
-                    return KeyMappingResult::Command(Cmd::DeleteLine { count: n });
+                    return KeyMappingResult::Command(Cmd::Scroll { scroll, count });
                 }
-                // 'g' then 'g' => MoveToStartOfFile
-                ([KeyCode::Char('g')], KeyCode::Char('g')) => {
+                // ---- Tags: Ctrl-] jumps, Ctrl-T pops ----
+                // Legacy terminals report Ctrl-] as Ctrl-5.
+                let tag = match event.code {
+                    Char(']' | '5') => Some(Cmd::JumpToTag(None)),
+                    Char('t') => Some(Cmd::PopTag),
+                    _ => None,
+                };
+                if let Some(cmd) = tag {
                     pending.clear();
-                    return KeyMappingResult::Command(Cmd::MoveToStartOfFile);
+                    return KeyMappingResult::Command(cmd);
                 }
-                // Unknown second key after a prefix: drop the prefix and interpret fresh
-                ([KeyCode::Char('d')], _) | ([KeyCode::Char('g')], _) => {
+                // ---- Ctrl-W: into the file tree, when it's open ----
+                if event.code == Char('w') {
                     pending.clear();
-                    // fall through and treat this key as a fresh mapping
+                    return KeyMappingResult::Command(Cmd::SwitchPane);
+                }
+                // ---- Job control: Ctrl-Z suspends ----
+                if event.code == Char('z') {
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::Suspend);
                 }
-                _ => {}
             }
 
-            // ---- Start new prefixes ----
+            // ---- Start operators and prefixes ----
+            if let Some(op) = Operator::from_keys(&key_text(event.code)) {
+                return start_operator(pending, op);
+            }
+            if let Char(c @ ('g' | 'm' | '`' | '\'' | ']' | '[' | 'r' | '"' | 'z')) = event.code {
+                pending.push(Char(c));
+                return KeyMappingResult::UpdatePending;
+            }
+
+            // ---- Single-grapheme edits: x = dl, ~ toggles case ----
             match event.code {
-                KeyCode::Char('d') => {
-                    pending.push(KeyCode::Char('d'));
-                    return KeyMappingResult::UpdatePending;
+                Char('x') => {
+                    let count = pending.take_count();
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::Operate {
+                        op: Operator::Delete,
+                        target: Target::Motion(Motion::Right),
+                        count,
+                    });
                 }
-                KeyCode::Char('g') => {
-                    pending.push(KeyCode::Char('g'));
-                    return KeyMappingResult::UpdatePending;
+                Char('~') => {
+                    let count = pending.take_count();
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::ToggleCase { count });
+                }
+                Char('J') => {
+                    let count = pending.take_count();
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::JoinLines {
+                        count,
+                        spaces: true,
+                    });
                 }
                 _ => {}
             }
 
+            // ---- Put: p after, P before; Ctrl-P / Ctrl-N then cycle what was put ----
+            // ---- through the numbered registers, Alt-P / Alt-N through the kill ring ----
+            let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+            let alt = event.modifiers.contains(KeyModifiers::ALT);
+            match (event.code, ctrl) {
+                (Char(c @ ('p' | 'n')), true) => {
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::CyclePut { older: c == 'p' });
+                }
+                (Char(c @ ('p' | 'n')), false) if alt => {
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::YankPop { older: c == 'p' });
+                }
+                (Char(c @ ('p' | 'P')), false) => {
+                    let count = pending.take_count();
+                    let register = pending.register.take().unwrap_or('"');
+                    pending.clear();
+                    return KeyMappingResult::Command(Cmd::Put {
+                        register,
+                        before: c == 'P',
+                        count,
+                    });
+                }
+                _ => {}
+            }
+
+            // ---- Shorthands: C = c$, s = cl, S = cc, D = d$ ----
+            let shorthand = match event.code {
+                Char('C') => Some((Operator::Change, Target::Motion(Motion::LineEnd))),
+                Char('s') => Some((Operator::Change, Target::Motion(Motion::Right))),
+                Char('S') => Some((Operator::Change, Target::Line)),
+                Char('D') => Some((Operator::Delete, Target::Motion(Motion::LineEnd))),
+                _ => None,
+            };
+            if let Some((op, target)) = shorthand {
+                let count = pending.take_count();
+                pending.clear();
+                return KeyMappingResult::Command(Cmd::Operate { op, target, count });
+            }
+
+            // ---- Motions ----
+            if let Some(motion) = motion_for_key(event.code) {
+                let (motion, count) = line_count(motion, pending.count.take());
+                pending.clear();
+                return KeyMappingResult::Command(Cmd::Move { motion, count });
+            }
+
             // ---- Plain normal-mode mappings ----
+            pending.clear();
             match (event.code, event.modifiers) {
                 (KeyCode::Char('i'), _) => KeyMappingResult::Command(Cmd::EnterInsertMode),
-                (KeyCode::Char('w'), _) => {
-                    let n = pending.take_count();
-                    KeyMappingResult::Command(Cmd::WordForward { count: n })
-                }
+                (KeyCode::Char('a'), _) => KeyMappingResult::Command(Cmd::Append),
+                (KeyCode::Char('A'), _) => KeyMappingResult::Command(Cmd::AppendLineEnd),
+                (KeyCode::Char('I'), _) => KeyMappingResult::Command(Cmd::InsertLineStart),
+                (KeyCode::Char('R'), _) => KeyMappingResult::Command(Cmd::EnterReplaceMode),
+                (KeyCode::Char('V'), _) => KeyMappingResult::Command(Cmd::EnterVisualLine),
+                (KeyCode::Char('o'), _) => KeyMappingResult::Command(Cmd::OpenLineBelow),
+                (KeyCode::Char('O'), _) => KeyMappingResult::Command(Cmd::OpenLineAbove),
+                (KeyCode::Char(':'), _) => KeyMappingResult::Command(Cmd::EnterCommandMode),
                 (Left, _) => KeyMappingResult::Command(Cmd::MoveLeft),
                 (Right, _) => KeyMappingResult::Command(Cmd::MoveRight),
                 (Up, _) => KeyMappingResult::Command(Cmd::MoveUp),
@@ -130,34 +659,671 @@ pub fn map_key(event: KeyEvent, mode: EditorMode, pending: &mut Pending) -> KeyM
     }
 }
 
+/// Single-key motions usable on their own or after an operator.
+fn motion_for_key(code: KeyCode) -> Option<Motion> {
+    match code {
+        KeyCode::Char('h') => Some(Motion::Left),
+        KeyCode::Char('l') => Some(Motion::Right),
+        KeyCode::Char('k') => Some(Motion::Up),
+        KeyCode::Char('j') => Some(Motion::Down),
+        KeyCode::Char('w') => Some(Motion::WordForward),
+        KeyCode::Char('b') => Some(Motion::WordBackward),
+        KeyCode::Char('e') => Some(Motion::WordEnd),
+        KeyCode::Char('0') => Some(Motion::LineStart),
+        KeyCode::Char('^') => Some(Motion::FirstNonBlank),
+        KeyCode::Char('$') => Some(Motion::LineEnd),
+        KeyCode::Char('G') => Some(Motion::FileEnd),
+        KeyCode::Char('%') => Some(Motion::MatchPair),
+        _ => None,
+    }
+}
+
+/// A key as typed text, for matching operator key sequences.
+fn key_text(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(c) => c.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn start_operator(pending: &mut Pending, op: Operator) -> KeyMappingResult {
+    // The count typed before the operator multiplies the one typed after it.
+    pending.op_count = pending.count.take();
+    pending.operator = Some(op);
+    KeyMappingResult::UpdatePending
+}
+
+/// `G` and `gg` take a count as the line to go to (`5G`, `d3gg`) rather
+/// than a repeat.
+fn line_count(motion: Motion, count: Option<usize>) -> (Motion, usize) {
+    match (motion, count) {
+        (Motion::FileStart | Motion::FileEnd, Some(line)) => (Motion::GotoLine(line), 1),
+        (_, count) => (motion, count.unwrap_or(1)),
+    }
+}
+
+fn finish_operator(pending: &mut Pending, target: Target) -> KeyMappingResult {
+    let op = pending.operator.take().expect("operator pending");
+    let count = match (pending.op_count.take(), pending.count.take()) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(1) * b.unwrap_or(1)),
+    };
+    let (target, count) = match target {
+        Target::Motion(m) => {
+            let (m, count) = line_count(m, count);
+            (Target::Motion(m), count)
+        }
+        _ => (target, count.unwrap_or(1)),
+    };
+    pending.clear();
+    KeyMappingResult::Command(EditorCommand::Operate { op, target, count })
+}
+
+/// Second key of a prefixed sequence (`gg`, `gu`, `ma`, `` `a ``, `]x`, `rx`, `diw`).
+/// Returns None when the key doesn't continue the sequence; the prefix is
+/// dropped either way.
+fn map_prefixed(code: KeyCode, pending: &mut Pending) -> Option<KeyMappingResult> {
+    use EditorCommand as Cmd;
+
+    let prefix = match pending.prefix.as_slice() {
+        [KeyCode::Char(c)] => *c,
+        _ => {
+            pending.prefix.clear();
+            return None;
+        }
+    };
+    pending.prefix.clear();
+    let KeyCode::Char(key) = code else {
+        pending.clear();
+        return None;
+    };
+
+    // Two-key operators start (`gu`) or double (`gugu`) here.
+    let keys = format!("{prefix}{key}");
+    match pending.operator {
+        Some(op) if op.doubles(&keys) => return Some(finish_operator(pending, Target::Line)),
+        None => {
+            if let Some(op) = Operator::from_keys(&keys) {
+                return Some(start_operator(pending, op));
+            }
+        }
+        Some(_) => {}
+    }
+
+    let result = match (prefix, key, pending.operator) {
+        // Text objects only exist after an operator
+        ('i' | 'a', k, Some(_)) => match TextObject::from_key(prefix == 'a', k) {
+            Some(obj) => finish_operator(pending, Target::TextObject(obj)),
+            None => {
+                pending.clear();
+                KeyMappingResult::Noop
+            }
+        },
+        ('g', 'g', Some(_)) => finish_operator(pending, Target::Motion(Motion::FileStart)),
+        ('g', 'g', None) => {
+            let (motion, count) = line_count(Motion::FileStart, pending.count.take());
+            KeyMappingResult::Command(Cmd::Move { motion, count })
+        }
+        ('g', 'o', None) => {
+            let byte = pending.take_count();
+            pending.clear();
+            KeyMappingResult::Command(Cmd::GotoByte(byte))
+        }
+        ('g', 'J', None) => {
+            let count = pending.take_count();
+            pending.clear();
+            KeyMappingResult::Command(Cmd::JoinLines {
+                count,
+                spaces: false,
+            })
+        }
+        ('z', c @ ('z' | 't' | 'b'), None) => {
+            pending.clear();
+            KeyMappingResult::Command(Cmd::AlignView(match c {
+                'z' => Align::Center,
+                't' => Align::Top,
+                _ => Align::Bottom,
+            }))
+        }
+        // `"x` picks the register for the following put
+        ('"', c, None) if Registers::is_valid_name(c) => {
+            pending.register = Some(c);
+            KeyMappingResult::UpdatePending
+        }
+        // Any other name is refused, rather than `"a` reading as `a`
+        ('"', _, None) => {
+            pending.clear();
+            KeyMappingResult::Noop
+        }
+        ('r', ch, None) => {
+            let count = pending.take_count();
+            pending.clear();
+            KeyMappingResult::Command(Cmd::ReplaceChar { ch, count })
+        }
+        ('m', c, None) if c.is_ascii_lowercase() => {
+            pending.clear();
+            KeyMappingResult::Command(Cmd::SetMark(c))
+        }
+        // '`' / '\'' then letter => jump to mark (exact / line start)
+        ('`' | '\'', c, None) if c.is_ascii_lowercase() => {
+            pending.clear();
+            KeyMappingResult::Command(Cmd::JumpToMark {
+                name: c,
+                linewise: prefix == '\'',
+            })
+        }
+        // ']b' / '[b' => next / previous buffer
+        (']' | '[', 'b', None) => {
+            let count = pending.take_count() as isize;
+            pending.clear();
+            KeyMappingResult::Command(Cmd::StepBuffer(if prefix == ']' { count } else { -count }))
+        }
+        // ']x' / '[x' => next / previous conflict block
+        (']', 'x', None) => {
+            pending.clear();
+            KeyMappingResult::Command(Cmd::NextConflict)
+        }
+        ('[', 'x', None) => {
+            pending.clear();
+            KeyMappingResult::Command(Cmd::PrevConflict)
+        }
+        _ => {
+            pending.clear();
+            return None;
+        }
+    };
+    Some(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use crate::keys::{KeyCode, KeyEvent, KeyModifiers};
     #[test]
     fn test_quit_key() {
         let key = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
-        let mut pending = Pending {
-            count: None,
-            register: None,
-            prefix: Vec::new(),
-        };
+        let mut pending = Pending::default();
         let out = map_key(key, EditorMode::Insert, &mut pending);
-        assert_eq!(out, KeyMappingResult::Command(EditorCommand::Quit));
+        assert_eq!(
+            out,
+            KeyMappingResult::Command(EditorCommand::EnterNormalMode)
+        );
+        // In Normal mode it quits as `:q` does, asking about changes
+        let out = map_key(key, EditorMode::Normal, &mut pending);
+        assert_eq!(
+            out,
+            KeyMappingResult::Command(EditorCommand::Exit {
+                force: false,
+                confirm: false
+            })
+        );
     }
 
     #[test]
     fn test_insert_char() {
         let key = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE);
-        let mut pending = Pending {
-            count: None,
-            register: None,
-            prefix: Vec::new(),
-        };
+        let mut pending = Pending::default();
         let out = map_key(key, EditorMode::Insert, &mut pending);
         assert_eq!(
             out,
             KeyMappingResult::Command(EditorCommand::InsertChar('a'))
         );
     }
+
+    #[test]
+    fn mark_prefixes_map_to_mark_commands() {
+        let mut pending = Pending::default();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        assert_eq!(
+            map_key(key('m'), EditorMode::Normal, &mut pending),
+            KeyMappingResult::UpdatePending
+        );
+        assert_eq!(
+            map_key(key('a'), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::SetMark('a'))
+        );
+
+        map_key(key('`'), EditorMode::Normal, &mut pending);
+        assert_eq!(
+            map_key(key('a'), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::JumpToMark {
+                name: 'a',
+                linewise: false
+            })
+        );
+
+        map_key(key('\''), EditorMode::Normal, &mut pending);
+        assert_eq!(
+            map_key(key('a'), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::JumpToMark {
+                name: 'a',
+                linewise: true
+            })
+        );
+        assert!(pending.prefix.is_empty());
+    }
+
+    #[test]
+    fn delete_with_text_object() {
+        let mut pending = Pending::default();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        for c in ['d', 'i'] {
+            assert_eq!(
+                map_key(key(c), EditorMode::Normal, &mut pending),
+                KeyMappingResult::UpdatePending
+            );
+        }
+        assert_eq!(
+            map_key(key('('), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::Operate {
+                op: Operator::Delete,
+                target: Target::TextObject(TextObject::from_key(false, '(').unwrap()),
+                count: 1,
+            })
+        );
+        assert!(pending.prefix.is_empty());
+
+        // Unknown object key drops the whole sequence
+        map_key(key('d'), EditorMode::Normal, &mut pending);
+        map_key(key('a'), EditorMode::Normal, &mut pending);
+        assert_eq!(
+            map_key(key('z'), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Noop
+        );
+        assert!(pending.prefix.is_empty());
+
+        // A count steps that many buffers
+        map_key(key('2'), EditorMode::Normal, &mut pending);
+        map_key(key('['), EditorMode::Normal, &mut pending);
+        assert_eq!(
+            map_key(key('b'), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::StepBuffer(-2))
+        );
+    }
+
+    #[test]
+    fn command_mode_keys() {
+        let mut pending = Pending::default();
+        let key = |c| KeyEvent::new(c, KeyModifiers::NONE);
+
+        assert_eq!(
+            map_key(key(KeyCode::Char(':')), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::EnterCommandMode)
+        );
+        assert_eq!(
+            map_key(key(KeyCode::Char('q')), EditorMode::Command, &mut pending),
+            KeyMappingResult::Command(EditorCommand::CmdlineInput('q'))
+        );
+        // Esc cancels the command line instead of quitting
+        assert_eq!(
+            map_key(key(KeyCode::Esc), EditorMode::Command, &mut pending),
+            KeyMappingResult::Command(EditorCommand::EnterNormalMode)
+        );
+
+        map_key(key(KeyCode::Char(']')), EditorMode::Normal, &mut pending);
+        assert_eq!(
+            map_key(key(KeyCode::Char('x')), EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::NextConflict)
+        );
+    }
+
+    fn feed(keys: &str, pending: &mut Pending) -> KeyMappingResult {
+        let mut last = KeyMappingResult::Noop;
+        for c in keys.chars() {
+            let ev = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+            last = map_key(ev, EditorMode::Normal, pending);
+        }
+        last
+    }
+
+    fn operate(op: Operator, target: Target, count: usize) -> KeyMappingResult {
+        KeyMappingResult::Command(EditorCommand::Operate { op, target, count })
+    }
+
+    #[test]
+    fn operators_compose_with_motions_and_counts() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("2d3w", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::WordForward), 6)
+        );
+        assert_eq!(
+            feed("y$", &mut pending),
+            operate(Operator::Yank, Target::Motion(Motion::LineEnd), 1)
+        );
+        assert_eq!(
+            feed("dgg", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::FileStart), 1)
+        );
+        assert_eq!(
+            feed("d%", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::MatchPair), 1)
+        );
+        assert_eq!(
+            feed("gUiw", &mut pending),
+            operate(
+                Operator::Uppercase,
+                Target::TextObject(TextObject::from_key(false, 'w').unwrap()),
+                1
+            )
+        );
+        assert!(pending.operator.is_none() && pending.count.is_none());
+    }
+
+    #[test]
+    fn doubled_operators_are_linewise() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("3dd", &mut pending),
+            operate(Operator::Delete, Target::Line, 3)
+        );
+        assert_eq!(
+            feed("yy", &mut pending),
+            operate(Operator::Yank, Target::Line, 1)
+        );
+        assert_eq!(
+            feed("guu", &mut pending),
+            operate(Operator::Lowercase, Target::Line, 1)
+        );
+        assert_eq!(
+            feed("gUgU", &mut pending),
+            operate(Operator::Uppercase, Target::Line, 1)
+        );
+
+        // Every operator gets both doubled forms, with counts
+        for op in Operator::ALL {
+            let keys = op.keys();
+            let last = keys.chars().last().unwrap();
+            let expected = operate(op, Target::Line, 6);
+            assert_eq!(feed(&format!("2{keys}3{last}"), &mut pending), expected);
+            assert_eq!(feed(&format!("3{keys}2{keys}"), &mut pending), expected);
+        }
+    }
+
+    #[test]
+    fn zero_is_a_motion_unless_counting() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("0", &mut pending),
+            KeyMappingResult::Command(EditorCommand::Move {
+                motion: Motion::LineStart,
+                count: 1
+            })
+        );
+        assert_eq!(
+            feed("10j", &mut pending),
+            KeyMappingResult::Command(EditorCommand::Move {
+                motion: Motion::Down,
+                count: 10
+            })
+        );
+        // An invalid target cancels the operator
+        assert_eq!(feed("dz", &mut pending), KeyMappingResult::Noop);
+        assert!(pending.operator.is_none());
+    }
+
+    #[test]
+    fn change_operator_and_shorthands() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("cw", &mut pending),
+            operate(Operator::Change, Target::Motion(Motion::WordForward), 1)
+        );
+        assert_eq!(
+            feed("2cc", &mut pending),
+            operate(Operator::Change, Target::Line, 2)
+        );
+        assert_eq!(
+            feed("C", &mut pending),
+            operate(Operator::Change, Target::Motion(Motion::LineEnd), 1)
+        );
+        assert_eq!(
+            feed("3s", &mut pending),
+            operate(Operator::Change, Target::Motion(Motion::Right), 3)
+        );
+        assert_eq!(
+            feed("S", &mut pending),
+            operate(Operator::Change, Target::Line, 1)
+        );
+        assert_eq!(
+            feed("D", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::LineEnd), 1)
+        );
+    }
+
+    #[test]
+    fn x_r_and_tilde_keys() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("3x", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::Right), 3)
+        );
+        // The digit after 'r' is the replacement, not a count
+        assert_eq!(
+            feed("2r5", &mut pending),
+            KeyMappingResult::Command(EditorCommand::ReplaceChar { ch: '5', count: 2 })
+        );
+        assert_eq!(
+            feed("~", &mut pending),
+            KeyMappingResult::Command(EditorCommand::ToggleCase { count: 1 })
+        );
+    }
+
+    #[test]
+    fn insert_entry_keys() {
+        let mut pending = Pending::default();
+        for (key, cmd) in [
+            ("a", EditorCommand::Append),
+            ("A", EditorCommand::AppendLineEnd),
+            ("I", EditorCommand::InsertLineStart),
+        ] {
+            assert_eq!(feed(key, &mut pending), KeyMappingResult::Command(cmd));
+        }
+    }
+
+    #[test]
+    fn join_keys() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("3J", &mut pending),
+            KeyMappingResult::Command(EditorCommand::JoinLines {
+                count: 3,
+                spaces: true
+            })
+        );
+        assert_eq!(
+            feed("gJ", &mut pending),
+            KeyMappingResult::Command(EditorCommand::JoinLines {
+                count: 1,
+                spaces: false
+            })
+        );
+    }
+
+    #[test]
+    fn open_line_keys() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("o", &mut pending),
+            KeyMappingResult::Command(EditorCommand::OpenLineBelow)
+        );
+        assert_eq!(
+            feed("O", &mut pending),
+            KeyMappingResult::Command(EditorCommand::OpenLineAbove)
+        );
+    }
+
+    #[test]
+    fn indent_operators() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("2>>", &mut pending),
+            operate(Operator::Indent, Target::Line, 2)
+        );
+        assert_eq!(
+            feed("<j", &mut pending),
+            operate(Operator::Outdent, Target::Motion(Motion::Down), 1)
+        );
+    }
+
+    #[test]
+    fn prompt_keys_answer_the_prompt() {
+        let mut pending = Pending::default();
+        let key = |c| KeyEvent::new(c, KeyModifiers::NONE);
+        assert_eq!(
+            map_key(key(KeyCode::Char('Y')), EditorMode::Prompt, &mut pending),
+            KeyMappingResult::Command(EditorCommand::PromptAnswer('y'))
+        );
+        assert_eq!(
+            map_key(key(KeyCode::Esc), EditorMode::Prompt, &mut pending),
+            KeyMappingResult::Command(EditorCommand::PromptAnswer('c'))
+        );
+    }
+
+    #[test]
+    fn put_and_cycle_keys() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("3p", &mut pending),
+            KeyMappingResult::Command(EditorCommand::Put {
+                register: '"',
+                before: false,
+                count: 3
+            })
+        );
+        assert_eq!(
+            feed("2\"1P", &mut pending),
+            KeyMappingResult::Command(EditorCommand::Put {
+                register: '1',
+                before: true,
+                count: 2
+            })
+        );
+        let ctrl_p = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL);
+        assert_eq!(
+            map_key(ctrl_p, EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::CyclePut { older: true })
+        );
+        let alt_n = KeyEvent::new(KeyCode::Char('n'), KeyModifiers::ALT);
+        assert_eq!(
+            map_key(alt_n, EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::YankPop { older: false })
+        );
+    }
+
+    #[test]
+    fn replace_mode_keys() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("R", &mut pending),
+            KeyMappingResult::Command(EditorCommand::EnterReplaceMode)
+        );
+        let key = |c| KeyEvent::new(c, KeyModifiers::NONE);
+        assert_eq!(
+            map_key(key(KeyCode::Backspace), EditorMode::Replace, &mut pending),
+            KeyMappingResult::Command(EditorCommand::RestoreOverwritten)
+        );
+        assert_eq!(
+            map_key(key(KeyCode::Esc), EditorMode::Replace, &mut pending),
+            KeyMappingResult::Command(EditorCommand::EnterNormalMode)
+        );
+    }
+
+    #[test]
+    fn scroll_keys() {
+        let mut pending = Pending::default();
+        feed("5", &mut pending);
+        let ctrl_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(
+            map_key(ctrl_d, EditorMode::Normal, &mut pending),
+            KeyMappingResult::Command(EditorCommand::Scroll {
+                scroll: Scroll::HalfDown,
+                count: Some(5)
+            })
+        );
+        // Plain d still starts the operator
+        assert_eq!(feed("d", &mut pending), KeyMappingResult::UpdatePending);
+    }
+
+    #[test]
+    fn z_keys_align_the_view() {
+        let mut pending = Pending::default();
+        assert_eq!(
+            feed("zt", &mut pending),
+            KeyMappingResult::Command(EditorCommand::AlignView(Align::Top))
+        );
+        assert_eq!(feed("zq", &mut pending), KeyMappingResult::Noop);
+    }
+
+    #[test]
+    fn counts_on_g_and_gg_are_line_numbers() {
+        let mut pending = Pending::default();
+        let goto = |line| {
+            KeyMappingResult::Command(EditorCommand::Move {
+                motion: Motion::GotoLine(line),
+                count: 1,
+            })
+        };
+        assert_eq!(feed("12G", &mut pending), goto(12));
+        assert_eq!(feed("3gg", &mut pending), goto(3));
+        assert_eq!(
+            feed("G", &mut pending),
+            KeyMappingResult::Command(EditorCommand::Move {
+                motion: Motion::FileEnd,
+                count: 1,
+            })
+        );
+        assert_eq!(
+            feed("2d3G", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::GotoLine(6)), 1)
+        );
+        assert_eq!(
+            feed("dG", &mut pending),
+            operate(Operator::Delete, Target::Motion(Motion::FileEnd), 1)
+        );
+    }
+
+    #[test]
+    fn kitty_protocol_keys() {
+        let mut pending = Pending::default();
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        // Told apart from Enter and Esc, but meaning the same
+        assert_eq!(
+            map_key(ctrl('m'), EditorMode::Insert, &mut pending),
+            KeyMappingResult::Command(EditorCommand::InsertNewline)
+        );
+        assert_eq!(
+            map_key(ctrl('['), EditorMode::Command, &mut pending),
+            KeyMappingResult::Command(EditorCommand::EnterNormalMode)
+        );
+        let enter = |modifiers| KeyEvent::new(KeyCode::Enter, modifiers);
+        assert_eq!(
+            map_key(
+                enter(KeyModifiers::CONTROL),
+                EditorMode::Insert,
+                &mut pending
+            ),
+            KeyMappingResult::Command(EditorCommand::OpenLineBelow)
+        );
+        assert_eq!(
+            map_key(enter(KeyModifiers::SHIFT), EditorMode::Insert, &mut pending),
+            KeyMappingResult::Command(EditorCommand::OpenLineAbove)
+        );
+    }
+
+    #[test]
+    fn ctrl_n_and_ctrl_p_complete_in_insert_mode() {
+        let mut pending = Pending::default();
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        assert_eq!(
+            map_key(ctrl('n'), EditorMode::Insert, &mut pending),
+            KeyMappingResult::Command(EditorCommand::CompleteWord { forward: true })
+        );
+        assert_eq!(
+            map_key(ctrl('p'), EditorMode::Insert, &mut pending),
+            KeyMappingResult::Command(EditorCommand::CompleteWord { forward: false })
+        );
+    }
 }
@@ -0,0 +1,175 @@
+use crate::input::{EditorCommand, KeyMappingResult};
+use crate::keys::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+
+/// Strokes kept for `showkeys`.
+const KEPT: usize = 5;
+
+/// A key in Vim's notation: `x`, `<C-r>`, `<Esc>`, `<S-Tab>`.
+pub fn notation(key: KeyEvent) -> String {
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("M-");
+    }
+    let name = match key.code {
+        // Shift is already in the character.
+        KeyCode::Char(' ') => "Space".to_owned(),
+        KeyCode::Char('<') => "lt".to_owned(),
+        KeyCode::Char(c) if prefix.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::BackTab => "S-Tab".to_owned(),
+        code => {
+            // <S-CR> only comes from terminals with the kitty protocol.
+            if key.modifiers.contains(KeyModifiers::SHIFT) {
+                prefix.push_str("S-");
+            }
+            match code {
+                KeyCode::Enter => "CR".to_owned(),
+                KeyCode::Backspace => "BS".to_owned(),
+                KeyCode::Delete => "Del".to_owned(),
+                KeyCode::F(n) => format!("F{n}"),
+                code => format!("{code:?}"),
+            }
+        }
+    };
+    format!("<{prefix}{name}>")
+}
+
+/// The keys of one command and what they came to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    pub keys: String,
+    /// The command, or None when the keys did nothing.
+    pub command: Option<String>,
+    /// More keys are awaited (`d` before its motion).
+    pub pending: bool,
+}
+
+/// Commands that type text: runs of them show as one stroke.
+fn typing(cmd: &EditorCommand) -> bool {
+    matches!(
+        cmd,
+        EditorCommand::InsertChar(_)
+            | EditorCommand::CmdlineInput(_)
+            | EditorCommand::PickerInput(_)
+    )
+}
+
+/// The variant name of a command, without its fields.
+fn variant(cmd: &EditorCommand) -> String {
+    let debug = format!("{cmd:?}");
+    let end = debug.find([' ', '(', '{']).unwrap_or(debug.len());
+    debug[..end].to_owned()
+}
+
+/// Recent keys grouped into the commands they made, for `showkeys`.
+#[derive(Debug, Clone, Default)]
+pub struct KeyLog {
+    strokes: VecDeque<Stroke>,
+}
+
+impl KeyLog {
+    pub fn record(&mut self, key: KeyEvent, result: &KeyMappingResult) {
+        let keys = notation(key);
+        match self.strokes.back_mut().filter(|s| s.pending) {
+            Some(open) => open.keys.push_str(&keys),
+            None => self.strokes.push_back(Stroke {
+                keys,
+                command: None,
+                pending: true,
+            }),
+        }
+        let mut stroke = self.strokes.pop_back().expect("a stroke was just pushed");
+        stroke.pending = matches!(result, KeyMappingResult::UpdatePending);
+        if let KeyMappingResult::Command(cmd) = result {
+            let name = variant(cmd);
+            let previous = self.strokes.back_mut();
+            match previous {
+                Some(prev) if typing(cmd) && prev.command.as_ref() == Some(&name) => {
+                    prev.keys.push_str(&stroke.keys);
+                    return;
+                }
+                _ if typing(cmd) => stroke.command = Some(name),
+                _ => stroke.command = Some(format!("{cmd:?}")),
+            }
+        }
+        self.strokes.push_back(stroke);
+        if self.strokes.len() > KEPT {
+            self.strokes.pop_front();
+        }
+    }
+
+    /// Oldest first.
+    pub fn strokes(&self) -> impl Iterator<Item = &Stroke> {
+        self.strokes.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::Motion;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn keys_in_vim_notation() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(notation(key(KeyCode::Char('x'), none)), "x");
+        assert_eq!(notation(key(KeyCode::Char('X'), KeyModifiers::SHIFT)), "X");
+        assert_eq!(
+            notation(key(KeyCode::Char('r'), KeyModifiers::CONTROL)),
+            "<C-r>"
+        );
+        assert_eq!(notation(key(KeyCode::Char(' '), none)), "<Space>");
+        assert_eq!(notation(key(KeyCode::Esc, none)), "<Esc>");
+        assert_eq!(notation(key(KeyCode::Up, KeyModifiers::SHIFT)), "<S-Up>");
+        assert_eq!(notation(key(KeyCode::F(5), none)), "<F5>");
+        // Apart only under the kitty keyboard protocol
+        assert_eq!(notation(key(KeyCode::Tab, none)), "<Tab>");
+        assert_eq!(
+            notation(key(KeyCode::Char('i'), KeyModifiers::CONTROL)),
+            "<C-i>"
+        );
+        assert_eq!(notation(key(KeyCode::Enter, KeyModifiers::SHIFT)), "<S-CR>");
+    }
+
+    #[test]
+    fn strokes_group_keys_by_command() {
+        let none = KeyModifiers::NONE;
+        let mut log = KeyLog::default();
+        let pending = KeyMappingResult::UpdatePending;
+        log.record(key(KeyCode::Char('2'), none), &pending);
+        let moved = KeyMappingResult::Command(EditorCommand::Move {
+            motion: Motion::Down,
+            count: 2,
+        });
+        log.record(key(KeyCode::Char('j'), none), &moved);
+        for c in "hi".chars() {
+            let typed = KeyMappingResult::Command(EditorCommand::InsertChar(c));
+            log.record(key(KeyCode::Char(c), none), &typed);
+        }
+        log.record(key(KeyCode::Char('Q'), none), &KeyMappingResult::Noop);
+        let strokes: Vec<_> = log.strokes().collect();
+        assert_eq!(strokes[0].keys, "2j");
+        assert_eq!(
+            strokes[0].command.as_deref(),
+            Some("Move { motion: Down, count: 2 }")
+        );
+        assert_eq!(strokes[1].keys, "hi");
+        assert_eq!(strokes[1].command.as_deref(), Some("InsertChar"));
+        assert_eq!(strokes[2].command, None);
+
+        for _ in 0..KEPT {
+            log.record(key(KeyCode::Char('d'), none), &pending);
+        }
+        // Still one stroke waiting on its motion
+        assert_eq!(log.strokes().last().unwrap().keys, "ddddd");
+        assert_eq!(log.strokes().count(), 4);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::editor::EditorMode;
+use crate::keys::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// A key as mappings see it. Shift is already in a character, so `J` is
+/// the same key whether or not the terminal also reports the Shift.
+type Key = (KeyCode, KeyModifiers);
+
+fn key(event: &KeyEvent) -> Key {
+    match event.code {
+        KeyCode::Char(_) => (event.code, event.modifiers - KeyModifiers::SHIFT),
+        code => (code, event.modifiers),
+    }
+}
+
+/// Keys in Vim's notation, the way `keylog::notation` writes them:
+/// `jk`, `<Esc>`, `<C-w>`, `<M-p>`, `<S-Tab>`, `<leader>`, `<lt>`.
+#[cfg(feature = "config")]
+pub fn parse_keys(src: &str, leader: &[KeyEvent]) -> Result<Vec<KeyEvent>, String> {
+    let invalid = || format!("E474: Invalid argument: {src}");
+    let mut keys = Vec::new();
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        let Some(name) = rest
+            .strip_prefix('<')
+            .and_then(|r| r.find('>').map(|end| &r[..end]))
+            .filter(|name| !name.is_empty())
+        else {
+            keys.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        rest = &rest[name.len() + 2..];
+        if name.eq_ignore_ascii_case("leader") {
+            keys.extend_from_slice(leader);
+            continue;
+        }
+        let mut modifiers = KeyModifiers::NONE;
+        let mut base = name;
+        while let Some((m, r)) = base
+            .split_once('-')
+            .filter(|(m, r)| m.len() == 1 && !r.is_empty())
+        {
+            modifiers |= match m.to_ascii_uppercase().as_str() {
+                "C" => KeyModifiers::CONTROL,
+                "M" | "A" => KeyModifiers::ALT,
+                "S" => KeyModifiers::SHIFT,
+                _ => return Err(invalid()),
+            };
+            base = r;
+        }
+        let code = match base.to_ascii_lowercase().as_str() {
+            _ if base.chars().count() == 1 => KeyCode::Char(base.chars().next().expect("one")),
+            "space" => KeyCode::Char(' '),
+            "lt" => KeyCode::Char('<'),
+            "cr" | "enter" | "return" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers -= KeyModifiers::SHIFT;
+                KeyCode::BackTab
+            }
+            "tab" => KeyCode::Tab,
+            "bs" => KeyCode::Backspace,
+            "del" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f => match f.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n) => KeyCode::F(n),
+                None => return Err(invalid()),
+            },
+        };
+        keys.push(KeyEvent::new(code, modifiers));
+    }
+    Ok(keys)
+}
+
+/// Mappings that start with some keys, and what those keys alone map to.
+#[derive(Debug, Default)]
+struct Node {
+    to: Option<Vec<KeyEvent>>,
+    next: HashMap<Key, Node>,
+}
+
+impl Node {
+    #[cfg(feature = "config")]
+    fn insert(&mut self, from: &[KeyEvent], to: Vec<KeyEvent>) {
+        let node = from
+            .iter()
+            .fold(self, |node, k| node.next.entry(key(k)).or_default());
+        node.to = Some(to);
+    }
+
+    fn find(&self, keys: &[KeyEvent]) -> Option<&Node> {
+        keys.iter().try_fold(self, |node, k| node.next.get(&key(k)))
+    }
+
+    /// Take the longest mapped run off the front of `typed`, or else its
+    /// first key as it is.
+    fn take(&self, typed: &mut Vec<KeyEvent>, out: &mut Vec<KeyEvent>) {
+        let mut node = self;
+        let mut longest = None;
+        for (n, k) in typed.iter().enumerate() {
+            let Some(next) = node.next.get(&key(k)) else {
+                break;
+            };
+            node = next;
+            if let Some(to) = &node.to {
+                longest = Some((n + 1, to));
+            }
+        }
+        match longest {
+            Some((n, to)) => {
+                out.extend_from_slice(to);
+                typed.drain(..n);
+            }
+            None => out.push(typed.remove(0)),
+        }
+    }
+}
+
+/// The user's key mappings, per mode, from the `[keymap]` table of the
+/// config file:
+///
+/// ```toml
+/// [keymap]
+/// leader = "<Space>"
+///
+/// [keymap.normal]
+/// "<leader>w" = ":w<CR>"
+///
+/// [keymap.insert]
+/// jk = "<Esc>"
+/// ```
+///
+/// Mapped keys are looked up in a trie as they are typed. What they map
+/// to goes to `input::map_key` as if typed, without being mapped again.
+#[derive(Debug, Default)]
+pub struct Keymap {
+    normal: Node,
+    insert: Node,
+    command: Node,
+}
+
+impl Keymap {
+    #[cfg(feature = "config")]
+    pub fn parse(table: &toml::Table) -> Result<Self, String> {
+        let leader = match table.get("leader") {
+            None => vec![KeyEvent::new(KeyCode::Char('\\'), KeyModifiers::NONE)],
+            Some(toml::Value::String(s)) => parse_keys(s, &[])?,
+            Some(_) => return Err("E474: keymap: leader must be a string".into()),
+        };
+        let mut keymap = Self::default();
+        for (mode, maps) in table {
+            let trie = match mode.as_str() {
+                "leader" => continue,
+                "normal" => &mut keymap.normal,
+                "insert" => &mut keymap.insert,
+                "command" => &mut keymap.command,
+                _ => return Err(format!("E474: keymap: unknown mode: {mode}")),
+            };
+            let Some(maps) = maps.as_table() else {
+                return Err(format!("E474: keymap.{mode} must be a table"));
+            };
+            for (from, to) in maps {
+                let Some(to) = to.as_str() else {
+                    return Err(format!("E474: keymap.{mode}: {from} must map to a string"));
+                };
+                let from = parse_keys(from, &leader)?;
+                if from.is_empty() {
+                    return Err(format!("E474: keymap.{mode}: empty keys"));
+                }
+                trie.insert(&from, parse_keys(to, &leader)?);
+            }
+        }
+        Ok(keymap)
+    }
+
+    fn trie(&self, mode: EditorMode) -> Option<&Node> {
+        match mode {
+            EditorMode::Normal => Some(&self.normal),
+            EditorMode::Insert => Some(&self.insert),
+            EditorMode::Command => Some(&self.command),
+            _ => None,
+        }
+    }
+
+    /// `key` typed after the `typed` keys still waiting: the keys to act
+    /// on now. `typed` keeps whatever could yet be the start of a mapping.
+    pub fn feed(
+        &self,
+        mode: EditorMode,
+        typed: &mut Vec<KeyEvent>,
+        key: KeyEvent,
+    ) -> Vec<KeyEvent> {
+        typed.push(key);
+        let Some(trie) = self.trie(mode) else {
+            return std::mem::take(typed);
+        };
+        let mut out = Vec::new();
+        // Wait while a longer mapping could still match.
+        while !typed.is_empty() && trie.find(typed).is_none_or(|n| n.next.is_empty()) {
+            trie.take(typed, &mut out);
+        }
+        out
+    }
+
+    /// No more keys came in time: what is waiting maps as far as it goes.
+    pub fn flush(&self, mode: EditorMode, typed: &mut Vec<KeyEvent>) -> Vec<KeyEvent> {
+        let Some(trie) = self.trie(mode) else {
+            return std::mem::take(typed);
+        };
+        let mut out = Vec::new();
+        while !typed.is_empty() {
+            trie.take(typed, &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(all(test, feature = "config"))]
+mod tests {
+    use super::*;
+    use crate::keylog::notation;
+
+    fn keymap(src: &str) -> Keymap {
+        let table: toml::Table = src.parse().unwrap();
+        Keymap::parse(table["keymap"].as_table().unwrap()).unwrap()
+    }
+
+    fn typed(keys: &str) -> Vec<KeyEvent> {
+        parse_keys(keys, &[]).unwrap()
+    }
+
+    fn shown(keys: &[KeyEvent]) -> String {
+        keys.iter().map(|&k| notation(k)).collect()
+    }
+
+    #[test]
+    fn notation_reads_back() {
+        for keys in [
+            "jk",
+            "<C-w>x",
+            "<Space><lt>",
+            "<S-Tab><M-p>",
+            "<F5><CR><Esc>",
+        ] {
+            assert_eq!(shown(&typed(keys)), keys);
+        }
+        assert_eq!(shown(&typed("<c-W>")), "<C-W>");
+        assert!(parse_keys("<Nope>", &[]).is_err());
+        // An unclosed '<' is just a '<'
+        assert_eq!(typed("<").len(), 1);
+    }
+
+    #[test]
+    fn mapped_keys_wait_for_the_rest() {
+        let map = keymap(
+            r#"
+            [keymap]
+            leader = "<Space>"
+            [keymap.normal]
+            "<leader>w" = ":w<CR>"
+            "<leader>ww" = ":wq<CR>"
+            [keymap.insert]
+            jk = "<Esc>"
+            "#,
+        );
+        let mut waiting = Vec::new();
+        let mut feed = |mode, keys: &str| {
+            let mut out = Vec::new();
+            for k in typed(keys) {
+                out.extend(map.feed(mode, &mut waiting, k));
+            }
+            shown(&out)
+        };
+        assert_eq!(feed(EditorMode::Insert, "j"), "");
+        assert_eq!(feed(EditorMode::Insert, "k"), "<Esc>");
+        // Not the mapping after all: both keys as typed
+        assert_eq!(feed(EditorMode::Insert, "jx"), "jx");
+        assert_eq!(feed(EditorMode::Insert, "jjk"), "j<Esc>");
+        // Only the mode's own mappings
+        assert_eq!(feed(EditorMode::Normal, "jk"), "jk");
+        assert_eq!(feed(EditorMode::Normal, "<Space>wx"), ":w<CR>x");
+
+        // The shorter mapping once no more keys come
+        assert_eq!(feed(EditorMode::Normal, "<Space>w"), "");
+        assert_eq!(
+            shown(&map.flush(EditorMode::Normal, &mut waiting)),
+            ":w<CR>"
+        );
+        assert!(waiting.is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use std::hash::{Hash, Hasher};
+use std::ops::{BitOr, BitOrAssign, Sub, SubAssign};
+
+/// A key as the editor takes it, whatever read it: a terminal, a window,
+/// a web page. The names follow crossterm's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Backspace,
+    Enter,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Tab,
+    /// Shift-Tab.
+    BackTab,
+    Delete,
+    Insert,
+    F(u8),
+    Char(char),
+    Esc,
+}
+
+/// Shift, Ctrl and the rest, held with a key.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyModifiers(u8);
+
+impl KeyModifiers {
+    pub const NONE: Self = Self(0);
+    pub const SHIFT: Self = Self(1);
+    pub const CONTROL: Self = Self(1 << 1);
+    pub const ALT: Self = Self(1 << 2);
+    pub const SUPER: Self = Self(1 << 3);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for KeyModifiers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for KeyModifiers {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl Sub for KeyModifiers {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl SubAssign for KeyModifiers {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::fmt::Debug for KeyModifiers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let names = [
+            (Self::SHIFT, "SHIFT"),
+            (Self::CONTROL, "CONTROL"),
+            (Self::ALT, "ALT"),
+            (Self::SUPER, "SUPER"),
+        ];
+        let held: Vec<&str> = names
+            .iter()
+            .filter(|&&(m, _)| self.contains(m))
+            .map(|&(_, name)| name)
+            .collect();
+        if held.is_empty() {
+            f.write_str("NONE")
+        } else {
+            f.write_str(&held.join(" | "))
+        }
+    }
+}
+
+/// Only terminals with the kitty protocol tell repeats and releases apart
+/// from presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+/// A key pressed (or released) with its modifiers. An upper case letter
+/// and the same with Shift held are equal: terminals differ on which they
+/// send.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+    pub kind: KeyEventKind,
+}
+
+impl KeyEvent {
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self::new_with_kind(code, modifiers, KeyEventKind::Press)
+    }
+
+    pub const fn new_with_kind(code: KeyCode, modifiers: KeyModifiers, kind: KeyEventKind) -> Self {
+        Self {
+            code,
+            modifiers,
+            kind,
+        }
+    }
+
+    /// Shift and the letter's case made to agree.
+    fn normalize_case(mut self) -> Self {
+        if let KeyCode::Char(c) = self.code {
+            if c.is_ascii_uppercase() {
+                self.modifiers |= KeyModifiers::SHIFT;
+            } else if self.modifiers.contains(KeyModifiers::SHIFT) {
+                self.code = KeyCode::Char(c.to_ascii_uppercase());
+            }
+        }
+        self
+    }
+
+    /// A key as crossterm reads it from the terminal; None for the keys the
+    /// editor has no use for (media keys, a modifier on its own).
+    #[cfg(feature = "terminal")]
+    pub fn from_crossterm(key: crossterm::event::KeyEvent) -> Option<Self> {
+        use crossterm::event::{KeyCode as Ct, KeyEventKind as Kind, KeyModifiers as Mods};
+        let code = match key.code {
+            Ct::Backspace => KeyCode::Backspace,
+            Ct::Enter => KeyCode::Enter,
+            Ct::Left => KeyCode::Left,
+            Ct::Right => KeyCode::Right,
+            Ct::Up => KeyCode::Up,
+            Ct::Down => KeyCode::Down,
+            Ct::Home => KeyCode::Home,
+            Ct::End => KeyCode::End,
+            Ct::PageUp => KeyCode::PageUp,
+            Ct::PageDown => KeyCode::PageDown,
+            Ct::Tab => KeyCode::Tab,
+            Ct::BackTab => KeyCode::BackTab,
+            Ct::Delete => KeyCode::Delete,
+            Ct::Insert => KeyCode::Insert,
+            Ct::F(n) => KeyCode::F(n),
+            Ct::Char(c) => KeyCode::Char(c),
+            Ct::Esc => KeyCode::Esc,
+            _ => return None,
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for (theirs, ours) in [
+            (Mods::SHIFT, KeyModifiers::SHIFT),
+            (Mods::CONTROL, KeyModifiers::CONTROL),
+            (Mods::ALT, KeyModifiers::ALT),
+            (Mods::SUPER, KeyModifiers::SUPER),
+        ] {
+            if key.modifiers.contains(theirs) {
+                modifiers |= ours;
+            }
+        }
+        let kind = match key.kind {
+            Kind::Press => KeyEventKind::Press,
+            Kind::Repeat => KeyEventKind::Repeat,
+            Kind::Release => KeyEventKind::Release,
+        };
+        Some(Self::new_with_kind(code, modifiers, kind))
+    }
+}
+
+impl PartialEq for KeyEvent {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.normalize_case(), other.normalize_case());
+        (a.code, a.modifiers, a.kind) == (b.code, b.modifiers, b.kind)
+    }
+}
+
+impl Eq for KeyEvent {}
+
+impl Hash for KeyEvent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let key = self.normalize_case();
+        (key.code, key.modifiers, key.kind).hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shift_and_case_agree() {
+        let key = |c, m| KeyEvent::new(KeyCode::Char(c), m);
+        assert_eq!(key('Q', KeyModifiers::NONE), key('Q', KeyModifiers::SHIFT));
+        assert_eq!(key('q', KeyModifiers::SHIFT), key('Q', KeyModifiers::NONE));
+        assert_ne!(key('q', KeyModifiers::NONE), key('Q', KeyModifiers::NONE));
+        let mods = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        assert!(mods.contains(KeyModifiers::CONTROL));
+        assert_eq!(mods - KeyModifiers::SHIFT, KeyModifiers::CONTROL);
+        assert_eq!(format!("{mods:?}"), "SHIFT | CONTROL");
+    }
+}
@@ -0,0 +1,1247 @@
+use crate::buffer::grapheme_width;
+use crate::buffer::{Buffer, CursorShape, Style};
+use crate::conflicts::{find_conflicts, ConflictRegion};
+use crate::editor::{Editor, EditorMode};
+use crate::graphemes::{abs_char_to_line_gcol, cell_width, gcol_to_vcol, line_gcol_to_abs_char};
+use crate::messages::Message;
+use crate::motion::matching_bracket;
+use crate::options::Options;
+use crate::theme::{Group, Theme};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// One concern of the screen. Layers draw in order into the same frame,
+/// later ones painting over or restyling what earlier ones drew.
+pub trait Layer {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer);
+}
+
+/// Back to front. Syntax, search and selection highlights belong after
+/// `Text`; floating windows go last.
+pub const LAYERS: &[&dyn Layer] = &[
+    &Text,
+    &TrailingWhitespace,
+    &MatchParen,
+    &ColorColumn,
+    &Conflicts,
+    &Selection,
+    &Gutter,
+    &Sidebar,
+    &QuickfixPane,
+    &StatusLine,
+    &BottomRow,
+    &CompletionMenu,
+    &PickerOverlay,
+    &KeyCast,
+];
+
+/// Rows below the text: the status line and the command line.
+pub const RESERVED_ROWS: u16 = 2;
+
+/// A `width` by `height` frame of `editor`, every layer drawn.
+pub fn compose(editor: &Editor, width: usize, height: usize) -> Buffer {
+    let mut buf = Buffer::new(width, height);
+    for layer in LAYERS {
+        layer.draw(editor, &mut buf);
+    }
+    buf
+}
+
+/// Shown in place of a NUL byte: one column, like the NUL it stands for
+/// in the grapheme math, so the cursor stays lined up.
+const NUL_PLACEHOLDER: &str = "\u{2400}";
+
+/// Whether a line on screen is past `longline`, and so goes without the
+/// extras.
+fn long_lines_on_screen(editor: &Editor) -> bool {
+    let opts = editor.options();
+    visible_lines(editor)
+        .iter()
+        .any(|l| opts.is_long(l.chars().count()))
+}
+
+/// A line as screen cells: each tab widened to the next tab stop, and in
+/// `list` mode whitespace swapped for the `listchars` glyphs. The flag
+/// marks those glyphs.
+fn cells(line: &str, opts: &Options) -> Vec<(String, bool)> {
+    let lcs = &opts.listchars;
+    let list = |glyph: Option<char>| glyph.filter(|_| opts.list);
+    let trail_from = line.trim_end_matches(' ').len();
+    let mut out = Vec::new();
+    let mut col = 0;
+    let mut byte = 0;
+    for g in line.graphemes(true) {
+        let w = cell_width(g, col, opts.tabstop);
+        match g {
+            "\t" => match list(lcs.tab.map(|(first, _)| first)) {
+                Some(first) => {
+                    let rest = lcs.tab.map_or(' ', |(_, rest)| rest);
+                    out.push((first.to_string(), true));
+                    out.extend((1..w).map(|_| (rest.to_string(), true)));
+                }
+                None => out.extend((0..w).map(|_| (" ".to_owned(), false))),
+            },
+            " " if byte >= trail_from && list(lcs.trail).is_some() => {
+                out.push((lcs.trail.unwrap_or(' ').to_string(), true));
+            }
+            "\u{a0}" if list(lcs.nbsp).is_some() => {
+                out.push((lcs.nbsp.unwrap_or(' ').to_string(), true));
+            }
+            _ => out.push((g.to_owned(), false)),
+        }
+        col += w;
+        byte += g.len();
+    }
+    if let Some(eol) = list(lcs.eol) {
+        out.push((eol.to_string(), true));
+    }
+    out
+}
+
+/// The cells from screen column `left`, whether text was cut there, and
+/// the width of what is left. A wide grapheme cut in half leaves a blank.
+fn scroll_line(cells: Vec<(String, bool)>, left: usize) -> (Vec<(String, bool)>, bool, usize) {
+    let mut col = 0;
+    let mut out = Vec::new();
+    for (g, glyph) in cells {
+        let w = grapheme_width(&g);
+        if col >= left {
+            out.push((g, glyph));
+        } else if col + w > left {
+            out.push((" ".to_owned(), false));
+        }
+        col += w;
+    }
+    (out, left > 0 && col > 0, col.saturating_sub(left))
+}
+
+/// Buffer rows on screen, without line breaks; a preview reads them from disk.
+fn visible_lines(editor: &Editor) -> Vec<String> {
+    let view = editor.view();
+    if let Some(preview) = editor.preview() {
+        return preview.lines(view.top, view.height).unwrap_or_default();
+    }
+    editor
+        .text
+        .lines()
+        .skip(view.top)
+        .take(view.height)
+        .map(|l| {
+            let l = l.to_string();
+            l.trim_end_matches('\n').trim_end_matches('\r').to_owned()
+        })
+        .collect()
+}
+
+// ------ Layers ---------------------------------------------------------------
+
+/// The buffer's text, right of the sidebar and the gutter, and the
+/// cursor over it.
+pub struct Text;
+
+impl Layer for Text {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let left = editor.text_left();
+        let view = editor.view();
+        let marker = editor.theme().get(Group::NonText);
+        let whitespace = editor.theme().get(Group::Whitespace);
+        for (y, line) in visible_lines(editor).iter().enumerate() {
+            // NULs and tabs would upset the terminal's own cursor, so NULs get
+            // a one-cell symbol and tabs are drawn as spaces.
+            let line = line.replace('\0', NUL_PLACEHOLDER);
+            let (shown, cut_left, width) = scroll_line(cells(&line, editor.options()), view.left);
+            let mut x = left;
+            for (g, glyph) in &shown {
+                let style = if *glyph { whitespace } else { Style::default() };
+                let end = buf.put_str(x, y, g, style);
+                if end == x {
+                    break;
+                }
+                x = end;
+            }
+            // `<` and `>` where the line goes on past the screen
+            if cut_left {
+                buf.put_str(left, y, "<", marker);
+            }
+            if width > buf.width.saturating_sub(left) {
+                buf.put_str(buf.width - 1, y, ">", marker);
+            }
+            if editor.preview().is_none() && view.top + y == editor.cursor_row {
+                let x = left + editor.cursor_vcol().saturating_sub(view.left);
+                buf.cursor = Some((x, y));
+            }
+        }
+        if editor.preview().is_none() && buf.cursor.is_none() {
+            // The empty line after a final line break
+            buf.cursor = Some((left, editor.cursor_row - view.top));
+        }
+        buf.cursor_shape = match editor.mode() {
+            EditorMode::Insert => CursorShape::Bar,
+            EditorMode::Replace => CursorShape::Underline,
+            _ => CursorShape::Block,
+        };
+    }
+}
+
+/// With `showtrailing`, a warning background behind the blanks ending each
+/// line. Not on the line being typed in Insert mode, where a space before
+/// the next word would flash.
+pub struct TrailingWhitespace;
+
+impl Layer for TrailingWhitespace {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        if !editor.options().showtrailing {
+            return;
+        }
+        let view = editor.view();
+        let left = editor.text_left();
+        let tabstop = editor.options().tabstop;
+        let style = editor.theme().get(Group::TrailingWhitespace);
+        let typing = editor.preview().is_none() && matches!(editor.mode(), EditorMode::Insert);
+        for (y, line) in visible_lines(editor).iter().enumerate() {
+            if typing && view.top + y == editor.cursor_row
+                || editor.options().is_long(line.chars().count())
+            {
+                continue;
+            }
+            let trimmed = line.trim_end_matches([' ', '\t']);
+            if trimmed.len() == line.len() {
+                continue;
+            }
+            let from = gcol_to_vcol(line, trimmed.graphemes(true).count(), tabstop);
+            let to = gcol_to_vcol(line, line.graphemes(true).count(), tabstop);
+            for vcol in from.max(view.left)..to {
+                if let Some(cell) = buf.get_mut(left + vcol - view.left, y) {
+                    cell.style = cell.style.patch(style);
+                }
+            }
+        }
+    }
+}
+
+/// With the cursor on a bracket, its partner is highlighted when it is on
+/// screen (Vim's matchparen). Not while a long line is on screen, which
+/// would be searched through on every key.
+pub struct MatchParen;
+
+impl Layer for MatchParen {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        if editor.preview().is_some() || long_lines_on_screen(editor) {
+            return;
+        }
+        let text = &editor.text;
+        let view = editor.view();
+        let cursor = line_gcol_to_abs_char(text, editor.cursor_row, editor.cursor_gcol);
+        // Only what is on screen is searched.
+        let last = (view.top + view.height).min(text.len_lines());
+        let on_screen = text.line_to_char(view.top.min(last))..text.line_to_char(last);
+        let Some(partner) = matching_bracket(text, cursor, on_screen) else {
+            return;
+        };
+        let (row, gcol) = abs_char_to_line_gcol(text, partner);
+        let line = text.line(row).to_string();
+        let vcol = gcol_to_vcol(&line, gcol, editor.options().tabstop);
+        let Some(x) = vcol.checked_sub(view.left) else {
+            return;
+        };
+        if let Some(cell) = buf.get_mut(editor.text_left() + x, row - view.top) {
+            cell.style = cell.style.patch(editor.theme().get(Group::MatchParen));
+        }
+    }
+}
+
+/// `colorcolumn` guides down every line of text on screen.
+pub struct ColorColumn;
+
+impl Layer for ColorColumn {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let view = editor.view();
+        let left = editor.text_left();
+        let style = editor.theme().get(Group::ColorColumn);
+        let rows = match editor.preview() {
+            Some(_) => view.height,
+            None => editor.text.len_lines().saturating_sub(view.top),
+        };
+        for &col in &editor.options().colorcolumn {
+            // Columns scrolled off to the left aren't drawn.
+            let Some(x) = (col - 1).checked_sub(view.left).map(|x| left + x) else {
+                continue;
+            };
+            let text_rows = buf.height.saturating_sub(RESERVED_ROWS as usize);
+            for y in 0..rows.min(view.height).min(text_rows) {
+                if let Some(cell) = buf.get_mut(x, y) {
+                    cell.style = cell.style.patch(style);
+                }
+            }
+        }
+    }
+}
+
+fn conflict_group(region: ConflictRegion) -> Group {
+    match region {
+        ConflictRegion::Marker => Group::ConflictMarker,
+        ConflictRegion::Ours => Group::ConflictOurs,
+        ConflictRegion::Base => Group::ConflictBase,
+        ConflictRegion::Theirs => Group::ConflictTheirs,
+    }
+}
+
+/// Colours the sides of merge conflict blocks.
+pub struct Conflicts;
+
+impl Layer for Conflicts {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let view = editor.view();
+        let left = editor.text_left();
+        for c in find_conflicts(&editor.text) {
+            for row in c.start.max(view.top)..=c.end.min(view.bottom()) {
+                let Some(region) = c.region(row) else {
+                    continue;
+                };
+                let style = editor.theme().get(conflict_group(region));
+                for x in left..buf.width {
+                    if let Some(cell) = buf.get_mut(x, row - view.top) {
+                        cell.style = cell.style.patch(style);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The lines selected in Visual mode, across the width of the text.
+pub struct Selection;
+
+impl Layer for Selection {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let Some(rows) = editor.selection() else {
+            return;
+        };
+        let view = editor.view();
+        let style = editor.theme().get(Group::Selection);
+        for row in *rows.start().max(&view.top)..=*rows.end().min(&view.bottom()) {
+            for x in editor.text_left()..buf.width {
+                if let Some(cell) = buf.get_mut(x, row - view.top) {
+                    cell.style = cell.style.patch(style);
+                }
+            }
+        }
+    }
+}
+
+/// The gutter: its components left to right, in `gutter` order.
+pub struct Gutter;
+
+impl Layer for Gutter {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let top = editor.view().top;
+        let rows = visible_lines(editor).len();
+        let mut left = editor.sidebar_width();
+        for (component, width) in editor.gutter() {
+            for y in 0..rows {
+                let (cell, group) = component.cell(editor, top + y, width);
+                buf.put_str(left, y, &cell, editor.theme().get(group));
+            }
+            left += width;
+        }
+    }
+}
+
+/// The file tree down the left, with a line between it and the text. The
+/// selected entry is highlighted, and has the cursor while the tree does.
+pub struct Sidebar;
+
+impl Layer for Sidebar {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let Some(tree) = editor.tree() else {
+            return;
+        };
+        let width = editor.sidebar_width();
+        let Some(edge) = width.checked_sub(1) else {
+            return;
+        };
+        let rows = buf.height.saturating_sub(RESERVED_ROWS as usize);
+        let theme = editor.theme();
+        let focused = matches!(editor.mode(), EditorMode::Tree);
+        for y in 0..rows {
+            buf.put_str(edge, y, "\u{2502}", theme.get(Group::LineNumber));
+        }
+        let shown = tree
+            .entries()
+            .iter()
+            .enumerate()
+            .skip(tree.top())
+            .take(rows);
+        for (y, (i, entry)) in shown.enumerate() {
+            let (marker, style) = if !entry.dir {
+                ("  ", Style::default())
+            } else if tree.is_expanded(&entry.path) {
+                ("\u{25be} ", theme.get(Group::Directory))
+            } else {
+                ("\u{25b8} ", theme.get(Group::Directory))
+            };
+            let label = format!("{}{marker}{}", "  ".repeat(entry.depth), entry.name());
+            let mut x = 0;
+            for g in label.graphemes(true) {
+                if x + grapheme_width(g) > edge {
+                    break;
+                }
+                x = buf.put_str(x, y, g, style);
+            }
+            if i == tree.selected_row() {
+                buf.set_style(0..edge, y, style.patch(theme.get(Group::Selection)));
+                if focused {
+                    buf.cursor = Some((0, y));
+                }
+            }
+        }
+    }
+}
+
+fn mode_name(editor: &Editor) -> &'static str {
+    if editor.preview().is_some() {
+        return "PREVIEW";
+    }
+    match editor.mode() {
+        EditorMode::Normal | EditorMode::Prompt => "NORMAL",
+        EditorMode::Insert => "INSERT",
+        EditorMode::Replace => "REPLACE",
+        EditorMode::Visual => "VISUAL LINE",
+        EditorMode::Command => "COMMAND",
+        EditorMode::Picker => "PICKER",
+        EditorMode::Tree => "TREE",
+    }
+}
+
+/// After `:copen`, the quickfix list under the text: a title line, then
+/// `file|line col N| text` for each entry, the current one highlighted.
+pub struct QuickfixPane;
+
+impl Layer for QuickfixPane {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let (rows, Some(list)) = (editor.quickfix_rows(), editor.quickfix()) else {
+            return;
+        };
+        if rows == 0 {
+            return;
+        }
+        let top = editor.view().height;
+        // Over the sidebar's rows too
+        for y in top..top + rows {
+            buf.put_str(0, y, &" ".repeat(buf.width), Style::default());
+        }
+        let title = editor.theme().get(Group::StatusLine);
+        buf.set_style(0..buf.width, top, title);
+        buf.put_str(0, top, &format!(" [Quickfix List] {}", list.title), title);
+        // Scrolled to keep the current entry in sight
+        let shown = rows - 1;
+        let first = (list.current + 1).saturating_sub(shown);
+        for (i, entry) in list.entries.iter().enumerate().skip(first).take(shown) {
+            let y = top + 1 + i - first;
+            let style = if i == list.current {
+                editor.theme().get(Group::Selection)
+            } else {
+                Style::default()
+            };
+            buf.set_style(0..buf.width, y, style);
+            let at = match entry.col {
+                Some(col) => format!("{} col {col}", entry.line),
+                None => entry.line.to_string(),
+            };
+            let name = list.name(entry).display();
+            buf.put_str(0, y, &format!("{name}|{at}| {}", entry.text), style);
+        }
+    }
+}
+
+/// Mode, file name and modified flag on the left; file format and
+/// `line:col` on the right. Short of room the right side goes a piece at
+/// a time, then the name loses its start; the mode always shows.
+pub struct StatusLine;
+
+impl Layer for StatusLine {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let Some(y) = buf.height.checked_sub(2) else {
+            return;
+        };
+        let name = match editor.file_path() {
+            _ if editor.is_notes() => "[Notes]".into(),
+            Some(p) => p.display().to_string(),
+            None => "[No Name]".into(),
+        };
+        let flag = if editor.modified() { " [+]" } else { "" };
+        let long = if long_lines_on_screen(editor) {
+            " [long lines]"
+        } else {
+            ""
+        };
+        let (position, byte) = match editor.preview() {
+            Some(_) => (format!("{}:1", editor.view().top + 1), String::new()),
+            None => (
+                format!("{}:{}", editor.cursor_row + 1, editor.cursor_gcol + 1),
+                format!(" byte {}", editor.cursor_byte()),
+            ),
+        };
+        let format = editor.file_format().label();
+
+        let width = |s: &str| s.graphemes(true).map(grapheme_width).sum::<usize>();
+        let mode = format!(" {} | ", mode_name(editor));
+        let tail = format!("{flag}{long}");
+        let fixed = width(&mode) + width(&tail);
+        // The right side sheds the byte offset, then the format, then the
+        // position before the name is cut.
+        let right = [
+            format!("{format}  {position}{byte} "),
+            format!("{format}  {position} "),
+            format!("{position} "),
+        ]
+        .into_iter()
+        .find(|right| fixed + width(&name) + 1 + width(right) <= buf.width)
+        .unwrap_or_default();
+        let gap = usize::from(!right.is_empty());
+        let room = buf.width.saturating_sub(fixed + width(&right) + gap);
+        let name = if width(&name) <= room {
+            name
+        } else {
+            cut_start(&name, room)
+        };
+
+        let style = editor.theme().get(Group::StatusLine);
+        buf.set_style(0..buf.width, y, style);
+        buf.put_str(0, y, &format!("{mode}{name}{tail}"), style);
+        buf.put_str(buf.width.saturating_sub(width(&right)), y, &right, style);
+    }
+}
+
+/// The end of `text` that fits in `room` columns, `<` marking the cut.
+fn cut_start(text: &str, room: usize) -> String {
+    let Some(mut left) = room.checked_sub(1) else {
+        return String::new();
+    };
+    let mut kept: Vec<&str> = Vec::new();
+    for g in text.graphemes(true).rev() {
+        let Some(rest) = left.checked_sub(grapheme_width(g)) else {
+            break;
+        };
+        left = rest;
+        kept.push(g);
+    }
+    kept.reverse();
+    format!("<{}", kept.concat())
+}
+
+/// The last row: the command line, a prompt waiting for an answer, or
+/// the last command's message.
+pub struct BottomRow;
+
+impl Layer for BottomRow {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let y = buf.height.saturating_sub(1);
+        let line = match editor.mode() {
+            EditorMode::Command => {
+                buf.cursor_shape = CursorShape::Bar;
+                let input = editor.input().expect("command mode has an input");
+                format!("{}{}", input.prefix(), input.text())
+            }
+            EditorMode::Prompt => {
+                // Choices listed above the question
+                let prompt = editor.prompt().unwrap_or_default();
+                match prompt.rsplit_once('\n') {
+                    Some((choices, question)) => {
+                        draw_above(choices, Style::default(), buf);
+                        question.to_owned()
+                    }
+                    None => prompt.to_owned(),
+                }
+            }
+            _ => {
+                if let Some(msg) = editor.message() {
+                    draw_message(msg, editor.theme(), buf);
+                }
+                return;
+            }
+        };
+        let end = buf.put_str(0, y, &line, Style::default());
+        buf.cursor = Some((end, y));
+    }
+}
+
+/// Ctrl-N's words in a box under the one being completed (over it when
+/// there's no room below), the one in the text highlighted.
+pub struct CompletionMenu;
+
+/// Items shown at once; the rest scroll.
+const MENU_ROWS: usize = 10;
+
+impl Layer for CompletionMenu {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let (Some(menu), Some((cursor_x, cursor_y))) = (editor.completion(), buf.cursor) else {
+            return;
+        };
+        let text_rows = buf.height.saturating_sub(RESERVED_ROWS as usize);
+        let rows = menu.items.len().min(MENU_ROWS);
+        let below = text_rows.saturating_sub(cursor_y + 1);
+        let top = if below >= rows || below >= cursor_y {
+            cursor_y + 1
+        } else {
+            cursor_y.saturating_sub(rows)
+        };
+        let rows = rows.min(text_rows.saturating_sub(top));
+        let first = match menu.selected {
+            Some(i) if i >= rows => i + 1 - rows,
+            _ => 0,
+        };
+        let width = |s: &str| s.graphemes(true).map(grapheme_width).sum::<usize>();
+        let inner = menu.items.iter().map(|w| width(w)).max().unwrap_or(0);
+        let word_x = cursor_x.saturating_sub(width(menu.current()));
+        let left = word_x
+            .saturating_sub(1)
+            .min(buf.width.saturating_sub(inner + 2));
+        let theme = editor.theme();
+        for (y, (i, item)) in (top..).zip(menu.items.iter().enumerate().skip(first).take(rows)) {
+            let style = if menu.selected == Some(i) {
+                theme.get(Group::Menu).patch(theme.get(Group::Selection))
+            } else {
+                theme.get(Group::Menu)
+            };
+            let line = format!(" {item}{} ", " ".repeat(inner - width(item)));
+            buf.put_str(left, y, &line, style);
+        }
+    }
+}
+
+/// An open picker over the text: the query, the matches with the selected
+/// one highlighted, and a preview of it on the right when there is room.
+pub struct PickerOverlay;
+
+/// Narrower screens show the list alone.
+const PREVIEW_MIN_WIDTH: usize = 60;
+
+impl Layer for PickerOverlay {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        let Some(picker) = editor.picker() else {
+            return;
+        };
+        let rows = buf.height.saturating_sub(RESERVED_ROWS as usize);
+        if rows == 0 {
+            return;
+        }
+        let list_width = if buf.width >= PREVIEW_MIN_WIDTH {
+            buf.width / 2
+        } else {
+            buf.width
+        };
+        let theme = editor.theme();
+        for y in 0..rows {
+            buf.put_str(0, y, &" ".repeat(buf.width), Style::default());
+        }
+
+        let prompt = format!("{}> {}", picker.title(), picker.query());
+        let end = buf.put_str(0, 0, &prompt, Style::default());
+        let count = format!("{}/{}", picker.matched(), picker.total());
+        if end + count.len() < list_width {
+            buf.put_str(
+                list_width - count.len() - 1,
+                0,
+                &count,
+                theme.get(Group::LineNumber),
+            );
+        }
+        buf.cursor = Some((end, 0));
+        buf.cursor_shape = CursorShape::Bar;
+
+        // Scroll the list to keep the selection in view.
+        let list_rows = rows - 1;
+        let first = (picker.selected() + 1).saturating_sub(list_rows);
+        for (i, m) in picker
+            .matches()
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(list_rows)
+        {
+            let y = 1 + i - first;
+            let base = if i == picker.selected() {
+                theme.get(Group::Selection)
+            } else {
+                Style::default()
+            };
+            buf.set_style(0..list_width.saturating_sub(1), y, base);
+            let label = &m.item.label;
+            let mut x = 1;
+            for (ci, c) in label.chars().enumerate() {
+                if x + 2 > list_width {
+                    break;
+                }
+                let style = if m.positions.contains(&ci) {
+                    base.patch(theme.get(Group::PickerMatch))
+                } else {
+                    base
+                };
+                x = buf.put_str(x, y, &c.to_string(), style);
+            }
+        }
+
+        if list_width < buf.width {
+            for y in 0..rows {
+                buf.put_str(list_width, y, "│", theme.get(Group::LineNumber));
+            }
+            let preview = picker.preview();
+            // The line the item points at goes in the middle.
+            let first = preview.focus.map_or(0, |f| f.saturating_sub(rows / 2));
+            for (y, line) in preview.lines.iter().skip(first).take(rows).enumerate() {
+                let base = if preview.focus == Some(first + y) {
+                    theme.get(Group::Selection)
+                } else {
+                    Style::default()
+                };
+                buf.set_style(list_width + 1..buf.width, y, base);
+                let spans = preview
+                    .syntax
+                    .filter(|_| !editor.options().is_long(line.chars().count()))
+                    .map(|s| s.highlight(line))
+                    .unwrap_or_default();
+                let mut x = list_width + 2;
+                for (at, g) in line.grapheme_indices(true) {
+                    let style = spans
+                        .iter()
+                        .find(|(range, _)| range.contains(&at))
+                        .map_or(base, |(_, group)| base.patch(theme.get(*group)));
+                    let g = if g == "\t" { "    " } else { g };
+                    let end = buf.put_str(x, y, g, style);
+                    if end == x {
+                        break;
+                    }
+                    x = end;
+                }
+            }
+        }
+    }
+}
+
+/// With `showkeys`, recent keys and the commands they made, newest at the
+/// bottom right of the text.
+pub struct KeyCast;
+
+/// Widest a `showkeys` line gets.
+const KEYCAST_WIDTH: usize = 48;
+
+impl Layer for KeyCast {
+    fn draw(&self, editor: &Editor, buf: &mut Buffer) {
+        if !editor.options().showkeys {
+            return;
+        }
+        let text_rows = buf.height.saturating_sub(RESERVED_ROWS as usize);
+        let style = editor.theme().get(Group::StatusLine);
+        let room = KEYCAST_WIDTH.min(buf.width);
+        let strokes: Vec<_> = editor.key_log().strokes().collect();
+        for (i, stroke) in strokes.iter().rev().take(text_rows).enumerate() {
+            let what = match (&stroke.command, stroke.pending) {
+                (_, true) => "…",
+                (Some(command), false) => command.as_str(),
+                (None, false) => "(not mapped)",
+            };
+            let mut line = String::new();
+            let mut width = 0;
+            for g in format!(" {}  {what} ", stroke.keys).graphemes(true) {
+                if width + grapheme_width(g) > room {
+                    line.pop();
+                    line.push('…');
+                    break;
+                }
+                width += grapheme_width(g);
+                line.push_str(g);
+            }
+            buf.put_str(buf.width - width, text_rows - 1 - i, &line, style);
+        }
+    }
+}
+
+/// A long message keeps its end, like Vim: `<` marks the cut. Several
+/// lines scroll up over the text with a line asking for Enter.
+fn draw_message(msg: &Message, theme: &Theme, buf: &mut Buffer) {
+    let style = if msg.error {
+        theme.get(Group::ErrorMsg)
+    } else {
+        Style::default()
+    };
+    let bottom = buf.height.saturating_sub(1);
+    if !msg.is_multiline() {
+        let graphemes: Vec<&str> = msg.text.graphemes(true).collect();
+        // The last column is left free so the terminal never wraps.
+        let room = buf.width.saturating_sub(1);
+        if graphemes.len() > room {
+            let tail = graphemes[graphemes.len() + 1 - room..].concat();
+            buf.put_str(0, bottom, &format!("<{tail}"), style);
+        } else {
+            buf.put_str(0, bottom, &msg.text, style);
+        }
+        return;
+    }
+    draw_above(&msg.text, style, buf);
+    let end = buf.put_str(0, bottom, CONTINUE, theme.get(Group::MoreMsg));
+    buf.cursor = Some((end, bottom));
+}
+
+/// Lines of `text` over the text area, ending just above the bottom row;
+/// the first ones go if they don't fit.
+fn draw_above(text: &str, style: Style, buf: &mut Buffer) {
+    let bottom = buf.height.saturating_sub(1);
+    let lines: Vec<&str> = text.lines().collect();
+    let shown = &lines[lines.len().saturating_sub(bottom)..];
+    for (y, line) in (bottom - shown.len()..).zip(shown) {
+        buf.put_str(0, y, &" ".repeat(buf.width), Style::default());
+        buf.put_str(0, y, line, style);
+    }
+}
+
+const CONTINUE: &str = "Press ENTER or type command to continue";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::graphemes::gcol_to_vcol;
+    use crate::input::EditorCommand;
+    use crate::motion::Motion;
+
+    fn editor_with(text: &str) -> Editor {
+        let mut ed = Editor::new();
+        for c in text.chars() {
+            ed = ed.handle_command(EditorCommand::InsertChar(c));
+        }
+        ed
+    }
+
+    fn frame(editor: &Editor, width: usize, height: usize) -> Buffer {
+        compose(editor, width, height)
+    }
+
+    #[test]
+    fn composes_text_gutter_and_bottom_row() {
+        let mut ed = editor_with("a\0b\n\tc");
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "nu".into(),
+            value: true,
+        });
+        let buf = frame(&ed, 50, 5);
+        assert_eq!(buf.row_text(0), "  1 a\u{2400}b");
+        assert_eq!(buf.row_text(1), "  2         c");
+        assert_eq!(
+            buf.row_text(3),
+            " NORMAL | [No Name] [+]    utf-8 unix  2:3 byte 7"
+        );
+        assert_eq!(buf.row_text(4), "");
+        assert_eq!(buf.cursor, Some((13, 1)));
+
+        ed = ed.handle_command(EditorCommand::EnterCommandMode);
+        ed = ed.handle_command(EditorCommand::CmdlineInput('w'));
+        let buf = frame(&ed, 50, 5);
+        assert!(buf.row_text(3).starts_with(" COMMAND |"));
+        assert_eq!(buf.row_text(4), ":w");
+        assert_eq!(buf.cursor, Some((2, 4)));
+        assert_eq!(buf.cursor_shape, CursorShape::Bar);
+    }
+
+    #[test]
+    fn cursor_lands_after_wide_graphemes() {
+        let mut ed = editor_with(
+            "日本語
+ab",
+        );
+        // Moving up keeps the screen column, landing on 本
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        assert_eq!(ed.cursor_gcol, 1);
+        let buf = frame(&ed, 20, 4);
+        assert_eq!(buf.row_text(0), "日本語");
+        assert_eq!(buf.cursor, Some((2, 0)));
+        assert_eq!(gcol_to_vcol("日本語", 3, 8), 6);
+        assert_eq!(gcol_to_vcol("a👍", 5, 8), 6);
+    }
+
+    #[test]
+    fn relative_numbers_count_from_the_cursor() {
+        let mut ed = editor_with("a\nb\nc\nd");
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        let numbers = |ed: &Editor| {
+            (0..4)
+                .map(|r| crate::gutter::line_number(ed, r))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(&ed), [1, 2, 3, 4]);
+
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "rnu".into(),
+            value: true,
+        });
+        assert_eq!(numbers(&ed), [2, 1, 3, 1]);
+        assert_eq!(ed.gutter_width(), 4);
+    }
+
+    #[test]
+    fn gutter_components_in_the_order_given() {
+        let mut ed = editor_with("a\nb\nc");
+        ed = ed.handle_command(EditorCommand::SetMark('q'));
+        let set = |ed: Editor, arg: &str| ed.handle_command(crate::ex::parse(arg).unwrap());
+        // Numbers off: nothing to show but the marks
+        ed = set(ed, "set gutter=marks,number");
+        assert_eq!(ed.gutter_width(), 2);
+        assert_eq!(frame(&ed, 20, 5).row_text(2), "q c");
+
+        ed = set(ed, "set nu");
+        ed = set(ed, "set gutter=number:6,marks");
+        assert_eq!(ed.gutter_width(), 8);
+        let buf = frame(&ed, 20, 5);
+        assert_eq!(buf.row_text(0), "    1   a");
+        assert_eq!(buf.row_text(2), "    3 q c");
+        assert_eq!(
+            ed.options().show("gutter").unwrap(),
+            "gutter=number:6,marks"
+        );
+        assert!(crate::ex::parse("set gutter=number,folds").is_err());
+    }
+
+    #[test]
+    fn quickfix_pane_lists_the_places_under_the_text() {
+        let mut ed = editor_with("x.rs:3: bad\ny.rs:1:2: worse\nz");
+        ed.set_view_size(40, 6);
+        for line in ["cbuffer", "copen"] {
+            ed = ed.handle_command(crate::ex::parse(line).unwrap());
+        }
+        let buf = frame(&ed, 40, 8);
+        assert_eq!(buf.row_text(2), "z");
+        assert_eq!(buf.row_text(3), " [Quickfix List] :cbuffer");
+        assert_eq!(buf.row_text(4), "x.rs|3| bad");
+        assert_eq!(buf.row_text(5), "y.rs|1 col 2| worse");
+        assert_eq!(
+            buf.get(0, 4).unwrap().style,
+            ed.theme().get(Group::Selection)
+        );
+    }
+
+    #[test]
+    fn conflict_sides_are_coloured() {
+        let ed = editor_with("<<<<<<< a\nmine\n=======\nyours\n>>>>>>> b\n");
+        let buf = frame(&ed, 12, 7);
+        assert_eq!(buf.get(0, 1).unwrap().style.fg, Some(Color::Green));
+        assert_eq!(buf.get(0, 3).unwrap().style.fg, Some(Color::Blue));
+        assert_eq!(buf.get(0, 5).unwrap().style.fg, None);
+    }
+
+    #[test]
+    fn visual_selection_is_highlighted() {
+        let mut ed = editor_with("a\nb\nc");
+        ed = ed.handle_command(EditorCommand::EnterVisualLine);
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        let buf = frame(&ed, 20, 5);
+        let selection = ed.theme().get(Group::Selection);
+        assert_eq!(buf.get(5, 1).unwrap().style, selection);
+        assert_eq!(buf.get(0, 2).unwrap().style, selection);
+        assert_eq!(buf.get(0, 0).unwrap().style, Style::default());
+        assert!(buf.row_text(3).starts_with(" VISUAL LINE |"));
+    }
+
+    #[test]
+    fn long_messages_are_cut_or_shown_over_the_text() {
+        let run = |ed: Editor, line: &str| {
+            let mut ed = ed.handle_command(EditorCommand::EnterCommandMode);
+            for c in line.chars() {
+                ed = ed.handle_command(EditorCommand::CmdlineInput(c));
+            }
+            ed.handle_command(EditorCommand::CmdlineExecute)
+        };
+        let ed = run(editor_with("text"), "frobnicate");
+        let buf = frame(&ed, 16, 4);
+        assert_eq!(buf.row_text(3), "<nd: frobnicate");
+        assert_eq!(buf.get(0, 3).unwrap().style.fg, Some(Color::Red));
+
+        let ed = run(run(ed, "nope"), "messages");
+        let buf = frame(&ed, 50, 4);
+        assert!(buf
+            .row_text(1)
+            .ends_with("] E492: Not an editor command: frobnicate"));
+        assert!(buf
+            .row_text(2)
+            .ends_with("] E492: Not an editor command: nope"));
+        assert_eq!(buf.row_text(3), CONTINUE);
+    }
+
+    #[test]
+    fn tabs_reach_the_next_tab_stop() {
+        let mut ed = editor_with("a\tb\n\tx");
+        let buf = frame(&ed, 20, 4);
+        assert_eq!(buf.row_text(0), "a       b");
+        assert_eq!(buf.row_text(1), "        x");
+        // On the tab in Normal mode the cursor takes its last cell
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        ed = ed.handle_command(EditorCommand::MoveLeft);
+        assert_eq!(frame(&ed, 20, 4).cursor, Some((7, 1)));
+
+        // Up from x keeps the screen column, so lands on b, not the tab
+        ed = ed.handle_command(EditorCommand::MoveRight);
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        assert_eq!(ed.cursor_gcol, 2);
+        ed = ed.handle_command(EditorCommand::SetNumber {
+            name: "ts".into(),
+            value: 4,
+        });
+        let buf = frame(&ed, 20, 4);
+        assert_eq!(buf.row_text(0), "a   b");
+        assert_eq!(buf.cursor, Some((4, 0)));
+    }
+
+    #[test]
+    fn long_lines_scroll_sideways_with_markers() {
+        let mut ed = editor_with("0123456789abcdef\nxy");
+        ed.set_view_size(10, 3);
+        ed = ed.handle_command(EditorCommand::MoveUp);
+        assert_eq!(frame(&ed, 10, 5).row_text(0), "012345678>");
+
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineEnd,
+            count: 1,
+        });
+        assert_eq!(ed.view().left, 6);
+        let buf = frame(&ed, 10, 5);
+        assert_eq!(buf.row_text(0), "<789abcdef");
+        assert_eq!(buf.row_text(1), "<");
+        assert_eq!(buf.cursor, Some((9, 0)));
+        assert_eq!(buf.get(0, 0).unwrap().style, Style::fg(Color::Blue));
+
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::LineStart,
+            count: 1,
+        });
+        assert_eq!(ed.view().left, 0);
+    }
+
+    #[test]
+    fn list_mode_shows_whitespace() {
+        let mut ed = editor_with("\tx \u{a0}y  \n");
+        let plain = frame(&ed, 30, 5);
+        assert_eq!(plain.row_text(0), "        x \u{a0}y");
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "list".into(),
+            value: true,
+        });
+        let buf = frame(&ed, 30, 5);
+        assert_eq!(buf.row_text(0), ">       x +y--$");
+        assert_eq!(buf.row_text(1), "$");
+        assert_eq!(buf.get(0, 0).unwrap().style, Style::fg(Color::DarkGrey));
+        assert_eq!(buf.get(8, 0).unwrap().style, Style::default());
+
+        ed = ed.handle_command(EditorCommand::SetListChars(
+            crate::options::ListChars::parse("tab:»·").unwrap(),
+        ));
+        let buf = frame(&ed, 30, 5);
+        assert_eq!(buf.row_text(0), "»·······x \u{a0}y");
+    }
+
+    #[test]
+    fn color_columns_run_down_the_text() {
+        let mut ed = editor_with("short\nline");
+        ed = ed.handle_command(EditorCommand::SetColorColumn(vec![3, 8]));
+        let buf = frame(&ed, 20, 5);
+        let guide = Style {
+            bg: Some(Color::DarkGrey),
+            ..Style::default()
+        };
+        for y in 0..2 {
+            assert_eq!(buf.get(2, y).unwrap().style, guide);
+            assert_eq!(buf.get(7, y).unwrap().style, guide);
+            assert_eq!(buf.get(3, y).unwrap().style, Style::default());
+        }
+        // Not past the end of the text, nor on the status line
+        assert_eq!(buf.get(2, 2).unwrap().style, Style::default());
+        assert_ne!(buf.get(2, 3).unwrap().style, guide);
+    }
+
+    #[test]
+    fn picker_overlays_the_text() {
+        let mut ed = editor_with("text");
+        ed = ed.handle_command(EditorCommand::OpenPicker("commands".into()));
+        for c in "wri".chars() {
+            ed = ed.handle_command(EditorCommand::PickerInput(c));
+        }
+        // Matching runs off the UI thread; wait for it as the main loop does.
+        while ed.picker().unwrap().busy() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            ed.update_picker();
+        }
+        let buf = frame(&ed, 40, 5);
+        let total = crate::ex::NAMES.len();
+        assert!(buf.row_text(0).starts_with("commands> wri"));
+        assert!(buf.row_text(0).ends_with(&format!("1/{total}")));
+        assert_eq!(buf.row_text(1), " write");
+        assert_eq!(buf.row_text(2), "");
+        assert_eq!(buf.cursor, Some((13, 0)));
+        let theme = ed.theme();
+        assert_eq!(
+            buf.get(1, 1).unwrap().style,
+            theme
+                .get(Group::Selection)
+                .patch(theme.get(Group::PickerMatch))
+        );
+    }
+
+    #[test]
+    fn trailing_whitespace_gets_a_warning_background() {
+        let mut ed = editor_with("ab \t\nc  ");
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "stw".into(),
+            value: true,
+        });
+        let warning = ed.theme().get(Group::TrailingWhitespace);
+        // Not on the line being typed
+        let typing = ed.handle_command(EditorCommand::EnterInsertMode);
+        let buf = frame(&typing, 20, 5);
+        assert_eq!(buf.get(1, 1).unwrap().style, Style::default());
+
+        let buf = frame(&ed, 20, 5);
+        assert_eq!(buf.get(1, 0).unwrap().style, Style::default());
+        // The space and the whole tab after it
+        for x in 2..8 {
+            assert_eq!(buf.get(x, 0).unwrap().style, warning);
+        }
+        assert_eq!(buf.get(8, 0).unwrap().style, Style::default());
+        assert_eq!(buf.get(2, 1).unwrap().style, warning);
+    }
+
+    #[test]
+    fn the_bracket_pairing_with_the_cursor_is_highlighted() {
+        let mut ed = editor_with("f(\tx)");
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::Left,
+            count: 1,
+        });
+        let paren = ed.theme().get(Group::MatchParen);
+        // On `)`: its `(` lights up
+        let buf = frame(&ed, 20, 5);
+        assert_eq!(buf.get(1, 0).unwrap().style, paren);
+        assert_eq!(buf.get(9, 0).unwrap().style, Style::default());
+
+        // And the other way, past the tab
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::MatchPair,
+            count: 1,
+        });
+        assert_eq!(ed.cursor_gcol, 1);
+        let buf = frame(&ed, 20, 5);
+        assert_eq!(buf.get(9, 0).unwrap().style, paren);
+        assert_eq!(buf.get(1, 0).unwrap().style, Style::default());
+    }
+
+    #[test]
+    fn narrow_status_line_keeps_the_mode() {
+        let ed = Editor::open("src/deeply/nested/module/file.rs".into()).unwrap();
+        let row = |width| frame(&ed, width, 5).row_text(3);
+        let name = " NORMAL | src/deeply/nested/module/file.rs";
+        assert_eq!(row(70), format!("{name}     utf-8 unix  1:1 byte 1"));
+        // The right side goes a piece at a time while the name fits
+        assert_eq!(row(60), format!("{name}  utf-8 unix  1:1"));
+        assert_eq!(row(48), format!("{name}  1:1"));
+        assert_eq!(row(44), name);
+        // Only then does the name lose its start
+        assert_eq!(row(30), " NORMAL | <sted/module/file.rs");
+        assert_eq!(row(8), " NORMAL");
+    }
+
+    #[test]
+    fn long_lines_go_without_the_extras() {
+        let mut ed = editor_with("f(x) \nf(xyzzy) ");
+        for arg in ["stw", "longline=6"] {
+            ed = ed.handle_command(crate::ex::parse(&format!("set {arg}")).unwrap());
+        }
+        ed = ed.handle_command(EditorCommand::Move {
+            motion: Motion::Left,
+            count: 2,
+        });
+        // No bracket matching while the long line is on screen, and no
+        // trailing space mark on it
+        let buf = frame(&ed, 60, 5);
+        assert_eq!(buf.get(8, 1).unwrap().style, Style::default());
+        assert_eq!(buf.get(1, 1).unwrap().style, Style::default());
+        assert_ne!(buf.get(4, 0).unwrap().style, Style::default());
+        assert!(buf.row_text(3).contains("[+] [long lines]"));
+
+        ed = ed.handle_command(crate::ex::parse("set longline=100").unwrap());
+        let buf = frame(&ed, 60, 5);
+        assert_ne!(buf.get(8, 1).unwrap().style, Style::default());
+        assert_ne!(buf.get(1, 1).unwrap().style, Style::default());
+        assert!(!buf.row_text(3).contains("long"));
+    }
+
+    #[test]
+    fn showkeys_lists_recent_strokes_in_the_corner() {
+        use crate::input::KeyMappingResult;
+        use crate::keys::{KeyCode, KeyEvent, KeyModifiers};
+        let mut ed = editor_with("text");
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        ed.record_key(key('d'), &KeyMappingResult::UpdatePending);
+        let buf = frame(&ed, 30, 5);
+        assert_eq!(buf.row_text(2), "");
+
+        ed = ed.handle_command(EditorCommand::SetFlag {
+            name: "showkeys".into(),
+            value: true,
+        });
+        let buf = frame(&ed, 30, 5);
+        assert_eq!(buf.row_text(2), format!("{} d  …", " ".repeat(24)));
+        ed.record_key(key('w'), &KeyMappingResult::Noop);
+        ed.record_key(key('Q'), &KeyMappingResult::Noop);
+        let buf = frame(&ed, 30, 5);
+        assert!(buf.row_text(1).ends_with(" dw  (not mapped)"));
+        assert!(buf.row_text(2).ends_with(" Q  (not mapped)"));
+        assert_eq!(
+            buf.get(29, 2).unwrap().style,
+            ed.theme().get(Group::StatusLine)
+        );
+    }
+
+    #[test]
+    fn sidebar_lists_the_tree_left_of_the_text() {
+        let mut ed = editor_with("hello");
+        ed.set_view_size(60, 4);
+        // The tests run in the crate's own directory
+        ed = ed.handle_command(EditorCommand::ToggleTree);
+        let buf = frame(&ed, 60, 6);
+        assert_eq!(
+            buf.row_text(0),
+            "\u{25b8} examples                   \u{2502}hello"
+        );
+        assert_eq!(buf.cursor, Some((0, 0)));
+        assert!(buf.row_text(4).starts_with(" TREE |"));
+        assert_eq!(
+            buf.get(0, 0).unwrap().style,
+            ed.theme()
+                .get(Group::Directory)
+                .patch(ed.theme().get(Group::Selection))
+        );
+
+        ed = ed.handle_command(EditorCommand::SwitchPane);
+        let buf = frame(&ed, 60, 6);
+        assert_eq!(buf.cursor, Some((35, 0)));
+    }
+
+    #[test]
+    fn completion_menu_opens_under_the_word() {
+        let mut ed = editor_with("alpha\nalps\nal");
+        ed = ed.handle_command(EditorCommand::EnterInsertMode);
+        ed = ed.handle_command(EditorCommand::CompleteWord { forward: true });
+        let buf = frame(&ed, 20, 8);
+        assert_eq!(buf.row_text(2), "alpha");
+        assert_eq!(buf.row_text(3), " alpha");
+        assert_eq!(buf.row_text(4), " alps");
+        let menu = ed.theme().get(Group::Menu);
+        assert_eq!(
+            buf.get(1, 3).unwrap().style,
+            menu.patch(ed.theme().get(Group::Selection))
+        );
+        assert_eq!(buf.get(5, 4).unwrap().style, menu);
+        assert_eq!(buf.get(6, 4).unwrap().style, menu);
+
+        // No room below: over the word instead
+        let buf = frame(&ed, 20, 5);
+        assert_eq!(buf.row_text(0), " alpha");
+        assert_eq!(buf.row_text(1), " alps");
+    }
+}
@@ -0,0 +1,52 @@
+pub mod buffer;
+pub mod changelog;
+pub mod color;
+mod comment;
+mod completion;
+pub mod config;
+mod conflicts;
+pub mod core;
+mod diff;
+pub mod editor;
+mod editorconfig;
+mod ex;
+mod export;
+mod fileformat;
+mod filetype;
+mod graphemes;
+mod gutter;
+mod health;
+mod indent;
+mod input;
+mod keylog;
+mod keymap;
+pub mod keys;
+mod layers;
+mod lineinput;
+mod marks;
+mod matcher;
+mod messages;
+mod modeline;
+mod motion;
+mod notebook;
+mod notes;
+mod operator;
+mod options;
+mod pairs;
+mod patch;
+mod path;
+mod picker;
+mod preview;
+mod prose;
+mod quickfix;
+mod registers;
+#[cfg(feature = "terminal")]
+pub mod renderer;
+mod shell;
+mod syntax;
+mod tags;
+mod textobject;
+mod theme;
+mod timers;
+mod tree;
+mod view;
@@ -0,0 +1,160 @@
+/// What a kind of line input (`:` commands, later `/` search and the like)
+/// does beyond plain typing.
+#[derive(Clone, Copy)]
+pub struct Spec {
+    /// Shown before the text, e.g. ':'.
+    pub prefix: char,
+    /// Whole lines the text could complete to; Tab fills in what they share.
+    pub complete: fn(&str) -> Vec<String>,
+    /// Checked on Enter; an error closes the input and is reported.
+    pub validate: fn(&str) -> Result<(), String>,
+}
+
+/// Inputs remembered per kind, oldest first, like Vim's.
+const HISTORY_LIMIT: usize = 50;
+
+/// A line being typed at the bottom of the screen.
+#[derive(Clone)]
+pub struct LineInput {
+    spec: Spec,
+    text: String,
+    /// While walking the history: the entry shown and the line that was
+    /// being typed before, restored when walking back past the newest.
+    browsing: Option<(usize, String)>,
+}
+
+impl LineInput {
+    pub fn new(spec: Spec) -> Self {
+        Self {
+            spec,
+            text: String::new(),
+            browsing: None,
+        }
+    }
+
+    pub fn prefix(&self) -> char {
+        self.spec.prefix
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.browsing = None;
+        self.text.push(c);
+    }
+
+    /// Remove the last char; false when there was nothing to remove.
+    pub fn backspace(&mut self) -> bool {
+        self.browsing = None;
+        self.text.pop().is_some()
+    }
+
+    /// Show the next older (or newer) entry of `history`.
+    pub fn walk_history(&mut self, history: &[String], older: bool) {
+        let (at, draft) = match self.browsing.take() {
+            Some(b) => b,
+            None => (history.len(), self.text.clone()),
+        };
+        let at = if older {
+            at.saturating_sub(1)
+        } else {
+            (at + 1).min(history.len())
+        };
+        self.text = history.get(at).cloned().unwrap_or_else(|| draft.clone());
+        if at < history.len() {
+            self.browsing = Some((at, draft));
+        }
+    }
+
+    /// Extend the text to what all completions have in common.
+    pub fn complete(&mut self) {
+        let candidates = (self.spec.complete)(&self.text);
+        let Some((first, rest)) = candidates.split_first() else {
+            return;
+        };
+        let mut common = first.as_str();
+        for c in rest {
+            let len = common
+                .char_indices()
+                .zip(c.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(c.len()), |((i, _), _)| i);
+            common = &common[..len];
+        }
+        if common.len() > self.text.len() {
+            self.browsing = None;
+            self.text = common.to_owned();
+        }
+    }
+
+    /// Validate the line for Enter and record it in `history`.
+    pub fn finish(self, history: &mut Vec<String>) -> Result<String, String> {
+        (self.spec.validate)(&self.text)?;
+        if !self.text.is_empty() {
+            history.retain(|h| *h != self.text);
+            history.push(self.text.clone());
+            if history.len() > HISTORY_LIMIT {
+                history.remove(0);
+            }
+        }
+        Ok(self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: Spec = Spec {
+        prefix: '/',
+        complete: |text| {
+            ["write", "wrap", "quit"]
+                .iter()
+                .filter(|c| c.starts_with(text))
+                .map(|c| c.to_string())
+                .collect()
+        },
+        validate: |text| match text {
+            "bad" => Err("nope".into()),
+            _ => Ok(()),
+        },
+    };
+
+    fn typed(text: &str) -> LineInput {
+        let mut input = LineInput::new(SPEC);
+        text.chars().for_each(|c| input.push(c));
+        input
+    }
+
+    #[test]
+    fn walks_history_and_keeps_the_draft() {
+        let history = vec!["one".to_string(), "two".to_string()];
+        let mut input = typed("dr");
+        input.walk_history(&history, true);
+        assert_eq!(input.text(), "two");
+        input.walk_history(&history, true);
+        input.walk_history(&history, true);
+        assert_eq!(input.text(), "one");
+        input.walk_history(&history, false);
+        input.walk_history(&history, false);
+        assert_eq!(input.text(), "dr");
+    }
+
+    #[test]
+    fn completes_common_prefix_and_validates() {
+        let mut input = typed("w");
+        input.complete();
+        assert_eq!(input.text(), "wr");
+        input.push('i');
+        input.complete();
+        assert_eq!(input.text(), "write");
+
+        let mut history = vec!["write".to_string(), "x".to_string()];
+        assert_eq!(input.finish(&mut history), Ok("write".into()));
+        assert_eq!(history, ["x", "write"]);
+        assert_eq!(typed("bad").finish(&mut history), Err("nope".into()));
+        assert_eq!(history.len(), 2);
+    }
+}
@@ -1,43 +1,201 @@
 use anyhow::Result;
 use crossterm::{
-    event::{self, Event},
-    terminal::{disable_raw_mode, enable_raw_mode},
+    cursor,
+    event::{
+        self, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute,
+    style::{Attribute, ResetColor, SetAttribute},
+    terminal::{self, disable_raw_mode, enable_raw_mode},
 };
-use std::io::stdout;
-use std::time::Duration;
-
-mod editor;
-mod graphemes;
-mod input;
-mod renderer;
+use neo2vim::color::ColorSupport;
+use neo2vim::core::{self, EditorCore, Event, Frame, Frontend};
+use neo2vim::editor::Editor;
+use neo2vim::keys::KeyEvent;
+use neo2vim::renderer::Renderer;
+use std::io::{self, stdout, Stdout};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 fn main() -> Result<()> {
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    let mut editor = editor::Editor::new();
-
-    loop {
-        if event::poll(Duration::from_millis(250))? {
-            if let Event::Key(key_event) = event::read()? {
-                let kmr = input::map_key(key_event, editor.mode(), editor.pending_mut());
-
-                match kmr {
-                    input::KeyMappingResult::Command(cmd) => {
-                        if let input::EditorCommand::Quit = cmd {
-                            break;
-                        }
-                        editor = editor.handle_command(cmd);
-                        renderer::render(&mut stdout, &editor)?;
-                    }
-                    input::KeyMappingResult::UpdatePending => {
-                        // optional: render a “waiting for second key…” UI
+    // `--follow FILE` tails it, like `less +F`
+    let mut args = std::env::args_os().skip(1).peekable();
+    let follow = args.next_if(|arg| arg == "--follow").is_some();
+    let editor = match args.next() {
+        Some(path) => {
+            let mut editor = Editor::open(PathBuf::from(path))?;
+            if follow {
+                editor.follow();
+            }
+            editor
+        }
+        None => Editor::new(),
+    };
+
+    let _terminal = TerminalGuard::new()?;
+    let (cols, rows) = terminal::size()?;
+    let mut core = EditorCore::new(editor, cols as usize, rows as usize, clock());
+    #[cfg(feature = "config")]
+    if let Some(path) = neo2vim::config::file() {
+        core.editor_mut().load_config(&path);
+    }
+    let mut terminal = Terminal {
+        stdout: stdout(),
+        renderer: Renderer::new(ColorSupport::detect()),
+    };
+    core::run(&mut core, &mut terminal)?;
+    Ok(())
+}
+
+/// The frontend the binary runs: crossterm's events in, escape sequences
+/// out.
+struct Terminal {
+    stdout: Stdout,
+    renderer: Renderer,
+}
+
+impl Frontend for Terminal {
+    type Error = std::io::Error;
+
+    fn now(&self) -> Duration {
+        clock()
+    }
+
+    fn next_event(&mut self, until: Duration) -> io::Result<Event> {
+        loop {
+            let timeout = until.saturating_sub(clock());
+            if !event::poll(timeout)? {
+                return Ok(Event::Tick);
+            }
+            match event::read()? {
+                event::Event::Key(key) => {
+                    if let Some(key) = KeyEvent::from_crossterm(key) {
+                        return Ok(Event::Key(key));
                     }
-                    input::KeyMappingResult::Noop => {}
                 }
+                event::Event::Resize(cols, rows) => {
+                    return Ok(Event::Resize {
+                        cols: cols as usize,
+                        rows: rows as usize,
+                    })
+                }
+                _ => {}
             }
         }
     }
 
-    disable_raw_mode()?;
+    fn draw(&mut self, frame: Frame) -> io::Result<()> {
+        self.renderer.render(&mut self.stdout, &frame)
+    }
+
+    fn progress(&mut self, done: u64, total: u64) {
+        let _ = self.renderer.render_progress(&mut self.stdout, done, total);
+    }
+
+    fn suspend(&mut self) -> io::Result<(usize, usize)> {
+        suspend()?;
+        // The shell had the screen, which may have changed size.
+        let (cols, rows) = terminal::size()?;
+        Ok((cols as usize, rows as usize))
+    }
+}
+
+/// The time since the Unix epoch.
+fn clock() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Raw mode for as long as it lives. Dropping it, on the way out of `main`
+/// for any reason, hands the terminal back; so does a panic, before its
+/// message prints.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> Result<Self> {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // A worker thread's panic leaves the editor running.
+            if std::thread::current().name() == Some("main") {
+                restore_terminal();
+                below_last_frame();
+            }
+            hook(info);
+        }));
+        enable_raw_mode()?;
+        enhance_keyboard();
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Set while the kitty keyboard protocol is on, so it gets turned off.
+static ENHANCED: AtomicBool = AtomicBool::new(false);
+
+/// The kitty keyboard protocol, where the terminal has it: Ctrl-I comes
+/// apart from Tab, Ctrl-Enter and Shift-Enter become keys of their own, and
+/// releases are reported. Other terminals go on as they were.
+fn enhance_keyboard() {
+    if !terminal::supports_keyboard_enhancement().unwrap_or(false) {
+        return;
+    }
+    let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+    if execute!(stdout(), PushKeyboardEnhancementFlags(flags)).is_ok() {
+        ENHANCED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Cooked mode, plain colours and the user's own visible cursor. Errors are
+/// ignored: there is nothing better to do with them here.
+fn restore_terminal() {
+    if ENHANCED.swap(false, Ordering::Relaxed) {
+        let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(
+        stdout(),
+        ResetColor,
+        SetAttribute(Attribute::Reset),
+        cursor::SetCursorStyle::DefaultUserShape,
+        cursor::Show
+    );
+    let _ = disable_raw_mode();
+}
+
+/// Move to a fresh line under the last frame, so what the shell prints
+/// next doesn't land on top of it.
+fn below_last_frame() {
+    if let Ok((_, rows)) = terminal::size() {
+        let _ = execute!(stdout(), cursor::MoveTo(0, rows.saturating_sub(1)));
+        println!();
+    }
+}
+
+/// Ctrl-Z: hand the terminal back and stop as the shell's job. SIGTSTP's
+/// default action stops the process inside `raise`, which returns once
+/// `fg` sends SIGCONT; then raw mode goes back on.
+#[cfg(unix)]
+fn suspend() -> io::Result<()> {
+    restore_terminal();
+    below_last_frame();
+    // SAFETY: raise only signals this process.
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+    enable_raw_mode()?;
+    enhance_keyboard();
+    Ok(())
+}
+
+/// No job control to hand over to.
+#[cfg(not(unix))]
+fn suspend() -> io::Result<()> {
     Ok(())
 }
@@ -0,0 +1,100 @@
+use std::ops::Range;
+
+/// Lowercase marks `a`–`z`, stored as absolute *char* indices into the rope.
+///
+/// Positions are kept valid across edits: the editor reports every insert and
+/// removal through `on_insert` / `on_remove`, and marks after the edit are
+/// shifted accordingly.
+#[derive(Clone, Default)]
+pub struct Marks {
+    slots: [Option<usize>; 26],
+}
+
+impl Marks {
+    #[inline]
+    fn slot(name: char) -> Option<usize> {
+        if name.is_ascii_lowercase() {
+            Some((name as u8 - b'a') as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Set mark `name` to `abs_char`. Returns false for names outside `a`–`z`.
+    pub fn set(&mut self, name: char, abs_char: usize) -> bool {
+        match Self::slot(name) {
+            Some(i) => {
+                self.slots[i] = Some(abs_char);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: char) -> Option<usize> {
+        Self::slot(name).and_then(|i| self.slots[i])
+    }
+
+    /// `len` chars were inserted at `at`: marks at or after `at` move right.
+    pub fn on_insert(&mut self, at: usize, len: usize) {
+        for pos in self.slots.iter_mut().flatten() {
+            if *pos >= at {
+                *pos += len;
+            }
+        }
+    }
+
+    /// Chars in `range` were removed: marks after it move left, marks inside
+    /// it collapse onto the start of the removed span.
+    pub fn on_remove(&mut self, range: Range<usize>) {
+        let len = range.end - range.start;
+        for pos in self.slots.iter_mut().flatten() {
+            if *pos >= range.end {
+                *pos -= len;
+            } else if *pos > range.start {
+                *pos = range.start;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_lowercase_names_are_accepted() {
+        let mut m = Marks::default();
+        assert!(m.set('a', 3));
+        assert!(!m.set('A', 3));
+        assert!(!m.set('1', 3));
+        assert_eq!(m.get('a'), Some(3));
+        assert_eq!(m.get('b'), None);
+    }
+
+    #[test]
+    fn insert_before_shifts_insert_after_does_not() {
+        let mut m = Marks::default();
+        m.set('a', 5);
+        m.set('b', 2);
+        m.on_insert(3, 4);
+        assert_eq!(m.get('a'), Some(9));
+        assert_eq!(m.get('b'), Some(2));
+
+        // Inserting exactly at the mark pushes it along with the text.
+        m.on_insert(9, 1);
+        assert_eq!(m.get('a'), Some(10));
+    }
+
+    #[test]
+    fn remove_shifts_and_collapses() {
+        let mut m = Marks::default();
+        m.set('a', 10);
+        m.set('b', 4);
+        m.set('c', 1);
+        m.on_remove(2..6);
+        assert_eq!(m.get('a'), Some(6));
+        assert_eq!(m.get('b'), Some(2)); // was inside the removed span
+        assert_eq!(m.get('c'), Some(1));
+    }
+}
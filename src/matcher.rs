@@ -0,0 +1,367 @@
+use crate::picker::Item;
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// How well `query` matches `label`: its characters in order (ignoring
+/// case unless the query has capitals), scoring runs and word starts.
+/// Returns the score and the matched char positions.
+pub fn fuzzy(query: &str, label: &str) -> Option<(i64, Vec<usize>)> {
+    let smart_case = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if smart_case {
+            c
+        } else {
+            c.to_ascii_lowercase()
+        }
+    };
+    let chars: Vec<char> = label.chars().collect();
+    let mut positions = Vec::new();
+    let mut at = 0;
+    for q in query.chars().map(fold) {
+        let found = (at..chars.len()).find(|&i| fold(chars[i]) == q)?;
+        positions.push(found);
+        at = found + 1;
+    }
+    let mut score = 0i64;
+    for (n, &i) in positions.iter().enumerate() {
+        let word_start = i == 0 || matches!(chars[i - 1], '/' | '_' | '-' | ' ' | '.');
+        if word_start {
+            score += 8;
+        }
+        match n.checked_sub(1).map(|p| positions[p]) {
+            Some(prev) if prev + 1 == i => score += 5,
+            Some(prev) => score -= (i - prev) as i64,
+            None => score -= i as i64,
+        }
+    }
+    // Among equals, the shorter label is the closer match.
+    Some((score * 64 - chars.len() as i64, positions))
+}
+
+/// Best matches sent back; the rest are only counted.
+pub const LIMIT: usize = 1000;
+/// Items scored between looks for a newer query.
+const CHUNK: usize = 4096;
+
+/// An item that matches the query, with where.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub item: Item,
+    pub positions: Vec<usize>,
+}
+
+/// Where matching has got to for `query`.
+#[derive(Debug)]
+pub struct Results {
+    pub query: String,
+    /// The best `LIMIT` matches, best first.
+    pub matches: Vec<Match>,
+    pub matched: usize,
+    pub total: usize,
+    /// Every item is in and scored.
+    pub done: bool,
+}
+
+enum Input {
+    Items(Vec<Item>),
+    /// The source has no more.
+    Done,
+    Query(String),
+    /// The picker is gone; so is the need for matches.
+    Close,
+}
+
+/// Fuzzy matching off the UI thread. Items stream in from the source on
+/// one thread and are scored on another, which looks for a newer query
+/// between chunks so typing never waits on a long list.
+#[derive(Debug)]
+pub struct Matcher {
+    tx: Sender<Input>,
+    rx: Receiver<Results>,
+    /// Where there are no threads (a browser), the matching is done here
+    /// instead, whenever results are asked for.
+    inline: Option<RefCell<Worker>>,
+}
+
+/// Whether the matcher can have threads of its own.
+const THREADS: bool = cfg!(not(target_family = "wasm"));
+
+impl Matcher {
+    /// Start matching what `produce` sends. It is told to stop, by its
+    /// sender returning false, once the matcher is dropped.
+    pub fn spawn<F>(produce: F) -> Self
+    where
+        F: FnOnce(&mut dyn FnMut(Vec<Item>) -> bool) + Send + 'static,
+    {
+        Self::start(produce, THREADS)
+    }
+
+    fn start<F>(produce: F, threads: bool) -> Self
+    where
+        F: FnOnce(&mut dyn FnMut(Vec<Item>) -> bool) + Send + 'static,
+    {
+        let (tx, inputs) = mpsc::channel();
+        let (results, rx) = mpsc::channel();
+        let items = tx.clone();
+        let source = move || {
+            produce(&mut |batch| items.send(Input::Items(batch)).is_ok());
+            let _ = items.send(Input::Done);
+        };
+        let worker = Worker::new(inputs, results);
+        if !threads {
+            source();
+            return Self {
+                tx,
+                rx,
+                inline: Some(RefCell::new(worker)),
+            };
+        }
+        thread::spawn(source);
+        thread::spawn(move || worker.run());
+        Self {
+            tx,
+            rx,
+            inline: None,
+        }
+    }
+
+    pub fn query(&self, query: &str) {
+        let _ = self.tx.send(Input::Query(query.to_owned()));
+    }
+
+    /// The latest results, if any came since last asked.
+    pub fn latest(&self) -> Option<Results> {
+        self.catch_up();
+        self.rx.try_iter().last()
+    }
+
+    /// Block for the next results; None if the worker is gone.
+    pub fn next(&self) -> Option<Results> {
+        if self.inline.is_some() {
+            self.catch_up();
+            return self.rx.try_recv().ok();
+        }
+        self.rx.recv().ok()
+    }
+
+    /// Matching without a thread: everything sent so far, scored.
+    fn catch_up(&self) {
+        if let Some(worker) = &self.inline {
+            let mut worker = worker.borrow_mut();
+            while worker.step(false) && !worker.idle() {}
+        }
+    }
+}
+
+impl Drop for Matcher {
+    // The source holds a sender too, so the worker has to be told.
+    fn drop(&mut self) {
+        let _ = self.tx.send(Input::Close);
+    }
+}
+
+/// One item's match: score, index and positions.
+type Hit = (i64, usize, Vec<usize>);
+
+/// The matching, as it stands between looks at its inputs.
+#[derive(Debug)]
+struct Worker {
+    inputs: Receiver<Input>,
+    results: Sender<Results>,
+    items: Vec<Item>,
+    done: bool,
+    query: String,
+    hits: Vec<Hit>,
+    /// Items before this are in `hits` if they match `query`.
+    scanned: usize,
+    changed: bool,
+}
+
+impl Worker {
+    fn new(inputs: Receiver<Input>, results: Sender<Results>) -> Self {
+        Self {
+            inputs,
+            results,
+            items: Vec::new(),
+            done: false,
+            query: String::new(),
+            hits: Vec::new(),
+            scanned: 0,
+            changed: true,
+        }
+    }
+
+    /// The matching thread: runs until the `Matcher` is dropped.
+    fn run(mut self) {
+        while self.step(true) {}
+    }
+
+    /// Nothing to score and nothing new to tell.
+    fn idle(&self) -> bool {
+        self.scanned == self.items.len() && !self.changed
+    }
+
+    /// Take in what has come, waiting for it if `wait` and idle, then
+    /// score a chunk more; results go out once everything is scored.
+    /// False once the matcher is gone.
+    fn step(&mut self, wait: bool) -> bool {
+        let first = if wait && self.idle() {
+            match self.inputs.recv() {
+                Ok(input) => Some(input),
+                Err(_) => return false,
+            }
+        } else {
+            None
+        };
+        for input in first.into_iter().chain(self.inputs.try_iter()) {
+            self.changed = true;
+            match input {
+                Input::Items(batch) => self.items.extend(batch),
+                Input::Done => self.done = true,
+                Input::Close => return false,
+                // A longer query only matches what the shorter one did.
+                Input::Query(q) if q.starts_with(&self.query) => {
+                    let items = &self.items;
+                    self.hits
+                        .retain_mut(|hit| match fuzzy(&q, &items[hit.1].label) {
+                            Some((score, positions)) => {
+                                (hit.0, hit.2) = (score, positions);
+                                true
+                            }
+                            None => false,
+                        });
+                    self.query = q;
+                }
+                Input::Query(q) => {
+                    self.hits.clear();
+                    self.scanned = 0;
+                    self.query = q;
+                }
+            }
+        }
+
+        let end = (self.scanned + CHUNK).min(self.items.len());
+        for (i, item) in self.items.iter().enumerate().take(end).skip(self.scanned) {
+            if let Some((score, positions)) = fuzzy(&self.query, &item.label) {
+                self.hits.push((score, i, positions));
+            }
+        }
+        self.scanned = end;
+
+        if self.scanned == self.items.len() && self.changed {
+            self.changed = false;
+            let sent = self.results.send(Results {
+                query: self.query.clone(),
+                matches: best(&mut self.hits, &self.items),
+                matched: self.hits.len(),
+                total: self.items.len(),
+                done: self.done,
+            });
+            if sent.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The `LIMIT` best hits, best first; equal scores keep the source's order.
+fn best(hits: &mut [Hit], items: &[Item]) -> Vec<Match> {
+    let key = |hit: &Hit| (-hit.0, hit.1);
+    if hits.len() > LIMIT {
+        hits.select_nth_unstable_by_key(LIMIT, key);
+    }
+    let shown = hits.len().min(LIMIT);
+    let top = &mut hits[..shown];
+    top.sort_unstable_by_key(key);
+    top.iter()
+        .map(|(_, i, positions)| Match {
+            item: items[*i].clone(),
+            positions: positions.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches_in_order_and_ranks() {
+        assert_eq!(fuzzy("edr", "src/editor.rs").unwrap().1, [4, 5, 9]);
+        assert!(fuzzy("rde", "src/editor.rs").is_none());
+        // Smart case
+        assert!(fuzzy("Ed", "src/editor.rs").is_none());
+        assert!(fuzzy("ED", "EDITOR").is_some());
+
+        let score = |q, l| fuzzy(q, l).unwrap().0;
+        // Runs and word starts beat scattered letters
+        assert!(score("main", "src/main.rs") > score("main", "src/marking.rs"));
+        assert!(score("ed", "src/editor.rs") > score("ed", "src/red.rs"));
+        assert!(score("", "a") > score("", "ab"));
+    }
+
+    fn item(label: String) -> Item {
+        Item {
+            label,
+            preview: None,
+            action: Vec::new(),
+        }
+    }
+
+    /// Results once everything is in and scored for `query`.
+    fn settled(matcher: &Matcher, query: &str) -> Results {
+        loop {
+            let results = matcher.next().expect("matcher running");
+            if results.done && results.query == query {
+                return results;
+            }
+        }
+    }
+
+    #[test]
+    fn matches_a_streamed_list_off_thread() {
+        let matcher = Matcher::spawn(|send| {
+            for batch in 0..50 {
+                let items = (0..4000).map(|i| item(format!("dir{batch}/file{i}.rs")));
+                if !send(items.collect()) {
+                    return;
+                }
+            }
+        });
+        matcher.query("");
+        let all = settled(&matcher, "");
+        assert_eq!(all.total, 200_000);
+        assert_eq!(all.matched, 200_000);
+        assert_eq!(all.matches.len(), LIMIT);
+
+        // Narrowing, then widening again
+        matcher.query("dir7/file12");
+        matcher.query("dir7/file123");
+        let narrow = settled(&matcher, "dir7/file123");
+        assert_eq!(narrow.matches[0].item.label, "dir7/file123.rs");
+        assert!(narrow.matched < 200_000);
+        matcher.query("dir49/file3999.");
+        let wide = settled(&matcher, "dir49/file3999.");
+        assert_eq!(wide.matches[0].item.label, "dir49/file3999.rs");
+    }
+
+    #[test]
+    fn matches_without_threads_when_asked() {
+        let matcher = Matcher::start(
+            |send| {
+                send((0..10).map(|i| item(format!("file{i}.rs"))).collect());
+            },
+            false,
+        );
+        matcher.query("");
+        matcher.query("file7");
+        let results = matcher.latest().expect("scored on the spot");
+        assert!(results.done);
+        assert_eq!(results.query, "file7");
+        assert_eq!(results.matches[0].item.label, "file7.rs");
+        assert_eq!(results.total, 10);
+        assert!(matcher.next().is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Messages kept for `:messages`, like Vim's default.
+const LIMIT: usize = 200;
+
+/// Feedback from the last command, shown under the status line until the
+/// next key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub text: String,
+    pub error: bool,
+}
+
+impl Message {
+    /// Too long for the message row: shown over the text with a
+    /// "Press ENTER" line until dismissed.
+    pub fn is_multiline(&self) -> bool {
+        self.text.contains('\n')
+    }
+}
+
+/// Every message shown, oldest first.
+#[derive(Clone, Default)]
+pub struct History {
+    entries: VecDeque<(Duration, Message)>,
+}
+
+impl History {
+    /// `message`, shown at `at` since the Unix epoch.
+    pub fn push(&mut self, at: Duration, message: Message) {
+        if self.entries.len() == LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((at, message));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `:messages` output: one `[hh:mm:ss] text` line per message (UTC).
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(at, m)| format!("[{}] {}", clock(*at), m.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn clock(at: Duration) -> String {
+    let secs = at.as_secs() % 86_400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(text: &str) -> Message {
+        Message {
+            text: text.into(),
+            error: false,
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_messages_with_times() {
+        let mut history = History::default();
+        let at = Duration::from_secs(86_400 + 13 * 3600 + 5 * 60 + 9);
+        history.push(at, info("first"));
+        history.push(at, info("second"));
+        assert_eq!(history.render(), "[13:05:09] first\n[13:05:09] second");
+
+        for i in 0..LIMIT {
+            history.push(at, info(&i.to_string()));
+        }
+        assert_eq!(history.entries.len(), LIMIT);
+        assert_eq!(history.entries[0].1, info("0"));
+    }
+}
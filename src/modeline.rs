@@ -0,0 +1,110 @@
+use ropey::Rope;
+
+/// The `:set` arguments of the modelines in the first and last `lines`
+/// lines of `text`, in the order they come. Both of Vim's forms are read:
+///
+/// ```text
+/// # vim: ts=4 sw=4 et
+/// /* vim: set ts=4 sw=4 et: */
+/// ```
+pub fn find(text: &Rope, lines: usize) -> Vec<String> {
+    let mut total = text.len_lines();
+    // Not the empty line after the last line break
+    if total > 1 && text.line(total - 1).len_chars() == 0 {
+        total -= 1;
+    }
+    let head = 0..lines.min(total);
+    let tail = total.saturating_sub(lines).max(head.end)..total;
+    head.chain(tail)
+        .flat_map(|row| parse(&text.line(row).to_string()))
+        .collect()
+}
+
+/// The arguments of one line's modeline; none when it hasn't one.
+fn parse(line: &str) -> Vec<String> {
+    let Some(rest) = options_start(line) else {
+        return Vec::new();
+    };
+    let rest = rest.trim_start();
+    match rest
+        .strip_prefix("set ")
+        .or_else(|| rest.strip_prefix("se "))
+    {
+        // `set` form: up to the first unescaped `:`, the rest is comment
+        Some(options) => {
+            let mut end = options.len();
+            let mut escaped = false;
+            for (i, c) in options.char_indices() {
+                match c {
+                    ':' if !escaped => {
+                        end = i;
+                        break;
+                    }
+                    _ => escaped = c == '\\' && !escaped,
+                }
+            }
+            options[..end]
+                .replace("\\:", ":")
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect()
+        }
+        // Plain form: options to the end of the line, split on `:` as well
+        None => rest
+            .split(|c: char| c == ':' || c.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    }
+}
+
+/// What follows `vi:`, `vim:`, `Vim:` or `ex:` in `line`. The marker
+/// starts the line or follows a blank, and `vi:` and `ex:` only the
+/// latter, so `lex:` or `navi:` in a line of text don't count.
+fn options_start(line: &str) -> Option<&str> {
+    let mut from = 0;
+    while from < line.len() {
+        let at = from + line[from..].find(':')?;
+        let before = &line[..at];
+        let marker = ["vim", "Vim", "vi", "ex"]
+            .into_iter()
+            .find(|m| before.ends_with(m));
+        if let Some(marker) = marker {
+            let start = at - marker.len();
+            let blank_before = line[..start].ends_with(char::is_whitespace);
+            if blank_before || (start == 0 && marker.eq_ignore_ascii_case("vim")) {
+                return Some(&line[at + 1..]);
+            }
+        }
+        from = at + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_both_forms_at_either_end() {
+        assert_eq!(parse("# vim: set ts=4 sw=4 et:"), ["ts=4", "sw=4", "et"]);
+        assert_eq!(parse("/* vim: set ft=c: */"), ["ft=c"]);
+        assert_eq!(parse("// vi:noet:ts=8 sw=8"), ["noet", "ts=8", "sw=8"]);
+        assert_eq!(parse("vim:nu"), ["nu"]);
+        assert_eq!(parse(r"# vim: set lcs=tab\:>-:"), ["lcs=tab:>-"]);
+        // Markers inside words, and `ex:` at the very start, aren't modelines
+        assert_eq!(parse("see the index: vim:ts=2"), ["ts=2"]);
+        assert!(parse("complex: yes").is_empty());
+        assert!(parse("ex:ts=2").is_empty());
+
+        let mut src: String = (0..20).map(|i| format!("line {i}\n")).collect();
+        src.insert_str(0, "# vim: ts=2\n");
+        src.push_str("# vim: set sw=2 :\n");
+        let text = Rope::from_str(&src);
+        assert_eq!(find(&text, 5), ["ts=2", "sw=2"]);
+        // The middle isn't looked at
+        let src = format!("{}# vim: et\n{src}", "x\n".repeat(5));
+        assert_eq!(find(&Rope::from_str(&src), 5), ["sw=2"]);
+        assert_eq!(find(&Rope::from_str("vim: et\n"), 5), ["et"]);
+    }
+}
@@ -0,0 +1,390 @@
+use crate::graphemes::{
+    abs_char_to_line_gcol, line_gcol_to_abs_char, next_grapheme_abs_char, prev_grapheme_abs_char,
+};
+use crate::options::{IsKeyword, Options};
+use ropey::Rope;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    /// `{count}G` / `{count}gg`: the first non-blank of line `count`
+    /// (1-based, clamped to the last line).
+    GotoLine(usize),
+    /// `%`: the bracket matching the first one from the cursor on its line.
+    MatchPair,
+}
+
+/// How an operator treats the span between the cursor and the motion target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotionKind {
+    /// Target char is not included (`w`, `h`, `0`).
+    Exclusive,
+    /// Target grapheme is included (`e`, `$`).
+    Inclusive,
+    /// Whole lines from the cursor row to the target row (`j`, `gg`).
+    Linewise,
+}
+
+impl Motion {
+    pub fn kind(self) -> MotionKind {
+        match self {
+            Motion::WordEnd | Motion::LineEnd | Motion::MatchPair => MotionKind::Inclusive,
+            Motion::Up
+            | Motion::Down
+            | Motion::FileStart
+            | Motion::FileEnd
+            | Motion::GotoLine(_) => MotionKind::Linewise,
+            _ => MotionKind::Exclusive,
+        }
+    }
+}
+
+/// Vim's word classes: a word is a run of graphemes of the same class,
+/// and which characters make words is the `iskeyword` option's say.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharClass {
+    Blank,
+    Punct,
+    Word,
+}
+
+pub fn char_class(c: char, isk: &IsKeyword) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if isk.contains(c) {
+        CharClass::Word
+    } else {
+        CharClass::Punct
+    }
+}
+
+// ------ Line helpers ---------------------------------------------------------
+
+/// Absolute char index just past the last non-line-break char on `row`.
+pub fn line_end_abs(text: &Rope, row: usize) -> usize {
+    let start = text.line_to_char(row);
+    let mut end = text.line_to_char(row + 1);
+    if end > start && text.char(end - 1) == '\n' {
+        end -= 1;
+    }
+    if end > start && text.char(end - 1) == '\r' {
+        end -= 1;
+    }
+    end
+}
+
+/// Absolute char index of the first non-blank on `row` (or its end if blank).
+pub fn first_non_blank_abs(text: &Rope, row: usize) -> usize {
+    let start = text.line_to_char(row);
+    let offset = text
+        .line(row)
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .count();
+    start + offset
+}
+
+/// The class of the grapheme at `ci`. With `subword`, `_` parts words
+/// the way blanks do.
+pub fn class_at(text: &Rope, ci: usize, opts: &Options) -> CharClass {
+    match text.char(ci) {
+        '_' if opts.subword => CharClass::Blank,
+        c => char_class(c, &opts.iskeyword),
+    }
+}
+
+/// Whether the grapheme at `b`, just after the one at `a`, is in the same
+/// word. With `subword`, camelCase humps start words of their own.
+pub fn same_word(text: &Rope, a: usize, b: usize, opts: &Options) -> bool {
+    let cls = class_at(text, a, opts);
+    class_at(text, b, opts) == cls && !(opts.subword && cls == CharClass::Word && hump(text, a, b))
+}
+
+/// `fooBar` between `o` and `B`, and `HTTPServer` between `P` and `S`.
+fn hump(text: &Rope, a: usize, b: usize) -> bool {
+    let (x, y) = (text.char(a), text.char(b));
+    if !y.is_uppercase() {
+        return false;
+    }
+    if !x.is_uppercase() {
+        return true;
+    }
+    // An acronym ends where a capitalised word starts
+    let c = next_grapheme_abs_char(text, b);
+    c < text.len_chars() && text.char(c).is_lowercase()
+}
+
+/// True when `ci` is the start of a line with no content (an empty line
+/// counts as a word of its own for `w` / `b`).
+#[inline]
+fn is_empty_line_at(text: &Rope, ci: usize) -> bool {
+    let row = text.char_to_line(ci);
+    text.line_to_char(row) == ci && line_end_abs(text, row) == ci
+}
+
+// ------ Word motions ---------------------------------------------------------
+
+fn word_forward(text: &Rope, mut p: usize, opts: &Options) -> usize {
+    let len = text.len_chars();
+    if p >= len {
+        return len;
+    }
+    if class_at(text, p, opts) != CharClass::Blank {
+        loop {
+            let n = next_grapheme_abs_char(text, p);
+            let more = n < len && same_word(text, p, n, opts);
+            p = n;
+            if !more {
+                break;
+            }
+        }
+    }
+    while p < len && class_at(text, p, opts) == CharClass::Blank {
+        p = next_grapheme_abs_char(text, p);
+        if p < len && is_empty_line_at(text, p) {
+            break;
+        }
+    }
+    p
+}
+
+fn word_backward(text: &Rope, mut p: usize, opts: &Options) -> usize {
+    if p == 0 {
+        return 0;
+    }
+    p = prev_grapheme_abs_char(text, p);
+    while p > 0 && class_at(text, p, opts) == CharClass::Blank && !is_empty_line_at(text, p) {
+        p = prev_grapheme_abs_char(text, p);
+    }
+    if class_at(text, p, opts) == CharClass::Blank {
+        return p;
+    }
+    while p > 0 {
+        let q = prev_grapheme_abs_char(text, p);
+        if !same_word(text, q, p, opts) {
+            break;
+        }
+        p = q;
+    }
+    p
+}
+
+fn word_end(text: &Rope, p: usize, opts: &Options) -> usize {
+    let len = text.len_chars();
+    let mut p = next_grapheme_abs_char(text, p);
+    while p < len && class_at(text, p, opts) == CharClass::Blank {
+        p = next_grapheme_abs_char(text, p);
+    }
+    if p >= len {
+        return prev_grapheme_abs_char(text, len);
+    }
+    loop {
+        let n = next_grapheme_abs_char(text, p);
+        if n >= len || !same_word(text, p, n, opts) {
+            return p;
+        }
+        p = n;
+    }
+}
+
+// ------ Brackets -------------------------------------------------------------
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+fn is_bracket(c: char) -> bool {
+    PAIRS.iter().any(|&(open, close)| c == open || c == close)
+}
+
+/// `%` from `from`: the first bracket from there to the end of the line,
+/// then its partner. Stays put when there is none.
+fn match_pair(text: &Rope, from: usize) -> usize {
+    let eol = line_end_abs(text, text.char_to_line(from));
+    (from..eol)
+        .find(|&i| is_bracket(text.char(i)))
+        .and_then(|i| matching_bracket(text, i, 0..text.len_chars()))
+        .unwrap_or(from)
+}
+
+// ------ Public ---------------------------------------------------------------
+
+/// The bracket pairing with the one at char `at`, skipping nested pairs of
+/// the same kind. Only chars in `within` are searched, so a caller can
+/// bound the work. None when `at` isn't a bracket or has no partner.
+pub fn matching_bracket(text: &Rope, at: usize, within: Range<usize>) -> Option<usize> {
+    let c = text.get_char(at)?;
+    let &(open, close) = PAIRS.iter().find(|&&(o, cl)| c == o || c == cl)?;
+    let mut depth = 0usize;
+    let mut step = |d: char, i: usize| {
+        if d == c {
+            depth += 1;
+        } else if d == open || d == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+        None
+    };
+    if c == open {
+        let end = within.end.min(text.len_chars());
+        text.chars_at(at).zip(at..end).find_map(|(d, i)| step(d, i))
+    } else {
+        let mut chars = text.chars_at(at + 1);
+        (within.start..=at)
+            .rev()
+            .find_map(|i| step(chars.prev()?, i))
+    }
+}
+
+/// Where `motion` repeated `count` times lands, starting from absolute char
+/// `from`. Words are as `iskeyword` and `subword` in `opts` make them.
+pub fn apply(text: &Rope, from: usize, motion: Motion, count: usize, opts: &Options) -> usize {
+    let count = count.max(1);
+    let row = text.char_to_line(from);
+    let last_row = text.len_lines() - 1;
+
+    match motion {
+        Motion::Left => {
+            let bol = text.line_to_char(row);
+            (0..count).fold(from, |p, _| {
+                if p > bol {
+                    prev_grapheme_abs_char(text, p)
+                } else {
+                    p
+                }
+            })
+        }
+        Motion::Right => {
+            let eol = line_end_abs(text, row);
+            (0..count).fold(from, |p, _| {
+                if p < eol {
+                    next_grapheme_abs_char(text, p).min(eol)
+                } else {
+                    p
+                }
+            })
+        }
+        Motion::Up | Motion::Down => {
+            let (_, gcol) = abs_char_to_line_gcol(text, from);
+            let target = if motion == Motion::Up {
+                row.saturating_sub(count)
+            } else {
+                (row + count).min(last_row)
+            };
+            line_gcol_to_abs_char(text, target, gcol)
+        }
+        Motion::WordForward => (0..count).fold(from, |p, _| word_forward(text, p, opts)),
+        Motion::WordBackward => (0..count).fold(from, |p, _| word_backward(text, p, opts)),
+        Motion::WordEnd => (0..count).fold(from, |p, _| word_end(text, p, opts)),
+        Motion::LineStart => text.line_to_char(row),
+        Motion::FirstNonBlank => first_non_blank_abs(text, row),
+        Motion::LineEnd => {
+            let target = (row + count - 1).min(last_row);
+            let eol = line_end_abs(text, target);
+            if eol > text.line_to_char(target) {
+                prev_grapheme_abs_char(text, eol)
+            } else {
+                eol
+            }
+        }
+        Motion::FileStart => first_non_blank_abs(text, 0),
+        Motion::FileEnd => first_non_blank_abs(text, last_row),
+        Motion::GotoLine(line) => first_non_blank_abs(text, line.saturating_sub(1).min(last_row)),
+        Motion::MatchPair => match_pair(text, from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src: &str, from: usize, m: Motion, count: usize) -> usize {
+        apply(&Rope::from_str(src), from, m, count, &Options::default())
+    }
+
+    #[test]
+    fn word_motions() {
+        let src = "foo.bar  baz\n\nqux";
+        assert_eq!(run(src, 0, Motion::WordForward, 1), 3); // '.'
+        assert_eq!(run(src, 0, Motion::WordForward, 3), 9); // 'baz'
+        assert_eq!(run(src, 9, Motion::WordForward, 1), 13); // empty line
+        assert_eq!(run(src, 13, Motion::WordForward, 1), 14); // 'qux'
+        assert_eq!(run(src, 14, Motion::WordBackward, 1), 13);
+        assert_eq!(run(src, 9, Motion::WordBackward, 1), 4);
+        assert_eq!(run(src, 0, Motion::WordEnd, 1), 2);
+        assert_eq!(run(src, 2, Motion::WordEnd, 1), 3);
+        assert_eq!(run(src, 11, Motion::WordEnd, 1), 16);
+    }
+
+    #[test]
+    fn word_motion_skips_emoji_cluster_whole() {
+        let src = "a 👨‍👩‍👧‍👦 b";
+        let family = "👨‍👩‍👧‍👦".chars().count();
+        assert_eq!(run(src, 0, Motion::WordForward, 1), 2);
+        assert_eq!(run(src, 2, Motion::WordForward, 1), 2 + family + 1);
+    }
+
+    #[test]
+    fn line_motions_stay_on_line() {
+        let src = "  ab\r\ncd";
+        assert_eq!(run(src, 3, Motion::LineStart, 1), 0);
+        assert_eq!(run(src, 0, Motion::FirstNonBlank, 1), 2);
+        assert_eq!(run(src, 0, Motion::LineEnd, 1), 3); // on 'b', not '\r'
+        assert_eq!(run(src, 0, Motion::LineEnd, 2), 7);
+        assert_eq!(run(src, 2, Motion::Left, 5), 0);
+        assert_eq!(run(src, 2, Motion::Right, 5), 4);
+        assert_eq!(run(src, 6, Motion::Up, 1), 0);
+    }
+
+    #[test]
+    fn percent_jumps_between_nested_pairs() {
+        let src = "if (a[0] + (b)) {\n  x;\n}";
+        assert_eq!(run(src, 3, Motion::MatchPair, 1), 14);
+        assert_eq!(run(src, 14, Motion::MatchPair, 1), 3);
+        assert_eq!(run(src, 5, Motion::MatchPair, 1), 7);
+        // From before the first bracket on the line, and across lines
+        assert_eq!(run(src, 0, Motion::MatchPair, 1), 14);
+        assert_eq!(run(src, 15, Motion::MatchPair, 1), 23);
+        assert_eq!(run(src, 23, Motion::MatchPair, 1), 16);
+        // No bracket, or an unmatched one: stay put
+        assert_eq!(run(src, 18, Motion::MatchPair, 1), 18);
+        assert_eq!(run("(a", 0, Motion::MatchPair, 1), 0);
+
+        let text = Rope::from_str(src);
+        assert_eq!(matching_bracket(&text, 16, 0..20), None);
+        assert_eq!(matching_bracket(&text, 1, 0..20), None);
+    }
+
+    #[test]
+    fn subword_stops_at_humps_and_underscores() {
+        let opts = Options {
+            subword: true,
+            ..Options::default()
+        };
+        let sub =
+            |src: &str, from: usize, m: Motion| apply(&Rope::from_str(src), from, m, 1, &opts);
+        let src = "parseHTTPServer snake_case x";
+        assert_eq!(sub(src, 0, Motion::WordForward), 5); // 'HTTP'
+        assert_eq!(sub(src, 5, Motion::WordForward), 9); // 'Server'
+        assert_eq!(sub(src, 9, Motion::WordForward), 16); // 'snake'
+        assert_eq!(sub(src, 16, Motion::WordForward), 22); // 'case'
+        assert_eq!(sub(src, 22, Motion::WordBackward), 16);
+        assert_eq!(sub(src, 9, Motion::WordBackward), 5);
+        assert_eq!(sub(src, 0, Motion::WordEnd), 4);
+        assert_eq!(sub(src, 16, Motion::WordEnd), 20);
+        // Off, the identifier is one word
+        assert_eq!(run(src, 0, Motion::WordForward, 1), 16);
+    }
+}
@@ -0,0 +1,152 @@
+use ropey::Rope;
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+
+/// Runners for languages that have a usual one, by the name a fence
+/// gives: the command the block is fed to on standard input.
+const RUNNERS: &[(&str, &str)] = &[
+    ("sh", "sh"),
+    ("shell", "sh"),
+    ("bash", "bash"),
+    ("python", "python3"),
+    ("py", "python3"),
+    ("javascript", "node"),
+    ("js", "node"),
+];
+
+/// The info string a block of output is fenced with.
+const OUTPUT: &str = "output";
+
+/// A fenced code block: its language and the rows of its code, between
+/// the fences.
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    pub lang: String,
+    pub code: Range<usize>,
+    /// The row of the closing fence.
+    pub close: usize,
+}
+
+fn line(text: &Rope, row: usize) -> String {
+    text.line(row)
+        .to_string()
+        .trim_end_matches(['\n', '\r'])
+        .to_owned()
+}
+
+/// The fence a line opens or closes with, and its info string.
+fn fence(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    let ch = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = trimmed.len() - trimmed.trim_start_matches(ch).len();
+    (len >= 3).then(|| (&trimmed[..len], trimmed[len..].trim()))
+}
+
+/// The fenced block holding `row`, its fences included.
+pub fn block_at(text: &Rope, row: usize) -> Option<Block> {
+    // Fences pair up from the top, so which ones open is counted.
+    let mut open: Option<(usize, String, String)> = None;
+    for r in 0..text.len_lines() {
+        let line = line(text, r);
+        let Some((marks, info)) = fence(&line) else {
+            continue;
+        };
+        match &open {
+            None => open = Some((r, marks.to_owned(), info.to_owned())),
+            Some((start, opened, lang))
+                if marks.starts_with(opened.as_str()) && info.is_empty() =>
+            {
+                if (*start..=r).contains(&row) {
+                    let lang = lang.split_whitespace().next().unwrap_or_default();
+                    return Some(Block {
+                        lang: lang.to_owned(),
+                        code: start + 1..r,
+                        close: r,
+                    });
+                }
+                if r > row {
+                    return None;
+                }
+                open = None;
+            }
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+/// The rows of the output block right after the closing fence `close`,
+/// left there by an earlier run.
+pub fn output_after(text: &Rope, close: usize) -> Option<RangeInclusive<usize>> {
+    let start = close + 1;
+    let block = block_at(text, start).filter(|b| b.lang == OUTPUT)?;
+    (block.code.start == start + 1).then_some(start..=block.close)
+}
+
+/// `output` fenced, ready to go under the block that made it.
+pub fn fenced(output: &str) -> String {
+    let brk = if output.is_empty() || output.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("```{OUTPUT}\n{output}{brk}```\n")
+}
+
+/// The `[runner]` table of the config file, a command for each language:
+///
+/// ```toml
+/// [runner]
+/// python = "python3 -q"
+/// ```
+#[cfg(feature = "config")]
+pub fn parse_runners(table: &toml::Value) -> Result<HashMap<String, String>, String> {
+    let table = table.as_table().ok_or("E474: runner must be a table")?;
+    table
+        .iter()
+        .map(|(lang, runner)| match runner.as_str() {
+            Some(runner) => Ok((lang.clone(), runner.to_owned())),
+            None => Err(format!("E474: runner.{lang} must be a command")),
+        })
+        .collect()
+}
+
+/// The command to run `lang` with: the config's `[runner]` table first.
+pub fn runner<'a>(lang: &str, configured: &'a HashMap<String, String>) -> Option<&'a str> {
+    configured.get(lang).map(String::as_str).or_else(|| {
+        RUNNERS
+            .iter()
+            .find(|&&(l, _)| l == lang)
+            .map(|&(_, runner)| runner)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_block_and_its_output() {
+        let text = Rope::from_str(
+            "# Notes\n```sh\necho hi\necho there\n```\n```output\nhi\nthere\n```\ntext\n~~~python\n~~~\n",
+        );
+        let block = block_at(&text, 2).unwrap();
+        assert_eq!(block.lang, "sh");
+        assert_eq!(block.code, 2..4);
+        assert_eq!(output_after(&text, block.close), Some(5..=8));
+        assert_eq!(block_at(&text, 0), None);
+        assert_eq!(block_at(&text, 9), None);
+        // The output is a block of its own
+        assert_eq!(block_at(&text, 6).unwrap().lang, "output");
+        assert_eq!(block_at(&text, 10).unwrap().lang, "python");
+        assert_eq!(output_after(&text, 11), None);
+
+        let configured = HashMap::from([("python".to_owned(), "uv run -".to_owned())]);
+        assert_eq!(runner("python", &configured), Some("uv run -"));
+        assert_eq!(runner("sh", &configured), Some("sh"));
+        assert_eq!(runner("cobol", &configured), None);
+        assert_eq!(fenced("a\n"), "```output\na\n```\n");
+        assert_eq!(fenced("a"), "```output\na\n```\n");
+        assert_eq!(fenced(""), "```output\n```\n");
+    }
+}
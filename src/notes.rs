@@ -0,0 +1,69 @@
+use crate::path;
+use std::path::{Path, PathBuf};
+
+/// File of the `:notes` scratch buffer for the project around `cwd`: one
+/// per git work tree (or directory when there is none), kept under
+/// `$XDG_STATE_HOME/mters/notes`, falling back to `%LOCALAPPDATA%` on
+/// Windows and `~/.local/state` elsewhere.
+pub fn path(cwd: &Path) -> Result<PathBuf, String> {
+    path_with(cwd, |name| std::env::var(name).ok())
+}
+
+/// `path` with an injectable variable lookup.
+pub fn path_with(cwd: &Path, lookup: impl Fn(&str) -> Option<String>) -> Result<PathBuf, String> {
+    let state = match lookup("XDG_STATE_HOME")
+        .filter(|d| !d.is_empty())
+        .or_else(|| lookup("LOCALAPPDATA"))
+    {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = path::home_with(&lookup).ok_or("E121: Undefined variable: $HOME")?;
+            Path::new(&home).join(".local/state")
+        }
+    };
+    let project = cwd
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(cwd);
+    let name = project
+        .file_name()
+        .map_or("root".into(), |n| n.to_string_lossy());
+    // The name alone would mix up checkouts of the same project.
+    let file = format!(
+        "{name}-{:08x}.md",
+        fnv1a(project.as_os_str().as_encoded_bytes())
+    );
+    Ok(state.join("mters/notes").join(file))
+}
+
+/// 32-bit FNV-1a: short, and the same from one build to the next.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_notes_file_per_project() {
+        let env = |state: Option<&'static str>| {
+            move |name: &str| match name {
+                "XDG_STATE_HOME" => state.map(String::from),
+                "HOME" => Some("/home/me".into()),
+                _ => None,
+            }
+        };
+        let a = path_with(Path::new("/nowhere/a"), env(None)).unwrap();
+        assert!(a.starts_with("/home/me/.local/state/mters/notes"));
+        assert!(a.file_name().unwrap().to_str().unwrap().starts_with("a-"));
+
+        let b = path_with(Path::new("/nowhere/b"), env(Some("/st"))).unwrap();
+        assert!(b.starts_with("/st/mters/notes"));
+        let other = path_with(Path::new("/elsewhere/a"), env(None)).unwrap();
+        assert_ne!(a, other);
+        assert_eq!(fnv1a(b"a"), 0xe40c292c);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::graphemes::next_grapheme_abs_char;
+use crate::motion::{self, CharClass, Motion, MotionKind};
+use crate::options::Options;
+use crate::textobject::{TextObject, TextObjectKind};
+use ropey::Rope;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Lowercase,
+    Uppercase,
+    Indent,
+    Outdent,
+    Comment,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 8] = [
+        Operator::Delete,
+        Operator::Change,
+        Operator::Yank,
+        Operator::Lowercase,
+        Operator::Uppercase,
+        Operator::Indent,
+        Operator::Outdent,
+        Operator::Comment,
+    ];
+
+    /// Normal-mode keys that start this operator.
+    pub fn keys(self) -> &'static str {
+        match self {
+            Operator::Delete => "d",
+            Operator::Change => "c",
+            Operator::Yank => "y",
+            Operator::Lowercase => "gu",
+            Operator::Uppercase => "gU",
+            Operator::Indent => ">",
+            Operator::Outdent => "<",
+            Operator::Comment => "gc",
+        }
+    }
+
+    pub fn from_keys(keys: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.keys() == keys)
+    }
+
+    /// Whether `keys`, typed while this operator is pending, make it act on
+    /// whole lines: its own last key (`dd`, `guu`) or its whole sequence
+    /// again (`gugu`).
+    pub fn doubles(self, keys: &str) -> bool {
+        let own = self.keys();
+        keys == own || own.ends_with(keys) && keys.chars().count() == 1
+    }
+}
+
+/// What an operator acts on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Motion(Motion),
+    TextObject(TextObject),
+    /// The doubled form: `count` lines starting at the cursor row.
+    Line,
+}
+
+/// A resolved operator span in absolute chars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpRange {
+    pub range: Range<usize>,
+    pub linewise: bool,
+}
+
+/// Rows `a` to `b`, either way round, with the last one's line break.
+pub fn rows_range(text: &Rope, a: usize, b: usize) -> Range<usize> {
+    let (top, bottom) = if a <= b { (a, b) } else { (b, a) };
+    text.line_to_char(top)..text.line_to_char(bottom + 1)
+}
+
+/// Where `w` lands under operator `op`, with Vim's two special cases:
+/// `cw` on a non-blank changes to the end of the word like `ce` (`:h cw`),
+/// and when the last word moved over ends its line the operator stops at
+/// the line end instead of reaching into the next line (`:h exclusive`).
+fn word_forward_target(
+    text: &Rope,
+    at: usize,
+    op: Operator,
+    count: usize,
+    opts: &Options,
+) -> (usize, MotionKind) {
+    let len = text.len_chars();
+    let on_word = at < len && motion::class_at(text, at, opts) != CharClass::Blank;
+    if op == Operator::Change && on_word {
+        // Already on the word's last grapheme: that is the first end.
+        let next = next_grapheme_abs_char(text, at);
+        let at_end = next >= len || !motion::same_word(text, at, next, opts);
+        let to = match (at_end, count) {
+            (true, 1) => at,
+            (true, _) => motion::apply(text, at, Motion::WordEnd, count - 1, opts),
+            (false, _) => motion::apply(text, at, Motion::WordEnd, count, opts),
+        };
+        return (to, MotionKind::Inclusive);
+    }
+
+    let last_from = match count {
+        1 => at,
+        _ => motion::apply(text, at, Motion::WordForward, count - 1, opts),
+    };
+    let to = motion::apply(text, last_from, Motion::WordForward, 1, opts);
+    let row = text.char_to_line(last_from);
+    let eol = motion::line_end_abs(text, row);
+    // On an empty line there is nothing before the break: `dw` takes it.
+    if text.char_to_line(to) > row && eol > at {
+        return (eol, MotionKind::Exclusive);
+    }
+    (to, MotionKind::Exclusive)
+}
+
+/// Resolve `target` (repeated `count` times) from absolute char `at` for
+/// `op`, with words as `opts` make them. Returns None when the target is
+/// empty or can't be found.
+pub fn resolve(
+    text: &Rope,
+    at: usize,
+    op: Operator,
+    target: Target,
+    count: usize,
+    opts: &Options,
+) -> Option<OpRange> {
+    let count = count.max(1);
+    let op = match target {
+        Target::Line => {
+            let row = text.char_to_line(at);
+            let last = (row + count - 1).min(text.len_lines() - 1);
+            OpRange {
+                range: rows_range(text, row, last),
+                linewise: true,
+            }
+        }
+        Target::TextObject(obj) => OpRange {
+            range: obj.resolve(text, at, opts)?,
+            linewise: obj.kind == TextObjectKind::Paragraph,
+        },
+        Target::Motion(m) => {
+            let (to, kind) = match m {
+                Motion::WordForward => word_forward_target(text, at, op, count, opts),
+                _ => (motion::apply(text, at, m, count, opts), m.kind()),
+            };
+            let (lo, hi) = if at <= to { (at, to) } else { (to, at) };
+            match kind {
+                MotionKind::Exclusive => OpRange {
+                    range: lo..hi,
+                    linewise: false,
+                },
+                MotionKind::Inclusive => OpRange {
+                    range: lo..next_grapheme_abs_char(text, hi).max(hi),
+                    linewise: false,
+                },
+                MotionKind::Linewise => OpRange {
+                    range: rows_range(text, text.char_to_line(lo), text.char_to_line(hi)),
+                    linewise: true,
+                },
+            }
+        }
+    };
+    if op.range.is_empty() {
+        None
+    } else {
+        Some(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(src: &str, at: usize, target: Target, count: usize) -> Option<(String, bool)> {
+        op_span(src, at, Operator::Delete, target, count)
+    }
+
+    fn op_span(
+        src: &str,
+        at: usize,
+        op: Operator,
+        target: Target,
+        count: usize,
+    ) -> Option<(String, bool)> {
+        let text = Rope::from_str(src);
+        resolve(&text, at, op, target, count, &Options::default())
+            .map(|r| (text.slice(r.range).to_string(), r.linewise))
+    }
+
+    #[test]
+    fn exclusive_inclusive_and_linewise() {
+        let src = "one two\nthree\nfour";
+        assert_eq!(
+            span(src, 0, Target::Motion(Motion::WordForward), 1),
+            Some(("one ".into(), false))
+        );
+        assert_eq!(
+            span(src, 0, Target::Motion(Motion::WordEnd), 1),
+            Some(("one".into(), false))
+        );
+        assert_eq!(
+            span(src, 4, Target::Motion(Motion::LineEnd), 1),
+            Some(("two".into(), false))
+        );
+        assert_eq!(
+            span(src, 9, Target::Motion(Motion::Up), 1),
+            Some(("one two\nthree\n".into(), true))
+        );
+        assert_eq!(
+            span(src, 9, Target::Line, 5),
+            Some(("three\nfour".into(), true))
+        );
+    }
+
+    #[test]
+    fn empty_targets_resolve_to_none() {
+        assert_eq!(span("abc", 0, Target::Motion(Motion::Left), 1), None);
+        assert_eq!(span("abc", 0, Target::Motion(Motion::LineStart), 1), None);
+    }
+
+    #[test]
+    fn word_motion_special_cases() {
+        let w = Target::Motion(Motion::WordForward);
+        let text = |s: &str| s.to_owned();
+        // cw on a word is ce; on its last letter it changes just that one
+        assert_eq!(
+            op_span("one two", 0, Operator::Change, w, 1),
+            Some((text("one"), false))
+        );
+        assert_eq!(
+            op_span("one two", 2, Operator::Change, w, 1),
+            Some((text("e"), false))
+        );
+        assert_eq!(
+            op_span("one two three", 0, Operator::Change, w, 2),
+            Some((text("one two"), false))
+        );
+        // ... but on blanks it is plain cw
+        assert_eq!(
+            op_span("a   b", 1, Operator::Change, w, 1),
+            Some((text("   "), false))
+        );
+
+        // dw on the last word of a line stops at its end
+        assert_eq!(
+            span("one two\n  three", 4, w, 1),
+            Some((text("two"), false))
+        );
+        assert_eq!(span("one\r\ntwo", 0, w, 1), Some((text("one"), false)));
+        assert_eq!(span("a b\nc d", 0, w, 3), Some((text("a b\nc "), false)));
+        // An empty line has nothing before its break, so dw takes the break
+        assert_eq!(span("\n\nx", 0, w, 1), Some((text("\n"), false)));
+    }
+}
@@ -0,0 +1,537 @@
+/// What `list` mode draws in place of whitespace; unset ones draw as usual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListChars {
+    /// First cell of a tab, then the rest of it.
+    pub tab: Option<(char, char)>,
+    /// Spaces after the last non-blank of a line.
+    pub trail: Option<char>,
+    /// U+00A0 no-break spaces.
+    pub nbsp: Option<char>,
+    /// One cell past the end of each line.
+    pub eol: Option<char>,
+}
+
+impl Default for ListChars {
+    fn default() -> Self {
+        Self {
+            tab: Some(('>', ' ')),
+            trail: Some('-'),
+            nbsp: Some('+'),
+            eol: Some('$'),
+        }
+    }
+}
+
+impl ListChars {
+    /// Parse Vim's `tab:>-,trail:-,eol:$` form. Items left out are unset.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("E474: Invalid argument: listchars={value}");
+        let mut chars = Self {
+            tab: None,
+            trail: None,
+            nbsp: None,
+            eol: None,
+        };
+        for item in value.split(',').filter(|i| !i.is_empty()) {
+            let (name, glyphs) = item.split_once(':').ok_or_else(invalid)?;
+            let glyphs: Vec<char> = glyphs.chars().collect();
+            match (name, glyphs.as_slice()) {
+                ("tab", &[first, rest]) => chars.tab = Some((first, rest)),
+                ("trail", &[c]) => chars.trail = Some(c),
+                ("nbsp", &[c]) => chars.nbsp = Some(c),
+                ("eol", &[c]) => chars.eol = Some(c),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(chars)
+    }
+}
+
+impl std::fmt::Display for ListChars {
+    /// The form `parse` reads.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut items = Vec::new();
+        if let Some((first, rest)) = self.tab {
+            items.push(format!("tab:{first}{rest}"));
+        }
+        for (name, glyph) in [
+            ("trail", self.trail),
+            ("nbsp", self.nbsp),
+            ("eol", self.eol),
+        ] {
+            if let Some(c) = glyph {
+                items.push(format!("{name}:{c}"));
+            }
+        }
+        write!(f, "{}", items.join(","))
+    }
+}
+
+/// `gutter`: the components left of the text, in order, each with the
+/// least columns it gets if given: `marks,number:6`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GutterColumns(pub Vec<(&'static str, Option<usize>)>);
+
+impl Default for GutterColumns {
+    fn default() -> Self {
+        Self(vec![("number", None)])
+    }
+}
+
+impl GutterColumns {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("E474: Invalid argument: gutter={value}");
+        let mut columns = Vec::new();
+        for item in value.split(',').filter(|i| !i.is_empty()) {
+            let (name, width) = match item.split_once(':') {
+                Some((name, width)) => (name, Some(width.parse().map_err(|_| invalid())?)),
+                None => (item, None),
+            };
+            let component = crate::gutter::find(name).ok_or_else(invalid)?;
+            columns.push((component.name(), width));
+        }
+        Ok(Self(columns))
+    }
+}
+
+impl std::fmt::Display for GutterColumns {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let items: Vec<String> = self
+            .0
+            .iter()
+            .map(|&(name, width)| match width {
+                Some(w) => format!("{name}:{w}"),
+                None => name.to_owned(),
+            })
+            .collect();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+/// `iskeyword`: which characters below 256 make words, in Vim's form
+/// `@,48-57,_,-`. Parts are characters, ranges of characters or of their
+/// codes, `@` for all letters, and `^` before a part to take it out.
+/// Above 255, letters and digits are word characters whatever it says.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsKeyword {
+    spec: String,
+    set: Box<[bool; 256]>,
+}
+
+impl Default for IsKeyword {
+    /// Letters, digits and `_`, as words have always been.
+    fn default() -> Self {
+        Self::parse("@,48-57,_").expect("valid")
+    }
+}
+
+impl IsKeyword {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("E474: Invalid argument: iskeyword={spec}");
+        // `65` is a code, `a` or `5` the character itself
+        let code = |s: &str| -> Option<usize> {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c as usize),
+                _ => s.parse().ok(),
+            }
+        };
+        let mut set = Box::new([false; 256]);
+        for part in spec.split(',').filter(|p| !p.is_empty()) {
+            let (on, part) = match part.strip_prefix('^').filter(|p| !p.is_empty()) {
+                Some(rest) => (false, rest),
+                None => (true, part),
+            };
+            let range = match part {
+                "@" => {
+                    for (c, slot) in set.iter_mut().enumerate() {
+                        if char::from(c as u8).is_alphabetic() {
+                            *slot = on;
+                        }
+                    }
+                    continue;
+                }
+                "@-@" => 64..=64,
+                _ => match part
+                    .split_once('-')
+                    .filter(|(a, b)| !a.is_empty() && !b.is_empty())
+                {
+                    Some((a, b)) => code(a).ok_or_else(invalid)?..=code(b).ok_or_else(invalid)?,
+                    None => {
+                        let c = code(part).ok_or_else(invalid)?;
+                        c..=c
+                    }
+                },
+            };
+            if *range.end() > 255 || range.is_empty() {
+                return Err(invalid());
+            }
+            set[range].fill(on);
+        }
+        Ok(Self {
+            spec: spec.to_owned(),
+            set,
+        })
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        match self.set.get(c as usize) {
+            Some(&on) => on,
+            None => c.is_alphanumeric(),
+        }
+    }
+
+    /// `:set isk+=-`: `parts` added to the end, or with `add` false taken
+    /// out wherever they are.
+    pub fn changed(&self, parts: &str, add: bool) -> Result<Self, String> {
+        let mut spec: Vec<&str> = self.spec.split(',').filter(|p| !p.is_empty()).collect();
+        for part in parts.split(',').filter(|p| !p.is_empty()) {
+            spec.retain(|p| *p != part);
+            if add {
+                spec.push(part);
+            }
+        }
+        Self::parse(&spec.join(","))
+    }
+}
+
+impl std::fmt::Display for IsKeyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// Every option by full name and short name (empty when it has none), in
+/// the order `:set all` lists them.
+pub const NAMES: &[(&str, &str)] = &[
+    ("autocapitalize", ""),
+    ("autoindent", "ai"),
+    ("autopairs", "ap"),
+    ("autoread", "ar"),
+    ("autosave", ""),
+    ("autosnapshot", "asn"),
+    ("colorcolumn", "cc"),
+    ("commentstring", "cms"),
+    ("confirm", "cf"),
+    ("expandtab", "et"),
+    ("fixendofline", "fixeol"),
+    ("follow", ""),
+    ("gutter", ""),
+    ("iskeyword", "isk"),
+    ("list", ""),
+    ("listchars", "lcs"),
+    ("longline", ""),
+    ("makeprg", "mp"),
+    ("modeline", "ml"),
+    ("modelines", "mls"),
+    ("number", "nu"),
+    ("prose", ""),
+    ("relativenumber", "rnu"),
+    ("shiftwidth", "sw"),
+    ("showkeys", "sk"),
+    ("showtrailing", "stw"),
+    ("smartdashes", ""),
+    ("smartindent", "si"),
+    ("smartquotes", ""),
+    ("subword", "sbw"),
+    ("tabstop", "ts"),
+    ("timeoutlen", "tm"),
+    ("trimtrailing", ""),
+    ("updatetime", "ut"),
+    ("whitespacewarn", "wsw"),
+];
+
+/// The full name of an option given either of its names.
+pub fn full_name(name: &str) -> Option<&'static str> {
+    NAMES
+        .iter()
+        .find(|&&(full, short)| name == full || (!short.is_empty() && name == short))
+        .map(|&(full, _)| full)
+}
+
+/// Editor settings. Defaults suit Rust sources: four-space indents, no tabs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Columns added or removed by one `>>` / `<<`; 0 for `tabstop`'s.
+    pub shiftwidth: usize,
+    /// Display width of a tab character.
+    pub tabstop: usize,
+    /// Indent with spaces instead of tabs.
+    pub expandtab: bool,
+    /// Start a new line with the indent of the line it was opened from.
+    pub autoindent: bool,
+    /// Indent a level more after a line that opens a block.
+    pub smartindent: bool,
+    /// Close brackets and quotes as they are typed in Insert mode.
+    pub autopairs: bool,
+    /// Typing for prose in Insert mode: the transforms below that are on
+    /// apply, and a backspace straight after one takes it back. Meant for
+    /// `[filetype.markdown]` and `[filetype.text]` in the config file.
+    pub prose: bool,
+    /// `"` and `'` typed as curly quotes, opening or closing by what's
+    /// before them.
+    pub smartquotes: bool,
+    /// `--` typed as an en dash, `---` as an em dash.
+    pub smartdashes: bool,
+    /// A letter starting a sentence typed as a capital.
+    pub autocapitalize: bool,
+    /// The program `:make` runs, its arguments after.
+    pub makeprg: String,
+    /// How `gc` comments a line: `%s` stands for the line, as in `// %s`.
+    pub commentstring: String,
+    /// Ask before discarding changes or overwriting a file instead of
+    /// refusing (the same as prefixing the command with `:confirm`).
+    pub confirm: bool,
+    /// Show line numbers in a gutter left of the text.
+    pub number: bool,
+    /// Number other lines by their distance from the cursor line.
+    pub relativenumber: bool,
+    /// What the gutter shows, in order.
+    pub gutter: GutterColumns,
+    /// Keep a snapshot of the text at each write, named `write1`, `write2`...
+    pub autosnapshot: bool,
+    /// Show whitespace with the glyphs in `listchars`.
+    pub list: bool,
+    pub listchars: ListChars,
+    /// Screen columns (1-based) highlighted on every line as a guide.
+    pub colorcolumn: Vec<usize>,
+    /// Give spaces and tabs at the end of lines a warning background.
+    pub showtrailing: bool,
+    /// Show recent keys and the commands they made in a corner.
+    pub showkeys: bool,
+    /// Milliseconds without a key before `CursorHold` fires.
+    pub updatetime: usize,
+    /// Milliseconds to wait for the rest of a mapping before taking the
+    /// keys typed as they are.
+    pub timeoutlen: usize,
+    /// Write a modified file on `CursorHold`.
+    pub autosave: bool,
+    /// On `CursorHold`, read the file again if it changed on disk and the
+    /// buffer has no changes of its own.
+    pub autoread: bool,
+    /// Tail the file like `less +F`: what's appended to it is read in, and
+    /// the view keeps to the end if the cursor is there. Stops once the
+    /// buffer has changes of its own.
+    pub follow: bool,
+    /// Ask before a `:w` that would change only whitespace or line endings
+    /// in the file on disk.
+    pub whitespacewarn: bool,
+    /// The characters words are made of, for word motions and objects.
+    pub iskeyword: IsKeyword,
+    /// Word motions and objects stop at camelCase humps and `_`.
+    pub subword: bool,
+    /// Strip spaces and tabs from the ends of lines when writing.
+    pub trimtrailing: bool,
+    /// End the file with a line break when writing, if it has none.
+    pub fixendofline: bool,
+    /// Characters past which a line is too long for the extras redone on
+    /// every key: bracket matching, trailing whitespace marks and preview
+    /// highlighting are left off it, and the status line says so. 0 for
+    /// no limit.
+    pub longline: usize,
+    /// Read `vim: set ...:` lines in opened files.
+    pub modeline: bool,
+    /// How many lines at each end of a file may hold a modeline.
+    pub modelines: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            shiftwidth: 4,
+            tabstop: 8,
+            expandtab: true,
+            confirm: false,
+            number: false,
+            relativenumber: false,
+            gutter: GutterColumns::default(),
+            autosnapshot: false,
+            list: false,
+            listchars: ListChars::default(),
+            colorcolumn: Vec::new(),
+            showtrailing: false,
+            showkeys: false,
+            updatetime: 4000,
+            timeoutlen: 1000,
+            autosave: false,
+            autoread: false,
+            follow: false,
+            whitespacewarn: false,
+            iskeyword: IsKeyword::default(),
+            subword: false,
+            trimtrailing: false,
+            fixendofline: false,
+            longline: 10_000,
+            modeline: true,
+            autoindent: true,
+            smartindent: false,
+            autopairs: false,
+            prose: false,
+            smartquotes: true,
+            smartdashes: true,
+            autocapitalize: true,
+            commentstring: String::new(),
+            makeprg: "make".into(),
+            modelines: 5,
+        }
+    }
+}
+
+impl Options {
+    /// Whether a line of `chars` characters is past `longline`.
+    pub fn is_long(&self, chars: usize) -> bool {
+        self.longline > 0 && chars > self.longline
+    }
+
+    /// Columns one shift moves by: `shiftwidth`, or `tabstop` when it's 0.
+    pub fn shift_columns(&self) -> usize {
+        match self.shiftwidth {
+            0 => self.tabstop,
+            sw => sw,
+        }
+    }
+
+    /// Boolean option by full or short name, for `:set name` / `:set noname`.
+    pub fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "et" | "expandtab" => Some(&mut self.expandtab),
+            "ai" | "autoindent" => Some(&mut self.autoindent),
+            "si" | "smartindent" => Some(&mut self.smartindent),
+            "ap" | "autopairs" => Some(&mut self.autopairs),
+            "prose" => Some(&mut self.prose),
+            "smartquotes" => Some(&mut self.smartquotes),
+            "smartdashes" => Some(&mut self.smartdashes),
+            "autocapitalize" => Some(&mut self.autocapitalize),
+            "cf" | "confirm" => Some(&mut self.confirm),
+            "nu" | "number" => Some(&mut self.number),
+            "rnu" | "relativenumber" => Some(&mut self.relativenumber),
+            "asn" | "autosnapshot" => Some(&mut self.autosnapshot),
+            "list" => Some(&mut self.list),
+            "stw" | "showtrailing" => Some(&mut self.showtrailing),
+            "sk" | "showkeys" => Some(&mut self.showkeys),
+            "autosave" => Some(&mut self.autosave),
+            "ar" | "autoread" => Some(&mut self.autoread),
+            "follow" => Some(&mut self.follow),
+            "wsw" | "whitespacewarn" => Some(&mut self.whitespacewarn),
+            "trimtrailing" => Some(&mut self.trimtrailing),
+            "sbw" | "subword" => Some(&mut self.subword),
+            "fixeol" | "fixendofline" => Some(&mut self.fixendofline),
+            "ml" | "modeline" => Some(&mut self.modeline),
+            _ => None,
+        }
+    }
+
+    /// An option as `:set name?` shows it: `tabstop=8`, `nonumber`.
+    pub fn show(&self, name: &str) -> Option<String> {
+        let name = full_name(name)?;
+        // Reading through the `_mut` lookups keeps one list of names.
+        let mut copy = self.clone();
+        if let Some(&mut on) = copy.flag_mut(name) {
+            return Some(if on {
+                name.to_owned()
+            } else {
+                format!("no{name}")
+            });
+        }
+        if let Some(&mut n) = copy.number_mut(name) {
+            return Some(format!("{name}={n}"));
+        }
+        let value = match name {
+            "colorcolumn" => self
+                .colorcolumn
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            "listchars" => self.listchars.to_string(),
+            "gutter" => self.gutter.to_string(),
+            "iskeyword" => self.iskeyword.to_string(),
+            "commentstring" => self.commentstring.clone(),
+            "makeprg" => self.makeprg.clone(),
+            _ => return None,
+        };
+        Some(format!("{name}={value}"))
+    }
+
+    /// Options set away from their defaults, as `:set` on its own lists them.
+    pub fn changed(&self) -> Vec<String> {
+        let default = Self::default();
+        NAMES
+            .iter()
+            .filter_map(|&(name, _)| {
+                self.show(name)
+                    .filter(|v| default.show(name).as_ref() != Some(v))
+            })
+            .collect()
+    }
+
+    /// Numeric option by full or short name, for `:set name=N`.
+    pub fn number_mut(&mut self, name: &str) -> Option<&mut usize> {
+        match name {
+            "sw" | "shiftwidth" => Some(&mut self.shiftwidth),
+            "ts" | "tabstop" => Some(&mut self.tabstop),
+            "ut" | "updatetime" => Some(&mut self.updatetime),
+            "tm" | "timeoutlen" => Some(&mut self.timeoutlen),
+            "mls" | "modelines" => Some(&mut self.modelines),
+            "longline" => Some(&mut self.longline),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listchars() {
+        let chars = ListChars::parse("tab:»·,eol:¬").unwrap();
+        assert_eq!(chars.tab, Some(('»', '·')));
+        assert_eq!(chars.eol, Some('¬'));
+        assert_eq!(chars.trail, None);
+        assert!(ListChars::parse("tab:>").is_err());
+        assert!(ListChars::parse("space:.").is_err());
+        assert_eq!(chars.to_string(), "tab:»·,eol:¬");
+    }
+
+    #[test]
+    fn every_option_shows_by_either_name() {
+        let mut options = Options::default();
+        for &(name, short) in NAMES {
+            assert!(options.show(name).is_some(), "{name}");
+            if !short.is_empty() {
+                assert_eq!(options.show(short), options.show(name));
+            }
+        }
+        assert!(options.changed().is_empty());
+        options.number = true;
+        options.tabstop = 4;
+        options.colorcolumn = vec![80, 100];
+        assert_eq!(options.show("ts").unwrap(), "tabstop=4");
+        assert_eq!(options.show("et").unwrap(), "expandtab");
+        assert_eq!(
+            options.changed(),
+            ["colorcolumn=80,100", "number", "tabstop=4"]
+        );
+        assert_eq!(options.show("nope"), None);
+    }
+
+    #[test]
+    fn iskeyword_parts() {
+        let isk = IsKeyword::default();
+        assert!(isk.contains('a') && isk.contains('É') && isk.contains('7') && isk.contains('_'));
+        assert!(!isk.contains('-') && !isk.contains('.'));
+        // Beyond Latin-1 it goes by the character
+        assert!(isk.contains('ж') && !isk.contains('—'));
+
+        let css = isk.changed("-", true).unwrap();
+        assert_eq!(css.to_string(), "@,48-57,_,-");
+        assert!(css.contains('-'));
+        assert_eq!(css.changed("-", false).unwrap(), isk);
+
+        let isk = IsKeyword::parse("a-c,^b,58").unwrap();
+        assert!(isk.contains('a') && !isk.contains('b') && isk.contains(':'));
+        assert!(IsKeyword::parse("300").is_err());
+        assert!(IsKeyword::parse("z-a").is_err());
+    }
+}
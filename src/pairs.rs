@@ -0,0 +1,64 @@
+/// What `autopairs` closes: brackets, and quotes, which close themselves.
+const PAIRS: [(char, char); 5] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// What typing a character does between `before` and `after`, the
+/// characters either side of the cursor.
+#[derive(Debug, PartialEq)]
+pub enum Typed {
+    /// Insert it and its closer, with the cursor between them.
+    Pair(char),
+    /// Step over the same closer already there.
+    Over,
+    /// Insert it as usual.
+    Plain,
+}
+
+pub fn typed(c: char, before: Option<char>, after: Option<char>) -> Typed {
+    let Some(&(open, close)) = PAIRS.iter().find(|&&(o, cl)| c == o || c == cl) else {
+        return Typed::Plain;
+    };
+    if c == close && after == Some(close) {
+        return Typed::Over;
+    }
+    if c != open {
+        return Typed::Plain;
+    }
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    // Only before blanks, closers or nothing: `(` typed before `x` is
+    // about to wrap it. A quote after a letter is an apostrophe.
+    let closes = |c: char| PAIRS.iter().any(|&(_, cl)| cl == c);
+    let room = after.is_none_or(|a| a.is_whitespace() || (closes(a) && a != open));
+    if room && !(open == close && word(before)) {
+        Typed::Pair(close)
+    } else {
+        Typed::Plain
+    }
+}
+
+/// Whether backspace between `before` and `after` takes a pair with it.
+pub fn empty_pair(before: Option<char>, after: Option<char>) -> bool {
+    PAIRS
+        .iter()
+        .any(|&(o, cl)| before == Some(o) && after == Some(cl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_where_there_is_room() {
+        assert_eq!(typed('(', None, None), Typed::Pair(')'));
+        assert_eq!(typed('[', Some('x'), Some(' ')), Typed::Pair(']'));
+        assert_eq!(typed('{', None, Some(')')), Typed::Pair('}'));
+        assert_eq!(typed('(', None, Some('x')), Typed::Plain);
+        assert_eq!(typed(')', Some('('), Some(')')), Typed::Over);
+        assert_eq!(typed(')', None, None), Typed::Plain);
+        assert_eq!(typed('"', Some(' '), None), Typed::Pair('"'));
+        assert_eq!(typed('"', Some('a'), Some('"')), Typed::Over);
+        assert_eq!(typed('\'', Some('n'), Some(' ')), Typed::Plain); // don't
+        assert_eq!(typed('x', None, None), Typed::Plain);
+        assert!(empty_pair(Some('{'), Some('}')));
+        assert!(!empty_pair(Some('('), Some(']')));
+    }
+}
@@ -0,0 +1,185 @@
+use ropey::Rope;
+use std::path::PathBuf;
+
+/// One `@@ -a,b +c,d @@` hunk of a unified diff, with the file it targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// Path from the `+++` header, without git's `b/` prefix.
+    pub path: PathBuf,
+    pub old_start: usize,
+    pub new_start: usize,
+    /// Body lines as (tag, text): tag is ' ', '-' or '+'; text has no line break.
+    pub lines: Vec<(char, String)>,
+}
+
+fn line_str(text: &Rope, row: usize) -> String {
+    let mut s = text.line(row).to_string();
+    while s.ends_with('\n') || s.ends_with('\r') {
+        s.pop();
+    }
+    s
+}
+
+/// Parse "@@ -a[,b] +c[,d] @@ ..." into (a, c).
+fn parse_header(line: &str) -> Option<(usize, usize)> {
+    let rest = line.strip_prefix("@@ -")?;
+    let (old, rest) = rest.split_once(" +")?;
+    let (new, _) = rest.split_once(" @@")?;
+    let start = |r: &str| r.split(',').next()?.parse::<usize>().ok();
+    Some((start(old)?, start(new)?))
+}
+
+/// The hunk whose header or body contains `row`, if the buffer is a diff.
+pub fn hunk_at(text: &Rope, row: usize) -> Option<Hunk> {
+    let header_row = (0..=row)
+        .rev()
+        .find(|&r| line_str(text, r).starts_with("@@ "))?;
+    let (old_start, new_start) = parse_header(&line_str(text, header_row))?;
+
+    let path = (0..header_row)
+        .rev()
+        .map(|r| line_str(text, r))
+        .find_map(|l| l.strip_prefix("+++ ").map(str::to_owned))?;
+    let path = path.split('\t').next().unwrap_or_default();
+    let path = PathBuf::from(path.strip_prefix("b/").unwrap_or(path));
+
+    let mut lines = Vec::new();
+    for r in header_row + 1..text.len_lines() {
+        let l = line_str(text, r);
+        match l.chars().next() {
+            Some(tag @ (' ' | '-' | '+')) if !l.starts_with("--- ") && !l.starts_with("+++ ") => {
+                lines.push((tag, l[1..].to_owned()));
+            }
+            // "\ No newline at end of file" annotates the previous line; skip it.
+            Some('\\') => {}
+            // Some tools drop the single space on empty context lines.
+            None if r + 1 < text.len_lines() => lines.push((' ', String::new())),
+            _ => break,
+        }
+    }
+    if row > header_row + lines.len() {
+        return None; // cursor is past this hunk's body
+    }
+
+    Some(Hunk {
+        path,
+        old_start,
+        new_start,
+        lines,
+    })
+}
+
+impl Hunk {
+    /// Apply the hunk to `content` (or undo it when `reverse`), locating the
+    /// expected lines nearest to the header's line number.
+    pub fn apply_to(&self, content: &str, reverse: bool) -> Result<String, String> {
+        let (remove, add, expected) = if reverse {
+            ('+', '-', self.new_start)
+        } else {
+            ('-', '+', self.old_start)
+        };
+        let before: Vec<&str> = self
+            .lines
+            .iter()
+            .filter(|(t, _)| *t == ' ' || *t == remove)
+            .map(|(_, l)| l.as_str())
+            .collect();
+        let after: Vec<&str> = self
+            .lines
+            .iter()
+            .filter(|(t, _)| *t == ' ' || *t == add)
+            .map(|(_, l)| l.as_str())
+            .collect();
+
+        let file: Vec<&str> = content.split_inclusive('\n').collect();
+        let strip = |l: &str| l.trim_end_matches('\n').trim_end_matches('\r').to_owned();
+        let matches_at = |i: usize| {
+            i + before.len() <= file.len()
+                && before
+                    .iter()
+                    .zip(&file[i..])
+                    .all(|(want, have)| *want == strip(have))
+        };
+
+        let expected = expected.saturating_sub(1);
+        let at = (0..=file.len().saturating_sub(before.len()))
+            .filter(|&i| matches_at(i))
+            .min_by_key(|&i| i.abs_diff(expected))
+            .ok_or_else(|| format!("hunk does not apply to {}", self.path.display()))?;
+
+        let eol = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let end = at + before.len();
+        // Keep a missing final newline missing if the hunk reaches EOF.
+        let open_ended = end == file.len() && file.last().is_some_and(|l| !l.ends_with('\n'));
+
+        let mut out: String = file[..at].concat();
+        for (i, line) in after.iter().enumerate() {
+            out.push_str(line);
+            if !(open_ended && i + 1 == after.len()) {
+                out.push_str(eol);
+            }
+        }
+        out.push_str(&file[end..].concat());
+        Ok(out)
+    }
+
+    /// Apply (or revert) the hunk to its target file on disk.
+    pub fn apply_to_file(&self, reverse: bool) -> Result<(), String> {
+        let err = |e: std::io::Error| format!("{}: {e}", self.path.display());
+        let content = std::fs::read_to_string(&self.path).map_err(err)?;
+        let patched = self.apply_to(&content, reverse)?;
+        std::fs::write(&self.path, patched).map_err(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/src/x.txt b/src/x.txt
+--- a/src/x.txt
++++ b/src/x.txt
+@@ -2,3 +2,3 @@ fn main
+ two
+-three
++THREE
+ four
+";
+
+    #[test]
+    fn parses_hunk_under_cursor() {
+        let text = Rope::from_str(DIFF);
+        let hunk = hunk_at(&text, 5).unwrap();
+        assert_eq!(hunk.path, PathBuf::from("src/x.txt"));
+        assert_eq!((hunk.old_start, hunk.new_start), (2, 2));
+        assert_eq!(hunk.lines.len(), 4);
+        assert_eq!(hunk.lines[1], ('-', "three".to_owned()));
+        // File headers are above the first hunk
+        assert_eq!(hunk_at(&text, 1), None);
+    }
+
+    #[test]
+    fn applies_and_reverts() {
+        let hunk = hunk_at(&Rope::from_str(DIFF), 4).unwrap();
+        let old = "one\ntwo\nthree\nfour\nfive\n";
+        let new = hunk.apply_to(old, false).unwrap();
+        assert_eq!(new, "one\ntwo\nTHREE\nfour\nfive\n");
+        assert_eq!(hunk.apply_to(&new, true).unwrap(), old);
+        assert!(hunk.apply_to(old, true).is_err());
+    }
+
+    #[test]
+    fn keeps_crlf_and_missing_final_newline() {
+        let hunk = hunk_at(&Rope::from_str(DIFF), 4).unwrap();
+        let old = "one\r\ntwo\r\nthree\r\nfour";
+        assert_eq!(
+            hunk.apply_to(old, false).unwrap(),
+            "one\r\ntwo\r\nTHREE\r\nfour"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+
+/// Paths are written the Windows way: `\` separates too, and `C:` starts
+/// a path on a drive. Code that splits typed paths takes this as an
+/// argument, so tests on any system cover both ways.
+pub const WINDOWS: bool = cfg!(windows);
+
+/// Where the last component of a typed path starts: `src/ma` splits into
+/// `src/` and `ma`, and on Windows `C:ma` into `C:` and `ma`.
+pub fn split_last(path: &str, windows: bool) -> (&str, &str) {
+    let end = path
+        .char_indices()
+        .rfind(|&(i, c)| c == '/' || (windows && (c == '\\' || (c == ':' && i == 1))))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    path.split_at(end)
+}
+
+/// The home directory: `$HOME`, else `%USERPROFILE%`, which Windows sets
+/// in its place.
+pub fn home_with(lookup: impl Fn(&str) -> Option<String>) -> Option<String> {
+    lookup("HOME")
+        .filter(|h| !h.is_empty())
+        .or_else(|| lookup("USERPROFILE"))
+}
+
+/// Expand a user-typed path: a leading `~` / `~/` becomes `$HOME`, and
+/// `$VAR` / `${VAR}` are replaced from the environment. Every place that
+/// accepts a path goes through here so they all behave the same.
+pub fn expand(input: &str) -> Result<PathBuf, String> {
+    expand_with(input, |name| std::env::var(name).ok())
+}
+
+/// `expand` with an injectable variable lookup (for tests).
+pub fn expand_with(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<PathBuf, String> {
+    expand_on(input, lookup, WINDOWS)
+}
+
+/// `expand_with`, where on Windows `~\` starts a path from home too and
+/// `%VAR%` is a variable as in `cmd`.
+fn expand_on(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    windows: bool,
+) -> Result<PathBuf, String> {
+    let var = |name: &str| lookup(name).ok_or_else(|| format!("E121: Undefined variable: ${name}"));
+
+    let mut out = String::new();
+    let mut rest = input;
+    if rest == "~" || rest.starts_with("~/") || (windows && rest.starts_with("~\\")) {
+        out.push_str(&home_with(&lookup).ok_or("E121: Undefined variable: $HOME")?);
+        rest = &rest[1..];
+    }
+    let windows_vars;
+    if windows {
+        windows_vars = percent_vars(rest, var)?;
+        rest = &windows_vars;
+    }
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("E15: Invalid expression: \"{input}\""))?;
+            out.push_str(&var(&braced[..end])?);
+            rest = &braced[end + 1..];
+        } else {
+            let len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            if len == 0 {
+                // A lone '$' is literal.
+                out.push('$');
+            } else {
+                out.push_str(&var(&after[..len])?);
+            }
+            rest = &after[len..];
+        }
+    }
+    out.push_str(rest);
+    Ok(PathBuf::from(out))
+}
+
+/// `%VAR%` replaced; `%%` and a `%` with no partner stay as they are.
+fn percent_vars(
+    input: &str,
+    var: impl Fn(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = input;
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        match after.find('%') {
+            Some(end) if end > 0 => {
+                out.push_str(&var(&after[..end])?);
+                rest = &after[end + 1..];
+            }
+            _ => {
+                out.push('%');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/me".into()),
+            "PROJ" => Some("work/mters".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_home_and_variables() {
+        let e = |s| expand_with(s, env).unwrap();
+        assert_eq!(e("~"), PathBuf::from("/home/me"));
+        assert_eq!(e("~/notes.md"), PathBuf::from("/home/me/notes.md"));
+        assert_eq!(
+            e("$HOME/$PROJ/src"),
+            PathBuf::from("/home/me/work/mters/src")
+        );
+        assert_eq!(e("${PROJ}_old.rs"), PathBuf::from("work/mters_old.rs"));
+        // Only a leading ~ is special, and a bare $ is literal
+        assert_eq!(e("a~b/$"), PathBuf::from("a~b/$"));
+    }
+
+    #[test]
+    fn unset_variables_are_errors() {
+        assert_eq!(
+            expand_with("$NOPE/x", env),
+            Err("E121: Undefined variable: $NOPE".to_string())
+        );
+        assert!(expand_with("${PROJ", env).is_err());
+        assert!(expand_with("~", |_| None).is_err());
+    }
+
+    #[test]
+    fn windows_paths() {
+        let env = |name: &str| match name {
+            "USERPROFILE" => Some(r"C:\Users\me".into()),
+            "APPDATA" => Some(r"C:\Users\me\AppData\Roaming".into()),
+            _ => None,
+        };
+        let e = |s| expand_on(s, env, true).unwrap();
+        assert_eq!(e(r"~\notes.md"), PathBuf::from(r"C:\Users\me\notes.md"));
+        assert_eq!(
+            e(r"%APPDATA%\mters"),
+            PathBuf::from(r"C:\Users\me\AppData\Roaming\mters")
+        );
+        assert_eq!(e("100%"), PathBuf::from("100%"));
+        // Not on other systems: a file may be named that
+        assert_eq!(
+            expand_on("%APPDATA%", env, false).unwrap(),
+            PathBuf::from("%APPDATA%")
+        );
+
+        assert_eq!(split_last(r"C:\src\ma", true), (r"C:\src\", "ma"));
+        assert_eq!(split_last("C:ma", true), ("C:", "ma"));
+        assert_eq!(split_last(r"src\ma", false), ("", r"src\ma"));
+        assert_eq!(split_last("src/ma", false), ("src/", "ma"));
+    }
+}
@@ -0,0 +1,472 @@
+use crate::ex;
+use crate::input::EditorCommand;
+use crate::matcher::{Match, Matcher, Results};
+use crate::syntax::{self, Syntax};
+use crate::tags::{self, Address};
+use crate::theme;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// One line of a picker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub label: String,
+    /// File shown beside the list, centred on the line the address points
+    /// at when there is one.
+    pub preview: Option<(PathBuf, Option<Address>)>,
+    /// Run in order when the item is picked.
+    pub action: Vec<EditorCommand>,
+}
+
+/// What a source gets to work from.
+pub struct Context {
+    pub cwd: PathBuf,
+    pub file: Option<PathBuf>,
+}
+
+/// Hands a batch of items to the matcher; false once the picker is closed
+/// and the source should stop.
+pub type Sink<'a> = &'a mut dyn FnMut(Vec<Item>) -> bool;
+
+/// Where a picker's items come from. Plain data like `lineinput::Spec`:
+/// a new picker is a new source; filtering, the preview and the overlay
+/// are shared. Sources run on their own thread and may send their items
+/// in batches as they find them.
+pub struct Source {
+    pub name: &'static str,
+    pub items: fn(&Context, Sink),
+}
+
+/// Sources for `:Pick {name}`.
+pub const SOURCES: &[Source] = &[
+    Source {
+        name: "files",
+        items: files,
+    },
+    Source {
+        name: "commands",
+        items: commands,
+    },
+    Source {
+        name: "symbols",
+        items: symbols,
+    },
+    Source {
+        name: "themes",
+        items: themes,
+    },
+];
+
+pub fn source(name: &str) -> Option<&'static Source> {
+    SOURCES.iter().find(|s| s.name == name)
+}
+
+/// Walking stops here so a huge tree can't run on forever.
+const MAX_FILES: usize = 200_000;
+/// Files sent to the matcher at a time.
+const BATCH: usize = 1024;
+
+/// Files under the working directory, skipping hidden ones and `target`,
+/// shallowest first.
+fn files(ctx: &Context, send: Sink) {
+    let mut dirs = VecDeque::from([ctx.cwd.clone()]);
+    let mut batch = Vec::new();
+    let mut found = 0;
+    while let Some(dir) = dirs.pop_front() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|e| {
+                let name = e.file_name();
+                !name.to_string_lossy().starts_with('.') && name != "target"
+            })
+            .map(|e| e.path())
+            .collect();
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                dirs.push_back(path);
+                continue;
+            }
+            let label = path
+                .strip_prefix(&ctx.cwd)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            batch.push(Item {
+                label,
+                preview: Some((path.clone(), None)),
+                action: vec![EditorCommand::Edit(Some(path))],
+            });
+            found += 1;
+            if found == MAX_FILES {
+                send(batch);
+                return;
+            }
+            if batch.len() == BATCH && !send(std::mem::take(&mut batch)) {
+                return;
+            }
+        }
+    }
+    send(batch);
+}
+
+/// Ex commands; picking one starts it on the command line.
+fn commands(_: &Context, send: Sink) {
+    let items = ex::NAMES
+        .iter()
+        .map(|name| {
+            let typed = format!("{name} ");
+            let mut action = vec![EditorCommand::EnterCommandMode];
+            action.extend(typed.chars().map(EditorCommand::CmdlineInput));
+            Item {
+                label: name.to_string(),
+                preview: None,
+                action,
+            }
+        })
+        .collect();
+    send(items);
+}
+
+/// Everything in the tags files.
+fn symbols(ctx: &Context, send: Sink) {
+    let items = tags::all(ctx.file.as_deref())
+        .into_iter()
+        .map(|tag| {
+            let kind = tag.kind.as_deref().unwrap_or(" ");
+            let label = format!("{kind} {}  {}", tag.name, tag.file.display());
+            Item {
+                label,
+                preview: Some((tag.file.clone(), Some(tag.address.clone()))),
+                action: vec![EditorCommand::GotoTag(tag)],
+            }
+        })
+        .collect();
+    send(items);
+}
+
+/// `*.toml` themes in `theme::dir()`.
+fn themes(_: &Context, send: Sink) {
+    send(theme_files());
+}
+
+fn theme_files() -> Vec<Item> {
+    let Some(dir) = theme::dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| Item {
+            label: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            preview: Some((path.clone(), None)),
+            action: vec![EditorCommand::LoadTheme(path)],
+        })
+        .collect()
+}
+
+/// Part of a file shown beside the list.
+#[derive(Debug, Default)]
+pub struct Preview {
+    pub lines: Vec<String>,
+    /// Index in `lines` of the line the item points at.
+    pub focus: Option<usize>,
+    pub syntax: Option<&'static Syntax>,
+}
+
+/// Lines read for a preview without a line to centre on.
+const PREVIEW_LINES: usize = 200;
+/// Lines kept either side of the line a preview centres on.
+const PREVIEW_CONTEXT: usize = 100;
+
+/// Read the preview of `path` around where `at` points, or its top when
+/// `at` is None or points nowhere. The file is read only as far as needed.
+/// None for unreadable and non-UTF-8 files.
+fn read_preview(path: &Path, at: Option<&Address>) -> Option<Preview> {
+    let file = File::open(path).ok()?;
+    // The top, shown when `at` isn't found, and the lines just read.
+    let mut head = Vec::new();
+    let mut window = VecDeque::new();
+    let mut focus = None;
+    for (row, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.ok()?.trim_end_matches('\r').to_owned();
+        if focus.is_none() && at.is_some_and(|a| tags::points_at(a, row, &line)) {
+            focus = Some(window.len());
+        }
+        if head.len() < PREVIEW_LINES {
+            head.push(line.clone());
+        }
+        window.push_back(line);
+        match focus {
+            Some(f) if window.len() > f + PREVIEW_CONTEXT => break,
+            None if window.len() > PREVIEW_CONTEXT => {
+                window.pop_front();
+            }
+            _ => {}
+        }
+        if at.is_none() && head.len() == PREVIEW_LINES {
+            break;
+        }
+    }
+    let syntax = syntax::for_path(path);
+    Some(match focus {
+        Some(_) => Preview {
+            lines: window.into(),
+            focus,
+            syntax,
+        },
+        None => Preview {
+            lines: head,
+            focus: None,
+            syntax,
+        },
+    })
+}
+
+/// An open picker: the query typed so far, the matches for it and the
+/// selection. Matching runs on the matcher's threads; `update` takes in
+/// what it has found.
+#[derive(Debug, Clone)]
+pub struct Picker {
+    title: &'static str,
+    matcher: Rc<Matcher>,
+    query: String,
+    /// The best matches found so far, best first, and the query they are for.
+    matches: Rc<Vec<Match>>,
+    shown: String,
+    matched: usize,
+    total: usize,
+    /// Matches for the query aren't all in yet.
+    busy: bool,
+    selected: usize,
+    preview: Rc<Preview>,
+    /// The selection moved since the preview was read. Reading waits for
+    /// the keys to settle, so skimming the list never reads a file.
+    preview_stale: bool,
+}
+
+impl Picker {
+    pub fn new(source: &Source, ctx: Context) -> Self {
+        let items = source.items;
+        let matcher = Matcher::spawn(move |send| items(&ctx, send));
+        matcher.query("");
+        Self {
+            title: source.name,
+            matcher: Rc::new(matcher),
+            query: String::new(),
+            matches: Rc::default(),
+            shown: String::new(),
+            matched: 0,
+            total: 0,
+            busy: true,
+            selected: 0,
+            preview: Rc::default(),
+            preview_stale: false,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        self.title
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn matches(&self) -> &[Match] {
+        &self.matches
+    }
+
+    /// Items matching the query; more than `matches` holds past `LIMIT`.
+    pub fn matched(&self) -> usize {
+        self.matched
+    }
+
+    /// Items the source has sent so far.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn busy(&self) -> bool {
+        self.busy
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn preview(&self) -> &Preview {
+        &self.preview
+    }
+
+    pub fn preview_stale(&self) -> bool {
+        self.preview_stale
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.matcher.query(&self.query);
+        self.busy = true;
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.matcher.query(&self.query);
+        self.busy = true;
+    }
+
+    /// Take in the matcher's latest results, if it has any.
+    pub fn update(&mut self) {
+        if let Some(results) = self.matcher.latest() {
+            self.show(results);
+        }
+    }
+
+    /// Block until the matches for the query are all in.
+    pub fn wait(&mut self) {
+        while self.busy {
+            match self.matcher.next() {
+                Some(results) => self.show(results),
+                None => break,
+            }
+        }
+    }
+
+    fn show(&mut self, results: Results) {
+        // A new query starts from the top; more items keep the selection.
+        if results.query != self.shown || self.selected >= results.matches.len() {
+            self.selected = 0;
+        }
+        self.busy = !(results.query == self.query && results.done);
+        self.shown = results.query;
+        self.matches = Rc::new(results.matches);
+        self.matched = results.matched;
+        self.total = results.total;
+        self.preview_stale = true;
+    }
+
+    /// Move the selection, wrapping at either end.
+    pub fn step(&mut self, by: isize) {
+        let n = self.matches.len() as isize;
+        if n > 0 {
+            self.selected = (self.selected as isize + by).rem_euclid(n) as usize;
+            self.preview_stale = true;
+        }
+    }
+
+    /// What picking the selected item does.
+    pub fn accept(&self) -> Option<Vec<EditorCommand>> {
+        let m = self.matches.get(self.selected)?;
+        Some(m.item.action.clone())
+    }
+
+    /// Read the selected item's preview.
+    pub fn load_preview(&mut self) {
+        let preview = self
+            .matches
+            .get(self.selected)
+            .and_then(|m| m.item.preview.as_ref())
+            .and_then(|(path, at)| read_preview(path, at.as_ref()));
+        self.preview = Rc::new(preview.unwrap_or_default());
+        self.preview_stale = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(_: &Context, send: Sink) {
+        let items = ["one", "two", "three"].map(|label| Item {
+            label: label.into(),
+            preview: None,
+            action: vec![EditorCommand::ShowMessages],
+        });
+        send(items.into());
+    }
+
+    #[test]
+    fn picker_filters_and_selects() {
+        let source = Source {
+            name: "numbers",
+            items: numbers,
+        };
+        let ctx = Context {
+            cwd: PathBuf::from("."),
+            file: None,
+        };
+        let mut picker = Picker::new(&source, ctx);
+        picker.wait();
+        assert_eq!(picker.matches().len(), 3);
+        assert_eq!(picker.total(), 3);
+        picker.step(-1);
+        assert_eq!(picker.selected(), 2);
+        picker.push('t');
+        assert!(picker.busy());
+        picker.wait();
+        let labels: Vec<&str> = picker
+            .matches()
+            .iter()
+            .map(|m| m.item.label.as_str())
+            .collect();
+        assert_eq!(labels, ["two", "three"]);
+        assert_eq!(picker.selected(), 0);
+        picker.push('x');
+        picker.wait();
+        assert_eq!(picker.accept(), None);
+        picker.backspace();
+        picker.wait();
+        assert_eq!(picker.accept(), Some(vec![EditorCommand::ShowMessages]));
+        // The preview waits to be read
+        assert!(picker.preview_stale());
+        picker.load_preview();
+        assert!(!picker.preview_stale());
+    }
+
+    #[test]
+    fn previews_centre_on_the_item() {
+        let path = std::env::temp_dir().join(format!("mters-preview-{}.rs", std::process::id()));
+        let text: String = (0..500).map(|i| format!("line {i}\r\n")).collect();
+        std::fs::write(&path, text).unwrap();
+
+        let top = read_preview(&path, None).unwrap();
+        assert_eq!(top.lines.len(), PREVIEW_LINES);
+        assert_eq!(top.lines[0], "line 0");
+        assert_eq!(top.focus, None);
+        assert!(top.syntax.is_some());
+
+        let at = Address::Pattern {
+            text: "line 300".into(),
+            start: true,
+            end: true,
+        };
+        let around = read_preview(&path, Some(&at)).unwrap();
+        let focus = around.focus.unwrap();
+        assert_eq!(focus, PREVIEW_CONTEXT);
+        assert_eq!(around.lines[focus], "line 300");
+        assert_eq!(around.lines.len(), 2 * PREVIEW_CONTEXT + 1);
+
+        // A line it never finds shows the top
+        let missing = read_preview(&path, Some(&Address::Line(9999))).unwrap();
+        assert_eq!(missing.lines[0], "line 0");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
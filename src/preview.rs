@@ -0,0 +1,180 @@
+use ropey::{Rope, RopeBuilder};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Files larger than this open as a read-only preview.
+pub const THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const CHUNK: usize = 1024 * 1024;
+
+/// Byte offsets of the line starts found so far.
+struct LineIndex {
+    starts: Vec<u64>,
+    /// Bytes scanned for line breaks.
+    scanned: u64,
+}
+
+/// A huge file shown without loading it: lines are read from disk as they
+/// scroll into view, and the whole rope is only built once editing starts.
+/// Clones share the index.
+#[derive(Clone)]
+pub struct Preview {
+    path: PathBuf,
+    len: u64,
+    index: Arc<Mutex<LineIndex>>,
+}
+
+impl Preview {
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let len = std::fs::metadata(&path)?.len();
+        Ok(Self {
+            path,
+            len,
+            index: Arc::new(Mutex::new(LineIndex {
+                starts: vec![0],
+                scanned: 0,
+            })),
+        })
+    }
+
+    /// Scan until line `row` has a known start (or EOF); returns how many
+    /// line starts are known.
+    fn index_to(&self, row: usize) -> io::Result<usize> {
+        let mut index = self.index.lock().unwrap();
+        let want = row.saturating_add(1);
+        if index.starts.len() > want || index.scanned == self.len {
+            return Ok(index.starts.len());
+        }
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(index.scanned))?;
+        let mut buf = vec![0; CHUNK];
+        while index.starts.len() <= want && index.scanned < self.len {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let base = index.scanned;
+            for (i, _) in buf[..n].iter().enumerate().filter(|(_, b)| **b == b'\n') {
+                index.starts.push(base + i as u64 + 1);
+            }
+            index.scanned += n as u64;
+        }
+        Ok(index.starts.len())
+    }
+
+    /// `row` clamped to the file's last line, scanning only as far as needed.
+    pub fn clamp_row(&self, row: usize) -> usize {
+        let known = self.index_to(row).unwrap_or(1);
+        let index = self.index.lock().unwrap();
+        // A start at EOF is the empty line after a final '\n'.
+        let last = known - 1 - usize::from(known > 1 && index.starts[known - 1] == self.len);
+        row.min(last)
+    }
+
+    /// Up to `count` lines from `top`, without their line breaks.
+    pub fn lines(&self, top: usize, count: usize) -> io::Result<Vec<String>> {
+        let bottom = self.clamp_row(top.saturating_add(count.max(1) - 1));
+        if top > bottom {
+            return Ok(Vec::new());
+        }
+        let starts = self.index.lock().unwrap().starts.clone();
+        let from = starts[top];
+        let to = starts.get(bottom + 1).copied().unwrap_or(self.len);
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(from))?;
+        let mut bytes = vec![0; (to - from) as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes)
+            .split_inclusive('\n')
+            .map(|l| l.trim_end_matches('\n').trim_end_matches('\r').to_owned())
+            .collect())
+    }
+
+    /// Read the whole file into a rope, reporting (bytes read, total) after
+    /// each chunk.
+    pub fn materialize(&self, mut progress: impl FnMut(u64, u64)) -> io::Result<Rope> {
+        let mut file = File::open(&self.path)?;
+        let mut builder = RopeBuilder::new();
+        let mut buf = vec![0; CHUNK];
+        // Bytes of a UTF-8 sequence split across two chunks.
+        let mut carry = 0;
+        let mut done = 0;
+        loop {
+            let n = file.read(&mut buf[carry..])?;
+            if n == 0 {
+                break;
+            }
+            let filled = carry + n;
+            let valid = match std::str::from_utf8(&buf[..filled]) {
+                Ok(s) => s.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "stream did not contain valid UTF-8",
+                    ))
+                }
+            };
+            builder.append(std::str::from_utf8(&buf[..valid]).expect("checked above"));
+            buf.copy_within(valid..filled, 0);
+            carry = filled - valid;
+            done += n as u64;
+            progress(done, self.len);
+        }
+        if carry > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            ));
+        }
+        Ok(builder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("mters-preview-{}-{name}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_only_the_lines_asked_for() {
+        let content: String = (0..1000).map(|i| format!("line {i}\r\n")).collect();
+        let path = temp_file("lines", &content);
+        let preview = Preview::open(path.clone()).unwrap();
+
+        assert_eq!(preview.lines(10, 2).unwrap(), ["line 10", "line 11"]);
+        assert_eq!(preview.lines(998, 5).unwrap(), ["line 998", "line 999"]);
+        assert_eq!(preview.clamp_row(usize::MAX), 999);
+        assert!(preview.lines(1000, 5).unwrap().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn materializes_with_progress() {
+        // A two-byte character straddles the chunk boundary
+        let content = format!("a{}", "é".repeat(CHUNK));
+        let path = temp_file("rope", &content);
+        let preview = Preview::open(path.clone()).unwrap();
+
+        let mut reports = Vec::new();
+        let rope = preview
+            .materialize(|done, total| reports.push((done, total)))
+            .unwrap();
+        assert_eq!(rope.to_string(), content);
+        let len = content.len() as u64;
+        assert_eq!(reports.last(), Some(&(len, len)));
+        assert!(reports.len() > 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
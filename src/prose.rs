@@ -0,0 +1,106 @@
+use crate::options::Options;
+
+/// What `prose` made of a typed character: `text` in place of the
+/// `replaced` chars before the cursor, and what they and the character
+/// were as typed, which a backspace straight after puts back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    pub replaced: usize,
+    pub text: String,
+    pub literal: String,
+}
+
+/// The transform for `c` typed after `before`, the line up to the cursor,
+/// if one of the enabled ones applies. `paragraph` says the line is the
+/// first of its paragraph, where a sentence starts at the first letter.
+pub fn transform(c: char, before: &str, paragraph: bool, options: &Options) -> Option<Transform> {
+    let last = before.chars().next_back();
+    let plain = |text: &str| Transform {
+        replaced: 0,
+        text: text.to_owned(),
+        literal: c.to_string(),
+    };
+    match c {
+        '"' | '\'' if options.smartquotes => {
+            // Open after a space, a bracket or nothing; close (or make an
+            // apostrophe) after anything else.
+            let opens = last.is_none_or(|l| l.is_whitespace() || "([{<“‘—–".contains(l));
+            let quote = match (c, opens) {
+                ('"', true) => "“",
+                ('"', false) => "”",
+                (_, true) => "‘",
+                (_, false) => "’",
+            };
+            Some(plain(quote))
+        }
+        '-' if options.smartdashes => match last? {
+            '-' => Some(Transform {
+                replaced: 1,
+                text: "–".into(),
+                literal: "--".into(),
+            }),
+            '–' => Some(Transform {
+                replaced: 1,
+                text: "—".into(),
+                literal: "---".into(),
+            }),
+            _ => None,
+        },
+        _ if options.autocapitalize && c.is_lowercase() && sentence_starts(before, paragraph) => {
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(u), None) => Some(plain(&u.to_string())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether a letter typed after `before` starts a sentence: it's the first
+/// on a paragraph's first line, or it follows `.`, `!` or `?` and a space.
+fn sentence_starts(before: &str, paragraph: bool) -> bool {
+    let trimmed = before.trim_end();
+    if trimmed.is_empty() {
+        return paragraph;
+    }
+    trimmed.len() < before.len()
+        && trimmed
+            .trim_end_matches(['"', '\'', ')', '”', '’'])
+            .ends_with(['.', '!', '?'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn made(c: char, before: &str, paragraph: bool) -> Option<String> {
+        transform(c, before, paragraph, &Options::default()).map(|t| t.text)
+    }
+
+    #[test]
+    fn quotes_dashes_and_capitals() {
+        assert_eq!(made('"', "say ", false).as_deref(), Some("“"));
+        assert_eq!(made('"', "say “hi", false).as_deref(), Some("”"));
+        assert_eq!(made('\'', "don", false).as_deref(), Some("’"));
+        assert_eq!(made('\'', "(", false).as_deref(), Some("‘"));
+        assert_eq!(made('-', "a -", false).as_deref(), Some("–"));
+        assert_eq!(made('-', "a –", false).as_deref(), Some("—"));
+        assert_eq!(made('-', "a ", false), None);
+        assert_eq!(made('w', "", true).as_deref(), Some("W"));
+        assert_eq!(made('w', "", false), None);
+        assert_eq!(made('w', "Done. ", false).as_deref(), Some("W"));
+        assert_eq!(made('w', "Done?” ", false).as_deref(), Some("W"));
+        assert_eq!(made('w', "Done.", false), None);
+        assert_eq!(made('w', "and ", false), None);
+        assert_eq!(made('W', "", true), None);
+
+        let options = Options {
+            smartdashes: false,
+            ..Options::default()
+        };
+        assert_eq!(transform('-', "-", false, &options), None);
+        let em = transform('-', "–", false, &Options::default()).unwrap();
+        assert_eq!((em.replaced, em.literal.as_str()), (1, "---"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+/// A place in a file a command's output pointed at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub file: PathBuf,
+    /// 1-based, as the command wrote them.
+    pub line: usize,
+    pub col: Option<usize>,
+    pub text: String,
+}
+
+/// The quickfix list: the places in the last `:grep`, `:make` or
+/// `:cbuffer`, and the one gone to last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickFix {
+    /// The command that made it, shown over the list.
+    pub title: String,
+    /// Where it ran; files are shown relative to it.
+    pub dir: PathBuf,
+    pub entries: Vec<Entry>,
+    pub current: usize,
+}
+
+impl QuickFix {
+    /// The places in `output` of a command run in `dir`.
+    pub fn new(title: String, dir: PathBuf, output: &str) -> Self {
+        Self {
+            title,
+            entries: parse(output, &dir),
+            dir,
+            current: 0,
+        }
+    }
+
+    /// `entry`'s file as the list shows it.
+    pub fn name<'a>(&self, entry: &'a Entry) -> &'a Path {
+        entry.file.strip_prefix(&self.dir).unwrap_or(&entry.file)
+    }
+
+    /// Move `by` entries (`:cnext`, `:cprevious`); an error past either
+    /// end, where the list stays put.
+    pub fn step(&mut self, by: isize) -> Result<&Entry, String> {
+        let to = self
+            .current
+            .checked_add_signed(by)
+            .filter(|&i| i < self.entries.len())
+            .ok_or("E553: No more items")?;
+        self.current = to;
+        Ok(&self.entries[to])
+    }
+
+    /// Go to entry `n` (1-based, `:cc N`), or the current one for None.
+    pub fn select(&mut self, n: Option<usize>) -> Result<&Entry, String> {
+        let to = match n {
+            Some(n) => n.checked_sub(1).ok_or("E16: Invalid range")?,
+            None => self.current,
+        };
+        if to >= self.entries.len() {
+            return Err("E42: No Errors".into());
+        }
+        self.current = to;
+        Ok(&self.entries[to])
+    }
+
+    /// `(2 of 5): the text`, as Vim shows an entry gone to.
+    pub fn describe(&self) -> String {
+        let entry = &self.entries[self.current];
+        let total = self.entries.len();
+        format!("({} of {total}): {}", self.current + 1, entry.text)
+    }
+}
+
+/// The places in `output`, files taken relative to `dir`. Lines read are
+/// `file:line:col: text` and `file:line: text` (grep -n, gcc, cargo's
+/// `--message-format=short`), and rustc's `--> file:line:col` under the
+/// line with the message. Other lines are skipped.
+pub fn parse(output: &str, dir: &Path) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut message = "";
+    for line in output.lines() {
+        if let Some(at) = line.trim_start().strip_prefix("--> ") {
+            if let Some(mut entry) = parse_line(at, dir) {
+                entry.text = message.to_owned();
+                entries.push(entry);
+            }
+            continue;
+        }
+        match parse_line(line, dir) {
+            Some(entry) => entries.push(entry),
+            None if line.starts_with("error") || line.starts_with("warning") => message = line,
+            None => {}
+        }
+    }
+    entries
+}
+
+fn parse_line(line: &str, dir: &Path) -> Option<Entry> {
+    // `C:\src\a.c:3: ...` keeps its drive
+    let drive = match line.as_bytes() {
+        [d, b':', b'\\' | b'/', ..] if d.is_ascii_alphabetic() => 2,
+        _ => 0,
+    };
+    let (file, rest) = line[drive..].split_once(':')?;
+    let file = &line[..drive + file.len()];
+    let (number, rest) = rest.split_once(':').unwrap_or((rest, ""));
+    let number = number.trim().parse().ok().filter(|&n| n > 0)?;
+    if file.is_empty() || file.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (col, text) = match rest.split_once(':') {
+        Some((col, text)) => match col.parse() {
+            Ok(col) => (Some(col), text),
+            Err(_) => (None, rest),
+        },
+        None => match rest.trim().parse() {
+            Ok(col) => (Some(col), ""),
+            Err(_) => (None, rest),
+        },
+    };
+    Some(Entry {
+        file: dir.join(file),
+        line: number,
+        col,
+        text: text.trim().to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_grep_and_compiler_output() {
+        let dir = Path::new("/w");
+        let grep = "src/a.rs:3:fn main() {\nBinary file x matches\nb.txt:10:x: y\n";
+        let entries = parse(grep, dir);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].file, Path::new("/w/src/a.rs"));
+        assert_eq!((entries[0].line, entries[0].col), (3, None));
+        assert_eq!(entries[0].text, "fn main() {");
+        assert_eq!(entries[1].text, "x: y");
+
+        let short = "src/lib.rs:4:9: error[E0425]: cannot find value `x`\n";
+        let entry = &parse(short, dir)[0];
+        assert_eq!((entry.line, entry.col), (4, Some(9)));
+        assert_eq!(entry.text, "error[E0425]: cannot find value `x`");
+
+        let rustc =
+            "warning: unused variable: `y`\n  --> src/main.rs:2:9\n   |\n2 |     let y = 1;\n";
+        let entries = parse(rustc, dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file, Path::new("/w/src/main.rs"));
+        assert_eq!((entries[0].line, entries[0].col), (2, Some(9)));
+        assert_eq!(entries[0].text, "warning: unused variable: `y`");
+
+        let mut list = QuickFix::new("grep".into(), dir.into(), grep);
+        assert_eq!(list.name(&list.entries[1]), Path::new("b.txt"));
+        assert_eq!(list.step(1).unwrap().line, 10);
+        assert_eq!(list.describe(), "(2 of 2): x: y");
+        assert_eq!(list.step(1), Err("E553: No more items".into()));
+        assert_eq!(list.select(Some(1)).unwrap().line, 3);
+        assert_eq!(list.select(Some(3)), Err("E42: No Errors".into()));
+    }
+}
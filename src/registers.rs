@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+/// Deletes and yanks the kill ring remembers.
+pub const KILL_RING: usize = 30;
+
+/// Text captured by a delete or yank.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Register {
+    pub text: String,
+    /// Linewise contents always end in a line break and are put as whole lines.
+    pub linewise: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Registers {
+    /// The unnamed register `"`, written by every delete and yank.
+    pub unnamed: Option<Register>,
+    /// `"0`: the last yank.
+    yank: Option<Register>,
+    /// `"1`–`"9`: deletes of a line or more, newest first.
+    numbered: VecDeque<Register>,
+    /// `"-`: the last delete within a line.
+    small_delete: Option<Register>,
+    /// Every delete and yank, whatever its size, newest first: what
+    /// Alt-P / Alt-N walk after a put.
+    ring: VecDeque<Register>,
+}
+
+impl Registers {
+    pub fn record_yank(&mut self, text: String, linewise: bool) {
+        let reg = Register { text, linewise };
+        self.yank = Some(reg.clone());
+        self.push_ring(reg.clone());
+        self.unnamed = Some(reg);
+    }
+
+    /// Deletes (and changes) of whole lines or across lines shift through
+    /// `"1`–`"9`; smaller ones only replace `"-`.
+    pub fn record_delete(&mut self, text: String, linewise: bool) {
+        let reg = Register { text, linewise };
+        if linewise || reg.text.contains('\n') {
+            self.numbered.push_front(reg.clone());
+            self.numbered.truncate(9);
+        } else {
+            self.small_delete = Some(reg.clone());
+        }
+        self.push_ring(reg.clone());
+        self.unnamed = Some(reg);
+    }
+
+    fn push_ring(&mut self, reg: Register) {
+        self.ring.push_front(reg);
+        self.ring.truncate(KILL_RING);
+    }
+
+    /// Kill ring entry `i`, 0 being the newest.
+    pub fn ring(&self, i: usize) -> Option<&Register> {
+        self.ring.get(i)
+    }
+
+    /// Where `reg` is in the kill ring, if it is.
+    pub fn ring_position(&self, reg: &Register) -> Option<usize> {
+        self.ring.iter().position(|r| r == reg)
+    }
+
+    /// Register by name: `"`, `0`–`9` or `-`.
+    pub fn get(&self, name: char) -> Option<&Register> {
+        match name {
+            '"' => self.unnamed.as_ref(),
+            '0' => self.yank.as_ref(),
+            '1'..='9' => self.numbered.get(name as usize - '1' as usize),
+            '-' => self.small_delete.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn is_valid_name(name: char) -> bool {
+        matches!(name, '"' | '0'..='9' | '-')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(regs: &Registers, name: char) -> Option<&str> {
+        regs.get(name).map(|r| r.text.as_str())
+    }
+
+    #[test]
+    fn yanks_and_deletes_go_to_their_registers() {
+        let mut regs = Registers::default();
+        regs.record_yank("kept".into(), false);
+        regs.record_delete("a\n".into(), true);
+        regs.record_delete("word".into(), false);
+        regs.record_delete("b\nc".into(), false);
+
+        assert_eq!(text(&regs, '"'), Some("b\nc"));
+        assert_eq!(text(&regs, '0'), Some("kept"));
+        assert_eq!(text(&regs, '1'), Some("b\nc"));
+        assert_eq!(text(&regs, '2'), Some("a\n"));
+        assert_eq!(text(&regs, '-'), Some("word"));
+        assert_eq!(text(&regs, '3'), None);
+    }
+
+    #[test]
+    fn numbered_registers_keep_nine_deletes() {
+        let mut regs = Registers::default();
+        for i in 0..12 {
+            regs.record_delete(format!("{i}\n"), true);
+        }
+        assert_eq!(text(&regs, '1'), Some("11\n"));
+        assert_eq!(text(&regs, '9'), Some("3\n"));
+    }
+
+    #[test]
+    fn the_kill_ring_keeps_small_deletes_and_yanks_too() {
+        let mut regs = Registers::default();
+        regs.record_delete("word".into(), false);
+        regs.record_yank("kept".into(), false);
+        regs.record_delete("a\n".into(), true);
+        let ring: Vec<_> = (0..4)
+            .map(|i| regs.ring(i).map(|r| r.text.as_str()))
+            .collect();
+        assert_eq!(ring, [Some("a\n"), Some("kept"), Some("word"), None]);
+        assert_eq!(regs.ring_position(regs.get('0').unwrap()), Some(1));
+
+        for i in 0..KILL_RING {
+            regs.record_yank(i.to_string(), false);
+        }
+        assert_eq!(regs.ring_position(regs.get('-').unwrap()), None);
+    }
+}
@@ -1,20 +1,138 @@
-use crate::editor::Editor;
-use crossterm::terminal::{Clear, ClearType};
-use crossterm::{cursor, execute};
+use crate::buffer::{Buffer, CursorShape, Style};
+use crate::color::{self, Color, ColorSupport};
+use crate::core::Frame;
+use crossterm::style::{
+    self as ct, Attribute, Colors, Print, SetAttribute, SetAttributes, SetColors,
+};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{cursor, execute, queue};
 use std::io::{Result, Stdout, Write};
 
-pub fn render(stdout: &mut Stdout, editor: &Editor) -> Result<()> {
-    execute!(stdout, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+/// Draws frames to the terminal, only the cells that changed since the
+/// last.
+pub struct Renderer {
+    colors: ColorSupport,
+}
+
+impl Renderer {
+    pub fn new(colors: ColorSupport) -> Self {
+        Self { colors }
+    }
+
+    /// Write what changed since the frame's last one.
+    pub fn render(&self, stdout: &mut Stdout, frame: &Frame) -> Result<()> {
+        draw(stdout, frame.cells, frame.last, self.colors)
+    }
 
-    for (row, line) in editor.text.lines().enumerate() {
-        write!(stdout, "{}", line)?; // prints text + '\n' if present
-        execute!(stdout, cursor::MoveTo(0, (row + 1) as u16))?; // reset x to 0 for next row
+    /// Progress line shown while a previewed file is loaded in full, drawn
+    /// over the frame: the core draws the next one whole.
+    pub fn render_progress(&self, stdout: &mut Stdout, done: u64, total: u64) -> Result<()> {
+        let (_, rows) = terminal::size()?;
+        execute!(
+            stdout,
+            cursor::MoveTo(0, rows.saturating_sub(1)),
+            Clear(ClearType::CurrentLine)
+        )?;
+        let percent = done * 100 / total.max(1);
+        write!(
+            stdout,
+            "Loading {percent}% ({} / {} MiB)",
+            done >> 20,
+            total >> 20
+        )?;
+        stdout.flush()
     }
+}
 
-    execute!(
+fn set_style(stdout: &mut Stdout, style: Style, colors: ColorSupport) -> Result<()> {
+    let mut attrs = ct::Attributes::default();
+    for attr in style.attrs.iter() {
+        attrs.set(match attr {
+            color::Attribute::Bold => Attribute::Bold,
+            color::Attribute::Italic => Attribute::Italic,
+            color::Attribute::Underlined => Attribute::Underlined,
+            color::Attribute::Reverse => Attribute::Reverse,
+        });
+    }
+    queue!(
         stdout,
-        cursor::MoveTo(editor.cursor_gcol as u16, editor.cursor_row as u16),
-    )?;
-    stdout.flush()?;
-    Ok(())
+        SetAttribute(Attribute::Reset),
+        SetColors(Colors {
+            foreground: colors.adapt(style.fg).map(crossterm_color),
+            background: colors.adapt(style.bg).map(crossterm_color),
+        }),
+        SetAttributes(attrs)
+    )
+}
+
+fn crossterm_color(color: Color) -> ct::Color {
+    match color {
+        Color::Reset => ct::Color::Reset,
+        Color::Black => ct::Color::Black,
+        Color::DarkGrey => ct::Color::DarkGrey,
+        Color::Red => ct::Color::Red,
+        Color::DarkRed => ct::Color::DarkRed,
+        Color::Green => ct::Color::Green,
+        Color::DarkGreen => ct::Color::DarkGreen,
+        Color::Yellow => ct::Color::Yellow,
+        Color::DarkYellow => ct::Color::DarkYellow,
+        Color::Blue => ct::Color::Blue,
+        Color::DarkBlue => ct::Color::DarkBlue,
+        Color::Magenta => ct::Color::Magenta,
+        Color::DarkMagenta => ct::Color::DarkMagenta,
+        Color::Cyan => ct::Color::Cyan,
+        Color::DarkCyan => ct::Color::DarkCyan,
+        Color::White => ct::Color::White,
+        Color::Grey => ct::Color::Grey,
+        Color::Rgb { r, g, b } => ct::Color::Rgb { r, g, b },
+        Color::AnsiValue(n) => ct::Color::AnsiValue(n),
+    }
+}
+
+fn draw(
+    stdout: &mut Stdout,
+    buf: &Buffer,
+    prev: Option<&Buffer>,
+    colors: ColorSupport,
+) -> Result<()> {
+    queue!(stdout, cursor::Hide)?;
+    let mut style = None;
+    for (y, xs) in buf.damage(prev) {
+        queue!(stdout, cursor::MoveTo(xs.start as u16, y as u16))?;
+        // Trailing blanks are cleared in one go rather than printed.
+        let blank_from = buf.blank_from(y).max(xs.start);
+        for x in xs.start..xs.end.min(blank_from) {
+            let cell = buf.get(x, y).expect("damage is in bounds");
+            if cell.symbol.is_empty() {
+                // Right half of a wide grapheme: the terminal moved past it.
+                continue;
+            }
+            if style != Some(cell.style) {
+                set_style(stdout, cell.style, colors)?;
+                style = Some(cell.style);
+            }
+            queue!(stdout, Print(&cell.symbol))?;
+        }
+        if xs.end > blank_from {
+            if style != Some(Style::default()) {
+                set_style(stdout, Style::default(), colors)?;
+                style = Some(Style::default());
+            }
+            queue!(stdout, Clear(ClearType::UntilNewLine))?;
+        }
+    }
+    if style.is_some() {
+        queue!(stdout, SetAttribute(Attribute::Reset))?;
+    }
+    if prev.is_none_or(|p| p.cursor_shape != buf.cursor_shape) {
+        let shape = match buf.cursor_shape {
+            CursorShape::Block => cursor::SetCursorStyle::SteadyBlock,
+            CursorShape::Bar => cursor::SetCursorStyle::SteadyBar,
+            CursorShape::Underline => cursor::SetCursorStyle::SteadyUnderScore,
+        };
+        queue!(stdout, shape)?;
+    }
+    let (x, y) = buf.cursor.unwrap_or((0, buf.height.saturating_sub(1)));
+    queue!(stdout, cursor::MoveTo(x as u16, y as u16), cursor::Show)?;
+    stdout.flush()
 }
@@ -0,0 +1,82 @@
+use crate::path;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// `cmd` run by the system shell with `input` on its standard input.
+/// Returns what it wrote to standard output, or, when it fails, an
+/// error with the first line it wrote to standard error.
+pub fn filter(cmd: &str, input: &str) -> Result<String, String> {
+    let (shell, flag) = shell();
+    let mut child = Command::new(shell)
+        .args([flag, cmd])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("E282: Can't run {shell}: {e}"))?;
+    // Fed from a thread so a command writing before it has read it all
+    // can't block on a full pipe.
+    let mut stdin = child.stdin.take().expect("piped");
+    let input = input.to_owned();
+    let feeder = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("E282: {cmd}: {e}"))?;
+    // A command that stops reading early closes the pipe: not an error.
+    let _ = feeder.join();
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let how = match output.status.code() {
+        Some(code) => format!("shell returned {code}"),
+        None => format!("{cmd}: killed"),
+    };
+    Err(match stderr.lines().next() {
+        Some(why) => format!("{how}: {why}"),
+        None => how,
+    })
+}
+
+/// `cmd` run by the system shell with nothing on its standard input.
+/// Returns all it wrote to standard output and error, however it exits:
+/// a compiler's errors and grep finding nothing aren't failures here.
+pub fn run(cmd: &str) -> Result<String, String> {
+    let (shell, flag) = shell();
+    // Errors in with the rest, in the order they came
+    let cmd = if path::WINDOWS {
+        format!("{cmd} 2>&1")
+    } else {
+        format!("exec 2>&1; {cmd}")
+    };
+    let output = Command::new(shell)
+        .args([flag, &cmd])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("E282: Can't run {shell}: {e}"))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn shell() -> (&'static str, &'static str) {
+    if path::WINDOWS {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_through_the_shell() {
+        assert_eq!(filter("tr a-z A-Z", "jq .\n").unwrap(), "JQ .\n");
+        assert_eq!(filter("head -c 1", &"x".repeat(1 << 20)).unwrap(), "x");
+        assert_eq!(
+            filter("echo oops >&2; exit 3", "").unwrap_err(),
+            "shell returned 3: oops"
+        );
+        assert_eq!(run("echo out; echo err >&2; exit 1").unwrap(), "out\nerr\n");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::theme::Group;
+use std::ops::Range;
+use std::path::Path;
+
+/// Enough of a language to colour it a line at a time: keywords, strings
+/// and line comments. Block comments and strings running over several
+/// lines aren't followed.
+#[derive(Debug)]
+pub struct Syntax {
+    extensions: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comment: &'static str,
+    quotes: &'static [char],
+}
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        extensions: &["rs"],
+        keywords: &[
+            "as", "break", "const", "continue", "crate", "else", "enum", "fn", "for", "if", "impl",
+            "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+            "Self", "static", "struct", "super", "trait", "type", "unsafe", "use", "where",
+            "while", "async", "await", "dyn",
+        ],
+        line_comment: "//",
+        // Not `'`, which also starts lifetimes.
+        quotes: &['"'],
+    },
+    Syntax {
+        extensions: &["c", "h", "cc", "cpp", "hpp", "java", "js", "ts", "go"],
+        keywords: &[
+            "break", "case", "class", "const", "continue", "default", "do", "else", "enum",
+            "extern", "for", "func", "function", "if", "import", "let", "new", "package", "return",
+            "static", "struct", "switch", "typedef", "var", "void", "while",
+        ],
+        line_comment: "//",
+        quotes: &['"', '\'', '`'],
+    },
+    Syntax {
+        extensions: &["py"],
+        keywords: &[
+            "and", "as", "class", "def", "elif", "else", "for", "from", "if", "import", "in", "is",
+            "lambda", "not", "or", "pass", "return", "while", "with", "yield",
+        ],
+        line_comment: "#",
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        extensions: &["sh", "bash"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "fi", "for", "function", "if", "in",
+            "then", "while",
+        ],
+        line_comment: "#",
+        quotes: &['"', '\''],
+    },
+];
+
+/// The syntax for a file, by its extension.
+pub fn for_path(path: &Path) -> Option<&'static Syntax> {
+    let ext = path.extension()?.to_str()?;
+    SYNTAXES.iter().find(|s| s.extensions.contains(&ext))
+}
+
+impl Syntax {
+    /// Byte ranges of `line` to colour, in order.
+    pub fn highlight(&self, line: &str) -> Vec<(Range<usize>, Group)> {
+        let mut spans = Vec::new();
+        let mut i = 0;
+        while let Some(c) = line[i..].chars().next() {
+            let rest = &line[i..];
+            if rest.starts_with(self.line_comment) {
+                spans.push((i..line.len(), Group::Comment));
+                break;
+            }
+            if self.quotes.contains(&c) {
+                // To the closing quote, or the end of the line without one.
+                let mut end = line.len();
+                let mut chars = rest.char_indices().skip(1);
+                while let Some((j, d)) = chars.next() {
+                    if d == '\\' {
+                        chars.next();
+                    } else if d == c {
+                        end = i + j + 1;
+                        break;
+                    }
+                }
+                spans.push((i..end, Group::String));
+                i = end;
+                continue;
+            }
+            if c.is_alphanumeric() || c == '_' {
+                let len = rest
+                    .find(|d: char| !(d.is_alphanumeric() || d == '_'))
+                    .unwrap_or(rest.len());
+                if self.keywords.contains(&&rest[..len]) {
+                    spans.push((i..i + len, Group::Keyword));
+                }
+                i += len;
+                continue;
+            }
+            i += c.len_utf8();
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_keywords_strings_and_comments() {
+        let rust = for_path(Path::new("src/main.rs")).unwrap();
+        assert_eq!(
+            rust.highlight(r#"let s = "a \" fn"; // fn"#),
+            [
+                (0..3, Group::Keyword),
+                (8..17, Group::String),
+                (19..24, Group::Comment),
+            ]
+        );
+        // Only whole words
+        assert!(rust.highlight("letter format").is_empty());
+        assert_eq!(rust.highlight("\"open"), [(0..5, Group::String)]);
+        assert!(for_path(Path::new("notes.md")).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+use ropey::Rope;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where a tag points inside its file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    /// 1-based line number.
+    Line(usize),
+    /// A `/^...$/` search pattern, delimiters and escapes already removed.
+    Pattern {
+        text: String,
+        start: bool,
+        end: bool,
+    },
+}
+
+/// One line of a tags file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub file: PathBuf,
+    pub address: Address,
+    /// The ctags kind (`f` function, `s` struct, ...), when given.
+    pub kind: Option<String>,
+}
+
+/// Tags files tried in order: next to the current file, then in the
+/// working directory (Vim's default `./tags,tags`).
+fn candidates(current: Option<&Path>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Some(dir) = current.and_then(Path::parent) {
+        files.push(dir.join("tags"));
+    }
+    files.push(PathBuf::from("tags"));
+    files.dedup_by(|a, b| {
+        a.canonicalize()
+            .ok()
+            .is_some_and(|a| b.canonicalize().ok() == Some(a))
+    });
+    files
+}
+
+/// Every tag in the tags files for `current`, or None without any.
+fn read(current: Option<&Path>) -> Option<Vec<Tag>> {
+    let mut tags = None;
+    for file in candidates(current) {
+        let Ok(src) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        let dir = file.parent().unwrap_or(Path::new(""));
+        tags.get_or_insert_with(Vec::new).extend(parse(&src, dir));
+    }
+    tags
+}
+
+/// Every tag, for the symbols picker.
+pub fn all(current: Option<&Path>) -> Vec<Tag> {
+    read(current).unwrap_or_default()
+}
+
+/// Every tag called `name` in the tags files for `current`.
+pub fn lookup(name: &str, current: Option<&Path>) -> Result<Vec<Tag>, String> {
+    let mut found = read(current).ok_or("E433: No tags file")?;
+    found.retain(|t| t.name == name);
+    if found.is_empty() {
+        return Err(format!("E426: Tag not found: {name}"));
+    }
+    Ok(found)
+}
+
+/// Parse a tags file; files are taken relative to `dir`, where it lives.
+/// Lines that aren't tags (the `!_TAG_` header, junk) are skipped.
+pub fn parse(src: &str, dir: &Path) -> Vec<Tag> {
+    src.lines()
+        .filter(|l| !l.starts_with("!_TAG_"))
+        .filter_map(|l| {
+            let mut fields = l.splitn(3, '\t');
+            let name = fields.next()?;
+            let file = fields.next()?;
+            let rest = fields.next()?;
+            // `address;"<TAB>kind<TAB>field:value...`
+            let (address, extra) = match rest.split_once(";\"") {
+                Some((address, extra)) => (address, extra),
+                None => (rest, ""),
+            };
+            let kind = extra
+                .split('\t')
+                .find(|f| !f.is_empty())
+                .map(|f| f.strip_prefix("kind:").unwrap_or(f))
+                .filter(|f| !f.contains(':'))
+                .map(str::to_owned);
+            Some(Tag {
+                name: name.to_owned(),
+                file: dir.join(file),
+                address: parse_address(address)?,
+                kind,
+            })
+        })
+        .collect()
+}
+
+fn parse_address(address: &str) -> Option<Address> {
+    if let Ok(line) = address.parse() {
+        return Some(Address::Line(line));
+    }
+    let delim = address.chars().next().filter(|c| matches!(c, '/' | '?'))?;
+    let body = address[1..].strip_suffix(delim)?;
+    let (start, body) = match body.strip_prefix('^') {
+        Some(body) => (true, body),
+        None => (false, body),
+    };
+    let (end, body) = match body.strip_suffix('$') {
+        Some(body) if !body.ends_with('\\') => (true, body),
+        _ => (false, body),
+    };
+    let mut text = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            text.extend(chars.next());
+        } else {
+            text.push(c);
+        }
+    }
+    Some(Address::Pattern { text, start, end })
+}
+
+/// Row (0-based) of `text` the address points at.
+pub fn locate(text: &Rope, address: &Address) -> Option<usize> {
+    match address {
+        Address::Line(n) => Some(n.saturating_sub(1).min(text.len_lines() - 1)),
+        Address::Pattern { .. } => text.lines().enumerate().position(|(row, line)| {
+            let line = line.to_string();
+            points_at(address, row, &line)
+        }),
+    }
+}
+
+/// Whether the address points at `line`, row `row` (0-based) of its file.
+pub fn points_at(address: &Address, row: usize, line: &str) -> bool {
+    let line = line.trim_end_matches('\n').trim_end_matches('\r');
+    match address {
+        Address::Line(n) => row + 1 == *n,
+        Address::Pattern {
+            text: pat,
+            start,
+            end,
+        } => match (start, end) {
+            (true, true) => line == pat,
+            (true, false) => line.starts_with(pat.as_str()),
+            (false, true) => line.ends_with(pat.as_str()),
+            (false, false) => line.contains(pat.as_str()),
+        },
+    }
+}
+
+/// Run the external `ctags -R` in the working directory, writing `tags`.
+pub fn generate() -> Result<(), String> {
+    let output = Command::new("ctags")
+        .arg("-R")
+        .output()
+        .map_err(|e| format!("Can't run ctags: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let err = String::from_utf8_lossy(&output.stderr);
+        Err(format!("ctags failed: {}", err.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tag_lines() {
+        let src = "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+                   main\tsrc/main.rs\t/^fn main() {$/;\"\tf\n\
+                   Editor\tsrc/editor.rs\t/^pub struct Editor {$/;\"\tkind:s\tline:96\n\
+                   LIMIT\tsrc/lib.rs\t12\n\
+                   half\tx.rs\n";
+        let tags = parse(src, Path::new("/p"));
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[0].file, PathBuf::from("/p/src/main.rs"));
+        assert_eq!(
+            tags[0].address,
+            Address::Pattern {
+                text: "fn main() {".into(),
+                start: true,
+                end: true
+            }
+        );
+        assert_eq!(tags[0].kind.as_deref(), Some("f"));
+        assert_eq!(tags[1].kind.as_deref(), Some("s"));
+        assert_eq!(tags[2].address, Address::Line(12));
+        assert_eq!(tags[2].kind, None);
+    }
+
+    #[test]
+    fn locates_patterns_and_lines() {
+        let text = Rope::from_str("use x;\r\nfn a/b() {\nfn main() {\n}\n");
+        let pat = parse_address(r"/^fn a\/b() {$/").unwrap();
+        assert_eq!(locate(&text, &pat), Some(1));
+        let pat = parse_address("/main() {$/").unwrap();
+        assert_eq!(locate(&text, &pat), Some(2));
+        assert_eq!(locate(&text, &parse_address("/^nope/").unwrap()), None);
+        assert_eq!(locate(&text, &Address::Line(99)), Some(4));
+    }
+}
@@ -0,0 +1,365 @@
+use crate::motion::{self, CharClass};
+use crate::options::Options;
+use ropey::Rope;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextObjectKind {
+    Word,
+    Quote(char),
+    Bracket { open: char, close: char },
+    Paragraph,
+}
+
+/// A text object as typed after an operator: `iw`, `a"`, `i(`, `ap`, ...
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextObject {
+    pub kind: TextObjectKind,
+    pub around: bool,
+}
+
+impl TextObject {
+    /// Parse the key that follows `i` / `a`. Returns None for unknown objects.
+    pub fn from_key(around: bool, key: char) -> Option<Self> {
+        let kind = match key {
+            'w' => TextObjectKind::Word,
+            '"' | '\'' | '`' => TextObjectKind::Quote(key),
+            '(' | ')' | 'b' => TextObjectKind::Bracket {
+                open: '(',
+                close: ')',
+            },
+            '[' | ']' => TextObjectKind::Bracket {
+                open: '[',
+                close: ']',
+            },
+            '{' | '}' | 'B' => TextObjectKind::Bracket {
+                open: '{',
+                close: '}',
+            },
+            '<' | '>' => TextObjectKind::Bracket {
+                open: '<',
+                close: '>',
+            },
+            'p' => TextObjectKind::Paragraph,
+            _ => return None,
+        };
+        Some(Self { kind, around })
+    }
+
+    /// Resolve the object around absolute char `at` to an absolute char
+    /// range. Words are as `opts` make them.
+    pub fn resolve(&self, text: &Rope, at: usize, opts: &Options) -> Option<Range<usize>> {
+        match self.kind {
+            TextObjectKind::Word => word(text, at, self.around, opts),
+            TextObjectKind::Quote(q) => quote(text, at, q, self.around),
+            TextObjectKind::Bracket { open, close } => bracket(text, at, open, close, self.around),
+            TextObjectKind::Paragraph => paragraph(text, at, self.around),
+        }
+    }
+}
+
+// ------ Line helpers ---------------------------------------------------------
+
+/// Line `row` without its trailing line ending, plus its absolute start char.
+fn line_content(text: &Rope, row: usize) -> (usize, String) {
+    let start = text.line_to_char(row);
+    let mut s = text.line(row).to_string();
+    if s.ends_with('\n') {
+        s.pop();
+        if s.ends_with('\r') {
+            s.pop();
+        }
+    }
+    (start, s)
+}
+
+// ------ Word -----------------------------------------------------------------
+
+fn word(text: &Rope, at: usize, around: bool, opts: &Options) -> Option<Range<usize>> {
+    let row = text.char_to_line(at);
+    let (line_start, line) = line_content(text, row);
+
+    // (start_char, end_char, class) per grapheme, line-relative.
+    let mut cells = Vec::new();
+    let mut ci = 0usize;
+    for g in line.graphemes(true) {
+        let n = g.chars().count();
+        cells.push((ci, ci + n, motion::class_at(text, line_start + ci, opts)));
+        ci += n;
+    }
+    if cells.is_empty() {
+        return None;
+    }
+
+    let col = at - line_start;
+    let idx = cells
+        .iter()
+        .position(|&(s, e, _)| s <= col && col < e)
+        .unwrap_or(cells.len() - 1);
+    let class = cells[idx].2;
+
+    let joined = |a: usize, b: usize| {
+        motion::same_word(text, line_start + cells[a].0, line_start + cells[b].0, opts)
+    };
+    let run = |from: usize| -> (usize, usize) {
+        let mut s = from;
+        while s > 0 && joined(s - 1, s) {
+            s -= 1;
+        }
+        let mut e = from;
+        while e + 1 < cells.len() && joined(e, e + 1) {
+            e += 1;
+        }
+        (s, e)
+    };
+
+    let (mut s, mut e) = run(idx);
+    if around {
+        if class == CharClass::Blank {
+            // "aw" on blanks: the blanks plus the following word.
+            if e + 1 < cells.len() {
+                e = run(e + 1).1;
+            }
+        } else if e + 1 < cells.len() && cells[e + 1].2 == CharClass::Blank {
+            e = run(e + 1).1;
+        } else if s > 0 && cells[s - 1].2 == CharClass::Blank {
+            s = run(s - 1).0;
+        }
+    }
+
+    Some(line_start + cells[s].0..line_start + cells[e].1)
+}
+
+// ------ Quotes ---------------------------------------------------------------
+
+fn quote(text: &Rope, at: usize, q: char, around: bool) -> Option<Range<usize>> {
+    let row = text.char_to_line(at);
+    let (line_start, line) = line_content(text, row);
+    let chars: Vec<char> = line.chars().collect();
+
+    let mut quotes = Vec::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == q && (i == 0 || chars[i - 1] != '\\') {
+            quotes.push(i);
+        }
+    }
+
+    // Quotes pair up left to right; pick the first pair that ends at or
+    // after the cursor (which may start after it, like Vim).
+    let col = at - line_start;
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|p| (p[0], p[1]))
+        .find(|&(_, close)| close >= col)?;
+
+    let range = if around {
+        let mut s = open;
+        let mut e = close + 1;
+        let trailing = chars[e..].iter().take_while(|c| c.is_whitespace()).count();
+        if trailing > 0 {
+            e += trailing;
+        } else {
+            s -= chars[..s]
+                .iter()
+                .rev()
+                .take_while(|c| c.is_whitespace())
+                .count();
+        }
+        s..e
+    } else {
+        open + 1..close
+    };
+    Some(line_start + range.start..line_start + range.end)
+}
+
+// ------ Brackets -------------------------------------------------------------
+
+fn bracket(text: &Rope, at: usize, open: char, close: char, around: bool) -> Option<Range<usize>> {
+    let len = text.len_chars();
+    if len == 0 {
+        return None;
+    }
+    let at = at.min(len - 1);
+
+    // Find the opening bracket enclosing `at`, or under it. A closer under the
+    // cursor needs no special case: scanning back from it finds its opener.
+    let o = if text.char(at) == open {
+        at
+    } else {
+        let mut depth = 0usize;
+        let mut i = at;
+        loop {
+            if i == 0 {
+                return None;
+            }
+            i -= 1;
+            let c = text.char(i);
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                if depth == 0 {
+                    break i;
+                }
+                depth -= 1;
+            }
+        }
+    };
+
+    // Matching closer.
+    let mut depth = 0usize;
+    let mut c_idx = None;
+    for i in o + 1..len {
+        let c = text.char(i);
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                c_idx = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let c = c_idx?;
+
+    if around {
+        return Some(o..c + 1);
+    }
+
+    // Multi-line blocks: keep the line breaks after the opener and before the
+    // closer's indentation, so `di{` leaves "{\n}" like Vim.
+    let mut s = o + 1;
+    let mut e = c;
+    if s < e && text.char(s) == '\r' && s + 1 < e && text.char(s + 1) == '\n' {
+        s += 2;
+    } else if s < e && text.char(s) == '\n' {
+        s += 1;
+    }
+    let close_bol = text.line_to_char(text.char_to_line(c));
+    if close_bol > s && (close_bol..c).all(|i| matches!(text.char(i), ' ' | '\t')) {
+        e = close_bol;
+    }
+    Some(s..e.max(s))
+}
+
+// ------ Paragraph ------------------------------------------------------------
+
+fn paragraph(text: &Rope, at: usize, around: bool) -> Option<Range<usize>> {
+    let mut nlines = text.len_lines();
+    // A trailing newline makes Ropey report an extra empty line; ignore it.
+    if nlines > 1 && text.char(text.len_chars() - 1) == '\n' {
+        nlines -= 1;
+    }
+    let blank = |row: usize| line_content(text, row).1.trim().is_empty();
+
+    let row = text.char_to_line(at).min(nlines - 1);
+    let run = |from: usize, is_blank: bool| -> (usize, usize) {
+        let mut s = from;
+        while s > 0 && blank(s - 1) == is_blank {
+            s -= 1;
+        }
+        let mut e = from;
+        while e + 1 < nlines && blank(e + 1) == is_blank {
+            e += 1;
+        }
+        (s, e)
+    };
+
+    let is_blank = blank(row);
+    let (mut s, mut e) = run(row, is_blank);
+    if around {
+        if e + 1 < nlines {
+            e = run(e + 1, !is_blank).1;
+        } else if !is_blank && s > 0 {
+            s = run(s - 1, true).0;
+        }
+    }
+
+    Some(text.line_to_char(s)..text.line_to_char(e + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sel(src: &str, at: usize, around: bool, key: char) -> Option<String> {
+        let text = Rope::from_str(src);
+        let obj = TextObject::from_key(around, key).unwrap();
+        obj.resolve(&text, at, &Options::default())
+            .map(|r| text.slice(r.start..r.end).to_string())
+    }
+
+    #[test]
+    fn inner_and_around_word() {
+        // cursor on 'o' of "foo"
+        assert_eq!(sel("say foo bar", 5, false, 'w').as_deref(), Some("foo"));
+        assert_eq!(sel("say foo bar", 5, true, 'w').as_deref(), Some("foo "));
+        // last word: no trailing blanks, so "aw" takes the leading ones
+        assert_eq!(sel("say foo", 5, true, 'w').as_deref(), Some(" foo"));
+        // punctuation is its own word
+        assert_eq!(sel("a.b", 1, false, 'w').as_deref(), Some("."));
+    }
+
+    #[test]
+    fn word_treats_emoji_as_one_cell() {
+        let src = "x 👨‍👩‍👧‍👦 y";
+        assert_eq!(sel(src, 3, false, 'w').as_deref(), Some("👨‍👩‍👧‍👦"));
+    }
+
+    #[test]
+    fn quotes_inside_before_and_escaped() {
+        let src = r#"let s = "a \"b\" c";"#;
+        assert_eq!(sel(src, 10, false, '"').as_deref(), Some(r#"a \"b\" c"#));
+        // cursor before the string: Vim selects the next one on the line
+        assert_eq!(sel(src, 0, true, '"').as_deref(), Some(r#" "a \"b\" c""#));
+        assert_eq!(sel("no quotes", 2, false, '"'), None);
+    }
+
+    #[test]
+    fn brackets_nested_and_on_delimiters() {
+        let src = "f(a, (b), c)";
+        assert_eq!(sel(src, 3, false, '(').as_deref(), Some("a, (b), c"));
+        assert_eq!(sel(src, 6, false, '(').as_deref(), Some("b"));
+        assert_eq!(sel(src, 5, true, ')').as_deref(), Some("(b)"));
+        assert_eq!(sel(src, 11, true, 'b').as_deref(), Some("(a, (b), c)"));
+        assert_eq!(sel(src, 1, false, '[').as_deref(), None);
+    }
+
+    #[test]
+    fn inner_block_keeps_delimiter_lines() {
+        let src = "fn x() {\n    a;\n    b;\n}\n";
+        assert_eq!(
+            sel(src, 13, false, '{').as_deref(),
+            Some("    a;\n    b;\n")
+        );
+    }
+
+    #[test]
+    fn paragraphs() {
+        let src = "a\nb\n\n\nc\n";
+        assert_eq!(sel(src, 0, false, 'p').as_deref(), Some("a\nb\n"));
+        assert_eq!(sel(src, 0, true, 'p').as_deref(), Some("a\nb\n\n\n"));
+        // last paragraph: "ap" swallows the blank lines before it
+        assert_eq!(sel(src, 6, true, 'p').as_deref(), Some("\n\nc\n"));
+    }
+
+    #[test]
+    fn subword_objects() {
+        let opts = Options {
+            subword: true,
+            ..Options::default()
+        };
+        let text = Rope::from_str("getUserName user_id");
+        let obj = |around, at| {
+            let r = TextObject::from_key(around, 'w')
+                .unwrap()
+                .resolve(&text, at, &opts)
+                .unwrap();
+            text.slice(r).to_string()
+        };
+        assert_eq!(obj(false, 4), "User");
+        assert_eq!(obj(true, 12), "user_");
+        assert_eq!(obj(false, 17), "id");
+    }
+}
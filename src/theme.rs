@@ -0,0 +1,295 @@
+use crate::buffer::Style;
+use crate::color::{Attribute, Color};
+use crate::config;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What a piece of the screen is, as opposed to how it looks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    Keyword,
+    String,
+    Comment,
+    StatusLine,
+    Selection,
+    LineNumber,
+    /// Characters that aren't in the text, like the `<` `>` of a cut line.
+    NonText,
+    /// `list` mode's stand-ins for tabs, trailing spaces and line ends.
+    Whitespace,
+    /// The `colorcolumn` guides.
+    ColorColumn,
+    /// Spaces and tabs ending a line, with `showtrailing` on.
+    TrailingWhitespace,
+    /// The bracket pairing with the one under the cursor.
+    MatchParen,
+    /// Letters of a picker item that match the query.
+    PickerMatch,
+    ErrorMsg,
+    /// "Press ENTER" and similar questions.
+    MoreMsg,
+    ConflictMarker,
+    ConflictOurs,
+    ConflictBase,
+    ConflictTheirs,
+    /// Directories in the file tree.
+    Directory,
+    /// The Ctrl-N completion menu; its selected item is `Selection`.
+    Menu,
+}
+
+/// Theme files name groups; without them nothing does.
+#[cfg(feature = "config")]
+impl Group {
+    const ALL: [Group; 20] = [
+        Group::Keyword,
+        Group::String,
+        Group::Comment,
+        Group::StatusLine,
+        Group::Selection,
+        Group::LineNumber,
+        Group::NonText,
+        Group::Whitespace,
+        Group::ColorColumn,
+        Group::TrailingWhitespace,
+        Group::MatchParen,
+        Group::PickerMatch,
+        Group::ErrorMsg,
+        Group::MoreMsg,
+        Group::ConflictMarker,
+        Group::ConflictOurs,
+        Group::ConflictBase,
+        Group::ConflictTheirs,
+        Group::Directory,
+        Group::Menu,
+    ];
+
+    /// Key used in theme files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::Keyword => "keyword",
+            Group::String => "string",
+            Group::Comment => "comment",
+            Group::StatusLine => "statusline",
+            Group::Selection => "selection",
+            Group::LineNumber => "line-number",
+            Group::NonText => "non-text",
+            Group::Whitespace => "whitespace",
+            Group::ColorColumn => "color-column",
+            Group::TrailingWhitespace => "trailing-whitespace",
+            Group::MatchParen => "match-paren",
+            Group::PickerMatch => "picker-match",
+            Group::ErrorMsg => "error-msg",
+            Group::MoreMsg => "more-msg",
+            Group::ConflictMarker => "conflict-marker",
+            Group::ConflictOurs => "conflict-ours",
+            Group::ConflictBase => "conflict-base",
+            Group::ConflictTheirs => "conflict-theirs",
+            Group::Directory => "directory",
+            Group::Menu => "menu",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| g.name() == name)
+    }
+}
+
+/// Styles for each highlight group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    styles: HashMap<Group, Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let reverse = Style {
+            attrs: Attribute::Reverse.into(),
+            ..Style::default()
+        };
+        let styles = HashMap::from([
+            (Group::Keyword, Style::fg(Color::Magenta)),
+            (Group::String, Style::fg(Color::Green)),
+            (Group::Comment, Style::fg(Color::DarkGrey)),
+            (Group::StatusLine, reverse),
+            (Group::Selection, reverse),
+            (Group::LineNumber, Style::fg(Color::DarkGrey)),
+            (Group::NonText, Style::fg(Color::Blue)),
+            (Group::Whitespace, Style::fg(Color::DarkGrey)),
+            (
+                Group::ColorColumn,
+                Style {
+                    bg: Some(Color::DarkGrey),
+                    ..Style::default()
+                },
+            ),
+            (
+                Group::TrailingWhitespace,
+                Style {
+                    bg: Some(Color::Red),
+                    ..Style::default()
+                },
+            ),
+            (
+                Group::MatchParen,
+                Style {
+                    bg: Some(Color::DarkCyan),
+                    ..Style::default()
+                },
+            ),
+            (Group::PickerMatch, Style::fg(Color::Yellow)),
+            (Group::ErrorMsg, Style::fg(Color::Red)),
+            (Group::MoreMsg, Style::fg(Color::Green)),
+            (Group::ConflictMarker, Style::fg(Color::Red)),
+            (Group::ConflictOurs, Style::fg(Color::Green)),
+            (Group::ConflictBase, Style::fg(Color::DarkGrey)),
+            (Group::ConflictTheirs, Style::fg(Color::Blue)),
+            (Group::Directory, Style::fg(Color::Blue)),
+            (
+                Group::Menu,
+                Style {
+                    bg: Some(Color::DarkGrey),
+                    ..Style::default()
+                },
+            ),
+        ]);
+        Self { styles }
+    }
+}
+
+/// Where `:Pick themes` looks: `themes` in the config directory.
+pub fn dir() -> Option<PathBuf> {
+    config::dir().map(|d| d.join("themes"))
+}
+
+#[cfg(feature = "config")]
+pub fn dir_with(lookup: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    config::dir_with(lookup).map(|d| d.join("themes"))
+}
+
+impl Theme {
+    pub fn get(&self, group: Group) -> Style {
+        self.styles.get(&group).copied().unwrap_or_default()
+    }
+
+    #[cfg(feature = "config")]
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|e| format!("E185: Cannot find color scheme '{}': {e}", path.display()))?;
+        Self::parse(&src)
+    }
+
+    #[cfg(not(feature = "config"))]
+    pub fn load(_path: &Path) -> Result<Self, String> {
+        Err(config::NOT_BUILT.into())
+    }
+
+    /// A theme file sets groups over the default look, each to a colour or
+    /// to a table of `fg`, `bg` and attributes:
+    ///
+    /// ```toml
+    /// keyword = "#c678dd"
+    /// statusline = { fg = "black", bg = "#98c379", bold = true }
+    /// ```
+    #[cfg(feature = "config")]
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let table: toml::Table = src.parse().map_err(|e| format!("E5: theme: {e}"))?;
+        let mut theme = Self::default();
+        for (name, value) in &table {
+            let group = Group::from_name(name)
+                .ok_or_else(|| format!("E411: Highlight group not found: {name}"))?;
+            let style = match value {
+                toml::Value::String(color) => Style::fg(parse_color(color)?),
+                toml::Value::Table(t) => parse_style(t)?,
+                _ => return Err(format!("E475: Invalid argument: {name}")),
+            };
+            theme.styles.insert(group, style);
+        }
+        Ok(theme)
+    }
+}
+
+#[cfg(feature = "config")]
+fn parse_style(table: &toml::Table) -> Result<Style, String> {
+    let mut style = Style::default();
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("fg", toml::Value::String(c)) => style.fg = Some(parse_color(c)?),
+            ("bg", toml::Value::String(c)) => style.bg = Some(parse_color(c)?),
+            (attr, toml::Value::Boolean(on)) => {
+                let attr = match attr {
+                    "bold" => Attribute::Bold,
+                    "italic" => Attribute::Italic,
+                    "underline" => Attribute::Underlined,
+                    "reverse" => Attribute::Reverse,
+                    _ => return Err(format!("E475: Invalid argument: {key}")),
+                };
+                if *on {
+                    style.attrs.set(attr);
+                }
+            }
+            _ => return Err(format!("E475: Invalid argument: {key}")),
+        }
+    }
+    Ok(style)
+}
+
+/// `#rrggbb` or one of the colour names (`red`, `dark_grey`, ...).
+#[cfg(feature = "config")]
+fn parse_color(s: &str) -> Result<Color, String> {
+    let invalid = || format!("E254: Cannot allocate color {s}");
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return Err(invalid());
+        }
+        let rgb = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+        return Ok(Color::Rgb {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        });
+    }
+    Color::from_name(s).ok_or_else(invalid)
+}
+
+#[cfg(all(test, feature = "config"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_files_override_the_defaults() {
+        let theme = Theme::parse(
+            r##"
+            # comments are fine
+            keyword = "#c678dd"
+            statusline = { fg = "black", bg = "#98c379", bold = true }
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            theme.get(Group::Keyword).fg,
+            Some(Color::Rgb {
+                r: 0xc6,
+                g: 0x78,
+                b: 0xdd
+            })
+        );
+        let status = theme.get(Group::StatusLine);
+        assert_eq!(status.fg, Some(Color::Black));
+        assert!(status.attrs.has(Attribute::Bold));
+        assert!(!status.attrs.has(Attribute::Reverse));
+        assert_eq!(theme.get(Group::LineNumber), Style::fg(Color::DarkGrey));
+    }
+
+    #[test]
+    fn bad_theme_files_are_errors() {
+        assert!(Theme::parse("nope = \"red\"")
+            .unwrap_err()
+            .starts_with("E411"));
+        assert!(Theme::parse("keyword = \"#12\"")
+            .unwrap_err()
+            .starts_with("E254"));
+        assert!(Theme::parse("keyword = { blink = true }").is_err());
+        assert!(Theme::parse("keyword = ").is_err());
+    }
+}
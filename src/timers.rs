@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+/// The shortest interval a timer takes, so a slip of the keyboard can't
+/// keep the editor busy.
+const SHORTEST: Duration = Duration::from_millis(100);
+
+/// An ex command run every so often: `:Every 5m w`.
+#[derive(Debug, Clone)]
+pub struct Timer {
+    pub id: usize,
+    pub every: Duration,
+    /// The buffer it was started in, which it only runs in while shown.
+    /// None for the config's timers, which run in whichever buffer is.
+    pub buffer: Option<usize>,
+    /// The command, as typed.
+    pub command: String,
+    next: Duration,
+}
+
+impl Timer {
+    pub fn new(
+        id: usize,
+        every: Duration,
+        buffer: Option<usize>,
+        command: String,
+        now: Duration,
+    ) -> Self {
+        Self {
+            id,
+            every,
+            buffer,
+            command,
+            next: now + every,
+        }
+    }
+
+    /// When it next comes due.
+    pub fn next(&self) -> Duration {
+        self.next
+    }
+
+    /// Whether it is due at `now`. If so, the next run is the first beat
+    /// after `now` on the schedule it started with: a late run doesn't
+    /// push the later ones back, and runs missed while the editor was
+    /// busy or suspended come to one run rather than a burst.
+    pub fn fire(&mut self, now: Duration) -> bool {
+        if now < self.next {
+            return false;
+        }
+        let beats = (now - self.next).as_nanos() / self.every.as_nanos() + 1;
+        let ahead = self.every.as_nanos() * beats;
+        self.next += Duration::from_nanos(u64::try_from(ahead).unwrap_or(u64::MAX));
+        true
+    }
+}
+
+/// `500ms`, `10s`, `5m` or `1h`, at least `SHORTEST`.
+pub fn parse_interval(s: &str) -> Option<Duration> {
+    let digits = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let n: u64 = s[..digits].parse().ok()?;
+    let every = match &s[digits..] {
+        "ms" => Duration::from_millis(n),
+        "s" => Duration::from_secs(n),
+        "m" => Duration::from_secs(n.checked_mul(60)?),
+        "h" => Duration::from_secs(n.checked_mul(3600)?),
+        _ => return None,
+    };
+    (every >= SHORTEST).then_some(every)
+}
+
+/// An interval as `parse_interval` reads it, in the largest whole unit.
+pub fn show_interval(every: Duration) -> String {
+    let ms = every.as_millis();
+    match ms {
+        _ if ms.is_multiple_of(3_600_000) => format!("{}h", ms / 3_600_000),
+        _ if ms.is_multiple_of(60_000) => format!("{}m", ms / 60_000),
+        _ if ms.is_multiple_of(1000) => format!("{}s", ms / 1000),
+        _ => format!("{ms}ms"),
+    }
+}
+
+/// The `[[timer]]` tables of the config file, as intervals and the
+/// commands to run:
+///
+/// ```toml
+/// [[timer]]
+/// every = "5m"
+/// run = "w"
+/// ```
+#[cfg(feature = "config")]
+pub fn parse_config(value: &toml::Value) -> Result<Vec<(Duration, String)>, String> {
+    let Some(tables) = value.as_array() else {
+        return Err("E474: timer must be an array of tables".into());
+    };
+    tables
+        .iter()
+        .map(|table| {
+            let get = |key| table.get(key).and_then(|v| v.as_str());
+            let (Some(every), Some(run)) = (get("every"), get("run")) else {
+                return Err("E474: a timer needs every and run".to_owned());
+            };
+            let every = parse_interval(every)
+                .ok_or_else(|| format!("E475: Invalid argument: every = {every}"))?;
+            // Checked now, so a bad one shows at startup rather than later
+            crate::ex::parse(run).map_err(|e| format!("timer: {e}"))?;
+            Ok((every, run.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_read_and_show() {
+        assert_eq!(parse_interval("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_interval("10s"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_interval("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_interval("2h"), Some(Duration::from_secs(7200)));
+        for bad in ["", "10", "s", "1.5s", "5 m", "10ms", "0s"] {
+            assert_eq!(parse_interval(bad), None, "{bad}");
+        }
+        assert_eq!(show_interval(Duration::from_secs(300)), "5m");
+        assert_eq!(show_interval(Duration::from_secs(90)), "90s");
+        assert_eq!(show_interval(Duration::from_millis(1500)), "1500ms");
+    }
+
+    #[test]
+    fn runs_keep_to_the_first_beat() {
+        let start = Duration::from_secs(1000);
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut timer = Timer::new(1, Duration::from_secs(1), None, "w".into(), start);
+        assert!(!timer.fire(at(999)));
+        // Late, but the next is still a second after the last was due
+        assert!(timer.fire(at(1300)));
+        assert_eq!(timer.next(), at(2000));
+        assert!(!timer.fire(at(1999)));
+        // Five missed beats come to one run
+        assert!(timer.fire(at(6500)));
+        assert!(!timer.fire(at(6600)));
+        assert_eq!(timer.next(), at(7000));
+    }
+}
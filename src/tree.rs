@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// A row of the file tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// Directories between it and the root.
+    pub depth: usize,
+    pub dir: bool,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        let name = self.path.file_name().unwrap_or(self.path.as_os_str());
+        name.to_string_lossy().into_owned()
+    }
+}
+
+/// The directory tree in the sidebar: what's under the root, with the
+/// expanded directories' contents shown under them. Hidden files and
+/// `target` are left out, as in the files picker.
+#[derive(Debug, Clone)]
+pub struct FileTree {
+    root: PathBuf,
+    expanded: BTreeSet<PathBuf>,
+    entries: Vec<Entry>,
+    selected: usize,
+    /// First row on screen.
+    top: usize,
+}
+
+impl FileTree {
+    pub fn new(root: PathBuf) -> Self {
+        let mut tree = Self {
+            root,
+            expanded: BTreeSet::new(),
+            entries: Vec::new(),
+            selected: 0,
+            top: 0,
+        };
+        tree.refresh();
+        tree
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn selected_row(&self) -> usize {
+        self.selected
+    }
+
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    /// Read the directories again, keeping the selection on the same path
+    /// if it's still there.
+    pub fn refresh(&mut self) {
+        let keep = self.selected().map(|e| e.path.clone());
+        self.entries.clear();
+        list(&self.root, 0, &self.expanded, &mut self.entries);
+        self.selected = keep
+            .and_then(|path| self.entries.iter().position(|e| e.path == path))
+            .unwrap_or(self.selected)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    pub fn step(&mut self, by: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(by).min(last);
+    }
+
+    /// Expand the selected directory, or collapse it if it is expanded.
+    pub fn toggle(&mut self) {
+        let Some(entry) = self.selected().filter(|e| e.dir) else {
+            return;
+        };
+        let path = entry.path.clone();
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+        self.refresh();
+    }
+
+    /// Collapse the selected directory; on anything else, select the
+    /// directory it's in.
+    pub fn collapse(&mut self) {
+        let Some(entry) = self.selected().cloned() else {
+            return;
+        };
+        if entry.dir && self.expanded.remove(&entry.path) {
+            self.refresh();
+            return;
+        }
+        let depth = entry.depth;
+        if let Some(parent) = self.entries[..self.selected]
+            .iter()
+            .rposition(|e| e.depth < depth)
+        {
+            self.selected = parent;
+        }
+    }
+
+    /// Scroll just enough to keep the selection in `height` rows.
+    pub fn follow(&mut self, height: usize) {
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if height > 0 && self.selected >= self.top + height {
+            self.top = self.selected + 1 - height;
+        }
+    }
+}
+
+/// What's in `dir`, directories first, each expanded one followed by its
+/// own entries.
+fn list(dir: &Path, depth: usize, expanded: &BTreeSet<PathBuf>, out: &mut Vec<Entry>) {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<Entry> = read
+        .flatten()
+        .filter(|e| {
+            let name = e.file_name();
+            !name.to_string_lossy().starts_with('.') && name != "target"
+        })
+        .map(|e| Entry {
+            dir: e.path().is_dir(),
+            path: e.path(),
+            depth,
+        })
+        .collect();
+    entries.sort_by(|a, b| (!a.dir, &a.path).cmp(&(!b.dir, &b.path)));
+    for entry in entries {
+        let open = entry.dir && expanded.contains(&entry.path);
+        let path = entry.path.clone();
+        out.push(entry);
+        if open {
+            list(&path, depth + 1, expanded, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_and_collapses_directories() {
+        let dir = std::env::temp_dir().join(format!("mters-tree-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        for file in ["b.txt", ".hidden", "src/main.rs", "src/deep/x.rs"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let mut tree = FileTree::new(dir.clone());
+        let names = |tree: &FileTree| -> Vec<String> {
+            tree.entries()
+                .iter()
+                .map(|e| format!("{}{}", "  ".repeat(e.depth), e.name()))
+                .collect()
+        };
+        assert_eq!(names(&tree), ["src", "b.txt"]);
+
+        tree.toggle();
+        assert_eq!(names(&tree), ["src", "  deep", "  main.rs", "b.txt"]);
+        tree.step(1);
+        tree.toggle();
+        assert_eq!(names(&tree).len(), 5);
+        // On a file, h goes up to its directory; then it collapses it
+        tree.step(1);
+        assert_eq!(tree.selected().unwrap().name(), "x.rs");
+        tree.collapse();
+        assert_eq!(tree.selected().unwrap().name(), "deep");
+        tree.collapse();
+        assert_eq!(names(&tree), ["src", "  deep", "  main.rs", "b.txt"]);
+        tree.step(10);
+        assert_eq!(tree.selected().unwrap().name(), "b.txt");
+        tree.follow(2);
+        assert_eq!(tree.top(), 2);
+
+        // The selection stays on its file when the listing changes
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        tree.refresh();
+        assert_eq!(tree.selected().unwrap().name(), "b.txt");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
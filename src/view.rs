@@ -0,0 +1,185 @@
+/// The part of the buffer currently on screen. Lines don't wrap, so long
+/// ones scroll sideways.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Viewport {
+    /// First buffer row shown.
+    pub top: usize,
+    /// Text rows available (the command line is not included).
+    pub height: usize,
+    /// First screen column of the text shown, past the gutter.
+    pub left: usize,
+    /// Screen columns, the gutter included.
+    pub width: usize,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        // Until the terminal reports its size.
+        Self {
+            top: 0,
+            height: 24,
+            left: 0,
+            width: 80,
+        }
+    }
+}
+
+/// Ctrl-D / Ctrl-U / Ctrl-F / Ctrl-B.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scroll {
+    HalfDown,
+    HalfUp,
+    PageDown,
+    PageUp,
+}
+
+/// `zz` / `zt` / `zb`: where to put the cursor line on screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Center,
+    Top,
+    Bottom,
+}
+
+impl Viewport {
+    /// Last buffer row that fits on screen.
+    pub fn bottom(&self) -> usize {
+        self.top + self.height.max(1) - 1
+    }
+
+    /// Scroll just enough to bring `row` on screen.
+    pub fn follow(&mut self, row: usize) {
+        if row < self.top {
+            self.top = row;
+        } else if row > self.bottom() {
+            self.top = row + 1 - self.height.max(1);
+        }
+    }
+
+    /// Scroll sideways just enough to show screen column `col` in `room`
+    /// columns of text.
+    pub fn follow_col(&mut self, col: usize, room: usize) {
+        if col < self.left {
+            self.left = col;
+        } else if col >= self.left + room.max(1) {
+            self.left = col + 1 - room.max(1);
+        }
+    }
+
+    /// Scroll so `row` sits at the centre, top or bottom of the screen.
+    pub fn align(&mut self, row: usize, how: Align) {
+        let height = self.height.max(1);
+        self.top = match how {
+            Align::Top => row,
+            Align::Center => row.saturating_sub((height - 1) / 2),
+            Align::Bottom => row.saturating_sub(height - 1),
+        };
+    }
+
+    /// Scroll the view and work out where the cursor goes: half pages move
+    /// both by the same amount, full pages keep two lines of overlap and
+    /// leave the cursor on the first (or last) row still visible.
+    /// Returns the new cursor row.
+    pub fn scroll(
+        &mut self,
+        how: Scroll,
+        count: Option<usize>,
+        row: usize,
+        last_row: usize,
+    ) -> usize {
+        let height = self.height.max(1);
+        match how {
+            Scroll::HalfDown | Scroll::HalfUp => {
+                let n = count.unwrap_or((height / 2).max(1));
+                if how == Scroll::HalfDown {
+                    self.top = (self.top + n).min(last_row);
+                    (row + n).min(last_row)
+                } else {
+                    self.top = self.top.saturating_sub(n);
+                    row.saturating_sub(n)
+                }
+            }
+            Scroll::PageDown => {
+                let n = height.saturating_sub(2).max(1) * count.unwrap_or(1);
+                self.top = (self.top + n).min(last_row);
+                row.max(self.top)
+            }
+            Scroll::PageUp => {
+                let n = height.saturating_sub(2).max(1) * count.unwrap_or(1);
+                self.top = self.top.saturating_sub(n);
+                row.min(self.bottom())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_scrolls_minimally() {
+        let mut view = Viewport {
+            height: 10,
+            ..Viewport::default()
+        };
+        view.follow(9);
+        assert_eq!(view.top, 0);
+        view.follow(12);
+        assert_eq!(view.top, 3);
+        view.follow(1);
+        assert_eq!(view.top, 1);
+    }
+
+    #[test]
+    fn follow_col_scrolls_sideways_minimally() {
+        let mut view = Viewport::default();
+        view.follow_col(79, 80);
+        assert_eq!(view.left, 0);
+        view.follow_col(85, 80);
+        assert_eq!(view.left, 6);
+        view.follow_col(10, 80);
+        assert_eq!(view.left, 6);
+        view.follow_col(2, 80);
+        assert_eq!(view.left, 2);
+    }
+
+    #[test]
+    fn align_cursor_row() {
+        let mut view = Viewport {
+            height: 10,
+            ..Viewport::default()
+        };
+        view.align(20, Align::Center);
+        assert_eq!(view.top, 16);
+        view.align(20, Align::Bottom);
+        assert_eq!(view.top, 11);
+        view.align(20, Align::Top);
+        assert_eq!(view.top, 20);
+        view.align(2, Align::Center);
+        assert_eq!(view.top, 0);
+    }
+
+    #[test]
+    fn half_and_full_pages() {
+        let mut view = Viewport {
+            height: 10,
+            ..Viewport::default()
+        };
+        assert_eq!(view.scroll(Scroll::HalfDown, None, 2, 100), 7);
+        assert_eq!(view.top, 5);
+        assert_eq!(view.scroll(Scroll::HalfUp, Some(3), 7, 100), 4);
+        assert_eq!(view.top, 2);
+
+        assert_eq!(view.scroll(Scroll::PageDown, None, 4, 100), 10);
+        assert_eq!(view.top, 10);
+        assert_eq!(view.scroll(Scroll::PageUp, None, 15, 100), 11);
+        assert_eq!(view.top, 2);
+
+        // Stops at the buffer's edges
+        assert_eq!(view.scroll(Scroll::HalfDown, Some(50), 20, 30), 30);
+        assert_eq!(view.top, 30);
+        assert_eq!(view.scroll(Scroll::PageUp, Some(9), 30, 30), 9);
+        assert_eq!(view.top, 0);
+    }
+}
@@ -0,0 +1,357 @@
+//! The real binary in a pseudo-terminal: keys go in as bytes, and what it
+//! writes back is played onto a grid of cells, which the tests read. This
+//! catches what tests on `Editor` can't: raw mode, escape sequences,
+//! resizes and handing the terminal back.
+#![cfg(unix)]
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a screen gets to show what a test waits for.
+const PATIENCE: Duration = Duration::from_secs(10);
+
+/// Just enough of a VT100 to follow the renderer: printing, cursor moves,
+/// line clears and the terminal queries crossterm makes.
+struct Screen {
+    cells: Vec<Vec<char>>,
+    cursor: (usize, usize),
+    /// Bytes of an escape sequence not yet complete.
+    partial: Vec<u8>,
+    /// Bytes of a UTF-8 character not yet complete.
+    utf8: Vec<u8>,
+}
+
+impl Screen {
+    fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cells: vec![vec![' '; cols]; rows],
+            cursor: (0, 0),
+            partial: Vec::new(),
+            utf8: Vec::new(),
+        }
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.cells.resize(rows, Vec::new());
+        for row in &mut self.cells {
+            row.resize(cols, ' ');
+        }
+    }
+
+    fn row(&self, y: usize) -> String {
+        self.cells[y]
+            .iter()
+            .collect::<String>()
+            .trim_end()
+            .to_owned()
+    }
+
+    fn text(&self) -> String {
+        (0..self.cells.len()).map(|y| self.row(y) + "\n").collect()
+    }
+
+    fn put(&mut self, c: char) {
+        let (x, y) = self.cursor;
+        if let Some(cell) = self.cells.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *cell = c;
+        }
+        self.cursor.0 += 1;
+    }
+
+    /// Play `bytes` onto the grid. Returns what the terminal would answer.
+    fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut reply = Vec::new();
+        for &b in bytes {
+            if !self.partial.is_empty() || b == 0x1b {
+                self.partial.push(b);
+                if let Some(answer) = self.escape() {
+                    reply.extend(answer);
+                }
+                continue;
+            }
+            match b {
+                b'\r' => self.cursor.0 = 0,
+                b'\n' => self.cursor.1 = (self.cursor.1 + 1).min(self.cells.len() - 1),
+                0x08 => self.cursor.0 = self.cursor.0.saturating_sub(1),
+                0x00..=0x1f => {}
+                _ => {
+                    self.utf8.push(b);
+                    if let Ok(s) = std::str::from_utf8(&self.utf8) {
+                        let c = s.chars().next().expect("one char");
+                        self.utf8.clear();
+                        self.put(c);
+                    }
+                }
+            }
+        }
+        reply
+    }
+
+    /// Act on `partial` if it is a whole escape sequence, and clear it.
+    fn escape(&mut self) -> Option<Vec<u8>> {
+        let seq = &self.partial;
+        if seq.len() < 2 {
+            return None;
+        }
+        if seq[1] != b'[' {
+            // Two-byte sequences like ESC 7 / ESC 8: nothing to draw.
+            self.partial.clear();
+            return None;
+        }
+        let last = *seq.last().expect("not empty");
+        if seq.len() == 2 || !(0x40..=0x7e).contains(&last) {
+            return None;
+        }
+        let body = String::from_utf8_lossy(&seq[2..seq.len() - 1]).into_owned();
+        self.partial.clear();
+        let numbers: Vec<usize> = body
+            .split(';')
+            .map(|n| n.trim_start_matches('?').parse().unwrap_or(0))
+            .collect();
+        let arg = |i: usize| numbers.get(i).copied().filter(|&n| n > 0).unwrap_or(1);
+        let (cols, rows) = (self.cells[0].len(), self.cells.len());
+        match last {
+            b'H' => self.cursor = ((arg(1) - 1).min(cols), (arg(0) - 1).min(rows - 1)),
+            b'G' => self.cursor.0 = (arg(0) - 1).min(cols),
+            b'K' => {
+                let (x, y) = self.cursor;
+                let row = &mut self.cells[y];
+                let span = match numbers[0] {
+                    1 => 0..(x + 1).min(cols),
+                    2 => 0..cols,
+                    _ => x.min(cols)..cols,
+                };
+                row[span].fill(' ');
+            }
+            b'J' if numbers[0] == 2 => self.cells.iter_mut().for_each(|r| r.fill(' ')),
+            // Primary device attributes: a VT220 without the kitty protocol.
+            b'c' if body.is_empty() => return Some(b"\x1b[?62;22c".to_vec()),
+            // Colours, modes, cursor shapes and kitty flags change nothing here.
+            _ => {}
+        }
+        None
+    }
+}
+
+/// The editor running in a pseudo-terminal of its own.
+struct Term {
+    master: File,
+    output: Receiver<Vec<u8>>,
+    child: Child,
+    screen: Screen,
+    /// The terminal side, kept to check the modes left on it.
+    slave: OwnedFd,
+    home: PathBuf,
+}
+
+fn winsize(cols: usize, rows: usize) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows as u16,
+        ws_col: cols as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+impl Term {
+    /// Run the editor in a `cols` × `rows` terminal. Its
+    /// configuration comes from an empty directory, not the user's.
+    fn spawn(name: &str, cols: usize, rows: usize) -> Self {
+        let home = std::env::temp_dir().join(format!("mters-pty-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&home).unwrap();
+        let (mut master, mut slave) = (0, 0);
+        let size = winsize(cols, rows);
+        // SAFETY: openpty fills in two new descriptors, which are owned below.
+        let opened = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &size,
+            )
+        };
+        assert_eq!(opened, 0, "openpty: {}", std::io::Error::last_os_error());
+        // SAFETY: both descriptors are fresh and owned by nothing else.
+        let (master, slave) = unsafe { (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+        let stdio = || Stdio::from(slave.try_clone().unwrap());
+        let mut command = Command::new(env!("CARGO_BIN_EXE_neo2vim"));
+        command
+            .env("TERM", "xterm-256color")
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join("config"))
+            .env_remove("COLORTERM")
+            .stdin(stdio())
+            .stdout(stdio())
+            // Kept apart to check that nothing is ever written there, where
+            // it would land over the screen.
+            .stderr(File::create(home.join("stderr")).unwrap());
+        // SAFETY: only async-signal-safe calls between fork and exec.
+        unsafe {
+            command.pre_exec(|| {
+                // A session of its own, with the pty as its terminal.
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn().unwrap();
+
+        let (tx, output) = mpsc::channel();
+        let mut reader = master.try_clone().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // Ends with an error once the editor has gone.
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                if tx.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut term = Self {
+            master,
+            output,
+            child,
+            screen: Screen::new(cols, rows),
+            slave,
+            home,
+        };
+        // Keys sent before raw mode would be cooked by the line discipline.
+        term.wait_for_row(rows - 2, "NORMAL");
+        term
+    }
+
+    fn send(&mut self, keys: &str) {
+        self.master.write_all(keys.as_bytes()).unwrap();
+    }
+
+    /// Play output onto the screen until `done` holds for it.
+    fn wait_for(&mut self, what: &str, done: impl Fn(&Screen) -> bool) {
+        let deadline = Instant::now() + PATIENCE;
+        while !done(&self.screen) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.output.recv_timeout(left) {
+                Ok(bytes) => {
+                    let reply = self.screen.feed(&bytes);
+                    self.master.write_all(&reply).unwrap();
+                }
+                Err(_) => panic!(
+                    "no {what} on the screen:\n{}\nstderr:\n{}",
+                    self.screen.text(),
+                    std::fs::read_to_string(self.home.join("stderr")).unwrap_or_default()
+                ),
+            }
+        }
+    }
+
+    fn wait_for_row(&mut self, y: usize, text: &str) {
+        self.wait_for(&format!("{text:?} on row {y}"), |s| s.row(y).contains(text));
+    }
+
+    /// Resize the terminal, which sends the editor SIGWINCH.
+    fn resize(&mut self, cols: usize, rows: usize) {
+        // SAFETY: a plain ioctl on a descriptor this owns.
+        let done = unsafe {
+            libc::ioctl(
+                self.master.as_raw_fd(),
+                libc::TIOCSWINSZ,
+                &winsize(cols, rows),
+            )
+        };
+        assert_eq!(done, 0);
+        self.screen.resize(cols, rows);
+    }
+
+    /// Wait for the editor to exit.
+    fn exit_status(&mut self) -> std::process::ExitStatus {
+        let deadline = Instant::now() + PATIENCE;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                let stderr = std::fs::read_to_string(self.home.join("stderr")).unwrap();
+                assert_eq!(stderr, "", "written to stderr, over the screen");
+                return status;
+            }
+            assert!(Instant::now() < deadline, "the editor didn't exit");
+            // Keep its output flowing so it never blocks on a full pty.
+            if let Ok(bytes) = self.output.recv_timeout(Duration::from_millis(20)) {
+                self.screen.feed(&bytes);
+            }
+        }
+    }
+
+    /// Whether the terminal is back in cooked mode: echo and line editing.
+    fn cooked(&self) -> bool {
+        // SAFETY: termios is plain data that tcgetattr fills in.
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        assert_eq!(
+            unsafe { libc::tcgetattr(self.slave.as_raw_fd(), &mut termios) },
+            0
+        );
+        termios.c_lflag & (libc::ICANON | libc::ECHO) == (libc::ICANON | libc::ECHO)
+    }
+}
+
+impl Drop for Term {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.home);
+    }
+}
+
+#[test]
+fn edits_and_writes_a_file_then_hands_the_terminal_back() {
+    let mut term = Term::spawn("write", 80, 12);
+    let path = term.home.join("a.txt");
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    term.send(&format!(":e {}\r", path.display()));
+    term.wait_for_row(0, "one");
+    term.wait_for_row(10, "a.txt");
+    assert_eq!(term.screen.row(2), "three");
+
+    term.send("jdd");
+    term.wait_for_row(10, "[+]");
+    assert_eq!(term.screen.row(1), "three");
+    // The cursor sits on the line it moved to
+    assert_eq!(term.screen.cursor, (0, 1));
+
+    // Typing, and leaving Insert mode again
+    term.send("Ox\ry\x1b");
+    term.wait_for_row(2, "y");
+    term.send("ddkdd");
+    term.wait_for("the typed lines gone", |s| {
+        s.row(1) == "three" && s.row(2) != "three"
+    });
+
+    term.send(":w\r");
+    term.wait_for_row(11, "written");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\nthree\n");
+
+    assert!(!term.cooked(), "raw mode while editing");
+    term.send(":q\r");
+    assert!(term.exit_status().success());
+    assert!(term.cooked(), "the terminal is handed back");
+}
+
+#[test]
+fn a_resize_redraws_at_the_new_size() {
+    let mut term = Term::spawn("resize", 60, 12);
+    term.wait_for_row(10, "[No Name]");
+    term.resize(50, 8);
+    term.wait_for_row(6, "[No Name]");
+    // The status line's right end follows the new width
+    term.wait_for("the position at the right edge", |s| {
+        s.row(6).ends_with("1:1 byte 1")
+    });
+    term.send(":q\r");
+    assert!(term.exit_status().success());
+}